@@ -0,0 +1,344 @@
+//! The `ar` command, listing (`t`), extracting (`x`), printing (`p`) or
+//! replacing (`r`) the members of Unix archives, like static libraries.
+//! The long names of GNU and BSD archives are read; those written are in
+//! the GNU way.
+
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::archive::*;
+use crate::utils::*;
+
+const MAGIC: &[u8] = b"!<arch>\n";
+const HEADER: usize = 60;
+/// Longer names are kept in the table of long names.
+const MAX_NAME: usize = 15;
+
+fn usage() -> ! {
+    eprintln!("Usage: ar [-]{{t|x|p|r}}[cuvo] ARCHIVE [MEMBER]...");
+    std::process::exit(-271);
+}
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("ar: {}", message);
+    std::process::exit(-271);
+}
+
+/// Parse a numeric field of a header, padded with spaces.
+fn number(field: &[u8], radix: u32) -> Option<u64> {
+    let field = std::str::from_utf8(field).ok()?.trim_end_matches(' ');
+    if field.is_empty() {
+        return Some(0);
+    }
+    u64::from_str_radix(field, radix).ok()
+}
+
+/// Parse the members of an archive, with their data. The symbol tables are
+/// skipped.
+fn parse(archive: &[u8]) -> Option<Vec<(Entry, Vec<u8>)>> {
+    let mut rest = archive.strip_prefix(MAGIC)?;
+    let mut long_names: &[u8] = &[];
+    let mut members = Vec::new();
+
+    while !rest.is_empty() {
+        let header = rest.get(..HEADER)?;
+        if &header[58..] != b"`\n" {
+            return None;
+        }
+        let size = number(&header[48..58], 10)? as usize;
+        let mut data = rest.get(HEADER..HEADER + size)?;
+        // The data is padded to an even size.
+        rest = rest.get(HEADER + size + size % 2..).unwrap_or_default();
+
+        let name = text(&header[..16]);
+        let name = match name.as_str() {
+            "/" | "/SYM64/" | "__.SYMDEF" | "__.SYMDEF SORTED" => continue,
+            "//" => {
+                long_names = data;
+                continue;
+            }
+            _ => {
+                if let Some(offset) = name.strip_prefix('/') {
+                    // A GNU long name, ended by "/\n".
+                    let long_name = long_names.get(offset.parse::<usize>().ok()?..)?;
+                    let end = long_name
+                        .iter()
+                        .position(|&byte| byte == b'\n')
+                        .unwrap_or(long_name.len());
+                    let long_name = long_name[..end]
+                        .strip_suffix(b"/")
+                        .unwrap_or(&long_name[..end]);
+                    String::from_utf8_lossy(long_name).into_owned()
+                } else if let Some(len) = name.strip_prefix("#1/") {
+                    // A BSD long name, at the start of the data.
+                    let len = len.parse().ok()?;
+                    let long_name = data.get(..len)?;
+                    data = &data[len..];
+                    let end = long_name.iter().position(|&byte| byte == 0).unwrap_or(len);
+                    String::from_utf8_lossy(&long_name[..end]).into_owned()
+                } else {
+                    name.strip_suffix('/').unwrap_or(&name).to_string()
+                }
+            }
+        };
+
+        let entry = Entry {
+            name,
+            kind: Kind::File,
+            mode: number(&header[40..48], 8)? as u32 & 0o7777,
+            uid: number(&header[28..34], 10)? as u32,
+            gid: number(&header[34..40], 10)? as u32,
+            user: String::new(),
+            group: String::new(),
+            mtime: number(&header[16..28], 10)? as i64,
+            size: data.len() as u64,
+            device: (0, 0),
+        };
+        members.push((entry, data.to_vec()));
+    }
+
+    Some(members)
+}
+
+/// Get the text of a header field, without its padding.
+fn text(field: &[u8]) -> String {
+    String::from_utf8_lossy(field)
+        .trim_end_matches(' ')
+        .to_string()
+}
+
+/// Format the members of an archive. The names too long for the headers
+/// are in a table of long names, the first member.
+fn format(members: &[(Entry, Vec<u8>)]) -> Vec<u8> {
+    let mut archive = MAGIC.to_vec();
+    let mut long_names = Vec::new();
+    let mut names = Vec::new();
+
+    for (entry, _) in members {
+        if entry.name.len() > MAX_NAME || entry.name.contains('/') {
+            names.push(format!("/{}", long_names.len()));
+            long_names.extend_from_slice(entry.name.as_bytes());
+            long_names.extend_from_slice(b"/\n");
+        } else {
+            names.push(format!("{}/", entry.name));
+        }
+    }
+
+    // The table has no attributes, and is padded in itself.
+    if !long_names.is_empty() {
+        if long_names.len() % 2 == 1 {
+            long_names.push(b'\n');
+        }
+        let header = format!("{:<48}{:<10}`\n", "//", long_names.len());
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(&long_names);
+    }
+
+    // Every field is cut to its width.
+    for ((entry, data), name) in members.iter().zip(&names) {
+        let header = format!(
+            "{:<16.16}{:<12.12}{:<6.6}{:<6.6}{:<8.8}{:<10.10}`\n",
+            name,
+            entry.mtime,
+            entry.uid,
+            entry.gid,
+            format!("{:o}", libc::S_IFREG | entry.mode),
+            data.len()
+        );
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            archive.push(b'\n');
+        }
+    }
+
+    archive
+}
+
+/// Format a member like `ls -l` does, without its type.
+fn long_listing(entry: &Entry) -> String {
+    let date = chrono::DateTime::from_timestamp(entry.mtime, 0)
+        .map(|date| {
+            date.with_timezone(&chrono::Local)
+                .format("%b %e %H:%M %Y")
+                .to_string()
+        })
+        .unwrap_or_default();
+
+    format!(
+        "{} {}/{} {:>6} {} {}",
+        format_mode(entry.mode),
+        id_name("/etc/passwd", entry.uid),
+        id_name("/etc/group", entry.gid),
+        entry.size,
+        date,
+        entry.name
+    )
+}
+
+/// Replace the members of `archive` named like the files of `paths`, or
+/// add them. With `update`, only the members older than their file are
+/// replaced.
+fn replace(archive: &str, paths: &[String], create: bool, update: bool, verbose: bool) {
+    let mut members = match std::fs::read(archive) {
+        Ok(data) => parse(&data)
+            .unwrap_or_else(|| fail(format_args!("{}: file format not recognized", archive))),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            if !create {
+                eprintln!("ar: creating {}", archive);
+            }
+            Vec::new()
+        }
+        Err(e) => fail(format_args!("{}: {}", archive, error_reason(&e))),
+    };
+
+    for path in paths {
+        let path = Path::new(path.as_str());
+        let metadata = std::fs::metadata(path)
+            .unwrap_or_else(|e| fail(format_args!("{}: {}", path.display(), error_reason(&e))));
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut entry = Entry::from_file(name, path, &metadata)
+            .unwrap_or_else(|e| fail(format_args!("{}: {}", path.display(), error_reason(&e))));
+        if entry.kind != Kind::File {
+            fail(format_args!(
+                "{}: file format not recognized",
+                path.display()
+            ));
+        }
+
+        let position = members
+            .iter()
+            .position(|(member, _)| member.name == entry.name);
+        if let (Some(i), true) = (position, update) {
+            if members[i].0.mtime >= entry.mtime {
+                continue;
+            }
+        }
+
+        let data = std::fs::read(path)
+            .unwrap_or_else(|e| fail(format_args!("{}: {}", path.display(), error_reason(&e))));
+        entry.size = data.len() as u64;
+        match position {
+            Some(i) => {
+                if verbose {
+                    println!("r - {}", entry.name);
+                }
+                members[i] = (entry, data);
+            }
+            None => {
+                if verbose {
+                    println!("a - {}", entry.name);
+                }
+                members.push((entry, data));
+            }
+        }
+    }
+
+    if let Err(e) = write_file(Path::new(archive), "ar", &format(&members)) {
+        fail(format_args!("{}: {}", archive, error_reason(&e)));
+    }
+}
+
+pub fn ar(args: &[String]) {
+    let Some((key, args)) = args.split_first() else {
+        usage();
+    };
+    let Some((archive, names)) = args.split_first() else {
+        usage();
+    };
+
+    let mut operation = None;
+    let mut create = false;
+    let mut update = false;
+    let mut verbose = false;
+    let mut keep_times = false;
+
+    for flag in key.strip_prefix('-').unwrap_or(key).chars() {
+        match flag {
+            't' | 'x' | 'p' | 'r' => {
+                if operation.is_some_and(|operation| operation != flag) {
+                    usage();
+                }
+                operation = Some(flag);
+            }
+            'c' => create = true,
+            'u' => update = true,
+            'v' => verbose = true,
+            'o' => keep_times = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-271);
+            }
+        }
+    }
+
+    let Some(operation) = operation else {
+        usage();
+    };
+    if operation == 'r' {
+        replace(archive, names, create, update, verbose);
+        return;
+    }
+
+    let data = std::fs::read(archive)
+        .unwrap_or_else(|e| fail(format_args!("{}: {}", archive, error_reason(&e))));
+    let members = parse(&data)
+        .unwrap_or_else(|| fail(format_args!("{}: file format not recognized", archive)));
+
+    let stdout = std::io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let mut extractor = Extractor::new("ar", true, 0)
+        .parents(false)
+        .times(keep_times);
+    let mut found = vec![false; names.len()];
+
+    for (entry, data) in &members {
+        // Without names, every member is selected.
+        if !names.is_empty() {
+            match names.iter().position(|name| **name == entry.name) {
+                Some(i) => found[i] = true,
+                None => continue,
+            }
+        }
+
+        let result = match operation {
+            't' if verbose => writeln!(out, "{}", long_listing(entry)),
+            't' => writeln!(out, "{}", entry.name),
+            'p' if verbose => {
+                write!(out, "\n<{}>\n\n", entry.name).and_then(|_| out.write_all(data))
+            }
+            'p' => out.write_all(data),
+            _ => {
+                let result = match verbose {
+                    true => writeln!(out, "x - {}", entry.name),
+                    false => Ok(()),
+                };
+                extractor.extract(entry, &mut &data[..]);
+                result
+            }
+        };
+
+        if let Err(e) = result {
+            fail(format_args!("{}", error_reason(&e)));
+        }
+    }
+
+    extractor.finish();
+    let _ = out.flush();
+
+    let mut failed = extractor.failed;
+    for (name, found) in names.iter().zip(found) {
+        if !found {
+            eprintln!("ar: no entry {} in archive", name);
+            failed = true;
+        }
+    }
+
+    if failed {
+        std::process::exit(-271);
+    }
+}
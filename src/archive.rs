@@ -0,0 +1,427 @@
+//! The members of archives, shared by the archivers: described from the
+//! files found when creating an archive, and extracted from one.
+
+use std::collections::HashMap;
+use std::fs::Metadata;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::utils::*;
+use crate::walk::Walk;
+
+/// The type of a member, with the target of links.
+#[derive(Clone, PartialEq)]
+pub enum Kind {
+    File,
+    Directory,
+    Symlink(String),
+    /// A hard link to a member archived before it.
+    HardLink(String),
+    CharDevice,
+    BlockDevice,
+    Fifo,
+}
+
+/// A member of an archive: a file and its attributes. The data of
+/// regular files is read separately.
+#[derive(Clone)]
+pub struct Entry {
+    pub name: String,
+    pub kind: Kind,
+    /// The permission bits, with the special ones.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// The names of the owner and group, if they are known.
+    pub user: String,
+    pub group: String,
+    pub mtime: i64,
+    pub size: u64,
+    /// The major and minor numbers of devices.
+    pub device: (u32, u32),
+}
+
+impl Entry {
+    /// Describe the file at `path` (not following symlinks), whose
+    /// metadata is `metadata`, as the member `name`.
+    pub fn from_file(name: String, path: &Path, metadata: &Metadata) -> std::io::Result<Entry> {
+        let file_type = metadata.file_type();
+        let kind = if file_type.is_file() {
+            Kind::File
+        } else if file_type.is_dir() {
+            Kind::Directory
+        } else if file_type.is_symlink() {
+            let target = std::fs::read_link(path)?;
+            Kind::Symlink(target.to_string_lossy().into_owned())
+        } else if file_type.is_char_device() {
+            Kind::CharDevice
+        } else if file_type.is_block_device() {
+            Kind::BlockDevice
+        } else if file_type.is_fifo() {
+            Kind::Fifo
+        } else {
+            return Err(std::io::Error::other("socket ignored"));
+        };
+
+        let device = (libc::major(metadata.rdev()), libc::minor(metadata.rdev()));
+
+        Ok(Entry {
+            name,
+            size: if kind == Kind::File {
+                metadata.len()
+            } else {
+                0
+            },
+            kind,
+            mode: metadata.mode() & 0o7777,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            user: search_id_name("/etc/passwd", metadata.uid()).unwrap_or_default(),
+            group: search_id_name("/etc/group", metadata.gid()).unwrap_or_default(),
+            mtime: metadata.mtime(),
+            device,
+        })
+    }
+
+    /// Get the character of the type of the member in long listings,
+    /// like `ls -l`, with `h` for hard links.
+    pub fn type_char(&self) -> char {
+        match self.kind {
+            Kind::File => '-',
+            Kind::Directory => 'd',
+            Kind::Symlink(_) => 'l',
+            Kind::HardLink(_) => 'h',
+            Kind::CharDevice => 'c',
+            Kind::BlockDevice => 'b',
+            Kind::Fifo => 'p',
+        }
+    }
+}
+
+/// The files with several hard links archived so far, by their device
+/// and inode, with the name of their first member.
+#[derive(Default)]
+pub struct Links(HashMap<(u64, u64), String>);
+
+impl Links {
+    /// Turn `entry` into a hard link if its file was archived before.
+    pub fn check(&mut self, entry: &mut Entry, metadata: &Metadata) {
+        if entry.kind != Kind::File || metadata.nlink() < 2 {
+            return;
+        }
+
+        match self.0.get(&(metadata.dev(), metadata.ino())) {
+            Some(first) => {
+                entry.kind = Kind::HardLink(first.clone());
+                entry.size = 0;
+            }
+            None => {
+                self.0
+                    .insert((metadata.dev(), metadata.ino()), entry.name.clone());
+            }
+        }
+    }
+}
+
+/// Walk the trees of `paths`, calling `visit` with the member of every
+/// file found, its path and its metadata. The names of the members don't
+/// start with `/`. The files for which `exclude` is true are skipped, with
+/// their contents. Returns false if some files couldn't be read.
+pub fn walk_members(
+    command: &str,
+    paths: &[&String],
+    exclude: impl Fn(&str) -> bool,
+    mut visit: impl FnMut(Entry, &Path, &Metadata),
+) -> bool {
+    let mut links = Links::default();
+    let mut warned = false;
+    let mut ok = true;
+
+    for path in paths {
+        let mut walk = Walk::new(path.as_str()).follow_root(false);
+
+        while let Some(entry) = walk.next() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    eprintln!(
+                        "{}: {}: {}",
+                        command,
+                        e.path.display(),
+                        error_reason(&e.error)
+                    );
+                    ok = false;
+                    continue;
+                }
+            };
+
+            let path = entry.path();
+            let name = path.to_string_lossy();
+            if exclude(&name) {
+                if entry.is_dir() {
+                    walk.skip_current_dir();
+                }
+                continue;
+            }
+
+            let name = name.trim_start_matches('/');
+            if name.len() < path.as_os_str().len() && !warned {
+                eprintln!("{}: Removing leading '/' from member names", command);
+                warned = true;
+            }
+            if name.is_empty() {
+                continue;
+            }
+
+            match Entry::from_file(name.to_string(), path, entry.metadata()) {
+                Ok(mut member) => {
+                    links.check(&mut member, entry.metadata());
+                    visit(member, path, entry.metadata());
+                }
+                Err(e) => {
+                    eprintln!("{}: {}: {}", command, path.display(), error_reason(&e));
+                    ok = false;
+                }
+            }
+        }
+    }
+
+    ok
+}
+
+/// Convert the modification time of a member.
+fn system_time(mtime: i64) -> SystemTime {
+    if mtime >= 0 {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(mtime as u64)
+    } else {
+        SystemTime::UNIX_EPOCH - Duration::from_secs(mtime.unsigned_abs())
+    }
+}
+
+/// Extracts members in the current directory. Their names can't lead
+/// outside of it: the members with `..` are skipped, and the symlinks which
+/// may lead outside are only created at the end, so that nothing is
+/// extracted through them.
+pub struct Extractor {
+    command: &'static str,
+    /// Keep the permissions as they are, instead of applying the umask.
+    preserve: bool,
+    umask: u32,
+    /// The number of leading components removed from the names.
+    strip: usize,
+    /// Restore the owners of the members, which only root can do.
+    owners: bool,
+    parents: bool,
+    times: bool,
+    keep_newer: bool,
+    warned: bool,
+    /// The directories extracted, whose permissions and time are set at the
+    /// end, once their contents are extracted.
+    directories: Vec<(PathBuf, Entry)>,
+    symlinks: Vec<(PathBuf, String)>,
+    pub failed: bool,
+}
+
+impl Extractor {
+    pub fn new(command: &'static str, preserve: bool, strip: usize) -> Self {
+        Extractor {
+            command,
+            preserve,
+            umask: current_umask(),
+            strip,
+            // SAFETY: `geteuid` can't fail.
+            owners: unsafe { libc::geteuid() } == 0,
+            parents: true,
+            times: true,
+            keep_newer: false,
+            warned: false,
+            directories: Vec::new(),
+            symlinks: Vec::new(),
+            failed: false,
+        }
+    }
+
+    /// Create the missing parent directories of the members (default: true).
+    pub fn parents(mut self, parents: bool) -> Self {
+        self.parents = parents;
+        self
+    }
+
+    /// Restore the modification times of the members (default: true).
+    pub fn times(mut self, times: bool) -> Self {
+        self.times = times;
+        self
+    }
+
+    /// Keep the existing files which aren't older than the members,
+    /// instead of replacing them (default: false).
+    pub fn keep_newer(mut self, keep_newer: bool) -> Self {
+        self.keep_newer = keep_newer;
+        self
+    }
+
+    fn error(&mut self, path: &Path, e: &std::io::Error) {
+        eprintln!("{}: {}: {}", self.command, path.display(), error_reason(e));
+        self.failed = true;
+    }
+
+    /// Get the path of the member `name`, without its leading `/` and the
+    /// components stripped. Returns `None` if nothing is left or if the
+    /// name contains `..`.
+    pub fn path(&mut self, name: &str) -> Option<PathBuf> {
+        let mut components = Vec::new();
+
+        for component in Path::new(name).components() {
+            match component {
+                Component::Normal(component) => components.push(component),
+                Component::ParentDir => {
+                    eprintln!("{}: {}: Member name contains '..'", self.command, name);
+                    self.failed = true;
+                    return None;
+                }
+                Component::RootDir if !self.warned => {
+                    eprintln!("{}: Removing leading '/' from member names", self.command);
+                    self.warned = true;
+                }
+                _ => {}
+            }
+        }
+
+        (components.len() > self.strip).then(|| components[self.strip..].iter().collect())
+    }
+
+    /// Set the modification time, owner and permissions of a file
+    /// extracted, in this order, since opening the file may need the
+    /// permissions it had, and changing the owner clears the special bits.
+    fn set_attributes(&self, path: &Path, entry: &Entry) -> std::io::Result<()> {
+        // Opening a FIFO would block, and the times of devices don't matter.
+        if let (Kind::File | Kind::Directory, true) = (&entry.kind, self.times) {
+            let file = std::fs::File::open(path)?;
+            let mtime = system_time(entry.mtime);
+            file.set_times(std::fs::FileTimes::new().set_modified(mtime))?;
+        }
+
+        if self.owners {
+            let uid = resolve_user(&entry.user).map_or(entry.uid, |(uid, _)| uid);
+            let gid = resolve_group(&entry.group).unwrap_or(entry.gid);
+            std::os::unix::fs::lchown(path, Some(uid), Some(gid))?;
+        }
+
+        if let Kind::Symlink(_) = entry.kind {
+            return Ok(());
+        }
+
+        let mode = if self.preserve {
+            entry.mode
+        } else {
+            entry.mode & !self.umask
+        };
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+    }
+
+    /// Extract `entry`, with the data of regular files read from `data`.
+    /// Existing files are replaced.
+    pub fn extract(&mut self, entry: &Entry, data: &mut dyn Read) {
+        let Some(path) = self.path(&entry.name) else {
+            return;
+        };
+
+        if let Err(e) = self.create(entry, &path, data) {
+            self.error(&path, &e);
+        }
+    }
+
+    fn create(&mut self, entry: &Entry, path: &Path, data: &mut dyn Read) -> std::io::Result<()> {
+        if let (Some(parent), true) = (path.parent(), self.parents) {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        // A symlink is removed rather than followed, and an existing
+        // directory is only kept for a directory.
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.is_dir() && entry.kind == Kind::Directory => {}
+            Ok(metadata) if self.keep_newer && metadata.mtime() >= entry.mtime => {
+                eprintln!(
+                    "{}: {} not created: newer or same age version exists",
+                    self.command,
+                    path.display()
+                );
+                return Ok(());
+            }
+            Ok(metadata) if metadata.is_dir() => std::fs::remove_dir(path)?,
+            Ok(_) => std::fs::remove_file(path)?,
+            Err(_) => {}
+        }
+
+        match &entry.kind {
+            Kind::File => {
+                let mut file = std::fs::File::create(path)?;
+                std::io::copy(data, &mut file)?;
+            }
+            Kind::Directory => {
+                if !path.is_dir() {
+                    std::fs::create_dir(path)?;
+                }
+                self.directories.push((path.to_path_buf(), entry.clone()));
+                return Ok(());
+            }
+            Kind::Symlink(target) => {
+                let target_path = Path::new(target);
+                if target_path.is_absolute()
+                    || target_path.components().any(|c| c == Component::ParentDir)
+                {
+                    self.symlinks.push((path.to_path_buf(), target.clone()));
+                    return Ok(());
+                }
+                std::os::unix::fs::symlink(target, path)?;
+            }
+            Kind::HardLink(target) => {
+                // The target was reported if it's invalid.
+                let Some(target) = self.path(target) else {
+                    return Ok(());
+                };
+                std::fs::hard_link(target, path)?;
+                return Ok(());
+            }
+            Kind::CharDevice | Kind::BlockDevice | Kind::Fifo => {
+                let file_type = match entry.kind {
+                    Kind::CharDevice => libc::S_IFCHR,
+                    Kind::BlockDevice => libc::S_IFBLK,
+                    _ => libc::S_IFIFO,
+                };
+                let device = libc::makedev(entry.device.0, entry.device.1);
+                let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+                    return Err(std::io::ErrorKind::InvalidInput.into());
+                };
+
+                // SAFETY: `c_path` is a valid C string.
+                if unsafe { libc::mknod(c_path.as_ptr(), file_type | 0o600, device) } != 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+            }
+        }
+
+        self.set_attributes(path, entry)
+    }
+
+    /// Create the symlinks put off, and set the attributes of the
+    /// directories, the deepest ones first.
+    pub fn finish(&mut self) {
+        for (path, target) in std::mem::take(&mut self.symlinks) {
+            if let Err(e) = std::os::unix::fs::symlink(&target, &path) {
+                self.error(&path, &e);
+            }
+        }
+
+        let mut directories = std::mem::take(&mut self.directories);
+        directories.sort_by_key(|(path, _)| std::cmp::Reverse(path.components().count()));
+        for (path, entry) in directories {
+            if let Err(e) = self.set_attributes(&path, &entry) {
+                self.error(&path, &e);
+            }
+        }
+    }
+}
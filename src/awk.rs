@@ -0,0 +1,3326 @@
+//! The `awk` command, a pattern scanning and processing language. The program
+//! is split into tokens, parsed into a syntax tree and run by walking it.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Read, Write};
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, ChildStdin, Stdio};
+use std::rc::Rc;
+
+use crate::regex::Regex;
+use crate::utils::*;
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("awk: {}", message);
+    std::process::exit(-190);
+}
+
+fn syntax_error(line: usize, message: &str) -> ! {
+    fail(format_args!(
+        "syntax error at source line {}: {}",
+        line, message
+    ));
+}
+
+/// The built-in functions.
+#[derive(Clone, Copy, PartialEq)]
+enum Builtin {
+    Length,
+    Substr,
+    Index,
+    Split,
+    Sub,
+    Gsub,
+    Match,
+    Sprintf,
+    Sin,
+    Cos,
+    Atan2,
+    Exp,
+    Log,
+    Sqrt,
+    Int,
+    Rand,
+    Srand,
+    ToLower,
+    ToUpper,
+    System,
+    Close,
+    Fflush,
+}
+
+impl Builtin {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "length" => Builtin::Length,
+            "substr" => Builtin::Substr,
+            "index" => Builtin::Index,
+            "split" => Builtin::Split,
+            "sub" => Builtin::Sub,
+            "gsub" => Builtin::Gsub,
+            "match" => Builtin::Match,
+            "sprintf" => Builtin::Sprintf,
+            "sin" => Builtin::Sin,
+            "cos" => Builtin::Cos,
+            "atan2" => Builtin::Atan2,
+            "exp" => Builtin::Exp,
+            "log" => Builtin::Log,
+            "sqrt" => Builtin::Sqrt,
+            "int" => Builtin::Int,
+            "rand" => Builtin::Rand,
+            "srand" => Builtin::Srand,
+            "tolower" => Builtin::ToLower,
+            "toupper" => Builtin::ToUpper,
+            "system" => Builtin::System,
+            "close" => Builtin::Close,
+            "fflush" => Builtin::Fflush,
+            _ => return None,
+        })
+    }
+
+    /// The minimum and maximum number of arguments.
+    fn arity(self) -> (usize, usize) {
+        match self {
+            Builtin::Length | Builtin::Srand | Builtin::Fflush => (0, 1),
+            Builtin::Rand => (0, 0),
+            Builtin::Substr | Builtin::Split | Builtin::Sub | Builtin::Gsub => (2, 3),
+            Builtin::Index | Builtin::Match | Builtin::Atan2 => (2, 2),
+            Builtin::Sprintf => (1, usize::MAX),
+            _ => (1, 1),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum Token {
+    Newline,
+    Eof,
+    Number(f64),
+    Str(String),
+    Regex(String),
+    Name(String),
+    /// A name followed by `(`, without blanks: a function call.
+    FuncName(String),
+    Builtin(Builtin),
+    Begin,
+    End,
+    Function,
+    If,
+    Else,
+    While,
+    For,
+    Do,
+    Break,
+    Continue,
+    Next,
+    NextFile,
+    Exit,
+    Return,
+    Delete,
+    In,
+    Getline,
+    Print,
+    Printf,
+    LBrace,
+    RBrace,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Semicolon,
+    Comma,
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    Percent,
+    Caret,
+    Not,
+    Greater,
+    Less,
+    Pipe,
+    Question,
+    Colon,
+    Tilde,
+    NoMatch,
+    Dollar,
+    Assign,
+    AddAssign,
+    SubAssign,
+    MulAssign,
+    DivAssign,
+    ModAssign,
+    PowAssign,
+    Eq,
+    Ne,
+    Le,
+    Ge,
+    And,
+    Or,
+    Incr,
+    Decr,
+    Append,
+}
+
+/// Decode the escape sequences of a string, in the program or in a command
+/// line assignment. Unknown escapes keep their backslash, for the regular
+/// expressions made from strings.
+fn unescape_string(text: &str) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut unescaped = String::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] != '\\' {
+            unescaped.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let Some(&chr) = chars.get(i + 1) else {
+            unescaped.push('\\');
+            break;
+        };
+        i += 2;
+
+        match chr {
+            'n' => unescaped.push('\n'),
+            't' => unescaped.push('\t'),
+            'r' => unescaped.push('\r'),
+            'a' => unescaped.push('\x07'),
+            'b' => unescaped.push('\x08'),
+            'f' => unescaped.push('\x0c'),
+            'v' => unescaped.push('\x0b'),
+            '\\' | '"' | '/' => unescaped.push(chr),
+            '\n' => {}
+            '0'..='7' => {
+                let mut code = chr.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.get(i).and_then(|c| c.to_digit(8)) {
+                        Some(digit) => code = code * 8 + digit,
+                        None => break,
+                    }
+                    i += 1;
+                }
+                unescaped.push(char::from_u32(code).unwrap_or('\0'));
+            }
+            _ => {
+                unescaped.push('\\');
+                unescaped.push(chr);
+            }
+        }
+    }
+
+    unescaped
+}
+
+/// Check if a `/` after `last` starts a regular expression, rather than
+/// being a division.
+fn regex_allowed(last: Option<&(Token, usize)>) -> bool {
+    !matches!(
+        last,
+        Some((
+            Token::Name(_)
+                | Token::Number(_)
+                | Token::Str(_)
+                | Token::Builtin(_)
+                | Token::RParen
+                | Token::RBracket
+                | Token::Dollar
+                | Token::Incr
+                | Token::Decr,
+            _
+        ))
+    )
+}
+
+/// Split the program into tokens, with their line.
+fn lex(source: &str) -> Vec<(Token, usize)> {
+    let chars = source.chars().collect::<Vec<_>>();
+    let mut tokens: Vec<(Token, usize)> = Vec::new();
+    let mut line = 1;
+    let mut i = 0;
+
+    while i < chars.len() {
+        let chr = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        let token = match chr {
+            ' ' | '\t' | '\r' => {
+                i += 1;
+                continue;
+            }
+            '\\' if next == Some('\n') => {
+                i += 2;
+                line += 1;
+                continue;
+            }
+            '#' => {
+                while i < chars.len() && chars[i] != '\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            '\n' => {
+                tokens.push((Token::Newline, line));
+                line += 1;
+                i += 1;
+                continue;
+            }
+            '"' => {
+                let start = i + 1;
+                i = start;
+                loop {
+                    match chars.get(i) {
+                        None | Some('\n') => syntax_error(line, "non-terminated string"),
+                        Some('"') => break,
+                        Some('\\') if chars.get(i + 1) == Some(&'\n') => {
+                            line += 1;
+                            i += 2;
+                        }
+                        Some('\\') => i += 2,
+                        Some(_) => i += 1,
+                    }
+                }
+                i += 1;
+                let raw = chars[start..i - 1].iter().collect::<String>();
+                Token::Str(unescape_string(&raw))
+            }
+            '/' if regex_allowed(tokens.last()) => {
+                i += 1;
+                let mut pattern = String::new();
+                let mut in_bracket = false;
+
+                loop {
+                    let Some(&chr) = chars.get(i) else {
+                        syntax_error(line, "non-terminated regular expression");
+                    };
+
+                    match chr {
+                        '\n' => syntax_error(line, "non-terminated regular expression"),
+                        '/' if !in_bracket => break,
+                        '\\' if chars.get(i + 1) == Some(&'/') => {
+                            pattern.push('/');
+                            i += 2;
+                            continue;
+                        }
+                        '\\' => {
+                            pattern.push('\\');
+                            pattern.extend(chars.get(i + 1));
+                            i += 2;
+                            continue;
+                        }
+                        '[' if !in_bracket => {
+                            // `]` is a member if it comes first.
+                            in_bracket = true;
+                            pattern.push('[');
+                            i += 1;
+                            if chars.get(i) == Some(&'^') {
+                                pattern.push('^');
+                                i += 1;
+                            }
+                            if chars.get(i) == Some(&']') {
+                                pattern.push(']');
+                                i += 1;
+                            }
+                            continue;
+                        }
+                        '[' if matches!(chars.get(i + 1), Some(':' | '.' | '=')) => {
+                            let kind = chars[i + 1];
+                            pattern.extend(['[', kind]);
+                            i += 2;
+                            while i < chars.len()
+                                && !(chars[i] == kind && chars.get(i + 1) == Some(&']'))
+                            {
+                                pattern.push(chars[i]);
+                                i += 1;
+                            }
+                            if i < chars.len() {
+                                pattern.extend([kind, ']']);
+                                i += 2;
+                            }
+                            continue;
+                        }
+                        ']' => in_bracket = false,
+                        _ => {}
+                    }
+
+                    pattern.push(chr);
+                    i += 1;
+                }
+
+                i += 1;
+                Token::Regex(pattern)
+            }
+            '0'..='9' | '.' if chr != '.' || next.is_some_and(|c| c.is_ascii_digit()) => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                if chars.get(i) == Some(&'.') {
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                if matches!(chars.get(i), Some('e' | 'E')) {
+                    let digits = match chars.get(i + 1) {
+                        Some('+' | '-') => i + 2,
+                        _ => i + 1,
+                    };
+                    if chars.get(digits).is_some_and(|c| c.is_ascii_digit()) {
+                        i = digits;
+                        while i < chars.len() && chars[i].is_ascii_digit() {
+                            i += 1;
+                        }
+                    }
+                }
+
+                let number = chars[start..i].iter().collect::<String>();
+                tokens.push((Token::Number(number.parse().unwrap_or(0.0)), line));
+                continue;
+            }
+            _ if chr.is_ascii_alphabetic() || chr == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+
+                let word = chars[start..i].iter().collect::<String>();
+                let token = match word.as_str() {
+                    "BEGIN" => Token::Begin,
+                    "END" => Token::End,
+                    "function" | "func" => Token::Function,
+                    "if" => Token::If,
+                    "else" => Token::Else,
+                    "while" => Token::While,
+                    "for" => Token::For,
+                    "do" => Token::Do,
+                    "break" => Token::Break,
+                    "continue" => Token::Continue,
+                    "next" => Token::Next,
+                    "nextfile" => Token::NextFile,
+                    "exit" => Token::Exit,
+                    "return" => Token::Return,
+                    "delete" => Token::Delete,
+                    "in" => Token::In,
+                    "getline" => Token::Getline,
+                    "print" => Token::Print,
+                    "printf" => Token::Printf,
+                    _ => match Builtin::from_name(&word) {
+                        Some(builtin) => Token::Builtin(builtin),
+                        None if chars.get(i) == Some(&'(') => Token::FuncName(word),
+                        None => Token::Name(word),
+                    },
+                };
+
+                tokens.push((token, line));
+                continue;
+            }
+            _ => {
+                let third = chars.get(i + 2).copied();
+                let (token, len) = match (chr, next) {
+                    ('*', Some('*')) if third == Some('=') => (Token::PowAssign, 3),
+                    ('*', Some('*')) => (Token::Caret, 2),
+                    ('+', Some('=')) => (Token::AddAssign, 2),
+                    ('-', Some('=')) => (Token::SubAssign, 2),
+                    ('*', Some('=')) => (Token::MulAssign, 2),
+                    ('/', Some('=')) => (Token::DivAssign, 2),
+                    ('%', Some('=')) => (Token::ModAssign, 2),
+                    ('^', Some('=')) => (Token::PowAssign, 2),
+                    ('=', Some('=')) => (Token::Eq, 2),
+                    ('!', Some('=')) => (Token::Ne, 2),
+                    ('<', Some('=')) => (Token::Le, 2),
+                    ('>', Some('=')) => (Token::Ge, 2),
+                    ('!', Some('~')) => (Token::NoMatch, 2),
+                    ('+', Some('+')) => (Token::Incr, 2),
+                    ('-', Some('-')) => (Token::Decr, 2),
+                    ('&', Some('&')) => (Token::And, 2),
+                    ('|', Some('|')) => (Token::Or, 2),
+                    ('>', Some('>')) => (Token::Append, 2),
+                    ('{', _) => (Token::LBrace, 1),
+                    ('}', _) => (Token::RBrace, 1),
+                    ('(', _) => (Token::LParen, 1),
+                    (')', _) => (Token::RParen, 1),
+                    ('[', _) => (Token::LBracket, 1),
+                    (']', _) => (Token::RBracket, 1),
+                    (';', _) => (Token::Semicolon, 1),
+                    (',', _) => (Token::Comma, 1),
+                    ('+', _) => (Token::Plus, 1),
+                    ('-', _) => (Token::Minus, 1),
+                    ('*', _) => (Token::Star, 1),
+                    ('/', _) => (Token::Slash, 1),
+                    ('%', _) => (Token::Percent, 1),
+                    ('^', _) => (Token::Caret, 1),
+                    ('!', _) => (Token::Not, 1),
+                    ('>', _) => (Token::Greater, 1),
+                    ('<', _) => (Token::Less, 1),
+                    ('|', _) => (Token::Pipe, 1),
+                    ('?', _) => (Token::Question, 1),
+                    (':', _) => (Token::Colon, 1),
+                    ('~', _) => (Token::Tilde, 1),
+                    ('$', _) => (Token::Dollar, 1),
+                    ('=', _) => (Token::Assign, 1),
+                    _ => syntax_error(line, &format!("unexpected character '{}'", chr)),
+                };
+
+                i += len;
+                token
+            }
+        };
+
+        tokens.push((token, line));
+    }
+
+    tokens.push((Token::Eof, line));
+    tokens
+}
+
+/// The special variables, which are the first globals.
+const NF: usize = 0;
+const NR: usize = 1;
+const FNR: usize = 2;
+const FS: usize = 3;
+const OFS: usize = 4;
+const ORS: usize = 5;
+const RS: usize = 6;
+const SUBSEP: usize = 7;
+const CONVFMT: usize = 8;
+const OFMT: usize = 9;
+const RSTART: usize = 10;
+const RLENGTH: usize = 11;
+const FILENAME: usize = 12;
+const ENVIRON: usize = 13;
+const ARGC: usize = 14;
+const ARGV: usize = 15;
+
+const SPECIAL_VARS: [&str; 16] = [
+    "NF", "NR", "FNR", "FS", "OFS", "ORS", "RS", "SUBSEP", "CONVFMT", "OFMT", "RSTART", "RLENGTH",
+    "FILENAME", "ENVIRON", "ARGC", "ARGV",
+];
+
+/// A variable: a global, or a parameter of the function being run.
+#[derive(Clone, Copy, PartialEq)]
+enum Var {
+    Global(usize),
+    Local(usize),
+}
+
+/// What can be assigned.
+enum LValue {
+    Var(Var),
+    Index(Var, Vec<Expr>),
+    Field(Box<Expr>),
+}
+
+#[derive(Clone, Copy)]
+enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Pow,
+}
+
+#[derive(Clone, Copy)]
+enum CmpOp {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+}
+
+/// Where `getline` reads from.
+enum GetlineSource {
+    /// The main input, the files given as operands.
+    Main,
+    File(Box<Expr>),
+    Command(Box<Expr>),
+}
+
+enum Expr {
+    Num(f64),
+    Str(Rc<str>),
+    /// A regex on its own, matched against the record.
+    Regex(Rc<Regex>),
+    LValue(LValue),
+    Assign(LValue, Option<BinOp>, Box<Expr>),
+    /// `++` or `--` (the delta), prefixed or not.
+    IncDec(LValue, f64, bool),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    /// The unary `+`, converting to a number.
+    Plus(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    Compare(CmpOp, Box<Expr>, Box<Expr>),
+    /// `~`, or `!~` if negated.
+    Match(bool, Box<Expr>, Box<Expr>),
+    Concat(Vec<Expr>),
+    In(Vec<Expr>, Var),
+    Call(usize, Vec<Expr>),
+    Builtin(Builtin, Vec<Expr>),
+    Getline(GetlineSource, Option<LValue>),
+    /// A parenthesized list, only valid before `in`.
+    Grouping(Vec<Expr>),
+}
+
+enum Redirect {
+    File,
+    Append,
+    Pipe,
+}
+
+enum Stmt {
+    Expr(Expr),
+    Print(Vec<Expr>, Option<(Redirect, Expr)>),
+    Printf(Vec<Expr>, Option<(Redirect, Expr)>),
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    While(Expr, Box<Stmt>),
+    Do(Box<Stmt>, Expr),
+    For(Option<Expr>, Option<Expr>, Option<Expr>, Box<Stmt>),
+    ForIn(Var, Var, Box<Stmt>),
+    Block(Vec<Stmt>),
+    Delete(Var, Option<Vec<Expr>>),
+    Next,
+    NextFile,
+    Exit(Option<Expr>),
+    Return(Option<Expr>),
+    Break,
+    Continue,
+}
+
+enum Pattern {
+    Begin,
+    End,
+    All,
+    Expr(Expr),
+    Range(Expr, Expr),
+}
+
+struct Rule {
+    pattern: Pattern,
+    /// Without an action, the record is printed.
+    action: Option<Vec<Stmt>>,
+}
+
+struct Function {
+    params: usize,
+    body: Vec<Stmt>,
+}
+
+struct Program {
+    rules: Vec<Rule>,
+    functions: Vec<Function>,
+    globals: HashMap<String, usize>,
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    globals: HashMap<String, usize>,
+    /// The parameters of the function being parsed.
+    params: Vec<String>,
+    functions: HashMap<String, usize>,
+    definitions: Vec<Option<Function>>,
+    /// The calls, with their number of arguments and their line.
+    calls: Vec<(usize, usize, usize)>,
+    /// In the arguments of `print`, `>` is a redirection.
+    no_gt: bool,
+}
+
+impl Parser {
+    fn new(tokens: Vec<(Token, usize)>) -> Self {
+        let globals = SPECIAL_VARS
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.to_string(), i))
+            .collect();
+
+        Parser {
+            tokens,
+            pos: 0,
+            globals,
+            params: Vec::new(),
+            functions: HashMap::new(),
+            definitions: Vec::new(),
+            calls: Vec::new(),
+            no_gt: false,
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.pos].0
+    }
+
+    fn peek_at(&self, offset: usize) -> &Token {
+        &self.tokens[(self.pos + offset).min(self.tokens.len() - 1)].0
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.pos].0.clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == token {
+            self.advance();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self) -> ! {
+        let (token, line) = &self.tokens[self.pos];
+        let near = match token {
+            Token::Newline => "end of line".to_owned(),
+            Token::Eof => "end of program".to_owned(),
+            Token::Name(name) | Token::FuncName(name) => format!("'{}'", name),
+            Token::Number(number) => format!("'{}'", number),
+            Token::Str(_) => "string".to_owned(),
+            _ => "token".to_owned(),
+        };
+        syntax_error(*line, &format!("unexpected {}", near));
+    }
+
+    fn expect(&mut self, token: &Token) {
+        if !self.eat(token) {
+            self.error();
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while *self.peek() == Token::Newline {
+            self.advance();
+        }
+    }
+
+    fn skip_terminators(&mut self) {
+        while matches!(self.peek(), Token::Newline | Token::Semicolon) {
+            self.advance();
+        }
+    }
+
+    fn var(&mut self, name: &str) -> Var {
+        if let Some(i) = self.params.iter().position(|param| param == name) {
+            return Var::Local(i);
+        }
+
+        let count = self.globals.len();
+        Var::Global(*self.globals.entry(name.to_owned()).or_insert(count))
+    }
+
+    fn function_index(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.functions.get(name) {
+            return index;
+        }
+
+        self.definitions.push(None);
+        self.functions
+            .insert(name.to_owned(), self.definitions.len() - 1);
+        self.definitions.len() - 1
+    }
+
+    fn parse(mut self) -> Program {
+        let mut rules = Vec::new();
+
+        loop {
+            self.skip_terminators();
+
+            let pattern = match self.peek() {
+                Token::Eof => break,
+                Token::Function => {
+                    self.function();
+                    continue;
+                }
+                Token::Begin | Token::End => {
+                    let pattern = match self.advance() {
+                        Token::Begin => Pattern::Begin,
+                        _ => Pattern::End,
+                    };
+                    self.skip_newlines();
+                    if *self.peek() != Token::LBrace {
+                        self.error();
+                    }
+                    pattern
+                }
+                Token::LBrace => Pattern::All,
+                _ => {
+                    let first = self.expr();
+                    if self.eat(&Token::Comma) {
+                        self.skip_newlines();
+                        Pattern::Range(first, self.expr())
+                    } else {
+                        Pattern::Expr(first)
+                    }
+                }
+            };
+
+            let action = (*self.peek() == Token::LBrace).then(|| self.block());
+            if !matches!(self.peek(), Token::Newline | Token::Semicolon | Token::Eof)
+                && action.is_none()
+            {
+                self.error();
+            }
+
+            rules.push(Rule { pattern, action });
+        }
+
+        for &(index, args, line) in &self.calls {
+            let name = self.functions.iter().find(|(_, &i)| i == index).unwrap().0;
+            match &self.definitions[index] {
+                None => syntax_error(line, &format!("calling undefined function {}", name)),
+                Some(function) if args > function.params => syntax_error(
+                    line,
+                    &format!("function {} called with too many arguments", name),
+                ),
+                _ => {}
+            }
+        }
+
+        Program {
+            rules,
+            functions: self.definitions.into_iter().map(Option::unwrap).collect(),
+            globals: self.globals,
+        }
+    }
+
+    fn function(&mut self) {
+        self.advance();
+        let name = match self.advance() {
+            Token::Name(name) | Token::FuncName(name) => name,
+            _ => {
+                self.pos -= 1;
+                self.error();
+            }
+        };
+
+        self.expect(&Token::LParen);
+        let mut params = Vec::new();
+        loop {
+            self.skip_newlines();
+            match self.advance() {
+                Token::RParen => break,
+                Token::Name(param) => params.push(param),
+                _ => {
+                    self.pos -= 1;
+                    self.error();
+                }
+            }
+
+            self.skip_newlines();
+            if !self.eat(&Token::Comma) {
+                self.expect(&Token::RParen);
+                break;
+            }
+        }
+        self.skip_newlines();
+
+        let index = self.function_index(&name);
+        if self.definitions[index].is_some() {
+            syntax_error(
+                self.tokens[self.pos].1,
+                &format!("function {} redefined", name),
+            );
+        }
+
+        self.params = params;
+        let body = self.block();
+        let params = std::mem::take(&mut self.params).len();
+        self.definitions[index] = Some(Function { params, body });
+    }
+
+    fn block(&mut self) -> Vec<Stmt> {
+        self.expect(&Token::LBrace);
+
+        let mut stmts = Vec::new();
+        loop {
+            self.skip_terminators();
+            if self.eat(&Token::RBrace) {
+                return stmts;
+            }
+            stmts.push(self.statement());
+        }
+    }
+
+    /// Check if the simple statement ends here.
+    fn at_simple_end(&self) -> bool {
+        matches!(
+            self.peek(),
+            Token::Semicolon | Token::Newline | Token::RBrace | Token::Eof
+        )
+    }
+
+    fn end_simple(&mut self) {
+        match self.peek() {
+            Token::Semicolon | Token::Newline => {
+                self.advance();
+            }
+            Token::RBrace | Token::Eof => {}
+            _ => self.error(),
+        }
+    }
+
+    /// Parse the body of `if`, `while` and `for`, which may be empty.
+    fn body(&mut self) -> Stmt {
+        if self.eat(&Token::Semicolon) {
+            return Stmt::Block(Vec::new());
+        }
+
+        self.skip_newlines();
+        self.statement()
+    }
+
+    fn statement(&mut self) -> Stmt {
+        match self.peek() {
+            Token::LBrace => Stmt::Block(self.block()),
+            Token::If => {
+                self.advance();
+                self.expect(&Token::LParen);
+                let cond = self.expr();
+                self.expect(&Token::RParen);
+                let then = self.body();
+
+                // The `else` may come after terminators.
+                let start = self.pos;
+                self.skip_terminators();
+                if self.eat(&Token::Else) {
+                    let otherwise = self.body();
+                    Stmt::If(cond, Box::new(then), Some(Box::new(otherwise)))
+                } else {
+                    self.pos = start;
+                    Stmt::If(cond, Box::new(then), None)
+                }
+            }
+            Token::While => {
+                self.advance();
+                self.expect(&Token::LParen);
+                let cond = self.expr();
+                self.expect(&Token::RParen);
+                Stmt::While(cond, Box::new(self.body()))
+            }
+            Token::Do => {
+                self.advance();
+                self.skip_newlines();
+                let body = self.statement();
+                self.skip_terminators();
+                self.expect(&Token::While);
+                self.expect(&Token::LParen);
+                let cond = self.expr();
+                self.expect(&Token::RParen);
+                self.end_simple();
+                Stmt::Do(Box::new(body), cond)
+            }
+            Token::For => {
+                self.advance();
+                self.expect(&Token::LParen);
+
+                if let (Token::Name(name), Token::In, Token::Name(array), Token::RParen) = (
+                    self.peek().clone(),
+                    self.peek_at(1).clone(),
+                    self.peek_at(2).clone(),
+                    self.peek_at(3),
+                ) {
+                    self.pos += 4;
+                    let var = self.var(&name);
+                    let array = self.var(&array);
+                    return Stmt::ForIn(var, array, Box::new(self.body()));
+                }
+
+                let init = (*self.peek() != Token::Semicolon).then(|| self.expr());
+                self.expect(&Token::Semicolon);
+                self.skip_newlines();
+                let cond = (*self.peek() != Token::Semicolon).then(|| self.expr());
+                self.expect(&Token::Semicolon);
+                self.skip_newlines();
+                let step = (*self.peek() != Token::RParen).then(|| self.expr());
+                self.expect(&Token::RParen);
+                Stmt::For(init, cond, step, Box::new(self.body()))
+            }
+            Token::Semicolon => {
+                self.advance();
+                Stmt::Block(Vec::new())
+            }
+            _ => {
+                let stmt = self.simple_statement();
+                self.end_simple();
+                stmt
+            }
+        }
+    }
+
+    fn simple_statement(&mut self) -> Stmt {
+        match self.advance() {
+            token @ (Token::Print | Token::Printf) => {
+                let args = self.print_args();
+                if token == Token::Printf && args.is_empty() {
+                    self.error();
+                }
+
+                let redirect = match self.peek() {
+                    Token::Greater => Some(Redirect::File),
+                    Token::Append => Some(Redirect::Append),
+                    Token::Pipe => Some(Redirect::Pipe),
+                    _ => None,
+                };
+                let redirect = redirect.map(|redirect| {
+                    self.advance();
+                    let no_gt = std::mem::replace(&mut self.no_gt, true);
+                    let target = self.concat();
+                    self.no_gt = no_gt;
+                    (redirect, target)
+                });
+
+                if token == Token::Print {
+                    Stmt::Print(args, redirect)
+                } else {
+                    Stmt::Printf(args, redirect)
+                }
+            }
+            Token::Delete => {
+                let Token::Name(name) = self.advance() else {
+                    self.pos -= 1;
+                    self.error();
+                };
+                let var = self.var(&name);
+
+                if self.eat(&Token::LBracket) {
+                    Stmt::Delete(var, Some(self.expr_list(Token::RBracket)))
+                } else {
+                    Stmt::Delete(var, None)
+                }
+            }
+            Token::Next => Stmt::Next,
+            Token::NextFile => Stmt::NextFile,
+            Token::Break => Stmt::Break,
+            Token::Continue => Stmt::Continue,
+            Token::Exit => Stmt::Exit((!self.at_simple_end()).then(|| self.expr())),
+            Token::Return => Stmt::Return((!self.at_simple_end()).then(|| self.expr())),
+            _ => {
+                self.pos -= 1;
+                Stmt::Expr(self.expr())
+            }
+        }
+    }
+
+    /// Parse the arguments of `print` and `printf`, which may be
+    /// parenthesized, so that `>` can be a comparison.
+    fn print_args(&mut self) -> Vec<Expr> {
+        let at_end = |parser: &Self| {
+            parser.at_simple_end()
+                || matches!(parser.peek(), Token::Greater | Token::Append | Token::Pipe)
+        };
+
+        if at_end(self) {
+            return Vec::new();
+        }
+
+        if *self.peek() == Token::LParen {
+            let start = self.pos;
+            self.advance();
+            let args = self.expr_list(Token::RParen);
+            if at_end(self) {
+                return args;
+            }
+
+            // The parentheses were only a part of the first argument.
+            self.pos = start;
+        }
+
+        let no_gt = std::mem::replace(&mut self.no_gt, true);
+        let mut args = vec![self.expr()];
+        while self.eat(&Token::Comma) {
+            self.skip_newlines();
+            args.push(self.expr());
+        }
+        self.no_gt = no_gt;
+
+        args
+    }
+
+    /// Parse expressions separated by commas, up to `end`.
+    fn expr_list(&mut self, end: Token) -> Vec<Expr> {
+        let no_gt = std::mem::replace(&mut self.no_gt, false);
+        let mut list = Vec::new();
+
+        self.skip_newlines();
+        if !self.eat(&end) {
+            loop {
+                list.push(self.expr());
+                self.skip_newlines();
+                if !self.eat(&Token::Comma) {
+                    self.expect(&end);
+                    break;
+                }
+                self.skip_newlines();
+            }
+        }
+
+        self.no_gt = no_gt;
+        list
+    }
+
+    fn expr(&mut self) -> Expr {
+        let lhs = self.ternary();
+
+        let op = match self.peek() {
+            Token::Assign => None,
+            Token::AddAssign => Some(BinOp::Add),
+            Token::SubAssign => Some(BinOp::Sub),
+            Token::MulAssign => Some(BinOp::Mul),
+            Token::DivAssign => Some(BinOp::Div),
+            Token::ModAssign => Some(BinOp::Mod),
+            Token::PowAssign => Some(BinOp::Pow),
+            _ => return lhs,
+        };
+
+        let Expr::LValue(lvalue) = lhs else {
+            self.error();
+        };
+        self.advance();
+        self.skip_newlines();
+
+        Expr::Assign(lvalue, op, Box::new(self.expr()))
+    }
+
+    fn ternary(&mut self) -> Expr {
+        let cond = self.or();
+        if !self.eat(&Token::Question) {
+            return cond;
+        }
+
+        self.skip_newlines();
+        let then = self.expr();
+        self.skip_newlines();
+        self.expect(&Token::Colon);
+        self.skip_newlines();
+        let otherwise = self.expr();
+
+        Expr::Cond(Box::new(cond), Box::new(then), Box::new(otherwise))
+    }
+
+    fn or(&mut self) -> Expr {
+        let mut lhs = self.and();
+        while self.eat(&Token::Or) {
+            self.skip_newlines();
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.and()));
+        }
+        lhs
+    }
+
+    fn and(&mut self) -> Expr {
+        let mut lhs = self.membership();
+        while self.eat(&Token::And) {
+            self.skip_newlines();
+            lhs = Expr::And(Box::new(lhs), Box::new(self.membership()));
+        }
+        lhs
+    }
+
+    fn membership(&mut self) -> Expr {
+        let mut lhs = self.matching();
+        while self.eat(&Token::In) {
+            let Token::Name(name) = self.advance() else {
+                self.pos -= 1;
+                self.error();
+            };
+
+            let subscripts = match lhs {
+                Expr::Grouping(list) => list,
+                lhs => vec![lhs],
+            };
+            lhs = Expr::In(subscripts, self.var(&name));
+        }
+        lhs
+    }
+
+    fn matching(&mut self) -> Expr {
+        let mut lhs = self.comparison();
+        loop {
+            let negated = match self.peek() {
+                Token::Tilde => false,
+                Token::NoMatch => true,
+                _ => return lhs,
+            };
+            self.advance();
+            lhs = Expr::Match(negated, Box::new(lhs), Box::new(self.comparison()));
+        }
+    }
+
+    fn comparison(&mut self) -> Expr {
+        let lhs = self.pipe_getline();
+
+        let op = match self.peek() {
+            Token::Less => CmpOp::Lt,
+            Token::Le => CmpOp::Le,
+            Token::Eq => CmpOp::Eq,
+            Token::Ne => CmpOp::Ne,
+            Token::Greater if !self.no_gt => CmpOp::Gt,
+            Token::Ge => CmpOp::Ge,
+            _ => return lhs,
+        };
+        self.advance();
+
+        Expr::Compare(op, Box::new(lhs), Box::new(self.pipe_getline()))
+    }
+
+    fn pipe_getline(&mut self) -> Expr {
+        let mut lhs = self.concat();
+        while *self.peek() == Token::Pipe && *self.peek_at(1) == Token::Getline {
+            self.pos += 2;
+            let target = self.optional_lvalue();
+            lhs = Expr::Getline(GetlineSource::Command(Box::new(lhs)), target);
+        }
+        lhs
+    }
+
+    fn concat(&mut self) -> Expr {
+        let first = self.additive();
+
+        let starts_operand = |token: &Token| {
+            matches!(
+                token,
+                Token::Number(_)
+                    | Token::Str(_)
+                    | Token::Regex(_)
+                    | Token::Name(_)
+                    | Token::FuncName(_)
+                    | Token::Builtin(_)
+                    | Token::Dollar
+                    | Token::Not
+                    | Token::LParen
+                    | Token::Incr
+                    | Token::Decr
+            )
+        };
+        if !starts_operand(self.peek()) {
+            return first;
+        }
+
+        let mut items = vec![first];
+        while starts_operand(self.peek()) {
+            items.push(self.additive());
+        }
+        Expr::Concat(items)
+    }
+
+    fn additive(&mut self) -> Expr {
+        let mut lhs = self.multiplicative();
+        loop {
+            let op = match self.peek() {
+                Token::Plus => BinOp::Add,
+                Token::Minus => BinOp::Sub,
+                _ => return lhs,
+            };
+            self.advance();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.multiplicative()));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Expr {
+        let mut lhs = self.unary();
+        loop {
+            let op = match self.peek() {
+                Token::Star => BinOp::Mul,
+                Token::Slash => BinOp::Div,
+                Token::Percent => BinOp::Mod,
+                _ => return lhs,
+            };
+            self.advance();
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()));
+        }
+    }
+
+    fn unary(&mut self) -> Expr {
+        match self.peek() {
+            Token::Not => {
+                self.advance();
+                Expr::Not(Box::new(self.unary()))
+            }
+            Token::Minus => {
+                self.advance();
+                Expr::Neg(Box::new(self.unary()))
+            }
+            Token::Plus => {
+                self.advance();
+                Expr::Plus(Box::new(self.unary()))
+            }
+            _ => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> Expr {
+        let base = self.postfix();
+        if !self.eat(&Token::Caret) {
+            return base;
+        }
+
+        // Right associative, and the exponent can be negated (`2^-1`).
+        Expr::Binary(BinOp::Pow, Box::new(base), Box::new(self.unary()))
+    }
+
+    fn postfix(&mut self) -> Expr {
+        let expr = self.primary();
+
+        let delta = match self.peek() {
+            Token::Incr => 1.0,
+            Token::Decr => -1.0,
+            _ => return expr,
+        };
+        let Expr::LValue(lvalue) = expr else {
+            return expr;
+        };
+        self.advance();
+
+        Expr::IncDec(lvalue, delta, false)
+    }
+
+    /// Parse the operand of `$`, which binds tighter than everything.
+    fn field_operand(&mut self) -> Expr {
+        match self.peek() {
+            Token::Minus => {
+                self.advance();
+                Expr::Neg(Box::new(self.field_operand()))
+            }
+            Token::Plus => {
+                self.advance();
+                Expr::Plus(Box::new(self.field_operand()))
+            }
+            _ => self.primary(),
+        }
+    }
+
+    /// Parse the variable of `getline`, if there's one.
+    fn optional_lvalue(&mut self) -> Option<LValue> {
+        if !matches!(self.peek(), Token::Name(_) | Token::Dollar) {
+            return None;
+        }
+
+        match self.primary() {
+            Expr::LValue(lvalue) => Some(lvalue),
+            _ => self.error(),
+        }
+    }
+
+    fn primary(&mut self) -> Expr {
+        let line = self.tokens[self.pos].1;
+
+        match self.advance() {
+            Token::Number(number) => Expr::Num(number),
+            Token::Str(text) => Expr::Str(text.into()),
+            Token::Regex(pattern) => match Regex::new(&pattern, true, false) {
+                Ok(regex) => Expr::Regex(Rc::new(regex)),
+                Err(message) => syntax_error(line, &message),
+            },
+            Token::Dollar => Expr::LValue(LValue::Field(Box::new(self.field_operand()))),
+            token @ (Token::Incr | Token::Decr) => {
+                let Expr::LValue(lvalue) = self.primary() else {
+                    self.pos -= 1;
+                    self.error();
+                };
+                let delta = if token == Token::Incr { 1.0 } else { -1.0 };
+                Expr::IncDec(lvalue, delta, true)
+            }
+            Token::LParen => {
+                let mut list = self.expr_list(Token::RParen);
+                match list.len() {
+                    0 => self.error(),
+                    1 => list.pop().unwrap(),
+                    _ if *self.peek() == Token::In => Expr::Grouping(list),
+                    _ => self.error(),
+                }
+            }
+            Token::Name(name) => {
+                let var = self.var(&name);
+                if self.eat(&Token::LBracket) {
+                    Expr::LValue(LValue::Index(var, self.expr_list(Token::RBracket)))
+                } else {
+                    Expr::LValue(LValue::Var(var))
+                }
+            }
+            Token::FuncName(name) => {
+                self.expect(&Token::LParen);
+                let args = self.expr_list(Token::RParen);
+                let index = self.function_index(&name);
+                self.calls.push((index, args.len(), line));
+                Expr::Call(index, args)
+            }
+            Token::Builtin(builtin) => {
+                // `length` can be used without parentheses.
+                let args = if builtin == Builtin::Length && *self.peek() != Token::LParen {
+                    Vec::new()
+                } else {
+                    self.expect(&Token::LParen);
+                    self.expr_list(Token::RParen)
+                };
+
+                let (min, max) = builtin.arity();
+                if args.len() < min || args.len() > max {
+                    syntax_error(line, "wrong number of arguments for a builtin function");
+                }
+
+                // The array of `split` and the target of `sub` must be variables.
+                let valid = match builtin {
+                    Builtin::Split => matches!(args[1], Expr::LValue(LValue::Var(_))),
+                    Builtin::Sub | Builtin::Gsub => {
+                        args.len() == 2 || matches!(args[2], Expr::LValue(_))
+                    }
+                    _ => true,
+                };
+                if !valid {
+                    syntax_error(line, "invalid argument for a builtin function");
+                }
+
+                Expr::Builtin(builtin, args)
+            }
+            Token::Getline => {
+                let target = self.optional_lvalue();
+                if self.eat(&Token::Less) {
+                    let file = self.postfix();
+                    Expr::Getline(GetlineSource::File(Box::new(file)), target)
+                } else {
+                    Expr::Getline(GetlineSource::Main, target)
+                }
+            }
+            _ => {
+                self.pos -= 1;
+                self.error();
+            }
+        }
+    }
+}
+
+/// Find the length of the number at the start of `text`, 0 if there's none.
+fn number_prefix(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let digits = |mut i: usize| {
+        while i < bytes.len() && bytes[i].is_ascii_digit() {
+            i += 1;
+        }
+        i
+    };
+
+    let mut i = 0;
+    if matches!(bytes.first(), Some(b'+' | b'-')) {
+        i += 1;
+    }
+
+    let integer_end = digits(i);
+    let mut end = integer_end;
+    let mut has_digits = integer_end > i;
+    if bytes.get(end) == Some(&b'.') {
+        let fraction_end = digits(end + 1);
+        has_digits |= fraction_end > end + 1;
+        end = fraction_end;
+    }
+    if !has_digits {
+        return 0;
+    }
+
+    if matches!(bytes.get(end), Some(b'e' | b'E')) {
+        let mut exponent = end + 1;
+        if matches!(bytes.get(exponent), Some(b'+' | b'-')) {
+            exponent += 1;
+        }
+        let exponent_end = digits(exponent);
+        if exponent_end > exponent {
+            end = exponent_end;
+        }
+    }
+
+    end
+}
+
+fn is_blank(chr: char) -> bool {
+    matches!(chr, ' ' | '\t' | '\n')
+}
+
+/// Convert a string to a number, from its leading numeric part.
+fn str_to_num(text: &str) -> f64 {
+    let text = text.trim_start_matches(is_blank);
+    text[..number_prefix(text)].parse().unwrap_or(0.0)
+}
+
+/// Check if a string is a number, apart from blanks around it.
+fn looks_numeric(text: &str) -> bool {
+    let text = text.trim_matches(is_blank);
+    !text.is_empty() && number_prefix(text) == text.len()
+}
+
+#[derive(Clone)]
+enum Value {
+    Uninit,
+    Num(f64),
+    Str(Rc<str>),
+    /// A string from the input, compared as a number if it looks like one.
+    StrNum(Rc<str>),
+}
+
+impl Value {
+    fn to_num(&self) -> f64 {
+        match self {
+            Value::Uninit => 0.0,
+            Value::Num(number) => *number,
+            Value::Str(text) | Value::StrNum(text) => str_to_num(text),
+        }
+    }
+
+    fn to_str(&self, convfmt: &str) -> Rc<str> {
+        match self {
+            Value::Uninit => Rc::from(""),
+            Value::Num(number) => number_to_str(*number, convfmt),
+            Value::Str(text) | Value::StrNum(text) => text.clone(),
+        }
+    }
+
+    fn to_bool(&self) -> bool {
+        match self {
+            Value::Uninit => false,
+            Value::Num(number) => *number != 0.0,
+            Value::Str(text) => !text.is_empty(),
+            Value::StrNum(text) if looks_numeric(text) => str_to_num(text) != 0.0,
+            Value::StrNum(text) => !text.is_empty(),
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        match self {
+            Value::Uninit | Value::Num(_) => true,
+            Value::Str(_) => false,
+            Value::StrNum(text) => looks_numeric(text),
+        }
+    }
+}
+
+fn bool_value(value: bool) -> Value {
+    Value::Num(if value { 1.0 } else { 0.0 })
+}
+
+/// Convert a number to a string: integers are printed as such, the
+/// other numbers with `format` (`CONVFMT` or `OFMT`).
+fn number_to_str(number: f64, format: &str) -> Rc<str> {
+    if number == number.trunc() && number.abs() < 1e16 {
+        return (number as i64).to_string().into();
+    }
+
+    sprintf(format, &[Value::Num(number)], "%.6g").into()
+}
+
+/// Format a number like the `%e` conversion.
+fn format_exponent(number: f64, precision: usize, upper: bool) -> String {
+    let formatted = format!("{:.*e}", precision, number);
+    let (mantissa, exponent) = formatted.split_once('e').unwrap();
+    let exponent = exponent.parse::<i32>().unwrap();
+
+    format!(
+        "{}{}{}{:02}",
+        mantissa,
+        if upper { 'E' } else { 'e' },
+        if exponent < 0 { '-' } else { '+' },
+        exponent.abs()
+    )
+}
+
+/// Format a number like the `%g` conversion: as `%e` for large and small
+/// exponents, as `%f` otherwise, without trailing zeros unless `alternate`.
+fn format_general(number: f64, precision: usize, alternate: bool, upper: bool) -> String {
+    let precision = precision.max(1);
+    let exponent = if number == 0.0 {
+        0
+    } else {
+        let formatted = format!("{:.*e}", precision - 1, number);
+        formatted.split_once('e').unwrap().1.parse::<i32>().unwrap()
+    };
+
+    let formatted = if exponent < -4 || exponent >= precision as i32 {
+        format_exponent(number, precision - 1, upper)
+    } else {
+        format!("{:.*}", (precision as i32 - 1 - exponent) as usize, number)
+    };
+
+    if alternate {
+        return formatted;
+    }
+
+    let (mantissa, exponent) =
+        formatted.split_at(formatted.find(['e', 'E']).unwrap_or(formatted.len()));
+    if !mantissa.contains('.') {
+        return formatted;
+    }
+    format!(
+        "{}{}",
+        mantissa.trim_end_matches('0').trim_end_matches('.'),
+        exponent
+    )
+}
+
+/// Pad `body` to `width` characters. With `zero`, zeros are inserted after
+/// the sign and the `0x` prefix.
+fn pad(body: String, width: usize, left: bool, zero: bool) -> String {
+    let len = body.chars().count();
+    if len >= width {
+        return body;
+    }
+
+    let fill = width - len;
+    if left {
+        return body + &" ".repeat(fill);
+    }
+    if !zero {
+        return " ".repeat(fill) + &body;
+    }
+
+    let mut prefix = usize::from(body.starts_with(['-', '+', ' ']));
+    if body[prefix..].starts_with("0x") || body[prefix..].starts_with("0X") {
+        prefix += 2;
+    }
+    format!("{}{}{}", &body[..prefix], "0".repeat(fill), &body[prefix..])
+}
+
+/// Format `args` like `printf` does. Numbers given for `%s` are converted
+/// with `convfmt`.
+fn sprintf(format: &str, args: &[Value], convfmt: &str) -> String {
+    let chars = format.chars().collect::<Vec<_>>();
+    let mut args = args.iter();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        if chars[i] != '%' {
+            out.push(chars[i]);
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        i += 1;
+
+        let (mut left, mut plus, mut space, mut alternate, mut zero) =
+            (false, false, false, false, false);
+        while let Some(&flag) = chars.get(i) {
+            match flag {
+                '-' => left = true,
+                '+' => plus = true,
+                ' ' => space = true,
+                '#' => alternate = true,
+                '0' => zero = true,
+                _ => break,
+            }
+            i += 1;
+        }
+
+        let mut width = 0;
+        if chars.get(i) == Some(&'*') {
+            let value = args.next().map_or(0.0, Value::to_num) as i64;
+            left |= value < 0;
+            width = value.unsigned_abs() as usize;
+            i += 1;
+        } else {
+            while let Some(digit) = chars.get(i).and_then(|c| c.to_digit(10)) {
+                width = width * 10 + digit as usize;
+                i += 1;
+            }
+        }
+
+        let mut precision = None;
+        if chars.get(i) == Some(&'.') {
+            i += 1;
+            if chars.get(i) == Some(&'*') {
+                let value = args.next().map_or(0.0, Value::to_num) as i64;
+                precision = usize::try_from(value).ok();
+                i += 1;
+            } else {
+                let mut value = 0;
+                while let Some(digit) = chars.get(i).and_then(|c| c.to_digit(10)) {
+                    value = value * 10 + digit as usize;
+                    i += 1;
+                }
+                precision = Some(value);
+            }
+        }
+
+        let Some(&conversion) = chars.get(i) else {
+            out.extend(&chars[start..]);
+            break;
+        };
+        i += 1;
+
+        let sign = |negative: bool| {
+            if negative {
+                "-"
+            } else if plus {
+                "+"
+            } else if space {
+                " "
+            } else {
+                ""
+            }
+        };
+        let arg = args.next().cloned().unwrap_or(Value::Uninit);
+
+        let (body, numeric) = match conversion {
+            '%' => {
+                out.push('%');
+                continue;
+            }
+            'd' | 'i' | 'o' | 'x' | 'X' | 'u' | 'e' | 'E' | 'f' | 'F' | 'g' | 'G'
+                if !arg.to_num().is_finite() =>
+            {
+                let number = arg.to_num();
+                let text = if number.is_nan() { "nan" } else { "inf" };
+                let text = if conversion.is_ascii_uppercase() {
+                    text.to_uppercase()
+                } else {
+                    text.to_owned()
+                };
+                (
+                    format!("{}{}", sign(number.is_sign_negative()), text),
+                    false,
+                )
+            }
+            'd' | 'i' => {
+                let number = arg.to_num().trunc();
+                let mut digits = format!("{:.0}", number.abs());
+                if let Some(precision) = precision {
+                    if precision == 0 && number == 0.0 {
+                        digits.clear();
+                    }
+                    while digits.len() < precision {
+                        digits.insert(0, '0');
+                    }
+                }
+                (
+                    format!("{}{}", sign(number < 0.0), digits),
+                    precision.is_none(),
+                )
+            }
+            'o' | 'x' | 'X' | 'u' => {
+                let number = arg.to_num().trunc();
+                let value = if number < 0.0 {
+                    number as i64 as u64
+                } else {
+                    number as u64
+                };
+
+                let mut digits = match conversion {
+                    'o' => format!("{:o}", value),
+                    'x' => format!("{:x}", value),
+                    'X' => format!("{:X}", value),
+                    _ => value.to_string(),
+                };
+                if let Some(precision) = precision {
+                    if precision == 0 && value == 0 {
+                        digits.clear();
+                    }
+                    while digits.len() < precision {
+                        digits.insert(0, '0');
+                    }
+                }
+                if alternate {
+                    match conversion {
+                        'o' if !digits.starts_with('0') => digits.insert(0, '0'),
+                        'x' if value != 0 => digits.insert_str(0, "0x"),
+                        'X' if value != 0 => digits.insert_str(0, "0X"),
+                        _ => {}
+                    }
+                }
+                (digits, precision.is_none())
+            }
+            'e' | 'E' | 'f' | 'F' | 'g' | 'G' => {
+                let number = arg.to_num();
+                let precision = precision.unwrap_or(6);
+                let upper = conversion.is_ascii_uppercase();
+
+                let mut digits = match conversion {
+                    'e' | 'E' => format_exponent(number.abs(), precision, upper),
+                    'f' | 'F' => format!("{:.*}", precision, number.abs()),
+                    _ => format_general(number.abs(), precision, alternate, upper),
+                };
+                if alternate && precision == 0 && !digits.contains('.') {
+                    digits.push('.');
+                }
+                (
+                    format!("{}{}", sign(number.is_sign_negative()), digits),
+                    true,
+                )
+            }
+            'c' => {
+                let chr = match &arg {
+                    Value::Num(_) | Value::StrNum(_) if arg.is_numeric() => {
+                        char::from_u32(arg.to_num() as u32)
+                            .map(String::from)
+                            .unwrap_or_default()
+                    }
+                    _ => arg.to_str(convfmt).chars().take(1).collect(),
+                };
+                (chr, false)
+            }
+            's' => {
+                let text = arg.to_str(convfmt);
+                let text = match precision {
+                    Some(precision) => text.chars().take(precision).collect(),
+                    None => text.to_string(),
+                };
+                (text, false)
+            }
+            _ => {
+                // Not a conversion: printed as is, without using an argument.
+                out.extend(&chars[start..i]);
+                continue;
+            }
+        };
+
+        out.push_str(&pad(body, width, left, zero && numeric && !left));
+    }
+
+    out
+}
+
+/// An array, keeping its elements in insertion order.
+#[derive(Default)]
+struct Array {
+    positions: HashMap<Rc<str>, usize>,
+    entries: Vec<(Rc<str>, Value)>,
+}
+
+impl Array {
+    /// Get an element, which is created if it doesn't exist.
+    fn get(&mut self, key: &Rc<str>) -> Value {
+        match self.positions.get(key) {
+            Some(&i) => self.entries[i].1.clone(),
+            None => {
+                self.set(key.clone(), Value::Uninit);
+                Value::Uninit
+            }
+        }
+    }
+
+    fn lookup(&self, key: &str) -> Option<&Value> {
+        self.positions.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    fn set(&mut self, key: Rc<str>, value: Value) {
+        match self.positions.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.positions.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    fn contains(&self, key: &str) -> bool {
+        self.positions.contains_key(key)
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(i) = self.positions.remove(key) {
+            self.entries.swap_remove(i);
+            if let Some((moved, _)) = self.entries.get(i) {
+                self.positions.insert(moved.clone(), i);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.positions.clear();
+        self.entries.clear();
+    }
+}
+
+/// The storage of a variable.
+enum Slot {
+    Uninit,
+    Value(Value),
+    Array(Rc<RefCell<Array>>),
+    /// A parameter given an uninitialized variable, which becomes this
+    /// array if it's used as one, in the function and for the caller.
+    Pending(Rc<RefCell<Array>>),
+}
+
+/// How records are separated, from `RS`.
+enum RecordSeparator {
+    Byte(u8),
+    /// `RS` is empty: records are separated by blank lines.
+    Paragraph,
+    Regex(Rc<Regex>),
+}
+
+/// How fields are separated, from `FS` or the argument of `split`.
+enum FieldSeparator {
+    /// `" "`: runs of blanks, ignored at the ends.
+    Blanks,
+    /// `""`: every character is a field.
+    Chars,
+    Char(char),
+    Regex(Rc<Regex>),
+}
+
+fn split_fields(text: &str, separator: &FieldSeparator) -> Vec<Rc<str>> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    match separator {
+        FieldSeparator::Blanks => text
+            .split(is_blank)
+            .filter(|field| !field.is_empty())
+            .map(Rc::from)
+            .collect(),
+        FieldSeparator::Chars => text.chars().map(|chr| Rc::from(chr.to_string())).collect(),
+        FieldSeparator::Char(separator) => text.split(*separator).map(Rc::from).collect(),
+        FieldSeparator::Regex(regex) => {
+            let chars = text.chars().collect::<Vec<_>>();
+            let mut fields = Vec::new();
+            let mut start = 0;
+            let mut search = 0;
+
+            while let Some(caps) = regex.find_at(&chars, search) {
+                let (match_start, match_end) = caps[0].unwrap();
+
+                // Empty matches don't separate fields.
+                if match_start == match_end {
+                    search = match_start + 1;
+                    continue;
+                }
+
+                fields.push(chars[start..match_start].iter().collect::<String>().into());
+                start = match_end;
+                search = match_end;
+            }
+
+            fields.push(chars[start..].iter().collect::<String>().into());
+            fields
+        }
+    }
+}
+
+/// Replace the first match of `regex` in `text` (every match if `global`)
+/// with `replacement`, in which `&` stands for the match. Returns the
+/// result and the number of replacements.
+fn substitute(regex: &Regex, text: &str, replacement: &str, global: bool) -> (String, usize) {
+    let chars = text.chars().collect::<Vec<_>>();
+    let replacement = replacement.chars().collect::<Vec<_>>();
+    let mut result = String::new();
+    let mut copied = 0;
+    let mut start = 0;
+    let mut count = 0;
+    let mut previous_end = None;
+
+    while let Some(caps) = regex.find_at(&chars, start) {
+        let (match_start, match_end) = caps[0].unwrap();
+
+        // An empty match right after the previous match isn't one.
+        if match_start == match_end && previous_end == Some(match_start) {
+            start = match_start + 1;
+            continue;
+        }
+        previous_end = Some(match_end);
+
+        result.extend(&chars[copied..match_start]);
+        let mut i = 0;
+        while i < replacement.len() {
+            match replacement[i] {
+                '\\' if matches!(replacement.get(i + 1), Some('&' | '\\')) => {
+                    result.push(replacement[i + 1]);
+                    i += 1;
+                }
+                '&' => result.extend(&chars[match_start..match_end]),
+                chr => result.push(chr),
+            }
+            i += 1;
+        }
+
+        copied = match_end;
+        count += 1;
+        if !global {
+            break;
+        }
+
+        start = if match_end > match_start {
+            match_end
+        } else {
+            match_end + 1
+        };
+    }
+
+    result.extend(&chars[copied..]);
+    (result, count)
+}
+
+/// Reads records from an input.
+struct RecordReader {
+    input: Box<dyn BufRead>,
+    /// For a regex separator, the rest of the input, and the position in it.
+    rest: Option<(Vec<char>, usize)>,
+}
+
+impl RecordReader {
+    fn new(input: Box<dyn BufRead>) -> Self {
+        RecordReader { input, rest: None }
+    }
+
+    fn read(&mut self, separator: &RecordSeparator) -> std::io::Result<Option<String>> {
+        let mut bytes = Vec::new();
+
+        match separator {
+            RecordSeparator::Byte(separator) => {
+                if self.input.read_until(*separator, &mut bytes)? == 0 {
+                    return Ok(None);
+                }
+                if bytes.last() == Some(separator) {
+                    bytes.pop();
+                }
+            }
+            RecordSeparator::Paragraph => {
+                // Leading newlines are skipped, and a blank line ends the record.
+                while self.input.fill_buf()?.first() == Some(&b'\n') {
+                    self.input.consume(1);
+                }
+
+                let mut line = Vec::new();
+                loop {
+                    line.clear();
+                    if self.input.read_until(b'\n', &mut line)? == 0 || line == b"\n" {
+                        break;
+                    }
+                    bytes.extend_from_slice(&line);
+                }
+
+                if bytes.is_empty() {
+                    return Ok(None);
+                }
+                if bytes.last() == Some(&b'\n') {
+                    bytes.pop();
+                }
+            }
+            RecordSeparator::Regex(regex) => {
+                // The input is read at once, to search it.
+                if self.rest.is_none() {
+                    let mut input = Vec::new();
+                    self.input.read_to_end(&mut input)?;
+                    self.rest = Some((String::from_utf8_lossy(&input).chars().collect(), 0));
+                }
+
+                let (chars, pos) = self.rest.as_mut().unwrap();
+                if *pos >= chars.len() {
+                    return Ok(None);
+                }
+
+                let mut search = *pos;
+                let (end, next) = loop {
+                    match regex.find_at(chars, search) {
+                        Some(caps) => {
+                            let (start, end) = caps[0].unwrap();
+                            if start < end {
+                                break (start, end);
+                            }
+                            search = start + 1;
+                        }
+                        None => break (chars.len(), chars.len()),
+                    }
+                };
+
+                let record = chars[*pos..end].iter().collect();
+                *pos = next;
+                return Ok(Some(record));
+            }
+        }
+
+        Ok(Some(match String::from_utf8(bytes) {
+            Ok(record) => record,
+            Err(e) => String::from_utf8_lossy(e.as_bytes()).into_owned(),
+        }))
+    }
+}
+
+/// An output opened by a redirection.
+enum Stream {
+    File(BufWriter<File>),
+    Pipe(BufWriter<ChildStdin>, Child),
+}
+
+/// How the execution of statements is interrupted.
+enum Flow {
+    Break,
+    Continue,
+    Next,
+    NextFile,
+    Exit,
+    Return(Value),
+}
+
+/// A resolved lvalue, evaluated once when it's both read and written.
+enum Place {
+    Var(Var),
+    Element(Rc<RefCell<Array>>, Rc<str>),
+    Field(usize),
+}
+
+/// Flatten the exit status of a command like `system` reports it.
+fn status_code(status: std::process::ExitStatus) -> f64 {
+    match status.code() {
+        Some(code) => code as f64,
+        None => 256.0 + status.signal().unwrap_or(0) as f64,
+    }
+}
+
+struct Interpreter<'a> {
+    program: &'a Program,
+    globals: Vec<Slot>,
+    /// The parameters of the functions being run.
+    frames: Vec<Vec<Slot>>,
+    record: Rc<str>,
+    /// `FS` when the record was read, which is used to split it.
+    record_fs: Rc<str>,
+    fields: Vec<Value>,
+    /// Whether the record was split into `fields`.
+    split: bool,
+    /// Whether each range pattern is active.
+    ranges: Vec<bool>,
+    input: Option<RecordReader>,
+    /// The next operand to read (`ARGV[i]`).
+    next_arg: usize,
+    read_file: bool,
+    readers: HashMap<String, (RecordReader, Option<Child>)>,
+    outputs: HashMap<String, Stream>,
+    stdout: BufWriter<std::io::Stdout>,
+    regexes: HashMap<String, Rc<Regex>>,
+    seed: f64,
+    random: u64,
+    exit_code: i32,
+    was_error: bool,
+}
+
+impl<'a> Interpreter<'a> {
+    fn new(program: &'a Program, operands: &[String]) -> Self {
+        let mut globals = Vec::new();
+        globals.resize_with(program.globals.len(), || Slot::Uninit);
+
+        let string = |text: &str| Slot::Value(Value::Str(text.into()));
+        globals[NF] = Slot::Value(Value::Num(0.0));
+        globals[NR] = Slot::Value(Value::Num(0.0));
+        globals[FNR] = Slot::Value(Value::Num(0.0));
+        globals[FS] = string(" ");
+        globals[OFS] = string(" ");
+        globals[ORS] = string("\n");
+        globals[RS] = string("\n");
+        globals[SUBSEP] = string("\x1c");
+        globals[CONVFMT] = string("%.6g");
+        globals[OFMT] = string("%.6g");
+        globals[RSTART] = Slot::Value(Value::Num(0.0));
+        globals[RLENGTH] = Slot::Value(Value::Num(-1.0));
+
+        let mut environ = Array::default();
+        for (name, value) in std::env::vars_os() {
+            environ.set(
+                name.to_string_lossy().into(),
+                Value::StrNum(value.to_string_lossy().into()),
+            );
+        }
+        globals[ENVIRON] = Slot::Array(Rc::new(RefCell::new(environ)));
+
+        let mut argv = Array::default();
+        argv.set("0".into(), Value::Str("awk".into()));
+        for (i, operand) in operands.iter().enumerate() {
+            argv.set(
+                (i + 1).to_string().into(),
+                Value::StrNum(operand.as_str().into()),
+            );
+        }
+        globals[ARGV] = Slot::Array(Rc::new(RefCell::new(argv)));
+        globals[ARGC] = Slot::Value(Value::Num(operands.len() as f64 + 1.0));
+
+        Interpreter {
+            program,
+            globals,
+            frames: Vec::new(),
+            record: "".into(),
+            record_fs: " ".into(),
+            fields: Vec::new(),
+            split: true,
+            ranges: vec![false; program.rules.len()],
+            input: None,
+            next_arg: 1,
+            read_file: false,
+            readers: HashMap::new(),
+            outputs: HashMap::new(),
+            stdout: BufWriter::new(std::io::stdout()),
+            regexes: HashMap::new(),
+            seed: 0.0,
+            random: 0,
+            exit_code: 0,
+            was_error: false,
+        }
+    }
+
+    /// Get the value of a special variable as a string.
+    fn special(&self, index: usize) -> Rc<str> {
+        match &self.globals[index] {
+            Slot::Value(value) => self.to_str(value),
+            _ => "".into(),
+        }
+    }
+
+    fn format_var(&self, index: usize) -> Rc<str> {
+        match &self.globals[index] {
+            Slot::Value(Value::Str(format) | Value::StrNum(format)) => format.clone(),
+            _ => "%.6g".into(),
+        }
+    }
+
+    fn to_str(&self, value: &Value) -> Rc<str> {
+        match value {
+            Value::Num(number) => number_to_str(*number, &self.format_var(CONVFMT)),
+            _ => value.to_str(""),
+        }
+    }
+
+    /// Convert a value to a string for `print`, which uses `OFMT`.
+    fn output_str(&self, value: &Value) -> Rc<str> {
+        match value {
+            Value::Num(number) => number_to_str(*number, &self.format_var(OFMT)),
+            _ => value.to_str(""),
+        }
+    }
+
+    fn compare(&self, a: &Value, b: &Value) -> std::cmp::Ordering {
+        if a.is_numeric() && b.is_numeric() {
+            a.to_num()
+                .partial_cmp(&b.to_num())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        } else {
+            self.to_str(a).cmp(&self.to_str(b))
+        }
+    }
+
+    /// Compile a regex given as a string, keeping it for the next times.
+    fn regex(&mut self, pattern: &str) -> Rc<Regex> {
+        if let Some(regex) = self.regexes.get(pattern) {
+            return regex.clone();
+        }
+
+        let regex = match Regex::new(pattern, true, false) {
+            Ok(regex) => Rc::new(regex),
+            Err(message) => fail(format_args!("{}", message)),
+        };
+
+        if self.regexes.len() > 1000 {
+            self.regexes.clear();
+        }
+        self.regexes.insert(pattern.to_owned(), regex.clone());
+        regex
+    }
+
+    /// Get the regex of an operand of `~` or of a builtin: a regex
+    /// literal, or any other expression, converted to a string.
+    fn regex_of(&mut self, expr: &Expr) -> Result<Rc<Regex>, Flow> {
+        if let Expr::Regex(regex) = expr {
+            return Ok(regex.clone());
+        }
+
+        let value = self.eval(expr)?;
+        let pattern = self.to_str(&value);
+        Ok(self.regex(&pattern))
+    }
+
+    fn record_separator(&mut self) -> RecordSeparator {
+        let rs = self.special(RS);
+        match rs.as_bytes() {
+            [] => RecordSeparator::Paragraph,
+            [byte] => RecordSeparator::Byte(*byte),
+            _ => RecordSeparator::Regex(self.regex(&rs)),
+        }
+    }
+
+    fn field_separator(&mut self, fs: &str) -> FieldSeparator {
+        let mut chars = fs.chars();
+        match (chars.next(), chars.next()) {
+            (None, _) => FieldSeparator::Chars,
+            (Some(' '), None) => FieldSeparator::Blanks,
+            (Some(chr), None) if chr != '\\' => FieldSeparator::Char(chr),
+            _ => FieldSeparator::Regex(self.regex(fs)),
+        }
+    }
+
+    fn slot(&mut self, var: Var) -> &mut Slot {
+        match var {
+            Var::Global(i) => &mut self.globals[i],
+            Var::Local(i) => &mut self.frames.last_mut().unwrap()[i],
+        }
+    }
+
+    fn get_var(&mut self, var: Var) -> Value {
+        if var == Var::Global(NF) {
+            self.split_record();
+        }
+
+        match self.slot(var) {
+            Slot::Value(value) => value.clone(),
+            Slot::Uninit | Slot::Pending(_) => Value::Uninit,
+            Slot::Array(_) => fail(format_args!("attempt to use an array in a scalar context")),
+        }
+    }
+
+    fn set_var(&mut self, var: Var, value: Value) {
+        if var == Var::Global(NF) {
+            self.split_record();
+        }
+
+        let slot = self.slot(var);
+        if let Slot::Array(_) = slot {
+            fail(format_args!("attempt to use an array in a scalar context"));
+        }
+        *slot = Slot::Value(value);
+
+        if var == Var::Global(NF) {
+            let count = self.get_var(var).to_num();
+            if count < 0.0 {
+                fail(format_args!("NF set to a negative value"));
+            }
+            self.fields.resize(count as usize, Value::Uninit);
+            self.rebuild_record();
+        }
+    }
+
+    fn array(&mut self, var: Var) -> Rc<RefCell<Array>> {
+        let slot = self.slot(var);
+        match slot {
+            Slot::Array(array) => array.clone(),
+            Slot::Pending(array) => {
+                let array = array.clone();
+                *slot = Slot::Array(array.clone());
+                array
+            }
+            Slot::Uninit => {
+                let array = Rc::new(RefCell::new(Array::default()));
+                *slot = Slot::Array(array.clone());
+                array
+            }
+            Slot::Value(_) => fail(format_args!("attempt to use a scalar as an array")),
+        }
+    }
+
+    fn increment(&mut self, index: usize) {
+        let value = self.get_var(Var::Global(index)).to_num();
+        self.set_var(Var::Global(index), Value::Num(value + 1.0));
+    }
+
+    fn set_record(&mut self, record: Rc<str>) {
+        self.record = record;
+        self.record_fs = self.special(FS);
+        self.split = false;
+    }
+
+    /// Split the record into fields, if it wasn't already.
+    fn split_record(&mut self) {
+        if self.split {
+            return;
+        }
+        self.split = true;
+
+        let record = self.record.clone();
+        let separator = self.field_separator(&self.record_fs.clone());
+
+        // Records separated by blank lines also have their lines separated.
+        let fields = if self.special(RS).is_empty() && !matches!(separator, FieldSeparator::Blanks)
+        {
+            record
+                .split('\n')
+                .flat_map(|line| split_fields(line, &separator))
+                .collect()
+        } else {
+            split_fields(&record, &separator)
+        };
+
+        self.fields = fields.into_iter().map(Value::StrNum).collect();
+        self.globals[NF] = Slot::Value(Value::Num(self.fields.len() as f64));
+    }
+
+    /// Rebuild the record from the fields, separated by `OFS`.
+    fn rebuild_record(&mut self) {
+        let ofs = self.special(OFS);
+        let mut record = String::new();
+
+        for (i, field) in self.fields.iter().enumerate() {
+            if i > 0 {
+                record.push_str(&ofs);
+            }
+            record.push_str(&self.to_str(field));
+        }
+
+        self.record = record.into();
+        self.globals[NF] = Slot::Value(Value::Num(self.fields.len() as f64));
+    }
+
+    fn field(&mut self, index: usize) -> Value {
+        if index == 0 {
+            return Value::StrNum(self.record.clone());
+        }
+
+        self.split_record();
+        self.fields.get(index - 1).cloned().unwrap_or(Value::Uninit)
+    }
+
+    fn set_field(&mut self, index: usize, value: Value) {
+        if index == 0 {
+            let record = self.to_str(&value);
+            self.set_record(record);
+            return;
+        }
+
+        self.split_record();
+        if index > self.fields.len() {
+            self.fields.resize(index, Value::Uninit);
+        }
+        self.fields[index - 1] = value;
+        self.rebuild_record();
+    }
+
+    /// Get the key of an array element, the subscripts being joined
+    /// with `SUBSEP`.
+    fn subscript(&mut self, subscripts: &[Expr]) -> Result<Rc<str>, Flow> {
+        if let [subscript] = subscripts {
+            let value = self.eval(subscript)?;
+            return Ok(self.to_str(&value));
+        }
+
+        let subsep = self.special(SUBSEP);
+        let mut key = String::new();
+        for (i, subscript) in subscripts.iter().enumerate() {
+            if i > 0 {
+                key.push_str(&subsep);
+            }
+            let value = self.eval(subscript)?;
+            key.push_str(&self.to_str(&value));
+        }
+
+        Ok(key.into())
+    }
+
+    fn place(&mut self, lvalue: &LValue) -> Result<Place, Flow> {
+        Ok(match lvalue {
+            LValue::Var(var) => Place::Var(*var),
+            LValue::Index(var, subscripts) => {
+                let key = self.subscript(subscripts)?;
+                Place::Element(self.array(*var), key)
+            }
+            LValue::Field(index) => {
+                let index = self.eval(index)?.to_num();
+                if index < 0.0 {
+                    fail(format_args!(
+                        "trying to access out of range field {}",
+                        index
+                    ));
+                }
+                Place::Field(index as usize)
+            }
+        })
+    }
+
+    fn get(&mut self, place: &Place) -> Value {
+        match place {
+            Place::Var(var) => self.get_var(*var),
+            Place::Element(array, key) => array.borrow_mut().get(key),
+            Place::Field(index) => self.field(*index),
+        }
+    }
+
+    fn set(&mut self, place: &Place, value: Value) {
+        match place {
+            Place::Var(var) => self.set_var(*var, value),
+            Place::Element(array, key) => array.borrow_mut().set(key.clone(), value),
+            Place::Field(index) => self.set_field(*index, value),
+        }
+    }
+
+    fn arithmetic(&self, op: BinOp, a: f64, b: f64) -> f64 {
+        match op {
+            BinOp::Add => a + b,
+            BinOp::Sub => a - b,
+            BinOp::Mul => a * b,
+            BinOp::Div if b == 0.0 => fail(format_args!("division by zero")),
+            BinOp::Div => a / b,
+            BinOp::Mod if b == 0.0 => fail(format_args!("division by zero in %")),
+            BinOp::Mod => a % b,
+            BinOp::Pow => a.powf(b),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, Flow> {
+        Ok(match expr {
+            Expr::Num(number) => Value::Num(*number),
+            Expr::Str(text) => Value::Str(text.clone()),
+            Expr::Regex(regex) => bool_value(regex.is_match(&self.record)),
+            Expr::LValue(lvalue) => {
+                let place = self.place(lvalue)?;
+                self.get(&place)
+            }
+            Expr::Assign(lvalue, op, value) => {
+                let place = self.place(lvalue)?;
+                let mut value = self.eval(value)?;
+                if let Some(op) = op {
+                    let current = self.get(&place).to_num();
+                    value = Value::Num(self.arithmetic(*op, current, value.to_num()));
+                }
+                self.set(&place, value.clone());
+                value
+            }
+            Expr::IncDec(lvalue, delta, prefix) => {
+                let place = self.place(lvalue)?;
+                let old = self.get(&place).to_num();
+                self.set(&place, Value::Num(old + delta));
+                Value::Num(if *prefix { old + delta } else { old })
+            }
+            Expr::Cond(cond, then, otherwise) => {
+                if self.eval(cond)?.to_bool() {
+                    self.eval(then)?
+                } else {
+                    self.eval(otherwise)?
+                }
+            }
+            Expr::And(a, b) => bool_value(self.eval(a)?.to_bool() && self.eval(b)?.to_bool()),
+            Expr::Or(a, b) => bool_value(self.eval(a)?.to_bool() || self.eval(b)?.to_bool()),
+            Expr::Not(value) => bool_value(!self.eval(value)?.to_bool()),
+            Expr::Neg(value) => Value::Num(-self.eval(value)?.to_num()),
+            Expr::Plus(value) => Value::Num(self.eval(value)?.to_num()),
+            Expr::Binary(op, a, b) => {
+                let a = self.eval(a)?.to_num();
+                let b = self.eval(b)?.to_num();
+                Value::Num(self.arithmetic(*op, a, b))
+            }
+            Expr::Compare(op, a, b) => {
+                let a = self.eval(a)?;
+                let b = self.eval(b)?;
+                let ordering = self.compare(&a, &b);
+                bool_value(match op {
+                    CmpOp::Lt => ordering.is_lt(),
+                    CmpOp::Le => ordering.is_le(),
+                    CmpOp::Eq => ordering.is_eq(),
+                    CmpOp::Ne => ordering.is_ne(),
+                    CmpOp::Gt => ordering.is_gt(),
+                    CmpOp::Ge => ordering.is_ge(),
+                })
+            }
+            Expr::Match(negated, text, regex) => {
+                let text = self.eval(text)?;
+                let text = self.to_str(&text);
+                let regex = self.regex_of(regex)?;
+                bool_value(regex.is_match(&text) != *negated)
+            }
+            Expr::Concat(items) => {
+                let mut text = String::new();
+                for item in items {
+                    let value = self.eval(item)?;
+                    text.push_str(&self.to_str(&value));
+                }
+                Value::Str(text.into())
+            }
+            Expr::In(subscripts, var) => {
+                let key = self.subscript(subscripts)?;
+                let array = self.array(*var);
+                let contains = array.borrow().contains(&key);
+                bool_value(contains)
+            }
+            Expr::Call(index, args) => self.call(*index, args)?,
+            Expr::Builtin(builtin, args) => self.builtin(*builtin, args)?,
+            Expr::Getline(source, target) => self.getline(source, target.as_ref())?,
+            Expr::Grouping(_) => unreachable!(),
+        })
+    }
+
+    fn call(&mut self, index: usize, args: &[Expr]) -> Result<Value, Flow> {
+        let function = &self.program.functions[index];
+        let mut frame = Vec::with_capacity(function.params);
+        // The uninitialized variables given, which may become arrays.
+        let mut pending = Vec::new();
+
+        for (i, arg) in args.iter().enumerate() {
+            let slot = match arg {
+                Expr::LValue(LValue::Var(var)) if *var != Var::Global(NF) => {
+                    match self.slot(*var) {
+                        Slot::Array(array) => Slot::Array(array.clone()),
+                        Slot::Pending(array) => Slot::Pending(array.clone()),
+                        Slot::Value(value) => Slot::Value(value.clone()),
+                        Slot::Uninit => {
+                            pending.push((i, *var));
+                            Slot::Pending(Rc::default())
+                        }
+                    }
+                }
+                _ => Slot::Value(self.eval(arg)?),
+            };
+            frame.push(slot);
+        }
+        frame.resize_with(function.params, || Slot::Uninit);
+
+        self.frames.push(frame);
+        let result = self.exec_block(&function.body);
+        let frame = self.frames.pop().unwrap();
+
+        for (i, var) in pending {
+            if let Slot::Array(array) = &frame[i] {
+                let slot = self.slot(var);
+                if let Slot::Uninit | Slot::Pending(_) = slot {
+                    *slot = Slot::Array(array.clone());
+                }
+            }
+        }
+
+        match result {
+            Ok(()) => Ok(Value::Uninit),
+            Err(Flow::Return(value)) => Ok(value),
+            Err(flow) => Err(flow),
+        }
+    }
+
+    fn builtin(&mut self, builtin: Builtin, args: &[Expr]) -> Result<Value, Flow> {
+        let number = |interpreter: &mut Self, i: usize| -> Result<f64, Flow> {
+            Ok(interpreter.eval(&args[i])?.to_num())
+        };
+        let string = |interpreter: &mut Self, i: usize| -> Result<Rc<str>, Flow> {
+            let value = interpreter.eval(&args[i])?;
+            Ok(interpreter.to_str(&value))
+        };
+
+        Ok(match builtin {
+            Builtin::Length => match args.first() {
+                None => Value::Num(self.record.chars().count() as f64),
+                Some(Expr::LValue(LValue::Var(var)))
+                    if matches!(self.slot(*var), Slot::Array(_)) =>
+                {
+                    let array = self.array(*var);
+                    let len = array.borrow().entries.len();
+                    Value::Num(len as f64)
+                }
+                Some(_) => Value::Num(string(self, 0)?.chars().count() as f64),
+            },
+            Builtin::Substr => {
+                let text = string(self, 0)?;
+                let len = text.chars().count() as f64;
+
+                // The positions are rounded, and clamped to the string.
+                let start = number(self, 1)?.round();
+                let end = match args.len() {
+                    3 => start + number(self, 2)?.round(),
+                    _ => f64::INFINITY,
+                };
+                let start = start.max(1.0);
+                let end = end.min(len + 1.0);
+
+                if start >= end {
+                    Value::Str("".into())
+                } else {
+                    let text = text
+                        .chars()
+                        .skip(start as usize - 1)
+                        .take((end - start) as usize)
+                        .collect::<String>();
+                    Value::Str(text.into())
+                }
+            }
+            Builtin::Index => {
+                let text = string(self, 0)?;
+                let target = string(self, 1)?;
+                let position = match text.find(&*target) {
+                    Some(i) => text[..i].chars().count() + 1,
+                    None => 0,
+                };
+                Value::Num(position as f64)
+            }
+            Builtin::Split => {
+                let text = string(self, 0)?;
+                let Expr::LValue(LValue::Var(var)) = &args[1] else {
+                    unreachable!();
+                };
+
+                let separator = match args.get(2) {
+                    None => {
+                        let fs = self.special(FS);
+                        self.field_separator(&fs)
+                    }
+                    Some(Expr::Regex(regex)) => FieldSeparator::Regex(regex.clone()),
+                    Some(_) => {
+                        let fs = string(self, 2)?;
+                        self.field_separator(&fs)
+                    }
+                };
+
+                let fields = split_fields(&text, &separator);
+                let array = self.array(*var);
+                let mut array = array.borrow_mut();
+                array.clear();
+                for (i, field) in fields.iter().enumerate() {
+                    array.set((i + 1).to_string().into(), Value::StrNum(field.clone()));
+                }
+
+                Value::Num(fields.len() as f64)
+            }
+            Builtin::Sub | Builtin::Gsub => {
+                let regex = self.regex_of(&args[0])?;
+                let replacement = string(self, 1)?;
+                let place = match args.get(2) {
+                    Some(Expr::LValue(lvalue)) => self.place(lvalue)?,
+                    _ => Place::Field(0),
+                };
+
+                let text = self.get(&place);
+                let text = self.to_str(&text);
+                let (result, count) =
+                    substitute(&regex, &text, &replacement, builtin == Builtin::Gsub);
+                if count > 0 {
+                    self.set(&place, Value::Str(result.into()));
+                }
+
+                Value::Num(count as f64)
+            }
+            Builtin::Match => {
+                let text = string(self, 0)?;
+                let regex = self.regex_of(&args[1])?;
+                let chars = text.chars().collect::<Vec<_>>();
+
+                let (start, len) = match regex.find_at(&chars, 0) {
+                    Some(caps) => {
+                        let (start, end) = caps[0].unwrap();
+                        (start as f64 + 1.0, (end - start) as f64)
+                    }
+                    None => (0.0, -1.0),
+                };
+                self.globals[RSTART] = Slot::Value(Value::Num(start));
+                self.globals[RLENGTH] = Slot::Value(Value::Num(len));
+
+                Value::Num(start)
+            }
+            Builtin::Sprintf => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+
+                let format = self.to_str(&values[0]);
+                let convfmt = self.format_var(CONVFMT);
+                Value::Str(sprintf(&format, &values[1..], &convfmt).into())
+            }
+            Builtin::Sin => Value::Num(number(self, 0)?.sin()),
+            Builtin::Cos => Value::Num(number(self, 0)?.cos()),
+            Builtin::Atan2 => {
+                let y = number(self, 0)?;
+                Value::Num(y.atan2(number(self, 1)?))
+            }
+            Builtin::Exp => Value::Num(number(self, 0)?.exp()),
+            Builtin::Log => Value::Num(number(self, 0)?.ln()),
+            Builtin::Sqrt => Value::Num(number(self, 0)?.sqrt()),
+            Builtin::Int => Value::Num(number(self, 0)?.trunc()),
+            Builtin::Rand => {
+                // splitmix64, which is good enough and seeded simply.
+                self.random = self.random.wrapping_add(0x9e3779b97f4a7c15);
+                let mut z = self.random;
+                z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+                z ^= z >> 31;
+
+                Value::Num((z >> 11) as f64 / (1u64 << 53) as f64)
+            }
+            Builtin::Srand => {
+                let previous = self.seed;
+                self.seed = match args.len() {
+                    0 => std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map_or(0, |time| time.as_secs()) as f64,
+                    _ => number(self, 0)?,
+                };
+                self.random = self.seed.to_bits();
+                Value::Num(previous)
+            }
+            Builtin::ToLower => Value::Str(string(self, 0)?.to_lowercase().into()),
+            Builtin::ToUpper => Value::Str(string(self, 0)?.to_uppercase().into()),
+            Builtin::System => {
+                let command = string(self, 0)?;
+                self.flush_all();
+
+                let status = std::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(&*command)
+                    .status();
+                Value::Num(status.map_or(-1.0, status_code))
+            }
+            Builtin::Close => {
+                let name = string(self, 0)?;
+                Value::Num(self.close(&name))
+            }
+            Builtin::Fflush => {
+                self.flush_all();
+                Value::Num(0.0)
+            }
+        })
+    }
+
+    fn getline(&mut self, source: &GetlineSource, target: Option<&LValue>) -> Result<Value, Flow> {
+        let record = match source {
+            GetlineSource::Main => match self.read_main_record() {
+                Some(record) => {
+                    self.increment(NR);
+                    self.increment(FNR);
+                    record
+                }
+                None => return Ok(Value::Num(0.0)),
+            },
+            GetlineSource::File(name) | GetlineSource::Command(name) => {
+                let name = self.eval(name)?;
+                let name = self.to_str(&name).to_string();
+                let command = matches!(source, GetlineSource::Command(_));
+
+                if !self.readers.contains_key(&name) {
+                    let reader = if command {
+                        self.flush_all();
+                        let child = std::process::Command::new("sh")
+                            .arg("-c")
+                            .arg(&name)
+                            .stdout(Stdio::piped())
+                            .spawn();
+                        match child {
+                            Ok(mut child) => {
+                                let stdout = child.stdout.take().unwrap();
+                                let input = Box::new(std::io::BufReader::new(stdout));
+                                (RecordReader::new(input), Some(child))
+                            }
+                            Err(_) => return Ok(Value::Num(-1.0)),
+                        }
+                    } else {
+                        let path = if name == "/dev/stdin" { "-" } else { &name };
+                        match open_input(path) {
+                            Ok(input) => (RecordReader::new(input), None),
+                            Err(_) => return Ok(Value::Num(-1.0)),
+                        }
+                    };
+                    self.readers.insert(name.clone(), reader);
+                }
+
+                let separator = self.record_separator();
+                let (reader, _) = self.readers.get_mut(&name).unwrap();
+                match reader.read(&separator) {
+                    Ok(Some(record)) => {
+                        if command {
+                            self.increment(NR);
+                        }
+                        record
+                    }
+                    Ok(None) => return Ok(Value::Num(0.0)),
+                    Err(_) => return Ok(Value::Num(-1.0)),
+                }
+            }
+        };
+
+        match target {
+            Some(lvalue) => {
+                let place = self.place(lvalue)?;
+                self.set(&place, Value::StrNum(record.into()));
+            }
+            None => self.set_record(record.into()),
+        }
+
+        Ok(Value::Num(1.0))
+    }
+
+    /// Assign a variable from an operand like `name=value`. Returns false
+    /// if the operand isn't an assignment.
+    fn assign_operand(&mut self, operand: &str) -> bool {
+        let Some((name, value)) = operand.split_once('=') else {
+            return false;
+        };
+
+        let valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_name {
+            return false;
+        }
+
+        // Variables that the program doesn't use are ignored.
+        if let Some(&index) = self.program.globals.get(name) {
+            self.set_var(
+                Var::Global(index),
+                Value::StrNum(unescape_string(value).into()),
+            );
+        }
+        true
+    }
+
+    /// Read the next record of the main input: the operands (`ARGV`) which
+    /// aren't assignments, or the standard input if there's none.
+    fn read_main_record(&mut self) -> Option<String> {
+        loop {
+            if self.input.is_some() {
+                let separator = self.record_separator();
+                match self.input.as_mut().unwrap().read(&separator) {
+                    Ok(Some(record)) => return Some(record),
+                    Ok(None) => {}
+                    Err(e) => {
+                        eprintln!("awk: {}: {}", self.special(FILENAME), error_reason(&e));
+                        self.was_error = true;
+                    }
+                }
+                self.input = None;
+            }
+
+            let argc = self.get_var(Var::Global(ARGC)).to_num();
+            if self.next_arg as f64 >= argc {
+                if self.read_file {
+                    return None;
+                }
+
+                self.read_file = true;
+                self.input = Some(RecordReader::new(open_input("-").unwrap()));
+                continue;
+            }
+
+            let key = self.next_arg.to_string();
+            self.next_arg += 1;
+
+            let argv = self.array(Var::Global(ARGV));
+            let Some(operand) = argv.borrow().lookup(&key).cloned() else {
+                continue;
+            };
+            let operand = self.to_str(&operand);
+            if operand.is_empty() || self.assign_operand(&operand) {
+                continue;
+            }
+
+            self.read_file = true;
+            match open_input(&operand) {
+                Ok(input) => {
+                    self.input = Some(RecordReader::new(input));
+                    self.set_var(Var::Global(FILENAME), Value::Str(operand));
+                    self.set_var(Var::Global(FNR), Value::Num(0.0));
+                }
+                Err(e) => {
+                    eprintln!("awk: cannot open \"{}\": {}", operand, error_reason(&e));
+                    self.was_error = true;
+                }
+            }
+        }
+    }
+
+    fn write_output(
+        &mut self,
+        redirect: &Option<(Redirect, Expr)>,
+        data: &[u8],
+    ) -> Result<(), Flow> {
+        let result = match redirect {
+            None => self.stdout.write_all(data),
+            Some((redirect, target)) => {
+                let name = self.eval(target)?;
+                let name = self.to_str(&name);
+
+                match &*name {
+                    "-" | "/dev/stdout" => self.stdout.write_all(data),
+                    "/dev/stderr" => std::io::stderr().write_all(data),
+                    _ => {
+                        if !self.outputs.contains_key(&*name) {
+                            let stream = self.open_stream(redirect, &name);
+                            self.outputs.insert(name.to_string(), stream);
+                        }
+
+                        match self.outputs.get_mut(&*name).unwrap() {
+                            Stream::File(file) => file.write_all(data),
+                            Stream::Pipe(pipe, _) => pipe.write_all(data),
+                        }
+                    }
+                }
+            }
+        };
+
+        if let Err(e) = result {
+            fail(format_args!("write error: {}", error_reason(&e)));
+        }
+        Ok(())
+    }
+
+    fn open_stream(&mut self, redirect: &Redirect, name: &str) -> Stream {
+        let result = match redirect {
+            Redirect::File => File::create(name).map(|file| Stream::File(BufWriter::new(file))),
+            Redirect::Append => File::options()
+                .append(true)
+                .create(true)
+                .open(name)
+                .map(|file| Stream::File(BufWriter::new(file))),
+            Redirect::Pipe => {
+                // The output of the command comes after what was printed.
+                let _ = self.stdout.flush();
+                std::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(name)
+                    .stdin(Stdio::piped())
+                    .spawn()
+                    .map(|mut child| {
+                        let stdin = child.stdin.take().unwrap();
+                        Stream::Pipe(BufWriter::new(stdin), child)
+                    })
+            }
+        };
+
+        result.unwrap_or_else(|e| {
+            fail(format_args!(
+                "can't redirect to {}: {}",
+                name,
+                error_reason(&e)
+            ))
+        })
+    }
+
+    /// Close an output or an input. Returns the exit status of a command,
+    /// 0 for a file, and -1 if nothing was open with that name.
+    fn close(&mut self, name: &str) -> f64 {
+        let mut status = -1.0;
+
+        if let Some(stream) = self.outputs.remove(name) {
+            status = match stream {
+                Stream::File(mut file) => file.flush().map_or(-1.0, |_| 0.0),
+                Stream::Pipe(pipe, mut child) => {
+                    drop(pipe);
+                    child.wait().map_or(-1.0, status_code)
+                }
+            };
+        }
+
+        if let Some((reader, child)) = self.readers.remove(name) {
+            drop(reader);
+            status = match child {
+                Some(mut child) => child.wait().map_or(-1.0, status_code),
+                None => 0.0,
+            };
+        }
+
+        status
+    }
+
+    fn flush_all(&mut self) {
+        let _ = self.stdout.flush();
+        for stream in self.outputs.values_mut() {
+            let _ = match stream {
+                Stream::File(file) => file.flush(),
+                Stream::Pipe(pipe, _) => pipe.flush(),
+            };
+        }
+    }
+
+    fn exec_block(&mut self, stmts: &[Stmt]) -> Result<(), Flow> {
+        for stmt in stmts {
+            self.exec(stmt)?;
+        }
+        Ok(())
+    }
+
+    /// Run the body of a loop. Returns false if the loop must stop.
+    fn loop_body(&mut self, body: &Stmt) -> Result<bool, Flow> {
+        match self.exec(body) {
+            Ok(()) | Err(Flow::Continue) => Ok(true),
+            Err(Flow::Break) => Ok(false),
+            Err(flow) => Err(flow),
+        }
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> Result<(), Flow> {
+        match stmt {
+            Stmt::Expr(expr) => {
+                self.eval(expr)?;
+            }
+            Stmt::Print(args, redirect) => {
+                let mut text = String::new();
+                if args.is_empty() {
+                    text.push_str(&self.record);
+                } else {
+                    let ofs = self.special(OFS);
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            text.push_str(&ofs);
+                        }
+                        let value = self.eval(arg)?;
+                        text.push_str(&self.output_str(&value));
+                    }
+                }
+                text.push_str(&self.special(ORS));
+
+                self.write_output(redirect, text.as_bytes())?;
+            }
+            Stmt::Printf(args, redirect) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+
+                let format = self.to_str(&values[0]);
+                let text = sprintf(&format, &values[1..], &self.format_var(CONVFMT));
+                self.write_output(redirect, text.as_bytes())?;
+            }
+            Stmt::If(cond, then, otherwise) => {
+                if self.eval(cond)?.to_bool() {
+                    self.exec(then)?;
+                } else if let Some(otherwise) = otherwise {
+                    self.exec(otherwise)?;
+                }
+            }
+            Stmt::While(cond, body) => {
+                while self.eval(cond)?.to_bool() {
+                    if !self.loop_body(body)? {
+                        break;
+                    }
+                }
+            }
+            Stmt::Do(body, cond) => while self.loop_body(body)? && self.eval(cond)?.to_bool() {},
+            Stmt::For(init, cond, step, body) => {
+                if let Some(init) = init {
+                    self.eval(init)?;
+                }
+
+                loop {
+                    if let Some(cond) = cond {
+                        if !self.eval(cond)?.to_bool() {
+                            break;
+                        }
+                    }
+                    if !self.loop_body(body)? {
+                        break;
+                    }
+                    if let Some(step) = step {
+                        self.eval(step)?;
+                    }
+                }
+            }
+            Stmt::ForIn(var, array, body) => {
+                let array = self.array(*array);
+                let keys = array
+                    .borrow()
+                    .entries
+                    .iter()
+                    .map(|(key, _)| key.clone())
+                    .collect::<Vec<_>>();
+
+                for key in keys {
+                    // The elements deleted by the loop are skipped.
+                    if !array.borrow().contains(&key) {
+                        continue;
+                    }
+
+                    self.set_var(*var, Value::StrNum(key));
+                    if !self.loop_body(body)? {
+                        break;
+                    }
+                }
+            }
+            Stmt::Block(stmts) => self.exec_block(stmts)?,
+            Stmt::Delete(var, subscripts) => match subscripts {
+                Some(subscripts) => {
+                    let key = self.subscript(subscripts)?;
+                    self.array(*var).borrow_mut().remove(&key);
+                }
+                None => self.array(*var).borrow_mut().clear(),
+            },
+            Stmt::Next => return Err(Flow::Next),
+            Stmt::NextFile => return Err(Flow::NextFile),
+            Stmt::Break => return Err(Flow::Break),
+            Stmt::Continue => return Err(Flow::Continue),
+            Stmt::Exit(code) => {
+                if let Some(code) = code {
+                    self.exit_code = self.eval(code)?.to_num() as i32;
+                }
+                return Err(Flow::Exit);
+            }
+            Stmt::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Uninit,
+                };
+                return Err(Flow::Return(value));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run a rule for the current record, if its pattern matches.
+    fn run_rule(&mut self, index: usize, rule: &Rule) -> Result<(), Flow> {
+        let matched = match &rule.pattern {
+            Pattern::Begin | Pattern::End => false,
+            Pattern::All => true,
+            Pattern::Expr(expr) => self.eval(expr)?.to_bool(),
+            Pattern::Range(start, end) => {
+                if !self.ranges[index] {
+                    if !self.eval(start)?.to_bool() {
+                        return Ok(());
+                    }
+                    self.ranges[index] = true;
+                }
+
+                // The range can end on the record that starts it.
+                if self.eval(end)?.to_bool() {
+                    self.ranges[index] = false;
+                }
+                true
+            }
+        };
+
+        if !matched {
+            return Ok(());
+        }
+
+        match &rule.action {
+            Some(action) => self.exec_block(action),
+            None => self.exec(&Stmt::Print(Vec::new(), None)),
+        }
+    }
+
+    /// Run the program. Returns the exit code.
+    fn run(&mut self) -> i32 {
+        let program = self.program;
+        let mut exiting = false;
+
+        for rule in &program.rules {
+            if let (Pattern::Begin, Some(action)) = (&rule.pattern, &rule.action) {
+                if let Err(Flow::Exit) = self.exec_block(action) {
+                    exiting = true;
+                    break;
+                }
+            }
+        }
+
+        // Without other rules than `BEGIN`, the input isn't read.
+        let reads_input = program
+            .rules
+            .iter()
+            .any(|rule| !matches!(rule.pattern, Pattern::Begin));
+
+        if !exiting && reads_input {
+            'records: while let Some(record) = self.read_main_record() {
+                self.set_record(record.into());
+                self.increment(NR);
+                self.increment(FNR);
+
+                for (index, rule) in program.rules.iter().enumerate() {
+                    match self.run_rule(index, rule) {
+                        Err(Flow::Next) => continue 'records,
+                        Err(Flow::NextFile) => {
+                            self.input = None;
+                            continue 'records;
+                        }
+                        Err(Flow::Exit) => break 'records,
+                        _ => {}
+                    }
+                }
+            }
+        }
+
+        // `END` runs even after `exit`, unless it's the one exiting.
+        for rule in &program.rules {
+            if let (Pattern::End, Some(action)) = (&rule.pattern, &rule.action) {
+                if let Err(Flow::Exit) = self.exec_block(action) {
+                    break;
+                }
+            }
+        }
+
+        if let Err(e) = self.stdout.flush() {
+            fail(format_args!("write error: {}", error_reason(&e)));
+        }
+        let names = self
+            .outputs
+            .keys()
+            .chain(self.readers.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in names {
+            self.close(&name);
+        }
+
+        if self.was_error && self.exit_code == 0 {
+            -190
+        } else {
+            self.exit_code
+        }
+    }
+}
+
+fn run_awk(args: &[String]) -> i32 {
+    let with_value = ["-F", "-v", "-f"];
+    let start = operands_start(args, &with_value);
+    let Some((opts, _)) = parse_options(&args[..start], &with_value) else {
+        eprintln!("Usage: awk [-F FS] [-v VAR=VALUE]... {{PROGRAM | -f FILE...}} [FILE]...");
+        std::process::exit(-190);
+    };
+
+    let mut field_separator = None;
+    let mut assignments = Vec::new();
+    let mut sources = Vec::new();
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+
+        match opt.as_str() {
+            // As in other awks, `-Ft` means a tab.
+            "-F" if value == "t" => field_separator = Some("\t".to_owned()),
+            "-F" => field_separator = Some(unescape_string(&value)),
+            "-v" => assignments.push(value),
+            "-f" => match std::fs::read_to_string(&value) {
+                Ok(source) => sources.push(source),
+                Err(e) => fail(format_args!(
+                    "can't open file {}: {}",
+                    value,
+                    error_reason(&e)
+                )),
+            },
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-190);
+            }
+        }
+    }
+
+    let mut operands = &args[start..];
+    if sources.is_empty() {
+        let Some((source, rest)) = operands.split_first() else {
+            eprintln!("Usage: awk [-F FS] [-v VAR=VALUE]... {{PROGRAM | -f FILE...}} [FILE]...");
+            std::process::exit(-190);
+        };
+        sources.push(source.clone());
+        operands = rest;
+    }
+
+    let program = Parser::new(lex(&sources.join("\n"))).parse();
+    let mut interpreter = Interpreter::new(&program, operands);
+
+    if let Some(fs) = field_separator {
+        interpreter.globals[FS] = Slot::Value(Value::Str(fs.into()));
+    }
+    for assignment in assignments {
+        if !interpreter.assign_operand(&assignment) {
+            fail(format_args!("invalid -v argument: {}", assignment));
+        }
+    }
+
+    interpreter.run()
+}
+
+pub fn awk(args: &[String]) {
+    let args = args.to_vec();
+
+    // Recursive functions need a larger stack than the main thread's.
+    let interpreter = std::thread::Builder::new()
+        .stack_size(256 << 20)
+        .spawn(move || run_awk(&args));
+
+    match interpreter.map(|thread| thread.join()) {
+        Ok(Ok(code)) => std::process::exit(code),
+        _ => std::process::exit(-190),
+    }
+}
@@ -0,0 +1,200 @@
+//! The `base64`, `base32` and `basenc` commands, encoding data as text,
+//! or decoding it with `-d`.
+
+use std::io::{BufRead, BufWriter, Write};
+
+use crate::codec::*;
+use crate::utils::*;
+
+/// Write `data`, broken into lines of `cols` characters (unless 0).
+/// `column` is where the current line is.
+fn write_wrapped(
+    out: &mut impl Write,
+    mut data: &[u8],
+    cols: usize,
+    column: &mut usize,
+) -> std::io::Result<()> {
+    if cols == 0 {
+        return out.write_all(data);
+    }
+
+    while !data.is_empty() {
+        let len = (cols - *column).min(data.len());
+        out.write_all(&data[..len])?;
+        data = &data[len..];
+        *column += len;
+
+        if *column == cols {
+            out.write_all(b"\n")?;
+            *column = 0;
+        }
+    }
+
+    Ok(())
+}
+
+fn encode(
+    input: &mut dyn BufRead,
+    out: &mut impl Write,
+    encoding: Encoding,
+    cols: usize,
+) -> std::io::Result<bool> {
+    let mut encoder = Encoder::new(encoding);
+    let mut encoded = Vec::new();
+    let mut column = 0;
+
+    loop {
+        let chunk = match input.fill_buf() {
+            Ok([]) => break,
+            Ok(chunk) => chunk,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        encoded.clear();
+        encoder.update(chunk, &mut encoded);
+        let len = chunk.len();
+        input.consume(len);
+        write_wrapped(out, &encoded, cols, &mut column)?;
+    }
+
+    encoded.clear();
+    let complete = encoder.finish(&mut encoded);
+    write_wrapped(out, &encoded, cols, &mut column)?;
+
+    if column > 0 {
+        out.write_all(b"\n")?;
+    }
+
+    Ok(complete)
+}
+
+fn decode(
+    input: &mut dyn BufRead,
+    out: &mut impl Write,
+    encoding: Encoding,
+    ignore_garbage: bool,
+) -> std::io::Result<bool> {
+    let mut decoder = Decoder::new(encoding, ignore_garbage);
+    let mut decoded = Vec::new();
+
+    loop {
+        let chunk = match input.fill_buf() {
+            Ok([]) => break,
+            Ok(chunk) => chunk,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        decoded.clear();
+        let valid = decoder.update(chunk, &mut decoded);
+        let len = chunk.len();
+        input.consume(len);
+        out.write_all(&decoded)?;
+
+        if !valid {
+            return Ok(false);
+        }
+    }
+
+    decoded.clear();
+    let valid = decoder.finish(&mut decoded);
+    out.write_all(&decoded)?;
+    Ok(valid)
+}
+
+fn run(args: &[String], command: &str, code: i32, encoding: Option<Encoding>) {
+    let Some((opts, files)) = parse_options(args, &["-w", "--wrap"]) else {
+        eprintln!("Usage: {} [-d] [-i] [-w COLS] [FILE]", command);
+        std::process::exit(code);
+    };
+
+    let mut encoding = encoding;
+    let mut decoding = false;
+    let mut ignore_garbage = false;
+    let mut cols = 76;
+
+    for (opt, value) in opts {
+        match opt.as_str() {
+            "-d" | "--decode" => decoding = true,
+            "-i" | "--ignore-garbage" => ignore_garbage = true,
+            "-w" | "--wrap" => {
+                let value = value.unwrap_or_default();
+                cols = value.parse().unwrap_or_else(|_| {
+                    eprintln!("{}: invalid wrap size: '{}'", command, value);
+                    std::process::exit(code);
+                });
+            }
+            "--base64" if command == "basenc" => encoding = Some(Encoding::Base64),
+            "--base64url" if command == "basenc" => encoding = Some(Encoding::Base64Url),
+            "--base32" if command == "basenc" => encoding = Some(Encoding::Base32),
+            "--base32hex" if command == "basenc" => encoding = Some(Encoding::Base32Hex),
+            "--base16" if command == "basenc" => encoding = Some(Encoding::Base16),
+            "--z85" if command == "basenc" => encoding = Some(Encoding::Z85),
+            _ => {
+                println!("Invalid command");
+                std::process::exit(code);
+            }
+        }
+    }
+
+    let Some(encoding) = encoding else {
+        eprintln!("{}: missing encoding type", command);
+        std::process::exit(code);
+    };
+    let path = match files[..] {
+        [] => "-",
+        [path] => path.as_str(),
+        _ => {
+            eprintln!("{}: extra operand '{}'", command, files[1]);
+            std::process::exit(code);
+        }
+    };
+
+    let mut input = match open_input(path) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("{}: {}: {}", command, path, error_reason(&e));
+            std::process::exit(code);
+        }
+    };
+    let mut out = BufWriter::new(std::io::stdout().lock());
+
+    let result = if decoding {
+        decode(&mut input, &mut out, encoding, ignore_garbage)
+    } else {
+        encode(&mut input, &mut out, encoding, cols)
+    };
+    let result = result.and_then(|valid| out.flush().map(|_| valid));
+
+    match result {
+        Ok(true) => {}
+        Ok(false) if !decoding => {
+            eprintln!(
+                "{}: invalid input (length must be multiple of 4 characters)",
+                command
+            );
+            std::process::exit(code);
+        }
+        Ok(false) => {
+            eprintln!("{}: invalid input", command);
+            std::process::exit(code);
+        }
+        Err(e) => {
+            eprintln!("{}: {}: {}", command, path, error_reason(&e));
+            std::process::exit(code);
+        }
+    }
+}
+
+pub fn base64(args: &[String]) {
+    run(args, "base64", -240, Some(Encoding::Base64));
+}
+
+pub fn base32(args: &[String]) {
+    run(args, "base32", -241, Some(Encoding::Base32));
+}
+
+pub fn basenc(args: &[String]) {
+    run(args, "basenc", -242, None);
+}
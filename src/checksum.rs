@@ -0,0 +1,525 @@
+//! The checksum commands (`md5sum`, `sha1sum`, `sha256sum`, `sha512sum`,
+//! `b2sum` and `cksum`), printing or checking the hashes of files.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, BufWriter, Write};
+
+use crate::hash::*;
+use crate::utils::*;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Algorithm {
+    /// The CRC of `cksum`.
+    Crc,
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+    /// BLAKE2b, with the length of its digest in bytes.
+    Blake2b(usize),
+}
+
+impl Algorithm {
+    /// Get the algorithm named `name` with `cksum -a`.
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "crc" => Algorithm::Crc,
+            "md5" => Algorithm::Md5,
+            "sha1" => Algorithm::Sha1,
+            "sha256" => Algorithm::Sha256,
+            "sha512" => Algorithm::Sha512,
+            "blake2b" => Algorithm::Blake2b(64),
+            _ => return None,
+        })
+    }
+
+    /// Get the algorithm of the tag of a BSD style line.
+    fn from_tag(tag: &str) -> Option<Self> {
+        Some(match tag {
+            "MD5" => Algorithm::Md5,
+            "SHA1" => Algorithm::Sha1,
+            "SHA256" => Algorithm::Sha256,
+            "SHA512" => Algorithm::Sha512,
+            "BLAKE2b" => Algorithm::Blake2b(64),
+            _ => {
+                let bits: usize = tag.strip_prefix("BLAKE2b-")?.parse().ok()?;
+                if bits == 0 || bits > 512 || !bits.is_multiple_of(8) {
+                    return None;
+                }
+                Algorithm::Blake2b(bits / 8)
+            }
+        })
+    }
+
+    fn tag(self) -> String {
+        match self {
+            Algorithm::Crc => "CRC".to_string(),
+            Algorithm::Md5 => "MD5".to_string(),
+            Algorithm::Sha1 => "SHA1".to_string(),
+            Algorithm::Sha256 => "SHA256".to_string(),
+            Algorithm::Sha512 => "SHA512".to_string(),
+            Algorithm::Blake2b(64) => "BLAKE2b".to_string(),
+            Algorithm::Blake2b(len) => format!("BLAKE2b-{}", len * 8),
+        }
+    }
+
+    fn hasher(self) -> Box<dyn Hasher> {
+        match self {
+            Algorithm::Crc => Box::new(Crc::new()),
+            Algorithm::Md5 => Box::new(Md5::new()),
+            Algorithm::Sha1 => Box::new(Sha1::new()),
+            Algorithm::Sha256 => Box::new(Sha256::new()),
+            Algorithm::Sha512 => Box::new(Sha512::new()),
+            Algorithm::Blake2b(len) => Box::new(Blake2b::new(len)),
+        }
+    }
+
+    /// Get the length of the digests, in bytes.
+    fn digest_len(self) -> usize {
+        match self {
+            Algorithm::Crc => 4,
+            Algorithm::Md5 => 16,
+            Algorithm::Sha1 => 20,
+            Algorithm::Sha256 => 32,
+            Algorithm::Sha512 => 64,
+            Algorithm::Blake2b(len) => len,
+        }
+    }
+}
+
+/// A checksum command, with its options.
+struct Checksum {
+    command: &'static str,
+    code: i32,
+    algorithm: Algorithm,
+    /// Whether the length of BLAKE2b was given with `-l`.
+    fixed_len: bool,
+    binary: bool,
+    tag: bool,
+    quiet: bool,
+    status: bool,
+    strict: bool,
+    ignore_missing: bool,
+    warn: bool,
+}
+
+/// Escape the backslashes and line breaks of a file name, for the lines
+/// which start with a backslash.
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape_name(name: &str) -> Option<String> {
+    let mut unescaped = String::new();
+    let mut chars = name.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            unescaped.push(match chars.next()? {
+                '\\' => '\\',
+                'n' => '\n',
+                'r' => '\r',
+                _ => return None,
+            });
+        } else {
+            unescaped.push(c);
+        }
+    }
+
+    Some(unescaped)
+}
+
+/// Hash the file at `path` in chunks. Returns its digest and its size.
+fn hash_file(path: &str, algorithm: Algorithm) -> std::io::Result<(Vec<u8>, u64)> {
+    let mut input = open_input(path)?;
+    let mut hasher = algorithm.hasher();
+    let mut size = 0;
+
+    loop {
+        let chunk = match input.fill_buf() {
+            Ok([]) => break,
+            Ok(chunk) => chunk,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        hasher.update(chunk);
+        let len = chunk.len();
+        size += len as u64;
+        input.consume(len);
+    }
+
+    Ok((hasher.finish(), size))
+}
+
+fn hex(digest: &[u8]) -> String {
+    let mut hex = String::new();
+    for byte in digest {
+        let _ = write!(hex, "{:02x}", byte);
+    }
+    hex
+}
+
+impl Checksum {
+    /// Print the checksums of `files`. Returns false if one couldn't be read.
+    fn print(&self, files: &[&str], named: bool) -> bool {
+        let mut out = BufWriter::new(std::io::stdout().lock());
+        let mut ok = true;
+
+        for &path in files {
+            let (digest, size) = match hash_file(path, self.algorithm) {
+                Ok(result) => result,
+                Err(e) => {
+                    let _ = out.flush();
+                    eprintln!("{}: {}: {}", self.command, path, error_reason(&e));
+                    ok = false;
+                    continue;
+                }
+            };
+
+            let escaped = path.contains(['\\', '\n', '\r']);
+            let name = if escaped {
+                escape(path)
+            } else {
+                path.to_string()
+            };
+            let prefix = if escaped { "\\" } else { "" };
+
+            let result = if self.algorithm == Algorithm::Crc {
+                let crc = u32::from_be_bytes(digest.try_into().unwrap());
+                if named {
+                    writeln!(out, "{} {} {}", crc, size, path)
+                } else {
+                    writeln!(out, "{} {}", crc, size)
+                }
+            } else if self.tag {
+                let tag = self.algorithm.tag();
+                writeln!(out, "{}{} ({}) = {}", prefix, tag, name, hex(&digest))
+            } else {
+                let mode = if self.binary { '*' } else { ' ' };
+                writeln!(out, "{}{} {}{}", prefix, hex(&digest), mode, name)
+            };
+
+            if let Err(e) = result {
+                eprintln!("{}: {}", self.command, error_reason(&e));
+                std::process::exit(self.code);
+            }
+        }
+
+        let _ = out.flush();
+        ok
+    }
+
+    /// Parse a line of a checksum file, in the format printed with or
+    /// without `--tag`. Returns the algorithm, the digest and the file name.
+    fn parse_line(&self, line: &str) -> Option<(Algorithm, Vec<u8>, String)> {
+        let (escaped, line) = match line.strip_prefix('\\') {
+            Some(line) => (true, line),
+            None => (false, line),
+        };
+
+        let tagged = line.split_once(" (").and_then(|(tag, rest)| {
+            let (name, hex) = rest.rsplit_once(") = ")?;
+            Some((Algorithm::from_tag(tag)?, hex, name))
+        });
+
+        let (algorithm, hex, name) = match tagged {
+            Some((algorithm, hex, name)) => {
+                // The tags tell the length of BLAKE2b, and cksum accepts any.
+                let same = matches!(
+                    (algorithm, self.algorithm),
+                    (Algorithm::Blake2b(_), Algorithm::Blake2b(_))
+                );
+                if algorithm != self.algorithm && !same && self.command != "cksum" {
+                    return None;
+                }
+                (algorithm, hex, name)
+            }
+            None => {
+                if self.algorithm == Algorithm::Crc {
+                    return None;
+                }
+                let (hex, rest) = line.split_once(' ')?;
+                let name = rest.strip_prefix([' ', '*'])?;
+
+                // Without `-l`, the length of BLAKE2b is the one of the line.
+                let algorithm = match self.algorithm {
+                    Algorithm::Blake2b(_) if !self.fixed_len && (2..=128).contains(&hex.len()) => {
+                        Algorithm::Blake2b(hex.len() / 2)
+                    }
+                    algorithm => algorithm,
+                };
+                (algorithm, hex, name)
+            }
+        };
+
+        if hex.len() != algorithm.digest_len() * 2 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digest = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+
+        let name = if escaped {
+            unescape_name(name)?
+        } else {
+            name.to_string()
+        };
+        if name.is_empty() {
+            return None;
+        }
+
+        Some((algorithm, digest, name))
+    }
+
+    /// Check the files listed with their checksums in `list`.
+    /// Returns false if a file couldn't be read or doesn't match.
+    fn check(&self, list: &str) -> bool {
+        let list_name = if list == "-" { "standard input" } else { list };
+        let mut input = match open_input(list) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("{}: {}: {}", self.command, list, error_reason(&e));
+                return false;
+            }
+        };
+
+        let mut out = BufWriter::new(std::io::stdout().lock());
+        let mut line = Vec::new();
+        let mut line_number = 0;
+        let mut improper = 0;
+        let mut proper = 0;
+        let mut unread = 0;
+        let mut mismatched = 0;
+        let mut verified = 0;
+
+        loop {
+            line.clear();
+            match input.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    let _ = out.flush();
+                    eprintln!("{}: {}: {}", self.command, list, error_reason(&e));
+                    return false;
+                }
+            }
+            line_number += 1;
+
+            let text = String::from_utf8_lossy(&line);
+            let text = text.strip_suffix('\n').unwrap_or(&text);
+            let Some((algorithm, expected, name)) = self.parse_line(text) else {
+                improper += 1;
+                if self.warn {
+                    let _ = out.flush();
+                    eprintln!(
+                        "{}: {}: {}: improperly formatted {} checksum line",
+                        self.command,
+                        list_name,
+                        line_number,
+                        self.algorithm.tag()
+                    );
+                }
+                continue;
+            };
+            proper += 1;
+
+            let shown = if name.contains(['\n', '\r']) {
+                format!("\\{}", escape(&name))
+            } else {
+                name.clone()
+            };
+
+            let digest = match hash_file(&name, algorithm) {
+                Ok((digest, _)) => digest,
+                Err(e) if self.ignore_missing && e.kind() == std::io::ErrorKind::NotFound => {
+                    continue;
+                }
+                Err(e) => {
+                    let _ = out.flush();
+                    eprintln!("{}: {}: {}", self.command, name, error_reason(&e));
+                    unread += 1;
+                    if !self.status {
+                        let _ = writeln!(out, "{}: FAILED open or read", shown);
+                    }
+                    continue;
+                }
+            };
+
+            verified += 1;
+            if digest != expected {
+                mismatched += 1;
+                if !self.status {
+                    let _ = writeln!(out, "{}: FAILED", shown);
+                }
+            } else if !self.status && !self.quiet {
+                let _ = writeln!(out, "{}: OK", shown);
+            }
+        }
+
+        let _ = out.flush();
+
+        if proper == 0 {
+            eprintln!(
+                "{}: {}: no properly formatted checksum lines found",
+                self.command, list_name
+            );
+            return false;
+        }
+
+        if !self.status {
+            let warnings = [
+                (
+                    improper,
+                    "line is improperly formatted",
+                    "lines are improperly formatted",
+                ),
+                (
+                    unread,
+                    "listed file could not be read",
+                    "listed files could not be read",
+                ),
+                (
+                    mismatched,
+                    "computed checksum did NOT match",
+                    "computed checksums did NOT match",
+                ),
+            ];
+            for (count, one, many) in warnings {
+                if count > 0 {
+                    let message = if count == 1 { one } else { many };
+                    eprintln!("{}: WARNING: {} {}", self.command, count, message);
+                }
+            }
+        }
+
+        if self.ignore_missing && verified == 0 {
+            eprintln!("{}: {}: no file was verified", self.command, list_name);
+            return false;
+        }
+
+        unread == 0 && mismatched == 0 && !(self.strict && improper > 0)
+    }
+}
+
+fn checksum(args: &[String], command: &'static str, code: i32, algorithm: Algorithm) {
+    let with_value = ["-a", "-l", "--algorithm", "--length"];
+    let Some((opts, files)) = parse_options(args, &with_value) else {
+        eprintln!("Usage: {} [-b] [-c] [--tag] [FILE]...", command);
+        std::process::exit(code);
+    };
+
+    let mut checksum = Checksum {
+        command,
+        code,
+        algorithm,
+        fixed_len: false,
+        binary: false,
+        tag: false,
+        quiet: false,
+        status: false,
+        strict: false,
+        ignore_missing: false,
+        warn: false,
+    };
+    let mut check = false;
+    let mut untagged = false;
+    let mut length = None;
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+        let blake2b = matches!(checksum.algorithm, Algorithm::Blake2b(_));
+
+        match opt.as_str() {
+            "-b" | "--binary" => checksum.binary = true,
+            "-t" | "--text" => checksum.binary = false,
+            "-c" | "--check" => check = true,
+            "-w" | "--warn" => checksum.warn = true,
+            "--tag" => checksum.tag = true,
+            "--untagged" if command == "cksum" => untagged = true,
+            "--quiet" => checksum.quiet = true,
+            "--status" => checksum.status = true,
+            "--strict" => checksum.strict = true,
+            "--ignore-missing" => checksum.ignore_missing = true,
+            "-a" | "--algorithm" if command == "cksum" => {
+                checksum.algorithm = Algorithm::from_name(&value).unwrap_or_else(|| {
+                    eprintln!(
+                        "{}: invalid argument '{}' for '--algorithm'",
+                        command, value
+                    );
+                    std::process::exit(code);
+                });
+            }
+            "-l" | "--length" if blake2b || command == "cksum" => {
+                length = match value.parse::<usize>() {
+                    Ok(bits) if bits > 0 && bits <= 512 && bits.is_multiple_of(8) => Some(bits / 8),
+                    _ => {
+                        eprintln!("{}: invalid length: '{}'", command, value);
+                        std::process::exit(code);
+                    }
+                };
+            }
+            _ => {
+                println!("Invalid command");
+                std::process::exit(code);
+            }
+        }
+    }
+
+    if let (Some(length), Algorithm::Blake2b(_)) = (length, checksum.algorithm) {
+        checksum.algorithm = Algorithm::Blake2b(length);
+        checksum.fixed_len = true;
+    }
+    // cksum prints tagged lines, except for its CRC.
+    if command == "cksum" {
+        checksum.tag = !untagged;
+    }
+
+    let named = !files.is_empty();
+    let files: Vec<&str> = if named {
+        files.iter().map(|file| file.as_str()).collect()
+    } else {
+        vec!["-"]
+    };
+
+    let ok = if check {
+        let mut ok = true;
+        for list in &files {
+            ok &= checksum.check(list);
+        }
+        ok
+    } else {
+        checksum.print(&files, named)
+    };
+
+    if !ok {
+        std::process::exit(code);
+    }
+}
+
+pub fn md5sum(args: &[String]) {
+    checksum(args, "md5sum", -230, Algorithm::Md5);
+}
+
+pub fn sha1sum(args: &[String]) {
+    checksum(args, "sha1sum", -231, Algorithm::Sha1);
+}
+
+pub fn sha256sum(args: &[String]) {
+    checksum(args, "sha256sum", -232, Algorithm::Sha256);
+}
+
+pub fn sha512sum(args: &[String]) {
+    checksum(args, "sha512sum", -233, Algorithm::Sha512);
+}
+
+pub fn b2sum(args: &[String]) {
+    checksum(args, "b2sum", -234, Algorithm::Blake2b(64));
+}
+
+pub fn cksum(args: &[String]) {
+    checksum(args, "cksum", -235, Algorithm::Crc);
+}
@@ -0,0 +1,225 @@
+//! The `cmp` command, comparing two files byte by byte.
+
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::os::unix::fs::MetadataExt;
+
+use crate::utils::*;
+
+fn usage() -> ! {
+    eprintln!("Usage: cmp [-l] [-s] [-n N] [-i SKIP1[:SKIP2]] FILE1 [FILE2]");
+    std::process::exit(-210);
+}
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("cmp: {}", message);
+    std::process::exit(-210);
+}
+
+/// How the differences are reported.
+#[derive(PartialEq)]
+enum Report {
+    /// The first difference.
+    First,
+    /// Every difference, with `-l`.
+    All,
+    /// Nothing, with `-s`.
+    Silent,
+}
+
+fn parse_count(count: &str) -> u64 {
+    parse_size(count).unwrap_or_else(|| fail(format_args!("invalid byte count '{}'", count)))
+}
+
+fn open(path: &str) -> Box<dyn BufRead> {
+    let input: Box<dyn Read> = if path == "-" {
+        Box::new(std::io::stdin())
+    } else {
+        match std::fs::File::open(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => fail(format_args!("{}: {}", path, error_reason(&e))),
+        }
+    };
+
+    Box::new(BufReader::with_capacity(64 * 1024, input))
+}
+
+/// Get the size of a regular file, if it's one.
+fn regular_size(path: &str) -> Option<u64> {
+    let metadata = std::fs::metadata(path).ok().filter(|_| path != "-")?;
+    metadata.is_file().then_some(metadata.len())
+}
+
+pub fn cmp(args: &[String]) {
+    let Some((opts, files)) = parse_options(args, &["-n", "-i", "--bytes", "--ignore-initial"])
+    else {
+        usage();
+    };
+
+    let mut report = Report::First;
+    let mut limit = None;
+    let mut skips = [0, 0];
+
+    for (opt, value) in opts {
+        match opt.as_str() {
+            "-l" | "--verbose" => report = Report::All,
+            "-s" | "--quiet" | "--silent" => report = Report::Silent,
+            "-n" | "--bytes" => limit = Some(parse_count(&value.unwrap_or_default())),
+            "-i" | "--ignore-initial" => {
+                let value = value.unwrap_or_default();
+                skips = match value.split_once(':') {
+                    Some((first, second)) => [parse_count(first), parse_count(second)],
+                    None => [parse_count(&value); 2],
+                };
+            }
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-210);
+            }
+        }
+    }
+
+    // The skips can also be given as operands.
+    let (names, operand_skips) = files.split_at(files.len().min(2));
+    let names = match names {
+        [first] => [first.as_str(), "-"],
+        [first, second] => [first.as_str(), second.as_str()],
+        _ => usage(),
+    };
+    if operand_skips.len() > 2 {
+        usage();
+    }
+    for (skip, operand) in skips.iter_mut().zip(operand_skips) {
+        *skip = parse_count(operand);
+    }
+
+    // A file is always equal to itself.
+    let ids = names.map(|name| {
+        std::fs::metadata(name)
+            .ok()
+            .filter(|_| name != "-")
+            .map(|metadata| (metadata.dev(), metadata.ino()))
+    });
+    if ids[0].is_some() && ids[0] == ids[1] && skips[0] == skips[1] {
+        std::process::exit(0);
+    }
+
+    let mut inputs = names.map(open);
+    for i in 0..2 {
+        if let Err(e) = std::io::copy(&mut inputs[i].as_mut().take(skips[i]), &mut std::io::sink())
+        {
+            fail(format_args!("{}: {}", names[i], error_reason(&e)));
+        }
+    }
+
+    // The offsets of `-l` are aligned on the largest one possible.
+    let sizes = [0, 1].map(|i| regular_size(names[i]).map(|size| size.saturating_sub(skips[i])));
+    let largest = sizes.into_iter().flatten().chain(limit).min().unwrap_or(0);
+    let width = largest.to_string().len();
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut offset = 0u64;
+    let mut line = 1u64;
+    let mut different = false;
+    let mut last = b'\n';
+
+    let result = (|| -> Result<Option<usize>, (usize, std::io::Error)> {
+        loop {
+            let remaining = limit.map_or(u64::MAX, |limit| limit - offset);
+            if remaining == 0 {
+                return Ok(None);
+            }
+
+            let [first, second] = &mut inputs;
+            let a = first.fill_buf().map_err(|e| (0, e))?;
+            let b = second.fill_buf().map_err(|e| (1, e))?;
+
+            let len = a
+                .len()
+                .min(b.len())
+                .min(remaining.try_into().unwrap_or(usize::MAX));
+            if len == 0 {
+                // The file which ended first is reported, unless both did.
+                return Ok(match (a.is_empty(), b.is_empty()) {
+                    (true, false) => Some(0),
+                    (false, true) => Some(1),
+                    _ => None,
+                });
+            }
+
+            let (a, b) = (&a[..len], &b[..len]);
+            if report != Report::All {
+                if let Some(i) = a.iter().zip(b).position(|(x, y)| x != y) {
+                    different = true;
+                    if report == Report::First {
+                        line += a[..i].iter().filter(|&&byte| byte == b'\n').count() as u64;
+                        let _ = writeln!(
+                            out,
+                            "{} {} differ: char {}, line {}",
+                            names[0],
+                            names[1],
+                            offset + i as u64 + 1,
+                            line
+                        );
+                    }
+                    return Ok(None);
+                }
+                line += a.iter().filter(|&&byte| byte == b'\n').count() as u64;
+            } else {
+                for (i, (x, y)) in a.iter().zip(b).enumerate() {
+                    if x != y {
+                        different = true;
+                        writeln!(
+                            out,
+                            "{:>width$} {:3o} {:3o}",
+                            offset + i as u64 + 1,
+                            x,
+                            y,
+                            width = width
+                        )
+                        .map_err(|e| (2, e))?;
+                    }
+                }
+            }
+
+            last = a[len - 1];
+            offset += len as u64;
+            first.consume(len);
+            second.consume(len);
+        }
+    })();
+
+    if let Err(e) = out.flush() {
+        fail(format_args!("{}", error_reason(&e)));
+    }
+
+    match result {
+        Ok(None) => {}
+        Ok(Some(shorter)) => {
+            different = true;
+            if report != Report::Silent {
+                if offset == 0 {
+                    eprintln!("cmp: EOF on {} which is empty", names[shorter]);
+                } else if report == Report::All {
+                    eprintln!("cmp: EOF on {} after byte {}", names[shorter], offset);
+                } else if last == b'\n' {
+                    eprintln!(
+                        "cmp: EOF on {} after byte {}, line {}",
+                        names[shorter],
+                        offset,
+                        line - 1
+                    );
+                } else {
+                    eprintln!(
+                        "cmp: EOF on {} after byte {}, in line {}",
+                        names[shorter], offset, line
+                    );
+                }
+            }
+        }
+        Err((2, e)) => fail(format_args!("{}", error_reason(&e))),
+        Err((i, e)) => fail(format_args!("{}: {}", names[i], error_reason(&e))),
+    }
+
+    std::process::exit(i32::from(different));
+}
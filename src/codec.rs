@@ -0,0 +1,242 @@
+//! Encodings of binary data as text (base64, base32, base16 and Z85),
+//! shared by `base64`, `base32` and `basenc`.
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE64_URL: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+const BASE32: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+const BASE32_HEX: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUV";
+const BASE16: &[u8] = b"0123456789ABCDEF";
+const Z85: &[u8] =
+    b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ.-:+=^!/*?&<>()[]{}@%$#";
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Encoding {
+    Base64,
+    /// Base64 with the URL and file name safe alphabet.
+    Base64Url,
+    Base32,
+    /// Base32 with the extended hex alphabet.
+    Base32Hex,
+    Base16,
+    /// ZeroMQ's base85, which encodes blocks of 4 bytes, without padding.
+    Z85,
+}
+
+impl Encoding {
+    fn alphabet(self) -> &'static [u8] {
+        match self {
+            Encoding::Base64 => BASE64,
+            Encoding::Base64Url => BASE64_URL,
+            Encoding::Base32 => BASE32,
+            Encoding::Base32Hex => BASE32_HEX,
+            Encoding::Base16 => BASE16,
+            Encoding::Z85 => Z85,
+        }
+    }
+
+    /// Get the size of the blocks of bytes, and of the characters
+    /// encoding them.
+    fn blocks(self) -> (usize, usize) {
+        match self {
+            Encoding::Base64 | Encoding::Base64Url => (3, 4),
+            Encoding::Base32 | Encoding::Base32Hex => (5, 8),
+            Encoding::Base16 => (1, 2),
+            Encoding::Z85 => (4, 5),
+        }
+    }
+
+    /// Get the number of bits encoded by a character, for the encodings
+    /// whose alphabet is a power of two.
+    fn bits(self) -> usize {
+        self.alphabet().len().trailing_zeros() as usize
+    }
+
+    /// Get the value of the character `c`, if it's in the alphabet.
+    fn value(self, c: u8) -> Option<u64> {
+        // Base16 is also accepted in lowercase.
+        let c = if self == Encoding::Base16 {
+            c.to_ascii_uppercase()
+        } else {
+            c
+        };
+
+        self.alphabet()
+            .iter()
+            .position(|&digit| digit == c)
+            .map(|value| value as u64)
+    }
+
+    /// Encode `block`, which may be shorter than a block at the end of the
+    /// input, completed with padding.
+    fn encode_block(self, block: &[u8], out: &mut Vec<u8>) {
+        let (bytes, chars) = self.blocks();
+        let alphabet = self.alphabet();
+        let value = block
+            .iter()
+            .chain(std::iter::repeat(&0))
+            .take(bytes)
+            .fold(0u64, |value, &byte| value << 8 | byte as u64);
+
+        if self == Encoding::Z85 {
+            for i in (0..chars).rev() {
+                out.push(alphabet[(value / 85u64.pow(i as u32) % 85) as usize]);
+            }
+            return;
+        }
+
+        let bits = self.bits();
+        let used = (block.len() * 8).div_ceil(bits);
+        for i in 0..used {
+            let shift = bytes * 8 - bits * (i + 1);
+            out.push(alphabet[(value >> shift) as usize & (alphabet.len() - 1)]);
+        }
+        out.resize(out.len() + chars - used, b'=');
+    }
+}
+
+/// Encodes a stream, block by block.
+pub struct Encoder {
+    encoding: Encoding,
+    pending: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new(encoding: Encoding) -> Self {
+        Encoder {
+            encoding,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Encode the complete blocks of `data` (after the bytes pending from
+    /// the previous call) into `out`.
+    pub fn update(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        let (bytes, _) = self.encoding.blocks();
+        self.pending.extend_from_slice(data);
+
+        let mut blocks = self.pending.chunks_exact(bytes);
+        for block in &mut blocks {
+            self.encoding.encode_block(block, out);
+        }
+
+        let rest = blocks.remainder().len();
+        self.pending.drain(..self.pending.len() - rest);
+    }
+
+    /// Encode the last, incomplete block. Returns false if the encoding
+    /// can't pad it (Z85).
+    pub fn finish(&mut self, out: &mut Vec<u8>) -> bool {
+        if self.pending.is_empty() {
+            return true;
+        } else if self.encoding == Encoding::Z85 {
+            return false;
+        }
+
+        self.encoding.encode_block(&self.pending, out);
+        self.pending.clear();
+        true
+    }
+}
+
+/// Decodes a stream, block by block. Line breaks are ignored, like any
+/// character outside of the alphabet when ignoring garbage.
+pub struct Decoder {
+    encoding: Encoding,
+    ignore_garbage: bool,
+    values: Vec<u64>,
+    padding: usize,
+}
+
+impl Decoder {
+    pub fn new(encoding: Encoding, ignore_garbage: bool) -> Self {
+        Decoder {
+            encoding,
+            ignore_garbage,
+            values: Vec::new(),
+            padding: 0,
+        }
+    }
+
+    /// Decode the block of the characters read, which may be incomplete
+    /// when it's padded. Returns false if it's invalid.
+    fn decode_block(&mut self, out: &mut Vec<u8>) -> bool {
+        let (bytes, chars) = self.encoding.blocks();
+        let len = self.values.len();
+        let values = std::mem::take(&mut self.values);
+        self.padding = 0;
+
+        if self.encoding == Encoding::Z85 {
+            let value = values.iter().fold(0, |value, &digit| value * 85 + digit);
+            if len != chars || value > u32::MAX as u64 {
+                return false;
+            }
+            out.extend_from_slice(&(value as u32).to_be_bytes());
+            return true;
+        }
+
+        // The characters must encode whole bytes, and nothing more.
+        let bits = self.encoding.bits();
+        let decoded = len * bits / 8;
+        if decoded == 0 || (decoded * 8).div_ceil(bits) != len {
+            return false;
+        }
+
+        let value =
+            values.iter().fold(0, |value, &digit| value << bits | digit) << ((chars - len) * bits);
+        out.extend((0..decoded).map(|i| (value >> ((bytes - 1 - i) * 8)) as u8));
+        true
+    }
+
+    /// Decode `data` into `out`. Returns false when it's invalid, after
+    /// decoding the blocks before.
+    pub fn update(&mut self, data: &[u8], out: &mut Vec<u8>) -> bool {
+        let (_, chars) = self.encoding.blocks();
+
+        for &c in data {
+            if c == b'\n' {
+                continue;
+            }
+
+            if c == b'=' && self.encoding != Encoding::Z85 {
+                if self.values.is_empty() {
+                    return false;
+                }
+                self.padding += 1;
+                if self.values.len() + self.padding == chars && !self.decode_block(out) {
+                    return false;
+                }
+                continue;
+            }
+
+            let Some(value) = self.encoding.value(c) else {
+                if self.ignore_garbage {
+                    continue;
+                }
+                return false;
+            };
+            if self.padding > 0 {
+                return false;
+            }
+
+            self.values.push(value);
+            if self.values.len() == chars && !self.decode_block(out) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Decode the last block, if the input ended in the middle of one,
+    /// which is invalid.
+    pub fn finish(&mut self, out: &mut Vec<u8>) -> bool {
+        if self.values.is_empty() {
+            return true;
+        }
+
+        if self.encoding != Encoding::Z85 {
+            self.decode_block(out);
+        }
+        false
+    }
+}
@@ -0,0 +1,105 @@
+//! The `comm` command, comparing two sorted files line by line.
+
+use std::{
+    cmp::Ordering,
+    io::{BufWriter, Write},
+};
+
+use crate::fields::*;
+use crate::utils::*;
+
+pub fn comm(args: &[String]) {
+    let Some((opts, files)) = parse_options(args, &["--output-delimiter"]) else {
+        eprintln!("Usage: comm [-123] [-z] FILE1 FILE2");
+        std::process::exit(-161);
+    };
+
+    let mut shown = [true; 3];
+    let mut delimiter = b'\n';
+    let mut column_separator = String::from("\t");
+
+    for (opt, value) in opts {
+        match opt.as_str() {
+            "-1" => shown[0] = false,
+            "-2" => shown[1] = false,
+            "-3" => shown[2] = false,
+            "-z" | "--zero-terminated" => delimiter = b'\0',
+            "--output-delimiter" => column_separator = value.unwrap_or_default(),
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-161);
+            }
+        }
+    }
+
+    let [first, second] = files.as_slice() else {
+        eprintln!("Usage: comm [-123] [-z] FILE1 FILE2");
+        std::process::exit(-161);
+    };
+
+    let mut inputs = [first, second].map(|path| match open_input(path) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("comm: {}: {}", path, error_reason(&e));
+            std::process::exit(-161);
+        }
+    });
+
+    // Every column is indented by the separators of the columns
+    // shown before it.
+    let prefixes = [0, 1, 2]
+        .map(|column| column_separator.repeat(shown[..column].iter().filter(|&&s| s).count()));
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut print = |column: usize, line: &[u8]| -> std::io::Result<()> {
+        if !shown[column] {
+            return Ok(());
+        }
+
+        out.write_all(prefixes[column].as_bytes())?;
+        out.write_all(line)?;
+        out.write_all(&[delimiter])
+    };
+
+    let mut lines = [Vec::new(), Vec::new()];
+
+    let result = (|| -> std::io::Result<()> {
+        let mut has_line = [false; 2];
+        for i in 0..2 {
+            has_line[i] = read_line(inputs[i].as_mut(), delimiter, &mut lines[i])?;
+        }
+
+        while has_line[0] || has_line[1] {
+            let order = match has_line {
+                [true, true] => lines[0].cmp(&lines[1]),
+                [true, false] => Ordering::Less,
+                _ => Ordering::Greater,
+            };
+
+            match order {
+                Ordering::Less => print(0, &lines[0])?,
+                Ordering::Greater => print(1, &lines[1])?,
+                Ordering::Equal => print(2, &lines[0])?,
+            }
+
+            // Advance the files whose line was printed.
+            for i in 0..2 {
+                let advance = match i {
+                    0 => order != Ordering::Greater,
+                    _ => order != Ordering::Less,
+                };
+
+                if advance {
+                    has_line[i] = read_line(inputs[i].as_mut(), delimiter, &mut lines[i])?;
+                }
+            }
+        }
+
+        Ok(())
+    })();
+
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        eprintln!("comm: {}", error_reason(&e));
+        std::process::exit(-161);
+    }
+}
@@ -0,0 +1,732 @@
+//! The `cpio` command, copying files into an archive (`-o`), out of it
+//! (`-i`), or listing it (`-t`). Archives are in the "new" ASCII format of
+//! SVR4 (newc) or in the portable ASCII format of POSIX (odc).
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufWriter, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::archive::*;
+use crate::utils::*;
+
+const NEWC_MAGIC: &[u8] = b"070701";
+/// The newc format with checksums, which aren't checked.
+const CRC_MAGIC: &[u8] = b"070702";
+const ODC_MAGIC: &[u8] = b"070707";
+/// The name of the member ending an archive.
+const TRAILER: &str = "TRAILER!!!";
+/// Archives are padded to blocks, which are counted in messages.
+const BLOCK: u64 = 512;
+
+fn usage() -> ! {
+    eprintln!("Usage: cpio -o [-H newc|odc] [-v] [-0] [-F ARCHIVE] < NAMES");
+    eprintln!("       cpio -i|-t [-d] [-m] [-u] [-v] [-F ARCHIVE] [PATTERN]...");
+    std::process::exit(-270);
+}
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("cpio: {}", message);
+    std::process::exit(-270);
+}
+
+fn data_error(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Newc,
+    Odc,
+}
+
+/// A member of an archive. A file with several links has a member for each
+/// of its names, which are told apart by their device and inode numbers.
+struct Member {
+    entry: Entry,
+    format: Format,
+    file: (u64, u64),
+    links: u64,
+}
+
+/// Reads an archive, counting the bytes read, since the headers and data of
+/// newc members are aligned to 4 bytes.
+struct Reader<'a> {
+    input: &'a mut dyn Read,
+    count: u64,
+}
+
+impl Read for Reader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.input.read(buf)?;
+        self.count += read as u64;
+        Ok(read)
+    }
+}
+
+impl Reader<'_> {
+    fn read_all(&mut self, buf: &mut [u8]) -> std::io::Result<()> {
+        self.read_exact(buf).map_err(|e| match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => data_error("premature end of archive"),
+            _ => e,
+        })
+    }
+
+    fn skip(&mut self, len: u64) -> std::io::Result<()> {
+        if std::io::copy(&mut self.take(len), &mut std::io::sink())? < len {
+            return Err(data_error("premature end of archive"));
+        }
+        Ok(())
+    }
+
+    /// Skip the padding of newc members.
+    fn align(&mut self, format: Format) -> std::io::Result<()> {
+        match format {
+            Format::Newc => self.skip(self.count.next_multiple_of(4) - self.count),
+            Format::Odc => Ok(()),
+        }
+    }
+
+    /// Read the numeric fields of a header, of the given widths.
+    fn numbers<const N: usize>(
+        &mut self,
+        widths: [usize; N],
+        radix: u32,
+    ) -> std::io::Result<[u64; N]> {
+        let mut numbers = [0; N];
+
+        for (number, width) in numbers.iter_mut().zip(widths) {
+            let mut field = vec![0; width];
+            self.read_all(&mut field)?;
+            *number = std::str::from_utf8(&field)
+                .ok()
+                .and_then(|field| u64::from_str_radix(field, radix).ok())
+                .ok_or_else(|| data_error("invalid header: bad number"))?;
+        }
+
+        Ok(numbers)
+    }
+
+    /// Read the header of a member and its name. Returns `None` at the
+    /// trailer.
+    fn header(&mut self) -> std::io::Result<Option<Member>> {
+        let mut magic = [0; 6];
+        self.read_all(&mut magic)?;
+
+        let (format, file, mode, uid, gid, links, mtime, device, name_len, size) = match &magic[..]
+        {
+            NEWC_MAGIC | CRC_MAGIC => {
+                let [ino, mode, uid, gid, links, mtime, size, major, minor, rmajor, rminor, name_len, _] =
+                    self.numbers([8; 13], 16)?;
+                let device = (rmajor as u32, rminor as u32);
+                let file = (major << 32 | minor, ino);
+                (
+                    Format::Newc,
+                    file,
+                    mode,
+                    uid,
+                    gid,
+                    links,
+                    mtime,
+                    device,
+                    name_len,
+                    size,
+                )
+            }
+            ODC_MAGIC => {
+                let [dev, ino, mode, uid, gid, links, rdev, mtime, name_len, size] =
+                    self.numbers([6, 6, 6, 6, 6, 6, 6, 11, 6, 11], 8)?;
+                let device = (libc::major(rdev), libc::minor(rdev));
+                (
+                    Format::Odc,
+                    (dev, ino),
+                    mode,
+                    uid,
+                    gid,
+                    links,
+                    mtime,
+                    device,
+                    name_len,
+                    size,
+                )
+            }
+            _ => return Err(data_error("invalid header: bad magic number")),
+        };
+
+        let mut name = vec![0; name_len as usize];
+        self.read_all(&mut name)?;
+        self.align(format)?;
+        let end = name
+            .iter()
+            .position(|&byte| byte == 0)
+            .unwrap_or(name.len());
+        let name = String::from_utf8_lossy(&name[..end]).into_owned();
+        if name == TRAILER {
+            return Ok(None);
+        }
+
+        let kind = match mode as u32 & libc::S_IFMT {
+            libc::S_IFDIR => Kind::Directory,
+            libc::S_IFLNK => {
+                let mut target = Vec::new();
+                self.take(size).read_to_end(&mut target)?;
+                if (target.len() as u64) < size {
+                    return Err(data_error("premature end of archive"));
+                }
+                self.align(format)?;
+                Kind::Symlink(String::from_utf8_lossy(&target).into_owned())
+            }
+            libc::S_IFCHR => Kind::CharDevice,
+            libc::S_IFBLK => Kind::BlockDevice,
+            libc::S_IFIFO => Kind::Fifo,
+            _ => Kind::File,
+        };
+        // Only regular files have data.
+        if !matches!(kind, Kind::File | Kind::Symlink(_)) && size > 0 {
+            self.skip(size)?;
+            self.align(format)?;
+        }
+
+        let entry = Entry {
+            name,
+            size: if kind == Kind::File { size } else { 0 },
+            kind,
+            mode: mode as u32 & 0o7777,
+            uid: uid as u32,
+            gid: gid as u32,
+            user: String::new(),
+            group: String::new(),
+            mtime: mtime as i64,
+            device,
+        };
+
+        Ok(Some(Member {
+            entry,
+            format,
+            file,
+            links,
+        }))
+    }
+}
+
+/// Read an archive, calling `visit` with every member and its data. Returns
+/// the number of bytes read.
+fn read_archive(
+    input: &mut dyn Read,
+    mut visit: impl FnMut(&Member, &mut dyn Read),
+) -> std::io::Result<u64> {
+    let mut reader = Reader { input, count: 0 };
+
+    while let Some(member) = reader.header()? {
+        let mut data = (&mut reader).take(member.entry.size);
+        visit(&member, &mut data);
+
+        // The data which wasn't read is skipped.
+        let left = data.limit();
+        reader.skip(left)?;
+        if member.entry.size > 0 {
+            reader.align(member.format)?;
+        }
+    }
+
+    Ok(reader.count)
+}
+
+/// Writes an archive.
+struct Writer<W: Write> {
+    out: W,
+    format: Format,
+    written: u64,
+}
+
+impl<W: Write> Writer<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.out.write_all(data)?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    /// Pad the archive with zeros to a multiple of `align` bytes.
+    fn pad(&mut self, align: u64) -> std::io::Result<()> {
+        let len = self.written.next_multiple_of(align) - self.written;
+        self.write(&vec![0; len as usize])
+    }
+
+    /// Write the header of `entry`, the `ino` file of the archive, which
+    /// has `links` names. The target of symlinks is written too.
+    fn header(&mut self, entry: &Entry, ino: u64, links: u64) -> std::io::Result<()> {
+        let kind = match entry.kind {
+            Kind::File | Kind::HardLink(_) => libc::S_IFREG,
+            Kind::Directory => libc::S_IFDIR,
+            Kind::Symlink(_) => libc::S_IFLNK,
+            Kind::CharDevice => libc::S_IFCHR,
+            Kind::BlockDevice => libc::S_IFBLK,
+            Kind::Fifo => libc::S_IFIFO,
+        };
+        let mode = kind | entry.mode;
+        let size = match &entry.kind {
+            Kind::Symlink(target) => target.len() as u64,
+            _ => entry.size,
+        };
+        let name_len = entry.name.len() as u64 + 1;
+        let (major, minor) = (entry.device.0 as u64, entry.device.1 as u64);
+
+        // The numbers too large for their fields are truncated, except the
+        // size.
+        let header = match self.format {
+            Format::Newc => {
+                if size > u32::MAX as u64 {
+                    return Err(std::io::Error::other(
+                        "file too large for the archive format",
+                    ));
+                }
+                let fields = [
+                    ino,
+                    mode as u64,
+                    entry.uid as u64,
+                    entry.gid as u64,
+                    links,
+                    entry.mtime as u64,
+                    size,
+                    0,
+                    0,
+                    major,
+                    minor,
+                    name_len,
+                    0,
+                ];
+                let fields: String = fields
+                    .iter()
+                    .map(|&field| format!("{:08x}", field & 0xffff_ffff))
+                    .collect();
+                format!("070701{}", fields)
+            }
+            Format::Odc => {
+                const SHORT: u64 = 0o777777;
+                const LONG: u64 = 0o77777777777;
+                if size > LONG {
+                    return Err(std::io::Error::other(
+                        "file too large for the archive format",
+                    ));
+                }
+                let rdev = libc::makedev(major as u32, minor as u32);
+                format!(
+                    "070707{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:011o}{:06o}{:011o}",
+                    0,
+                    ino & SHORT,
+                    mode as u64 & SHORT,
+                    entry.uid as u64 & SHORT,
+                    entry.gid as u64 & SHORT,
+                    links & SHORT,
+                    rdev & SHORT,
+                    entry.mtime as u64 & LONG,
+                    name_len & SHORT,
+                    size,
+                )
+            }
+        };
+
+        self.write(header.as_bytes())?;
+        self.write(entry.name.as_bytes())?;
+        self.write(&[0])?;
+        self.align()?;
+
+        if let Kind::Symlink(target) = &entry.kind {
+            self.write(target.as_bytes())?;
+            self.align()?;
+        }
+        Ok(())
+    }
+
+    /// Pad newc members to 4 bytes.
+    fn align(&mut self) -> std::io::Result<()> {
+        match self.format {
+            Format::Newc => self.pad(4),
+            Format::Odc => Ok(()),
+        }
+    }
+
+    /// Write `size` bytes of data from `input`, padded with zeros if it has
+    /// less. Returns the number of bytes read.
+    fn data(&mut self, input: &mut dyn Read, size: u64) -> std::io::Result<u64> {
+        let read = std::io::copy(&mut input.take(size), &mut self.out)?;
+        self.written += read;
+
+        let mut missing = size - read;
+        while missing > 0 {
+            let len = missing.min(BLOCK);
+            self.write(&[0; BLOCK as usize][..len as usize])?;
+            missing -= len;
+        }
+
+        self.align()?;
+        Ok(read)
+    }
+
+    /// Write the trailer, padding the archive to a block. Returns the output
+    /// and the number of blocks written.
+    fn finish(mut self) -> std::io::Result<(W, u64)> {
+        let trailer = Entry {
+            name: TRAILER.to_string(),
+            kind: Kind::File,
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            user: String::new(),
+            group: String::new(),
+            mtime: 0,
+            size: 0,
+            device: (0, 0),
+        };
+        self.header(&trailer, 0, 1)?;
+        self.pad(BLOCK)?;
+        self.out.flush()?;
+
+        Ok((self.out, self.written / BLOCK))
+    }
+}
+
+/// Format a member like `ls -l` does.
+fn long_listing(member: &Member) -> String {
+    let entry = &member.entry;
+    let size = match entry.kind {
+        Kind::CharDevice | Kind::BlockDevice => {
+            format!("{:>3}, {:>3}", entry.device.0, entry.device.1)
+        }
+        Kind::Symlink(ref target) => format!("{:>8}", target.len()),
+        _ => format!("{:>8}", entry.size),
+    };
+
+    // Like `ls`, the dates older than six months show the year.
+    let now = chrono::Local::now();
+    let date = chrono::DateTime::from_timestamp(entry.mtime, 0)
+        .map(|date| {
+            let date = date.with_timezone(&chrono::Local);
+            let format = if (now - date).num_days().abs() < 183 {
+                "%b %e %H:%M"
+            } else {
+                "%b %e  %Y"
+            };
+            date.format(format).to_string()
+        })
+        .unwrap_or_default();
+
+    let mut line = format!(
+        "{}{} {:>3} {:<8} {:<8} {} {} {}",
+        entry.type_char(),
+        format_mode(entry.mode),
+        member.links,
+        id_name("/etc/passwd", entry.uid),
+        id_name("/etc/group", entry.gid),
+        size,
+        date,
+        entry.name,
+    );
+    if let Kind::Symlink(target) = &entry.kind {
+        line.push_str(" -> ");
+        line.push_str(target);
+    }
+    line
+}
+
+#[derive(PartialEq)]
+enum Mode {
+    Create,
+    Extract,
+    List,
+}
+
+/// The settings of `cpio`.
+struct Cpio {
+    verbose: bool,
+    failed: bool,
+}
+
+impl Cpio {
+    /// Write the archive of the files named in `names` into `out`. Returns
+    /// the number of blocks written.
+    fn create(
+        &mut self,
+        out: impl Write,
+        format: Format,
+        names: &mut dyn BufRead,
+        separator: u8,
+    ) -> std::io::Result<u64> {
+        let mut writer = Writer {
+            out: BufWriter::new(out),
+            format,
+            written: 0,
+        };
+        // The files are numbered in the archive, their links sharing a number.
+        let mut inodes = HashMap::new();
+
+        for name in names.split(separator) {
+            let name = String::from_utf8_lossy(&name?).into_owned();
+            if name.is_empty() {
+                continue;
+            }
+
+            let path = Path::new(&name);
+            let metadata = match std::fs::symlink_metadata(path) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    eprintln!("cpio: {}: Cannot stat: {}", name, error_reason(&e));
+                    self.failed = true;
+                    continue;
+                }
+            };
+            let mut entry = match Entry::from_file(name.clone(), path, &metadata) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    eprintln!("cpio: {}: {}", name, error_reason(&e));
+                    self.failed = true;
+                    continue;
+                }
+            };
+
+            let count = inodes.len() as u64;
+            let ino = *inodes
+                .entry((metadata.dev(), metadata.ino()))
+                .or_insert(count + 1);
+            // In the newc format, only the first link of a file has its
+            // data; the odc format has it with every link.
+            if format == Format::Newc && ino <= count {
+                entry.size = 0;
+            }
+
+            let mut input = None;
+            if entry.kind == Kind::File && entry.size > 0 {
+                match std::fs::File::open(path) {
+                    Ok(file) => input = Some(file),
+                    Err(e) => {
+                        eprintln!("cpio: {}: Cannot open: {}", name, error_reason(&e));
+                        self.failed = true;
+                        continue;
+                    }
+                }
+            }
+
+            if self.verbose {
+                eprintln!("{}", name);
+            }
+            writer.header(&entry, ino, metadata.nlink())?;
+            if let Some(mut file) = input {
+                let read = writer.data(&mut file, entry.size)?;
+                if read < entry.size {
+                    eprintln!(
+                        "cpio: {}: File shrank by {} bytes; padding with zeros",
+                        name,
+                        entry.size - read
+                    );
+                    self.failed = true;
+                }
+            }
+        }
+
+        let (mut out, blocks) = writer.finish()?;
+        out.flush()?;
+        Ok(blocks)
+    }
+
+    /// List the members of the archive read from `input`, or extract them,
+    /// keeping those matching one of `patterns` if there are some. Returns
+    /// the number of blocks read.
+    fn read(
+        &mut self,
+        input: &mut dyn Read,
+        mode: Mode,
+        patterns: &[&String],
+        mut extractor: Extractor,
+    ) -> std::io::Result<u64> {
+        let out = std::io::stdout();
+        let mut out = BufWriter::new(out.lock());
+        // The first names extracted of the files with several links.
+        let mut linked: HashMap<(u64, u64), String> = HashMap::new();
+        // The newc links extracted before the member with their data.
+        let mut deferred: HashMap<(u64, u64), Vec<Entry>> = HashMap::new();
+        let mut result = Ok(());
+
+        let read = read_archive(input, |member, data| {
+            let entry = &member.entry;
+            if !patterns.is_empty()
+                && !patterns
+                    .iter()
+                    .any(|pattern| glob_match(pattern, &entry.name, false))
+            {
+                return;
+            }
+
+            if mode == Mode::List {
+                let line = if self.verbose {
+                    long_listing(member)
+                } else {
+                    entry.name.clone()
+                };
+                if result.is_ok() {
+                    result = writeln!(out, "{}", line);
+                }
+                return;
+            }
+
+            if self.verbose {
+                eprintln!("{}", entry.name);
+            }
+            if entry.kind != Kind::File || member.links < 2 {
+                extractor.extract(entry, data);
+                return;
+            }
+
+            if let Some(first) = linked.get(&member.file) {
+                let mut link = entry.clone();
+                link.kind = Kind::HardLink(first.clone());
+                extractor.extract(&link, &mut std::io::empty());
+            } else if member.format == Format::Newc && entry.size == 0 {
+                deferred.entry(member.file).or_default().push(entry.clone());
+            } else {
+                extractor.extract(entry, data);
+                linked.insert(member.file, entry.name.clone());
+
+                for mut link in deferred.remove(&member.file).unwrap_or_default() {
+                    link.kind = Kind::HardLink(entry.name.clone());
+                    extractor.extract(&link, &mut std::io::empty());
+                }
+            }
+        });
+
+        // The files whose data never came are empty.
+        for links in deferred.into_values() {
+            let mut links = links.into_iter();
+            let Some(first) = links.next() else {
+                continue;
+            };
+            extractor.extract(&first, &mut std::io::empty());
+            for mut link in links {
+                link.kind = Kind::HardLink(first.name.clone());
+                extractor.extract(&link, &mut std::io::empty());
+            }
+        }
+
+        extractor.finish();
+        self.failed |= extractor.failed;
+        result.and_then(|_| out.flush())?;
+
+        Ok(read?.div_ceil(BLOCK))
+    }
+}
+
+pub fn cpio(args: &[String]) {
+    let with_value = ["-H", "--format", "-F", "--file"];
+    let Some((opts, patterns)) = parse_options(args, &with_value) else {
+        usage();
+    };
+
+    let mut mode = None;
+    let mut format = Format::Newc;
+    let mut archive = String::from("-");
+    let mut separator = b'\n';
+    let mut make_directories = false;
+    let mut keep_times = false;
+    let mut unconditional = false;
+    let mut quiet = false;
+    let mut cpio = Cpio {
+        verbose: false,
+        failed: false,
+    };
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+        let new_mode = match opt.as_str() {
+            "-o" | "--create" => Mode::Create,
+            "-i" | "--extract" => Mode::Extract,
+            "-t" | "--list" => Mode::List,
+            "-H" | "--format" => {
+                format = match value.as_str() {
+                    "newc" | "crc" => Format::Newc,
+                    "odc" => Format::Odc,
+                    _ => fail(format_args!("invalid archive format '{}'", value)),
+                };
+                continue;
+            }
+            "-F" | "--file" => {
+                archive = value;
+                continue;
+            }
+            "-0" | "--null" => {
+                separator = 0;
+                continue;
+            }
+            "-d" | "--make-directories" => {
+                make_directories = true;
+                continue;
+            }
+            "-m" | "--preserve-modification-time" => {
+                keep_times = true;
+                continue;
+            }
+            "-u" | "--unconditional" => {
+                unconditional = true;
+                continue;
+            }
+            "-v" | "--verbose" => {
+                cpio.verbose = true;
+                continue;
+            }
+            "--quiet" => {
+                quiet = true;
+                continue;
+            }
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-270);
+            }
+        };
+
+        // `-t` lists with `-i` too.
+        match (&mode, new_mode) {
+            (Some(Mode::List), Mode::Extract) => {}
+            (Some(Mode::Extract) | None, new_mode) => mode = Some(new_mode),
+            (Some(mode), new_mode) if *mode != new_mode => usage(),
+            _ => {}
+        }
+    }
+
+    let Some(mode) = mode else {
+        usage();
+    };
+
+    let result = if mode == Mode::Create {
+        if !patterns.is_empty() {
+            usage();
+        }
+
+        let out: Box<dyn Write> = if archive == "-" {
+            Box::new(std::io::stdout().lock())
+        } else {
+            match std::fs::File::create(&archive) {
+                Ok(file) => Box::new(file),
+                Err(e) => fail(format_args!("{}: {}", archive, error_reason(&e))),
+            }
+        };
+        cpio.create(out, format, &mut std::io::stdin().lock(), separator)
+    } else {
+        let mut input = open_input(&archive)
+            .unwrap_or_else(|e| fail(format_args!("{}: {}", archive, error_reason(&e))));
+        // The permissions are restored as they are.
+        let extractor = Extractor::new("cpio", true, 0)
+            .parents(make_directories)
+            .times(keep_times)
+            .keep_newer(!unconditional);
+        cpio.read(&mut input, mode, &patterns, extractor)
+    };
+
+    match result {
+        Ok(blocks) if !quiet => eprintln!("{} blocks", blocks),
+        Ok(_) => {}
+        Err(e) => fail(format_args!("{}", error_reason(&e))),
+    }
+
+    if cpio.failed {
+        std::process::exit(-270);
+    }
+}
@@ -0,0 +1,284 @@
+//! The `cut` command, printing selected parts of lines.
+
+use std::io::{BufWriter, Write};
+
+use crate::fields::*;
+use crate::utils::*;
+
+/// What the positions of the list refer to.
+#[derive(PartialEq)]
+enum Mode {
+    Bytes,
+    Chars,
+    Fields,
+}
+
+/// Parse a list of positions (`N`, `N-`, `N-M` or `-M`, separated by
+/// commas or blanks) into sorted, merged ranges, counted from 0.
+fn parse_list(list: &str) -> Option<Vec<(usize, usize)>> {
+    let mut ranges = Vec::new();
+
+    for range in list.split([',', ' ', '\t']) {
+        let number =
+            |number: &str| -> Option<usize> { number.parse::<usize>().ok().filter(|&n| n > 0) };
+
+        let (start, end) = match range.split_once('-') {
+            Some(("", "")) => return None,
+            Some(("", end)) => (1, number(end)?),
+            Some((start, "")) => (number(start)?, usize::MAX),
+            Some((start, end)) => (number(start)?, number(end)?),
+            None => (number(range)?, number(range)?),
+        };
+
+        if start > end {
+            return None;
+        }
+
+        ranges.push((start - 1, end.saturating_sub(1)));
+    }
+
+    ranges.sort_unstable();
+
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+
+    Some(merged)
+}
+
+/// Invert sorted, merged ranges.
+fn complement(ranges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    let mut inverted = Vec::new();
+    let mut next = 0;
+
+    for &(start, end) in ranges {
+        if start > next {
+            inverted.push((next, start - 1));
+        }
+        next = end.saturating_add(1);
+    }
+
+    if next != usize::MAX {
+        inverted.push((next, usize::MAX));
+    }
+
+    inverted
+}
+
+/// The settings of `cut`.
+struct Cutter {
+    mode: Mode,
+    ranges: Vec<(usize, usize)>,
+    delimiter: u8,
+    output_delimiter: Option<Vec<u8>>,
+    only_delimited: bool,
+}
+
+impl Cutter {
+    /// Print the selected parts of `line`, without its terminator.
+    /// Returns false if the line isn't printed at all.
+    fn cut(&self, line: &[u8], out: &mut impl Write) -> std::io::Result<bool> {
+        if self.mode == Mode::Fields {
+            if !line.contains(&self.delimiter) {
+                if !self.only_delimited {
+                    out.write_all(line)?;
+                }
+                return Ok(!self.only_delimited);
+            }
+
+            let fields = split_fields(line, Some(self.delimiter));
+            let separator = self
+                .output_delimiter
+                .as_deref()
+                .unwrap_or(std::slice::from_ref(&self.delimiter));
+
+            let mut first = true;
+            for (i, field) in fields.iter().enumerate() {
+                if !self.selected(i) {
+                    continue;
+                }
+
+                if !first {
+                    out.write_all(separator)?;
+                }
+                out.write_all(field)?;
+                first = false;
+            }
+
+            return Ok(true);
+        }
+
+        // The boundaries of the units: bytes, or the characters
+        // of lines that are valid UTF-8.
+        let boundaries = match std::str::from_utf8(line) {
+            Ok(text) if self.mode == Mode::Chars => text
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([line.len()])
+                .collect(),
+            _ => (0..=line.len()).collect::<Vec<_>>(),
+        };
+        let units = boundaries.len() - 1;
+
+        for (i, &(start, end)) in self.ranges.iter().enumerate() {
+            if start >= units {
+                break;
+            }
+
+            // Only the output delimiter separates the ranges.
+            if i > 0 {
+                if let Some(separator) = &self.output_delimiter {
+                    out.write_all(separator)?;
+                }
+            }
+
+            let end = end.min(units - 1);
+            out.write_all(&line[boundaries[start]..boundaries[end + 1]])?;
+        }
+
+        Ok(true)
+    }
+
+    fn selected(&self, position: usize) -> bool {
+        self.ranges
+            .iter()
+            .any(|&(start, end)| (start..=end).contains(&position))
+    }
+}
+
+pub fn cut(args: &[String]) {
+    let with_value = [
+        "-b",
+        "--bytes",
+        "-c",
+        "--characters",
+        "-f",
+        "--fields",
+        "-d",
+        "--delimiter",
+        "--output-delimiter",
+    ];
+    let Some((opts, files)) = parse_options(args, &with_value) else {
+        eprintln!("Usage: cut -b LIST|-c LIST|-f LIST [-d DELIM] [-s] [--complement] [FILE]...");
+        std::process::exit(-170);
+    };
+
+    let mut mode_list = None;
+    let mut delimiter = None;
+    let mut output_delimiter = None;
+    let mut only_delimited = false;
+    let mut invert = false;
+    let mut terminator = b'\n';
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+
+        match opt.as_str() {
+            "-b" | "--bytes" | "-c" | "--characters" | "-f" | "--fields" => {
+                if mode_list.is_some() {
+                    eprintln!("cut: only one type of list may be specified");
+                    std::process::exit(-170);
+                }
+
+                let mode = match opt.as_str() {
+                    "-b" | "--bytes" => Mode::Bytes,
+                    "-c" | "--characters" => Mode::Chars,
+                    _ => Mode::Fields,
+                };
+                mode_list = Some((mode, value));
+            }
+            "-d" | "--delimiter" => match value.as_bytes() {
+                [delimiter_byte] => delimiter = Some(*delimiter_byte),
+                _ => {
+                    eprintln!("cut: the delimiter must be a single character");
+                    std::process::exit(-170);
+                }
+            },
+            "--output-delimiter" => output_delimiter = Some(value.into_bytes()),
+            "-s" | "--only-delimited" => only_delimited = true,
+            "--complement" => invert = true,
+            "-z" | "--zero-terminated" => terminator = b'\0',
+            "-n" => {}
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-170);
+            }
+        }
+    }
+
+    let Some((mode, list)) = mode_list else {
+        eprintln!("cut: you must specify a list of bytes, characters, or fields");
+        std::process::exit(-170);
+    };
+
+    if mode != Mode::Fields && (delimiter.is_some() || only_delimited) {
+        eprintln!("cut: a delimiter may be specified only when operating on fields");
+        std::process::exit(-170);
+    }
+
+    let Some(mut ranges) = parse_list(&list) else {
+        eprintln!("cut: invalid list '{}'", list);
+        std::process::exit(-170);
+    };
+    if invert {
+        ranges = complement(&ranges);
+    }
+
+    let cutter = Cutter {
+        mode,
+        ranges,
+        delimiter: delimiter.unwrap_or(b'\t'),
+        output_delimiter,
+        only_delimited,
+    };
+
+    let stdin = String::from("-");
+    let files = if files.is_empty() {
+        vec![&stdin]
+    } else {
+        files
+    };
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut was_error = false;
+
+    for file in files {
+        let mut input = match open_input(file) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("cut: {}: {}", file, error_reason(&e));
+                was_error = true;
+                continue;
+            }
+        };
+
+        let mut line = Vec::new();
+        let result = (|| -> std::io::Result<()> {
+            while read_line(input.as_mut(), terminator, &mut line)? {
+                if cutter.cut(&line, &mut out)? {
+                    out.write_all(&[terminator])?;
+                }
+            }
+
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            eprintln!("cut: {}: {}", file, error_reason(&e));
+            was_error = true;
+        }
+    }
+
+    if let Err(e) = out.flush() {
+        eprintln!("cut: {}", error_reason(&e));
+        was_error = true;
+    }
+
+    if was_error {
+        std::process::exit(-170);
+    }
+}
@@ -0,0 +1,925 @@
+//! The DEFLATE compression format (RFC 1951), used by `gzip`: the data
+//! is made of LZ77 matches and literals, encoded with Huffman codes.
+
+use std::collections::BinaryHeap;
+use std::io::{BufRead, Write};
+
+/// The size of the window in which matches are searched.
+const WINDOW: usize = 1 << 15;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+/// The size of the table of the hash chains.
+const HASH_SIZE: usize = 1 << 15;
+/// No position, at the end of a hash chain.
+const NONE: usize = usize::MAX;
+/// The number of symbols after which a block is ended.
+const BLOCK_SYMBOLS: usize = 1 << 14;
+
+/// The first length of each length code (from 257), and its extra bits.
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+/// The first distance of each distance code, and its extra bits.
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// The order in which the lengths of the code length code are stored.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn invalid() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "invalid compressed data--format violated",
+    )
+}
+
+fn truncated() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "unexpected end of file")
+}
+
+/// Get the lengths of the codes of the fixed Huffman codes, for the
+/// literals and lengths, and for the distances.
+fn fixed_lengths() -> (Vec<u8>, Vec<u8>) {
+    let litlen = (0..288)
+        .map(|symbol| match symbol {
+            0..=143 => 8,
+            144..=255 => 9,
+            256..=279 => 7,
+            _ => 8,
+        })
+        .collect();
+
+    (litlen, vec![5; 30])
+}
+
+/// Get the canonical Huffman codes of symbols whose codes have `lengths`
+/// (0 for the unused ones), with their bits reversed, since they are
+/// stored from the most significant bit. Returns `None` if there are too
+/// many codes of some length.
+fn canonical_codes(lengths: &[u8]) -> Option<Vec<u32>> {
+    let mut count = [0u32; 16];
+    for &len in lengths {
+        count[len as usize] += 1;
+    }
+    count[0] = 0;
+
+    let mut left = 1i64;
+    for &count in &count[1..] {
+        left = (left << 1) - count as i64;
+        if left < 0 {
+            return None;
+        }
+    }
+
+    let mut next = [0u32; 16];
+    for len in 1..16 {
+        next[len] = (next[len - 1] + count[len - 1]) << 1;
+    }
+
+    let codes = lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return 0;
+            }
+            let code = next[len as usize];
+            next[len as usize] += 1;
+            code.reverse_bits() >> (32 - len)
+        })
+        .collect();
+
+    Some(codes)
+}
+
+/// Reads bits from a stream, the least significant first. Whole bytes
+/// are read after skipping the bits up to the next byte.
+pub struct BitReader<'a> {
+    input: &'a mut dyn BufRead,
+    bits: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(input: &'a mut dyn BufRead) -> Self {
+        BitReader {
+            input,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    /// Read bytes until there are more than 56 bits, or the input ends.
+    fn refill(&mut self) -> std::io::Result<()> {
+        while self.count <= 56 {
+            let buffer = match self.input.fill_buf() {
+                Ok(buffer) => buffer,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if buffer.is_empty() {
+                break;
+            }
+
+            let len = buffer.len().min((64 - self.count) as usize / 8);
+            for &byte in &buffer[..len] {
+                self.bits |= (byte as u64) << self.count;
+                self.count += 8;
+            }
+            self.input.consume(len);
+        }
+
+        Ok(())
+    }
+
+    /// Get the next `n` bits without reading them. The bits past the end
+    /// of the input are zeros.
+    fn peek(&mut self, n: u32) -> std::io::Result<u64> {
+        if self.count < n {
+            self.refill()?;
+        }
+        Ok(self.bits & ((1 << n) - 1))
+    }
+
+    fn consume(&mut self, n: u32) -> std::io::Result<()> {
+        if n > self.count {
+            return Err(truncated());
+        }
+        self.bits >>= n;
+        self.count -= n;
+        Ok(())
+    }
+
+    /// Read `n` bits, up to 32.
+    pub fn bits(&mut self, n: u32) -> std::io::Result<u32> {
+        let value = self.peek(n)?;
+        self.consume(n)?;
+        Ok(value as u32)
+    }
+
+    /// Skip the bits up to the next byte.
+    pub fn align(&mut self) {
+        let n = self.count % 8;
+        self.bits >>= n;
+        self.count -= n;
+    }
+
+    /// Read the next whole byte.
+    pub fn byte(&mut self) -> std::io::Result<u8> {
+        self.align();
+        Ok(self.bits(8)? as u8)
+    }
+
+    /// Check if there are no whole bytes left to read.
+    pub fn at_end(&mut self) -> std::io::Result<bool> {
+        self.align();
+        self.refill()?;
+        Ok(self.count == 0)
+    }
+}
+
+/// A Huffman code, decoded with a table indexed by the next bits, which
+/// gives the symbol and the length of its code.
+struct Huffman {
+    table: Vec<(u16, u8)>,
+    bits: u32,
+}
+
+impl Huffman {
+    /// Build the code of symbols whose codes have `lengths`. Returns `None`
+    /// if it's invalid.
+    fn new(lengths: &[u8]) -> Option<Self> {
+        let codes = canonical_codes(lengths)?;
+        let bits = lengths.iter().copied().max().unwrap_or(0).max(1) as u32;
+        let mut table = vec![(0, 0); 1 << bits];
+
+        for (symbol, (&len, &code)) in lengths.iter().zip(&codes).enumerate() {
+            if len == 0 {
+                continue;
+            }
+            // Every entry whose low bits are the code.
+            for entry in table.iter_mut().skip(code as usize).step_by(1 << len) {
+                *entry = (symbol as u16, len);
+            }
+        }
+
+        Some(Huffman { table, bits })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> std::io::Result<usize> {
+        let (symbol, len) = self.table[reader.peek(self.bits)? as usize];
+        if len == 0 {
+            return Err(invalid());
+        }
+        reader.consume(len as u32)?;
+        Ok(symbol as usize)
+    }
+}
+
+/// The data decompressed, which is written in chunks, keeping the last
+/// window for the matches.
+struct Output<'a, W: Write> {
+    out: &'a mut W,
+    data: Vec<u8>,
+    written: usize,
+}
+
+impl<W: Write> Output<'_, W> {
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.write_all(&self.data[self.written..])?;
+        self.data.drain(..self.data.len().saturating_sub(WINDOW));
+        self.written = self.data.len();
+        Ok(())
+    }
+}
+
+/// Decompress a block encoded with the given codes.
+fn inflate_block<W: Write>(
+    reader: &mut BitReader,
+    output: &mut Output<W>,
+    litlen: &Huffman,
+    dist: &Huffman,
+) -> std::io::Result<()> {
+    loop {
+        if output.data.len() >= 4 * WINDOW {
+            output.flush()?;
+        }
+
+        let symbol = litlen.decode(reader)?;
+        if symbol < 256 {
+            output.data.push(symbol as u8);
+            continue;
+        } else if symbol == 256 {
+            return Ok(());
+        }
+
+        let code = symbol - 257;
+        if code >= LENGTH_BASE.len() {
+            return Err(invalid());
+        }
+        let length = LENGTH_BASE[code] as usize + reader.bits(LENGTH_EXTRA[code] as u32)? as usize;
+
+        let code = dist.decode(reader)?;
+        if code >= DIST_BASE.len() {
+            return Err(invalid());
+        }
+        let distance = DIST_BASE[code] as usize + reader.bits(DIST_EXTRA[code] as u32)? as usize;
+
+        let data = &mut output.data;
+        if distance > data.len() {
+            return Err(invalid());
+        }
+
+        let start = data.len() - distance;
+        if distance >= length {
+            data.extend_from_within(start..start + length);
+        } else {
+            // The match overlaps the bytes it produces.
+            for i in start..start + length {
+                data.push(data[i]);
+            }
+        }
+    }
+}
+
+/// Read the codes of a block with dynamic Huffman codes.
+fn read_codes(reader: &mut BitReader) -> std::io::Result<(Huffman, Huffman)> {
+    let litlen_count = reader.bits(5)? as usize + 257;
+    let dist_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+    if litlen_count > 286 || dist_count > 30 {
+        return Err(invalid());
+    }
+
+    let mut code_lengths = [0; 19];
+    for &symbol in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[symbol] = reader.bits(3)? as u8;
+    }
+    let code = Huffman::new(&code_lengths).ok_or_else(invalid)?;
+
+    let total = litlen_count + dist_count;
+    let mut lengths = Vec::with_capacity(total);
+    while lengths.len() < total {
+        let (len, repeat) = match code.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or_else(invalid)?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(len, repeat as usize));
+    }
+
+    if lengths.len() > total || lengths[256] == 0 {
+        return Err(invalid());
+    }
+
+    let litlen = Huffman::new(&lengths[..litlen_count]).ok_or_else(invalid)?;
+    let dist = Huffman::new(&lengths[litlen_count..]).ok_or_else(invalid)?;
+    Ok((litlen, dist))
+}
+
+/// Decompress a DEFLATE stream from `reader` into `out`, up to the end of
+/// its last block. What follows it is left in `reader`.
+pub fn inflate(reader: &mut BitReader, out: &mut impl Write) -> std::io::Result<()> {
+    let mut output = Output {
+        out,
+        data: Vec::with_capacity(5 * WINDOW),
+        written: 0,
+    };
+
+    loop {
+        let last = reader.bits(1)? == 1;
+
+        match reader.bits(2)? {
+            0 => {
+                let len = reader.byte()? as u16 | (reader.byte()? as u16) << 8;
+                let complement = reader.byte()? as u16 | (reader.byte()? as u16) << 8;
+                if len != !complement {
+                    return Err(invalid());
+                }
+                for _ in 0..len {
+                    output.data.push(reader.byte()?);
+                }
+                if output.data.len() >= 4 * WINDOW {
+                    output.flush()?;
+                }
+            }
+            1 => {
+                let (litlen, dist) = fixed_lengths();
+                let litlen = Huffman::new(&litlen).ok_or_else(invalid)?;
+                let dist = Huffman::new(&dist).ok_or_else(invalid)?;
+                inflate_block(reader, &mut output, &litlen, &dist)?;
+            }
+            2 => {
+                let (litlen, dist) = read_codes(reader)?;
+                inflate_block(reader, &mut output, &litlen, &dist)?;
+            }
+            _ => return Err(invalid()),
+        }
+
+        if last {
+            return output.flush();
+        }
+    }
+}
+
+/// Writes bits into a buffer, the least significant first.
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, n: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += n;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Write a stored block of `data`, up to 65535 bytes.
+    fn stored(&mut self, data: &[u8], last: bool) {
+        self.write(last as u32, 1);
+        self.write(0, 2);
+        self.align();
+        let len = data.len() as u16;
+        self.out.extend(len.to_le_bytes());
+        self.out.extend((!len).to_le_bytes());
+        self.out.extend_from_slice(data);
+    }
+
+    /// Fill the last byte with zeros.
+    fn align(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+            self.bits = 0;
+            self.count = 0;
+        }
+    }
+}
+
+/// Get the depth of each symbol in the Huffman tree built from the
+/// frequencies of the symbols (0 for the unused ones).
+fn tree_depths(freqs: &[u64]) -> Vec<u8> {
+    let mut heap = BinaryHeap::new();
+    for (symbol, &freq) in freqs.iter().enumerate() {
+        if freq > 0 {
+            heap.push(std::cmp::Reverse((freq, symbol)));
+        }
+    }
+
+    // The nodes are the symbols followed by the inner nodes, each created
+    // after its children.
+    let mut parents = vec![NONE; freqs.len()];
+    while heap.len() > 1 {
+        let std::cmp::Reverse((freq1, node1)) = heap.pop().unwrap();
+        let std::cmp::Reverse((freq2, node2)) = heap.pop().unwrap();
+        let node = parents.len();
+        parents.push(NONE);
+        parents[node1] = node;
+        parents[node2] = node;
+        heap.push(std::cmp::Reverse((freq1 + freq2, node)));
+    }
+
+    let mut depths = vec![0u8; parents.len()];
+    for node in (0..parents.len()).rev() {
+        if parents[node] != NONE {
+            depths[node] = depths[parents[node]] + 1;
+        }
+    }
+
+    depths.truncate(freqs.len());
+    depths
+}
+
+/// Get the lengths of the Huffman codes for the frequencies of the
+/// symbols, without codes longer than `limit`. At least two symbols get
+/// a code, as some decoders don't accept a code made of a single one.
+fn code_lengths(freqs: &[u64], limit: u8) -> Vec<u8> {
+    let mut freqs = freqs.to_vec();
+    if freqs.iter().filter(|&&freq| freq > 0).count() < 2 {
+        for freq in freqs.iter_mut().take(2) {
+            *freq = (*freq).max(1);
+        }
+    }
+
+    // Flatten the frequencies until the tree is short enough.
+    loop {
+        let depths = tree_depths(&freqs);
+        if depths.iter().all(|&depth| depth <= limit) {
+            return depths;
+        }
+
+        for freq in freqs.iter_mut().filter(|freq| **freq > 0) {
+            *freq = (*freq >> 1).max(1);
+        }
+    }
+}
+
+/// Encode the lengths of the codes with the code length symbols: the
+/// lengths themselves, and repetitions of the previous length (16) or of
+/// zeros (17 and 18). Returns the symbols with the value of their
+/// extra bits.
+fn run_lengths(lengths: &[u8]) -> Vec<(usize, u32)> {
+    let mut symbols = Vec::new();
+    let mut i = 0;
+
+    while i < lengths.len() {
+        let len = lengths[i];
+        let run = lengths[i..]
+            .iter()
+            .take_while(|&&other| other == len)
+            .count();
+
+        if len == 0 && run >= 11 {
+            let run = run.min(138);
+            symbols.push((18, run as u32 - 11));
+            i += run;
+        } else if len == 0 && run >= 3 {
+            symbols.push((17, run as u32 - 3));
+            i += run;
+        } else if run >= 4 {
+            let repeat = (run - 1).min(6);
+            symbols.push((len as usize, 0));
+            symbols.push((16, repeat as u32 - 3));
+            i += 1 + repeat;
+        } else {
+            symbols.push((len as usize, 0));
+            i += 1;
+        }
+    }
+
+    symbols
+}
+
+/// The number of extra bits of a code length symbol.
+fn run_length_extra(symbol: usize) -> u32 {
+    match symbol {
+        16 => 2,
+        17 => 3,
+        18 => 7,
+        _ => 0,
+    }
+}
+
+/// A literal byte or a match, found by LZ77.
+#[derive(Clone, Copy)]
+enum Symbol {
+    Literal(u8),
+    Match { length: u16, distance: u16 },
+}
+
+/// Get the code of a match length (from 257), or a distance.
+fn length_code(length: usize) -> usize {
+    LENGTH_BASE.partition_point(|&base| base as usize <= length) - 1
+}
+
+fn dist_code(distance: usize) -> usize {
+    DIST_BASE.partition_point(|&base| base as usize <= distance) - 1
+}
+
+/// The settings of a compression level, like zlib's: the length of match
+/// which is good enough to search less for a better one, the longest
+/// match for which a longer one is looked for at the next byte (lazy
+/// matching, disabled with 0), the length of match which ends the search,
+/// and the number of previous positions tried.
+struct Level {
+    good: usize,
+    lazy: usize,
+    nice: usize,
+    chain: usize,
+}
+
+const LEVELS: [Level; 9] = [
+    Level {
+        good: 4,
+        lazy: 0,
+        nice: 8,
+        chain: 4,
+    },
+    Level {
+        good: 4,
+        lazy: 0,
+        nice: 16,
+        chain: 8,
+    },
+    Level {
+        good: 4,
+        lazy: 0,
+        nice: 32,
+        chain: 32,
+    },
+    Level {
+        good: 4,
+        lazy: 4,
+        nice: 16,
+        chain: 16,
+    },
+    Level {
+        good: 8,
+        lazy: 16,
+        nice: 32,
+        chain: 32,
+    },
+    Level {
+        good: 8,
+        lazy: 16,
+        nice: 128,
+        chain: 128,
+    },
+    Level {
+        good: 8,
+        lazy: 32,
+        nice: 128,
+        chain: 256,
+    },
+    Level {
+        good: 32,
+        lazy: 128,
+        nice: 258,
+        chain: 1024,
+    },
+    Level {
+        good: 32,
+        lazy: 258,
+        nice: 258,
+        chain: 4096,
+    },
+];
+
+/// Compresses a stream into DEFLATE blocks. The data is kept from the
+/// start of the current block, or from the window before the next byte
+/// to compress if it's further. Positions are offsets in the whole input.
+pub struct Deflater {
+    level: &'static Level,
+    data: Vec<u8>,
+    /// The position of the first byte in `data`.
+    start: usize,
+    /// The position of the next byte to compress.
+    pos: usize,
+    block_start: usize,
+    /// The last position with each hash of 3 bytes, and for each position
+    /// (in the window) the previous one with the same hash.
+    head: Vec<usize>,
+    prev: Vec<usize>,
+    /// The match found at `pos`, when it was looked for by lazy matching.
+    pending: Option<(usize, usize)>,
+    symbols: Vec<Symbol>,
+    writer: BitWriter,
+}
+
+impl Deflater {
+    /// Create a compressor for a level from 1 (fastest) to 9 (best).
+    pub fn new(level: u32) -> Self {
+        Deflater {
+            level: &LEVELS[level.clamp(1, 9) as usize - 1],
+            data: Vec::new(),
+            start: 0,
+            pos: 0,
+            block_start: 0,
+            head: vec![NONE; HASH_SIZE],
+            prev: vec![NONE; WINDOW],
+            pending: None,
+            symbols: Vec::with_capacity(BLOCK_SYMBOLS),
+            writer: BitWriter {
+                out: Vec::new(),
+                bits: 0,
+                count: 0,
+            },
+        }
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = &self.data[pos - self.start..];
+        ((bytes[0] as usize) << 10 ^ (bytes[1] as usize) << 5 ^ bytes[2] as usize) & (HASH_SIZE - 1)
+    }
+
+    /// Add `pos` to the hash chains, if there are 3 bytes there.
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH <= self.start + self.data.len() {
+            let hash = self.hash(pos);
+            self.prev[pos % WINDOW] = self.head[hash];
+            self.head[hash] = pos;
+        }
+    }
+
+    /// Find the longest match for the bytes at `pos`, trying `chain`
+    /// previous positions. Returns its length and distance, or a length of
+    /// 0 if there's no match.
+    fn find(&self, pos: usize, mut chain: usize) -> (usize, usize) {
+        let end = self.start + self.data.len();
+        if pos + MIN_MATCH > end {
+            return (0, 0);
+        }
+
+        let here =
+            &self.data[pos - self.start..(end - self.start).min(pos - self.start + MAX_MATCH)];
+        let mut best = (MIN_MATCH - 1, 0);
+        let mut candidate = self.head[self.hash(pos)];
+
+        while candidate != NONE && candidate >= self.start && pos - candidate <= WINDOW && chain > 0
+        {
+            let there = &self.data[candidate - self.start..];
+            if there[best.0] == here[best.0] {
+                let len = here.iter().zip(there).take_while(|(a, b)| a == b).count();
+                if len > best.0 {
+                    best = (len, pos - candidate);
+                    if len >= self.level.nice || len == here.len() {
+                        break;
+                    }
+                }
+            }
+
+            // The chain ends where the window was overwritten.
+            let previous = self.prev[candidate % WINDOW];
+            if previous >= candidate {
+                break;
+            }
+            candidate = previous;
+            chain -= 1;
+        }
+
+        // Short matches far away cost more than the literals.
+        if best.1 == 0 || (best.0 == MIN_MATCH && best.1 > 4096) {
+            (0, 0)
+        } else {
+            best
+        }
+    }
+
+    /// Compress the bytes up to `limit`, ending blocks when they are full.
+    fn compress(&mut self, limit: usize) {
+        let level = self.level;
+
+        while self.pos < limit {
+            let pos = self.pos;
+            let (length, distance) = match self.pending.take() {
+                Some(found) => found,
+                None => {
+                    let found = self.find(pos, level.chain);
+                    self.insert(pos);
+                    found
+                }
+            };
+
+            if length == 0 {
+                self.symbols
+                    .push(Symbol::Literal(self.data[pos - self.start]));
+                self.pos += 1;
+            } else {
+                let mut inserted = pos + 1;
+
+                // A longer match at the next byte is better than this one.
+                if length < level.lazy && pos + 1 < limit {
+                    let chain = if length >= level.good {
+                        level.chain >> 2
+                    } else {
+                        level.chain
+                    };
+                    let next = self.find(pos + 1, chain);
+                    self.insert(pos + 1);
+                    inserted = pos + 2;
+
+                    if next.0 > length {
+                        self.symbols
+                            .push(Symbol::Literal(self.data[pos - self.start]));
+                        self.pos += 1;
+                        self.pending = Some(next);
+                        continue;
+                    }
+                }
+
+                self.symbols.push(Symbol::Match {
+                    length: length as u16,
+                    distance: distance as u16,
+                });
+                for next in inserted..pos + length {
+                    self.insert(next);
+                }
+                self.pos += length;
+            }
+
+            if self.symbols.len() >= BLOCK_SYMBOLS {
+                self.end_block(false);
+            }
+        }
+    }
+
+    /// Write the current block, in the smallest of the three forms: stored,
+    /// with the fixed codes or with its own codes.
+    fn end_block(&mut self, last: bool) {
+        let mut litlen_freqs = [0u64; 286];
+        let mut dist_freqs = [0u64; 30];
+        for &symbol in &self.symbols {
+            match symbol {
+                Symbol::Literal(byte) => litlen_freqs[byte as usize] += 1,
+                Symbol::Match { length, distance } => {
+                    litlen_freqs[257 + length_code(length as usize)] += 1;
+                    dist_freqs[dist_code(distance as usize)] += 1;
+                }
+            }
+        }
+        litlen_freqs[256] = 1;
+
+        let cost = |litlen: &[u8], dist: &[u8]| {
+            let literals: u64 = (0..286)
+                .map(|symbol| litlen_freqs[symbol] * litlen[symbol] as u64)
+                .sum();
+            let lengths: u64 = (0..29)
+                .map(|code| litlen_freqs[257 + code] * LENGTH_EXTRA[code] as u64)
+                .sum();
+            let distances: u64 = (0..30)
+                .map(|code| dist_freqs[code] * (dist[code] + DIST_EXTRA[code]) as u64)
+                .sum();
+            literals + lengths + distances
+        };
+
+        // The dynamic codes, and the code of their lengths.
+        let litlen = code_lengths(&litlen_freqs, 15);
+        let dist = code_lengths(&dist_freqs, 15);
+        let litlen_count = 257
+            + litlen[257..]
+                .iter()
+                .rposition(|&len| len > 0)
+                .map_or(0, |i| i + 1);
+        let dist_count = 1 + dist[1..]
+            .iter()
+            .rposition(|&len| len > 0)
+            .map_or(0, |i| i + 1);
+
+        let lengths = [&litlen[..litlen_count], &dist[..dist_count]].concat();
+        let runs = run_lengths(&lengths);
+        let mut code_freqs = [0u64; 19];
+        for &(symbol, _) in &runs {
+            code_freqs[symbol] += 1;
+        }
+        let code = code_lengths(&code_freqs, 7);
+        let code_count = 4 + CODE_LENGTH_ORDER[4..]
+            .iter()
+            .rposition(|&symbol| code[symbol] > 0)
+            .map_or(0, |i| i + 1);
+
+        let header: u64 = runs
+            .iter()
+            .map(|&(symbol, _)| (code[symbol] as u32 + run_length_extra(symbol)) as u64)
+            .sum();
+        let dynamic_cost = 3 + 14 + 3 * code_count as u64 + header + cost(&litlen, &dist);
+
+        let (fixed_litlen, fixed_dist) = fixed_lengths();
+        let fixed_cost = 3 + cost(&fixed_litlen, &fixed_dist);
+
+        let raw = self.block_start - self.start..self.pos - self.start;
+        let chunks = raw.len().div_ceil(0xffff).max(1) as u64;
+        let stored_cost = chunks * (3 + 32) + 7 + raw.len() as u64 * 8;
+
+        if stored_cost <= fixed_cost.min(dynamic_cost) {
+            let mut chunks = self.data[raw].chunks(0xffff).peekable();
+            if chunks.peek().is_none() {
+                self.writer.stored(&[], last);
+            }
+            while let Some(chunk) = chunks.next() {
+                self.writer.stored(chunk, last && chunks.peek().is_none());
+            }
+        } else if fixed_cost <= dynamic_cost {
+            self.writer.write(last as u32, 1);
+            self.writer.write(1, 2);
+            self.write_symbols(&fixed_litlen, &fixed_dist);
+        } else {
+            self.writer.write(last as u32, 1);
+            self.writer.write(2, 2);
+            self.writer.write(litlen_count as u32 - 257, 5);
+            self.writer.write(dist_count as u32 - 1, 5);
+            self.writer.write(code_count as u32 - 4, 4);
+            for &symbol in &CODE_LENGTH_ORDER[..code_count] {
+                self.writer.write(code[symbol] as u32, 3);
+            }
+
+            let codes = canonical_codes(&code).unwrap();
+            for &(symbol, extra) in &runs {
+                self.writer.write(codes[symbol], code[symbol] as u32);
+                self.writer.write(extra, run_length_extra(symbol));
+            }
+            self.write_symbols(&litlen, &dist);
+        }
+
+        self.symbols.clear();
+        self.block_start = self.pos;
+    }
+
+    /// Write the symbols of the block with the given codes, and its end.
+    fn write_symbols(&mut self, litlen: &[u8], dist: &[u8]) {
+        let litlen_codes = canonical_codes(litlen).unwrap();
+        let dist_codes = canonical_codes(dist).unwrap();
+        let writer = &mut self.writer;
+
+        for &symbol in &self.symbols {
+            match symbol {
+                Symbol::Literal(byte) => {
+                    writer.write(litlen_codes[byte as usize], litlen[byte as usize] as u32);
+                }
+                Symbol::Match { length, distance } => {
+                    let (length, distance) = (length as usize, distance as usize);
+                    let code = length_code(length);
+                    writer.write(litlen_codes[257 + code], litlen[257 + code] as u32);
+                    writer.write(
+                        (length - LENGTH_BASE[code] as usize) as u32,
+                        LENGTH_EXTRA[code] as u32,
+                    );
+
+                    let code = dist_code(distance);
+                    writer.write(dist_codes[code], dist[code] as u32);
+                    writer.write(
+                        (distance - DIST_BASE[code] as usize) as u32,
+                        DIST_EXTRA[code] as u32,
+                    );
+                }
+            }
+        }
+
+        writer.write(litlen_codes[256], litlen[256] as u32);
+    }
+
+    /// Compress `data` into `out`, except its last bytes, which may be the
+    /// start of matches with the data that follows.
+    pub fn update(&mut self, data: &[u8], out: &mut Vec<u8>) {
+        self.data.extend_from_slice(data);
+        let end = self.start + self.data.len();
+        if end > self.pos + MAX_MATCH {
+            self.compress(end - MAX_MATCH);
+        }
+
+        // Drop the data before the window and the current block.
+        let keep = self.block_start.min(self.pos.saturating_sub(WINDOW));
+        if keep - self.start >= WINDOW {
+            self.data.drain(..keep - self.start);
+            self.start = keep;
+        }
+
+        out.append(&mut self.writer.out);
+    }
+
+    /// Compress the rest of the data into `out`, ending the stream.
+    pub fn finish(&mut self, out: &mut Vec<u8>) {
+        self.compress(self.start + self.data.len());
+        self.end_block(true);
+        self.writer.align();
+        out.append(&mut self.writer.out);
+    }
+}
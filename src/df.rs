@@ -0,0 +1,432 @@
+//! The `df` command, reporting the space used and available on the mounted
+//! file systems, listed in `/proc/self/mountinfo`.
+
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::utils::*;
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("df: {}", message);
+    std::process::exit(-290);
+}
+
+/// A mounted file system.
+struct Mount {
+    source: String,
+    fs_type: String,
+    target: String,
+}
+
+/// Decode the octal escapes of the fields of `mountinfo` (`\040` for a
+/// space).
+fn unescape(field: &str) -> String {
+    let mut bytes = Vec::new();
+    let mut rest = field.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let code = tail
+            .get(..3)
+            .filter(|digits| byte == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match code {
+            Some(code) => {
+                bytes.push(code);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// Read the mounted file systems, in the order they were mounted. Each line
+/// of `mountinfo` has the mount point as 5th field, then the type and the
+/// source after a `-` field.
+fn mounts() -> std::io::Result<Vec<Mount>> {
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo")?;
+    let mut mounts = Vec::new();
+
+    for line in mountinfo.lines() {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let Some(separator) = fields.iter().position(|&field| field == "-") else {
+            continue;
+        };
+        let (Some(target), Some(fs_type), Some(source)) = (
+            fields.get(4),
+            fields.get(separator + 1),
+            fields.get(separator + 2),
+        ) else {
+            continue;
+        };
+
+        mounts.push(Mount {
+            source: unescape(source),
+            fs_type: unescape(fs_type),
+            target: unescape(target),
+        });
+    }
+
+    Ok(mounts)
+}
+
+/// Get the statistics of the file system of `path`.
+fn statvfs(path: &str) -> std::io::Result<libc::statvfs> {
+    let path = std::ffi::CString::new(Path::new(path).as_os_str().as_bytes())
+        .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+
+    // SAFETY: `path` is a valid C string, and `stats` is only read if
+    // `statvfs` filled it.
+    unsafe {
+        let mut stats = std::mem::zeroed();
+        if libc::statvfs(path.as_ptr(), &mut stats) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(stats)
+    }
+}
+
+/// A column of the output (named like for `--output`).
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Source,
+    FsType,
+    ITotal,
+    IUsed,
+    IAvail,
+    IPcent,
+    Size,
+    Used,
+    Avail,
+    Pcent,
+    File,
+    Target,
+}
+
+impl Field {
+    /// Every field, in the order of `--output` without a list.
+    const ALL: [Field; 12] = [
+        Field::Source,
+        Field::FsType,
+        Field::ITotal,
+        Field::IUsed,
+        Field::IAvail,
+        Field::IPcent,
+        Field::Size,
+        Field::Used,
+        Field::Avail,
+        Field::Pcent,
+        Field::File,
+        Field::Target,
+    ];
+
+    fn parse(name: &str) -> Option<Field> {
+        let names = [
+            "source", "fstype", "itotal", "iused", "iavail", "ipcent", "size", "used", "avail",
+            "pcent", "file", "target",
+        ];
+        let i = names.iter().position(|&field| field == name)?;
+        Some(Field::ALL[i])
+    }
+
+    /// Get the narrowest width of the column.
+    fn min_width(self) -> usize {
+        match self {
+            Field::Source => 14,
+            Field::FsType | Field::File | Field::Pcent | Field::IPcent => 4,
+            Field::Target => 0,
+            _ => 5,
+        }
+    }
+
+    /// Check if the values of the column are numbers, aligned to the right.
+    fn is_number(self) -> bool {
+        !matches!(
+            self,
+            Field::Source | Field::FsType | Field::File | Field::Target
+        )
+    }
+}
+
+/// Compute a percentage of use, rounded up, like `df` does. It's unknown
+/// if nothing can be used.
+fn percent(used: u64, available: u64) -> String {
+    let total = used as u128 + available as u128;
+    if total == 0 {
+        return String::from("-");
+    }
+    format!("{}%", (used as u128 * 100).div_ceil(total))
+}
+
+/// The settings of `df`.
+struct Df {
+    human: bool,
+    posix: bool,
+    /// The fields were chosen with `--output`.
+    output: bool,
+    fields: Vec<Field>,
+}
+
+impl Df {
+    fn header(&self, field: Field) -> &'static str {
+        match field {
+            Field::Source => "Filesystem",
+            Field::FsType => "Type",
+            Field::ITotal => "Inodes",
+            Field::IUsed => "IUsed",
+            Field::IAvail => "IFree",
+            Field::IPcent => "IUse%",
+            Field::Size if self.human => "Size",
+            Field::Size if self.posix => "1024-blocks",
+            Field::Size => "1K-blocks",
+            Field::Used => "Used",
+            Field::Avail if self.human || self.output => "Avail",
+            Field::Avail => "Available",
+            Field::Pcent if self.posix => "Capacity",
+            Field::Pcent => "Use%",
+            Field::File => "File",
+            Field::Target => "Mounted on",
+        }
+    }
+
+    /// Format a size in bytes, in blocks of 1K.
+    fn size(&self, bytes: u64) -> String {
+        if self.human {
+            human_size(bytes)
+        } else {
+            bytes.div_ceil(1024).to_string()
+        }
+    }
+
+    fn count(&self, count: u64) -> String {
+        if self.human {
+            human_size(count)
+        } else {
+            count.to_string()
+        }
+    }
+
+    /// Get the values of the fields for the file system mounted on `mount`,
+    /// found from `file`.
+    fn row(&self, mount: &Mount, file: &str, stats: &libc::statvfs) -> Vec<String> {
+        let block = stats.f_frsize;
+        let total = stats.f_blocks * block;
+        let used = stats.f_blocks.saturating_sub(stats.f_bfree) * block;
+        let available = stats.f_bavail * block;
+        let inodes = stats.f_files;
+        let free_inodes = stats.f_ffree;
+        let used_inodes = inodes.saturating_sub(free_inodes);
+
+        self.fields
+            .iter()
+            .map(|field| match field {
+                Field::Source => mount.source.clone(),
+                Field::FsType => mount.fs_type.clone(),
+                Field::ITotal => self.count(inodes),
+                Field::IUsed => self.count(used_inodes),
+                Field::IAvail => self.count(free_inodes),
+                Field::IPcent => percent(used_inodes, free_inodes),
+                Field::Size => self.size(total),
+                Field::Used => self.size(used),
+                Field::Avail => self.size(available),
+                Field::Pcent => percent(used, available),
+                Field::File => file.to_string(),
+                Field::Target => mount.target.clone(),
+            })
+            .collect()
+    }
+
+    /// Print the rows under the headers, in columns as wide as their
+    /// values. The last column isn't padded.
+    fn print(&self, rows: &[Vec<String>]) {
+        let headers: Vec<String> = self
+            .fields
+            .iter()
+            .map(|&field| self.header(field).to_string())
+            .collect();
+        let widths: Vec<usize> = self
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                rows.iter()
+                    .chain([&headers])
+                    .map(|row| row[i].chars().count())
+                    .fold(field.min_width(), usize::max)
+            })
+            .collect();
+
+        let stdout = std::io::stdout();
+        let mut out = std::io::BufWriter::new(stdout.lock());
+
+        for row in [&headers].into_iter().chain(rows) {
+            let mut line = String::new();
+            for (i, (value, field)) in row.iter().zip(&self.fields).enumerate() {
+                let width = widths[i];
+                if i > 0 {
+                    line.push(' ');
+                }
+                if field.is_number() {
+                    line.push_str(&format!("{:>width$}", value));
+                } else if i + 1 < row.len() {
+                    line.push_str(&format!("{:<width$}", value));
+                } else {
+                    line.push_str(value);
+                }
+            }
+            let _ = writeln!(out, "{}", line);
+        }
+
+        let _ = out.flush();
+    }
+}
+
+pub fn df(args: &[String]) {
+    let with_value = ["-x", "--exclude-type"];
+    let Some((opts, paths)) = parse_options(args, &with_value) else {
+        println!("Invalid command");
+        std::process::exit(-290);
+    };
+
+    let mut df = Df {
+        human: false,
+        posix: false,
+        output: false,
+        fields: Vec::new(),
+    };
+    let mut types = false;
+    let mut inodes = false;
+    let mut excluded_types = Vec::new();
+
+    for (opt, value) in opts {
+        match (opt.as_str(), value) {
+            ("-h" | "--human-readable", _) => df.human = true,
+            ("-T" | "--print-type", _) => types = true,
+            ("-i" | "--inodes", _) => inodes = true,
+            ("-P" | "--portability", _) => df.posix = true,
+            ("-k", _) => {}
+            ("-x" | "--exclude-type", Some(value)) => excluded_types.push(value),
+            ("--output", None) => {
+                df.output = true;
+                df.fields = Field::ALL.to_vec();
+            }
+            ("--output", Some(value)) => {
+                df.output = true;
+                for name in value.split(',') {
+                    let Some(field) = Field::parse(name) else {
+                        fail(format_args!("option --output: field '{}' unknown", name));
+                    };
+                    if df.fields.contains(&field) {
+                        fail(format_args!(
+                            "option --output: field '{}' used more than once",
+                            name
+                        ));
+                    }
+                    df.fields.push(field);
+                }
+            }
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-290);
+            }
+        }
+    }
+
+    if df.output {
+        for (set, option) in [(inodes, "-i"), (types, "-T"), (df.posix, "-P")] {
+            if set {
+                fail(format_args!(
+                    "options {} and --output are mutually exclusive",
+                    option
+                ));
+            }
+        }
+    } else {
+        df.fields.push(Field::Source);
+        if types {
+            df.fields.push(Field::FsType);
+        }
+        if inodes {
+            df.fields
+                .extend([Field::ITotal, Field::IUsed, Field::IAvail, Field::IPcent]);
+        } else {
+            df.fields
+                .extend([Field::Size, Field::Used, Field::Avail, Field::Pcent]);
+        }
+        df.fields.push(Field::Target);
+    }
+
+    let mounts = mounts()
+        .unwrap_or_else(|e| fail(format_args!("/proc/self/mountinfo: {}", error_reason(&e))));
+    let shown = |mount: &Mount| !excluded_types.contains(&mount.fs_type);
+    let mut rows = Vec::new();
+    let mut failed = false;
+
+    if paths.is_empty() {
+        // A device mounted several times (or mounts hidden under another
+        // one) is shown once, with its shortest mount point.
+        let mut devices: Vec<(u64, &Mount)> = Vec::new();
+        for mount in mounts.iter().filter(|&mount| shown(mount)) {
+            let Ok(metadata) = std::fs::metadata(&mount.target) else {
+                continue;
+            };
+            match devices.iter_mut().find(|(dev, _)| *dev == metadata.dev()) {
+                Some((_, kept)) if kept.target.len() > mount.target.len() => *kept = mount,
+                Some(_) => {}
+                None => devices.push((metadata.dev(), mount)),
+            }
+        }
+
+        // The file systems without blocks (`proc`, `sysfs`...) are skipped.
+        for (_, mount) in devices {
+            match statvfs(&mount.target) {
+                Ok(stats) if stats.f_blocks == 0 => {}
+                Ok(stats) => rows.push(df.row(mount, "-", &stats)),
+                Err(e) => {
+                    eprintln!("df: {}: {}", mount.target, error_reason(&e));
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    for path in paths {
+        let stats = std::fs::canonicalize(path).and_then(|real| Ok((real, statvfs(path)?)));
+        let (real, stats) = match stats {
+            Ok(stats) => stats,
+            Err(e) => {
+                eprintln!("df: {}: {}", path, error_reason(&e));
+                failed = true;
+                continue;
+            }
+        };
+
+        // The file system is the last one mounted on the closest ancestor.
+        let mount = mounts
+            .iter()
+            .filter(|mount| real.starts_with(&mount.target))
+            .max_by_key(|mount| Path::new(&mount.target).components().count());
+        if let Some(mount) = mount.filter(|&mount| shown(mount)) {
+            rows.push(df.row(mount, path, &stats));
+        }
+    }
+
+    if rows.is_empty() {
+        if !failed {
+            eprintln!("df: no file systems processed");
+        }
+        std::process::exit(-290);
+    }
+    df.print(&rows);
+
+    if failed {
+        std::process::exit(-290);
+    }
+}
@@ -0,0 +1,952 @@
+//! The `diff` command, comparing files line by line (or directories, file
+//! by file). The differences are found with Myers' algorithm, in linear
+//! space, and printed in the normal, context or unified format.
+
+use std::collections::HashMap;
+use std::io::{BufWriter, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+
+use crate::utils::*;
+use crate::walk::Walk;
+
+/// The lines of a file, without their newline.
+pub struct Lines {
+    pub data: Vec<u8>,
+    /// The start and end of every line in `data`.
+    pub bounds: Vec<(usize, usize)>,
+    /// The last line has no newline.
+    pub missing_newline: bool,
+}
+
+impl Lines {
+    pub fn new(data: Vec<u8>) -> Self {
+        let mut bounds = Vec::new();
+        let mut start = 0;
+
+        while start < data.len() {
+            let end = data[start..]
+                .iter()
+                .position(|&byte| byte == b'\n')
+                .map_or(data.len(), |i| start + i);
+            bounds.push((start, end));
+            start = end + 1;
+        }
+
+        let missing_newline = !data.is_empty() && data.last() != Some(&b'\n');
+        Lines {
+            data,
+            bounds,
+            missing_newline,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bounds.len()
+    }
+
+    pub fn line(&self, i: usize) -> &[u8] {
+        let (start, end) = self.bounds[i];
+        &self.data[start..end]
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Format {
+    Normal,
+    Context(usize),
+    Unified(usize),
+}
+
+struct Options {
+    format: Format,
+    recursive: bool,
+    new_file: bool,
+    brief: bool,
+    ignore_case: bool,
+    ignore_all_space: bool,
+    ignore_blank_lines: bool,
+    /// The options as given, repeated before each file compared in
+    /// directories.
+    switches: String,
+}
+
+/// A difference: the lines `a.0..a.1` of the first file are replaced by the
+/// lines `b.0..b.1` of the second one.
+#[derive(Clone, Copy)]
+struct Change {
+    a: (usize, usize),
+    b: (usize, usize),
+}
+
+/// Find the middle snake of the edit graph between `a` and `b`, which
+/// splits it in two halves of about the same cost. Past `max_cost` edits,
+/// the search gives up and splits at the furthest point reached, which
+/// keeps very different files fast at the expense of a minimal diff.
+fn middle_snake(
+    a: &[usize],
+    b: &[usize],
+    forward: &mut [usize],
+    backward: &mut [usize],
+    max_cost: isize,
+) -> (usize, usize) {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta & 1 == 1;
+    let offset = (forward.len() / 2) as isize;
+    let at = |k: isize| (k + offset) as usize;
+
+    forward[at(1)] = 0;
+    backward[at(1)] = 0;
+
+    for d in 0..=(n + m + 1) / 2 {
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && forward[at(k - 1)] < forward[at(k + 1)]) {
+                forward[at(k + 1)] as isize
+            } else {
+                forward[at(k - 1)] as isize + 1
+            };
+            let mut y = x - k;
+            let start = (x, y);
+
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[at(k)] = x as usize;
+
+            if odd && (k - delta).abs() < d && x + backward[at(delta - k)] as isize >= n {
+                return (start.0 as usize, start.1 as usize);
+            }
+        }
+
+        for k in (-d..=d).rev().step_by(2) {
+            let mut x = if k == -d || (k != d && backward[at(k - 1)] < backward[at(k + 1)]) {
+                backward[at(k + 1)] as isize
+            } else {
+                backward[at(k - 1)] as isize + 1
+            };
+            let mut y = x - k;
+
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[at(k)] = x as usize;
+
+            if !odd && (k - delta).abs() <= d && x + forward[at(delta - k)] as isize >= n {
+                return ((n - x) as usize, (m - y) as usize);
+            }
+        }
+
+        if d >= max_cost {
+            let mut best = (0, 0, 0);
+            for k in (-d..=d).step_by(2) {
+                let x = (forward[at(k)] as isize).min(n);
+                let y = x - k;
+                if (0..=m).contains(&y) && x + y > best.0 {
+                    best = (x + y, x, y);
+                }
+
+                let x = (backward[at(k)] as isize).min(n);
+                let y = x - k;
+                if (0..=m).contains(&y) && x + y > best.0 {
+                    best = (x + y, n - x, m - y);
+                }
+            }
+
+            // Splitting at an end wouldn't make progress.
+            let (x, y) = (best.1, best.2);
+            if (x, y) != (0, 0) && (x, y) != (n, m) {
+                return (x as usize, y as usize);
+            }
+        }
+    }
+
+    unreachable!()
+}
+
+/// Mark the lines of `a` and `b` which aren't part of a longest common
+/// subsequence, by splitting the problem at middle snakes.
+#[allow(clippy::too_many_arguments)]
+fn compare(
+    a: &[usize],
+    b: &[usize],
+    a_start: usize,
+    b_start: usize,
+    changed_a: &mut [bool],
+    changed_b: &mut [bool],
+    forward: &mut [usize],
+    backward: &mut [usize],
+    max_cost: isize,
+) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let (a, b) = (&a[prefix..], &b[prefix..]);
+    let (a_start, b_start) = (a_start + prefix, b_start + prefix);
+
+    let suffix = a
+        .iter()
+        .rev()
+        .zip(b.iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let (a, b) = (&a[..a.len() - suffix], &b[..b.len() - suffix]);
+
+    if a.is_empty() || b.is_empty() {
+        changed_a[a_start..a_start + a.len()].fill(true);
+        changed_b[b_start..b_start + b.len()].fill(true);
+        return;
+    }
+
+    let (x, y) = middle_snake(a, b, forward, backward, max_cost);
+    compare(
+        &a[..x],
+        &b[..y],
+        a_start,
+        b_start,
+        changed_a,
+        changed_b,
+        forward,
+        backward,
+        max_cost,
+    );
+    compare(
+        &a[x..],
+        &b[y..],
+        a_start + x,
+        b_start + y,
+        changed_a,
+        changed_b,
+        forward,
+        backward,
+        max_cost,
+    );
+}
+
+/// Shift the runs of changed lines of a file as GNU diff does: first up,
+/// merging them with previous runs, then down as far as they can go, and
+/// finally back to the last position where they are next to a change in
+/// the other file, so that replacements are shown together.
+fn shift_boundaries(ids: &[usize], changed: &mut [bool], other_changed: &[bool]) {
+    let len = ids.len();
+    // `j` is the matching position in the other file, which may go one
+    // line before its start.
+    let other = |j: isize| j >= 0 && other_changed.get(j as usize).copied().unwrap_or(false);
+
+    let (mut i, mut j) = (0, 0isize);
+    loop {
+        while i < len && !changed[i] {
+            while other(j) {
+                j += 1;
+            }
+            j += 1;
+            i += 1;
+        }
+        if i == len {
+            break;
+        }
+
+        let mut start = i;
+        while i < len && changed[i] {
+            i += 1;
+        }
+        while other(j) {
+            j += 1;
+        }
+
+        let mut corresponding;
+        loop {
+            let run = i - start;
+
+            while start > 0 && ids[start - 1] == ids[i - 1] {
+                start -= 1;
+                changed[start] = true;
+                i -= 1;
+                changed[i] = false;
+                while start > 0 && changed[start - 1] {
+                    start -= 1;
+                }
+                j -= 1;
+                while other(j) {
+                    j -= 1;
+                }
+            }
+
+            corresponding = if other(j - 1) { i } else { len };
+
+            while i < len && ids[start] == ids[i] {
+                changed[start] = false;
+                start += 1;
+                changed[i] = true;
+                i += 1;
+                while i < len && changed[i] {
+                    i += 1;
+                }
+                j += 1;
+                while other(j) {
+                    corresponding = i;
+                    j += 1;
+                }
+            }
+
+            if run == i - start {
+                break;
+            }
+        }
+
+        while corresponding < i {
+            start -= 1;
+            changed[start] = true;
+            i -= 1;
+            changed[i] = false;
+            j -= 1;
+            while other(j) {
+                j -= 1;
+            }
+        }
+    }
+}
+
+/// Find the changes between two files.
+fn find_changes(a: &Lines, b: &Lines, opts: &Options) -> Vec<Change> {
+    // Lines are compared by an identifier, equal for lines which are equal
+    // once normalized. The last line without a newline differs from one
+    // with it, unless white space is ignored.
+    let mut ids = HashMap::new();
+    let mut identify = |lines: &Lines| -> Vec<usize> {
+        (0..lines.len())
+            .map(|i| {
+                let mut line = lines.line(i).to_vec();
+                if opts.ignore_all_space {
+                    line.retain(|byte| !byte.is_ascii_whitespace());
+                }
+                if opts.ignore_case {
+                    line.make_ascii_lowercase();
+                }
+                let missing_newline =
+                    i + 1 == lines.len() && lines.missing_newline && !opts.ignore_all_space;
+
+                let count = ids.len();
+                *ids.entry((line, missing_newline)).or_insert(count)
+            })
+            .collect()
+    };
+    let (ids_a, ids_b) = (identify(a), identify(b));
+
+    let mut changed_a = vec![false; ids_a.len()];
+    let mut changed_b = vec![false; ids_b.len()];
+    let size = 2 * (ids_a.len() + ids_b.len()) + 4;
+    let (mut forward, mut backward) = (vec![0; size], vec![0; size]);
+
+    // About the square root of the number of lines, like GNU diff.
+    let mut max_cost = 1;
+    let mut diagonals = ids_a.len() + ids_b.len() + 3;
+    while diagonals != 0 {
+        max_cost <<= 1;
+        diagonals >>= 2;
+    }
+    let max_cost = max_cost.max(256);
+
+    compare(
+        &ids_a,
+        &ids_b,
+        0,
+        0,
+        &mut changed_a,
+        &mut changed_b,
+        &mut forward,
+        &mut backward,
+        max_cost,
+    );
+    shift_boundaries(&ids_a, &mut changed_a, &changed_b);
+    shift_boundaries(&ids_b, &mut changed_b, &changed_a);
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < ids_a.len() || j < ids_b.len() {
+        if i < ids_a.len() && j < ids_b.len() && !changed_a[i] && !changed_b[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+
+        let (start_a, start_b) = (i, j);
+        while i < ids_a.len() && changed_a[i] {
+            i += 1;
+        }
+        while j < ids_b.len() && changed_b[j] {
+            j += 1;
+        }
+        changes.push(Change {
+            a: (start_a, i),
+            b: (start_b, j),
+        });
+    }
+
+    if opts.ignore_blank_lines {
+        let blank = |lines: &Lines, (start, end): (usize, usize)| {
+            (start..end).all(|i| lines.line(i).iter().all(|byte| byte.is_ascii_whitespace()))
+        };
+        changes.retain(|change| !(blank(a, change.a) && blank(b, change.b)));
+    }
+
+    changes
+}
+
+/// Group the changes which are close enough to share their context.
+fn hunks(changes: &[Change], context: usize) -> Vec<&[Change]> {
+    let mut hunks = Vec::new();
+    let mut start = 0;
+
+    for i in 1..=changes.len() {
+        if i == changes.len() || changes[i].a.0 - changes[i - 1].a.1 > 2 * context {
+            hunks.push(&changes[start..i]);
+            start = i;
+        }
+    }
+
+    hunks
+}
+
+/// Format a range of lines for the normal format (1-based, inclusive).
+fn normal_range(start: usize, end: usize) -> String {
+    if end - start > 1 {
+        format!("{},{}", start + 1, end)
+    } else if end > start {
+        (start + 1).to_string()
+    } else {
+        start.to_string()
+    }
+}
+
+fn context_range(start: usize, end: usize) -> String {
+    if end - start > 1 {
+        format!("{},{}", start + 1, end)
+    } else {
+        end.to_string()
+    }
+}
+
+fn unified_range(start: usize, end: usize) -> String {
+    match end - start {
+        0 => format!("{},0", start),
+        1 => (start + 1).to_string(),
+        len => format!("{},{}", start + 1, len),
+    }
+}
+
+/// A file to compare, read at once.
+struct Input {
+    label: String,
+    lines: Lines,
+    /// The modification time, shown in the headers.
+    mtime: chrono::DateTime<chrono::Local>,
+}
+
+impl Input {
+    fn read(path: &Path, absent: bool) -> std::io::Result<Self> {
+        let label = path.to_string_lossy().into_owned();
+
+        if absent {
+            return Ok(Input {
+                label,
+                lines: Lines::new(Vec::new()),
+                mtime: chrono::DateTime::from(std::time::UNIX_EPOCH),
+            });
+        }
+
+        if label == "-" {
+            let mut data = Vec::new();
+            std::io::stdin().lock().read_to_end(&mut data)?;
+            return Ok(Input {
+                label,
+                lines: Lines::new(data),
+                mtime: chrono::Local::now(),
+            });
+        }
+
+        let data = std::fs::read(path)?;
+        let mtime = std::fs::metadata(path)?.modified()?;
+        Ok(Input {
+            label,
+            lines: Lines::new(data),
+            mtime: chrono::DateTime::from(mtime),
+        })
+    }
+
+    /// The label of the file with its time, which is shown like `ctime`
+    /// does in the context format.
+    fn header(&self, format: Format) -> String {
+        let time_format = match format {
+            Format::Context(_) => "%a %b %e %H:%M:%S %Y",
+            _ => "%Y-%m-%d %H:%M:%S%.9f %z",
+        };
+        format!("{}\t{}", self.label, self.mtime.format(time_format))
+    }
+}
+
+struct Differ {
+    opts: Options,
+    out: BufWriter<std::io::StdoutLock<'static>>,
+    different: bool,
+    failed: bool,
+}
+
+impl Differ {
+    /// Print the lines `start..end` of a file, with a prefix.
+    fn print_lines(
+        &mut self,
+        lines: &Lines,
+        (start, end): (usize, usize),
+        prefix: &str,
+    ) -> std::io::Result<()> {
+        for i in start..end {
+            self.out.write_all(prefix.as_bytes())?;
+            self.out.write_all(lines.line(i))?;
+            self.out.write_all(b"\n")?;
+
+            if i + 1 == lines.len() && lines.missing_newline {
+                self.out.write_all(b"\\ No newline at end of file\n")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn print_normal(&mut self, a: &Lines, b: &Lines, changes: &[Change]) -> std::io::Result<()> {
+        for change in changes {
+            let kind = match (change.a.0 < change.a.1, change.b.0 < change.b.1) {
+                (true, true) => 'c',
+                (true, false) => 'd',
+                _ => 'a',
+            };
+            writeln!(
+                self.out,
+                "{}{}{}",
+                normal_range(change.a.0, change.a.1),
+                kind,
+                normal_range(change.b.0, change.b.1)
+            )?;
+
+            self.print_lines(a, change.a, "< ")?;
+            if kind == 'c' {
+                writeln!(self.out, "---")?;
+            }
+            self.print_lines(b, change.b, "> ")?;
+        }
+
+        Ok(())
+    }
+
+    fn print_unified(
+        &mut self,
+        a: &Input,
+        b: &Input,
+        changes: &[Change],
+        context: usize,
+    ) -> std::io::Result<()> {
+        writeln!(self.out, "--- {}", a.header(Format::Unified(0)))?;
+        writeln!(self.out, "+++ {}", b.header(Format::Unified(0)))?;
+        let (a, b) = (&a.lines, &b.lines);
+
+        for hunk in hunks(changes, context) {
+            let (first, last) = (hunk[0], hunk[hunk.len() - 1]);
+            let start_a = first.a.0.saturating_sub(context);
+            let start_b = first.b.0 - (first.a.0 - start_a);
+            let end_a = (last.a.1 + context).min(a.len());
+            let end_b = last.b.1 + (end_a - last.a.1);
+
+            writeln!(
+                self.out,
+                "@@ -{} +{} @@",
+                unified_range(start_a, end_a),
+                unified_range(start_b, end_b)
+            )?;
+
+            let mut cursor = start_a;
+            for change in hunk {
+                self.print_lines(a, (cursor, change.a.0), " ")?;
+                self.print_lines(a, change.a, "-")?;
+                self.print_lines(b, change.b, "+")?;
+                cursor = change.a.1;
+            }
+            self.print_lines(a, (cursor, end_a), " ")?;
+        }
+
+        Ok(())
+    }
+
+    fn print_context(
+        &mut self,
+        a: &Input,
+        b: &Input,
+        changes: &[Change],
+        context: usize,
+    ) -> std::io::Result<()> {
+        writeln!(self.out, "*** {}", a.header(Format::Context(0)))?;
+        writeln!(self.out, "--- {}", b.header(Format::Context(0)))?;
+        let (a, b) = (&a.lines, &b.lines);
+
+        for hunk in hunks(changes, context) {
+            let (first, last) = (hunk[0], hunk[hunk.len() - 1]);
+            let start_a = first.a.0.saturating_sub(context);
+            let start_b = first.b.0 - (first.a.0 - start_a);
+            let end_a = (last.a.1 + context).min(a.len());
+            let end_b = last.b.1 + (end_a - last.a.1);
+
+            writeln!(self.out, "***************")?;
+
+            // Each side is shown only if it has changed lines, those which
+            // are replaced by others being marked with `!`.
+            writeln!(self.out, "*** {} ****", context_range(start_a, end_a))?;
+            if hunk.iter().any(|change| change.a.0 < change.a.1) {
+                let mut cursor = start_a;
+                for change in hunk {
+                    self.print_lines(a, (cursor, change.a.0), "  ")?;
+                    let prefix = if change.b.0 < change.b.1 { "! " } else { "- " };
+                    self.print_lines(a, change.a, prefix)?;
+                    cursor = change.a.1;
+                }
+                self.print_lines(a, (cursor, end_a), "  ")?;
+            }
+
+            writeln!(self.out, "--- {} ----", context_range(start_b, end_b))?;
+            if hunk.iter().any(|change| change.b.0 < change.b.1) {
+                let mut cursor = start_b;
+                for change in hunk {
+                    self.print_lines(b, (cursor, change.b.0), "  ")?;
+                    let prefix = if change.a.0 < change.a.1 { "! " } else { "+ " };
+                    self.print_lines(b, change.b, prefix)?;
+                    cursor = change.b.1;
+                }
+                self.print_lines(b, (cursor, end_b), "  ")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn error(&mut self, path: &Path, e: &std::io::Error) {
+        let _ = self.out.flush();
+        eprintln!("diff: {}: {}", path.display(), error_reason(e));
+        self.failed = true;
+    }
+
+    /// Compare two files, one of which may be absent (with `-N`). With
+    /// `header`, the files are in directories being compared.
+    fn diff_files(
+        &mut self,
+        path_a: &Path,
+        path_b: &Path,
+        absent: (bool, bool),
+        header: bool,
+    ) -> std::io::Result<()> {
+        let a = match Input::read(path_a, absent.0) {
+            Ok(a) => a,
+            Err(e) => {
+                self.error(path_a, &e);
+                return Ok(());
+            }
+        };
+        let b = match Input::read(path_b, absent.1) {
+            Ok(b) => b,
+            Err(e) => {
+                self.error(path_b, &e);
+                return Ok(());
+            }
+        };
+
+        let binary = a.lines.data.contains(&0) || b.lines.data.contains(&0);
+        if binary || self.opts.brief {
+            let same = if binary || !self.has_normalization() {
+                a.lines.data == b.lines.data
+            } else {
+                find_changes(&a.lines, &b.lines, &self.opts).is_empty()
+            };
+
+            if !same {
+                let kind = if binary { "Binary files" } else { "Files" };
+                writeln!(self.out, "{} {} and {} differ", kind, a.label, b.label)?;
+                self.different = true;
+            }
+            return Ok(());
+        }
+
+        let changes = find_changes(&a.lines, &b.lines, &self.opts);
+        if changes.is_empty() {
+            return Ok(());
+        }
+        self.different = true;
+
+        if header {
+            writeln!(
+                self.out,
+                "diff{} {} {}",
+                self.opts.switches, a.label, b.label
+            )?;
+        }
+
+        match self.opts.format {
+            Format::Normal => self.print_normal(&a.lines, &b.lines, &changes),
+            Format::Unified(context) => self.print_unified(&a, &b, &changes, context),
+            Format::Context(context) => self.print_context(&a, &b, &changes, context),
+        }
+    }
+
+    fn has_normalization(&self) -> bool {
+        self.opts.ignore_case || self.opts.ignore_all_space || self.opts.ignore_blank_lines
+    }
+
+    /// List the names of the files in a directory, sorted.
+    fn list_dir(&mut self, dir: &Path) -> Vec<std::ffi::OsString> {
+        let mut names = Vec::new();
+
+        for entry in Walk::new(dir).max_depth(1) {
+            match entry {
+                Ok(entry) if entry.depth() > 0 => {
+                    names.extend(entry.path().file_name().map(|name| name.to_owned()))
+                }
+                Ok(_) => {}
+                Err(e) => self.error(&e.path, &e.error),
+            }
+        }
+
+        names.sort();
+        names
+    }
+
+    /// Compare two directories, one of which may be absent (with `-N`).
+    fn diff_dirs(
+        &mut self,
+        dir_a: &Path,
+        dir_b: &Path,
+        absent: (bool, bool),
+    ) -> std::io::Result<()> {
+        let names_a = if absent.0 {
+            Vec::new()
+        } else {
+            self.list_dir(dir_a)
+        };
+        let names_b = if absent.1 {
+            Vec::new()
+        } else {
+            self.list_dir(dir_b)
+        };
+
+        let mut names = names_a.iter().chain(&names_b).cloned().collect::<Vec<_>>();
+        names.sort();
+        names.dedup();
+
+        for name in names {
+            let (path_a, path_b) = (dir_a.join(&name), dir_b.join(&name));
+            let kinds = [&path_a, &path_b].map(|path| std::fs::metadata(path).ok());
+
+            match kinds {
+                [Some(a), Some(b)] if a.is_dir() && b.is_dir() => {
+                    if self.opts.recursive {
+                        self.diff_dirs(&path_a, &path_b, (false, false))?;
+                    } else {
+                        writeln!(
+                            self.out,
+                            "Common subdirectories: {} and {}",
+                            path_a.display(),
+                            path_b.display()
+                        )?;
+                    }
+                }
+                [Some(a), Some(b)] if a.is_file() && b.is_file() => {
+                    self.diff_files(&path_a, &path_b, (false, false), true)?;
+                }
+                [Some(a), Some(b)] => {
+                    let kind = |metadata: &std::fs::Metadata| match metadata.is_dir() {
+                        true => "directory",
+                        false if metadata.is_file() => "regular file",
+                        false => "special file",
+                    };
+                    writeln!(
+                        self.out,
+                        "File {} is a {} while file {} is a {}",
+                        path_a.display(),
+                        kind(&a),
+                        path_b.display(),
+                        kind(&b)
+                    )?;
+                    self.different = true;
+                }
+                [present_a, present_b] if self.opts.new_file => {
+                    let present = present_a.as_ref().or(present_b.as_ref()).unwrap();
+                    let absent = (present_a.is_none(), present_b.is_none());
+
+                    if !present.is_dir() {
+                        self.diff_files(&path_a, &path_b, absent, true)?;
+                    } else if self.opts.recursive {
+                        self.diff_dirs(&path_a, &path_b, absent)?;
+                    }
+                }
+                [present_a, _] => {
+                    let (dir, name) = match present_a {
+                        Some(_) => (dir_a, &name),
+                        None => (dir_b, &name),
+                    };
+                    writeln!(
+                        self.out,
+                        "Only in {}: {}",
+                        dir.display(),
+                        name.to_string_lossy()
+                    )?;
+                    self.different = true;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn diff(&mut self, path_a: &Path, path_b: &Path) -> std::io::Result<()> {
+        let metadata = |path: &Path| match path.to_str() {
+            Some("-") => None,
+            _ => std::fs::metadata(path).ok(),
+        };
+        let (meta_a, meta_b) = (metadata(path_a), metadata(path_b));
+        let is_dir =
+            |meta: &Option<std::fs::Metadata>| meta.as_ref().is_some_and(|meta| meta.is_dir());
+
+        // The same file is never different.
+        if let (Some(a), Some(b)) = (&meta_a, &meta_b) {
+            if (a.dev(), a.ino()) == (b.dev(), b.ino()) && !a.is_dir() {
+                return Ok(());
+            }
+        }
+
+        match (is_dir(&meta_a), is_dir(&meta_b)) {
+            (true, true) => self.diff_dirs(path_a, path_b, (false, false)),
+            // A file is compared with the file of the same name in a directory.
+            (true, false) | (false, true) => {
+                let (dir, file) = if is_dir(&meta_a) {
+                    (path_a, path_b)
+                } else {
+                    (path_b, path_a)
+                };
+                let Some(name) = file.file_name().filter(|_| file.to_str() != Some("-")) else {
+                    let _ = self.out.flush();
+                    eprintln!("diff: cannot compare '-' to a directory");
+                    self.failed = true;
+                    return Ok(());
+                };
+
+                let inner = dir.join(name);
+                if is_dir(&meta_a) {
+                    self.diff(&inner, path_b)
+                } else {
+                    self.diff(path_a, &inner)
+                }
+            }
+            (false, false) => {
+                let absent_a =
+                    self.opts.new_file && meta_a.is_none() && path_a.to_str() != Some("-");
+                let absent_b =
+                    self.opts.new_file && meta_b.is_none() && path_b.to_str() != Some("-");
+                self.diff_files(path_a, path_b, (absent_a, absent_b), false)
+            }
+        }
+    }
+}
+
+pub fn diff(args: &[String]) {
+    // `-u N` is accepted too, for `-U N`.
+    let mut args = args.to_vec();
+    for i in 0..args.len().saturating_sub(1) {
+        if args[i] == "-u"
+            && !args[i + 1].is_empty()
+            && args[i + 1].bytes().all(|b| b.is_ascii_digit())
+        {
+            args[i] = "-U".to_owned();
+        }
+    }
+
+    let with_value = ["-U", "-C", "--unified", "--context"];
+    let Some((opts, files)) = parse_options(&args, &with_value) else {
+        eprintln!("Usage: diff [-u|-U N|-c|-C N] [-rNqiwB] FILE1 FILE2");
+        std::process::exit(-200);
+    };
+
+    let mut options = Options {
+        format: Format::Normal,
+        recursive: false,
+        new_file: false,
+        brief: false,
+        ignore_case: false,
+        ignore_all_space: false,
+        ignore_blank_lines: false,
+        switches: String::new(),
+    };
+
+    for (opt, value) in opts {
+        let context = || match value.as_deref().map(str::parse::<usize>) {
+            None => 3,
+            Some(Ok(context)) => context,
+            Some(Err(_)) => {
+                eprintln!(
+                    "diff: invalid context length '{}'",
+                    value.as_deref().unwrap_or_default()
+                );
+                std::process::exit(-200);
+            }
+        };
+
+        match opt.as_str() {
+            "-u" | "-U" | "--unified" => options.format = Format::Unified(context()),
+            "-c" | "-C" | "--context" => options.format = Format::Context(context()),
+            "-r" | "--recursive" => options.recursive = true,
+            "-N" | "--new-file" => options.new_file = true,
+            "-q" | "--brief" => options.brief = true,
+            "-i" | "--ignore-case" => options.ignore_case = true,
+            "-w" | "--ignore-all-space" => options.ignore_all_space = true,
+            "-B" | "--ignore-blank-lines" => options.ignore_blank_lines = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-200);
+            }
+        }
+    }
+
+    let [first, second] = files.as_slice() else {
+        eprintln!("Usage: diff [-u|-U N|-c|-C N] [-rNqiwB] FILE1 FILE2");
+        std::process::exit(-200);
+    };
+
+    let operands = [first.as_str(), second.as_str()];
+    options.switches = args
+        .iter()
+        .filter(|arg| !operands.contains(&arg.as_str()))
+        .map(|arg| format!(" {}", arg))
+        .collect();
+
+    for path in operands {
+        if path != "-" && !options.new_file && std::fs::metadata(path).is_err() {
+            let e = std::fs::metadata(path).unwrap_err();
+            eprintln!("diff: {}: {}", path, error_reason(&e));
+            std::process::exit(-200);
+        }
+    }
+
+    let mut differ = Differ {
+        opts: options,
+        out: BufWriter::new(std::io::stdout().lock()),
+        different: false,
+        failed: false,
+    };
+
+    let result = differ
+        .diff(&PathBuf::from(first), &PathBuf::from(second))
+        .and_then(|_| differ.out.flush());
+    if let Err(e) = result {
+        eprintln!("diff: {}", error_reason(&e));
+        std::process::exit(-200);
+    }
+
+    if differ.failed {
+        std::process::exit(-200);
+    }
+    std::process::exit(i32::from(differ.different));
+}
@@ -0,0 +1,280 @@
+//! The `du` command, summarizing the disk usage of directory trees. Files
+//! with several hard links are counted once.
+
+use std::collections::HashSet;
+use std::fs::Metadata;
+use std::io::Write;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::utils::*;
+use crate::walk::{DirEntry, Walk, WalkError};
+
+/// The settings of `du`.
+struct Du {
+    /// Count the sizes of the files instead of the space allocated to them.
+    apparent: bool,
+    /// The unit of the sizes printed, if they aren't for humans.
+    block_size: u64,
+    human: bool,
+    /// Print the files too, not only the directories.
+    all: bool,
+    /// The deepest directories printed.
+    max_depth: usize,
+    excludes: Vec<String>,
+    dereference: bool,
+    one_file_system: bool,
+    /// The files with several links and the directories already counted.
+    seen: Mutex<HashSet<(u64, u64)>>,
+    failed: AtomicBool,
+}
+
+impl Du {
+    /// Get the size of a file, in bytes.
+    fn size(&self, metadata: &Metadata) -> u64 {
+        if self.apparent {
+            metadata.len()
+        } else {
+            metadata.blocks() * 512
+        }
+    }
+
+    /// Check if a file is counted for the first time.
+    fn first_seen(&self, metadata: &Metadata) -> bool {
+        if !metadata.is_dir() && metadata.nlink() < 2 {
+            return true;
+        }
+
+        let id = (metadata.dev(), metadata.ino());
+        self.seen.lock().unwrap().insert(id)
+    }
+
+    /// Check if a file is excluded, by its path or its name.
+    fn excluded(&self, path: &Path) -> bool {
+        let path = path.to_string_lossy();
+        let name = path.rsplit('/').next().unwrap_or(&path);
+
+        self.excludes
+            .iter()
+            .any(|pattern| glob_match(pattern, &path, false) || glob_match(pattern, name, false))
+    }
+
+    fn error(&self, e: &WalkError) {
+        let what = match std::fs::symlink_metadata(&e.path) {
+            Ok(metadata) if metadata.is_dir() => "cannot read directory",
+            _ => "cannot access",
+        };
+        eprintln!(
+            "du: {} '{}': {}",
+            what,
+            e.path.display(),
+            error_reason(&e.error)
+        );
+        self.failed.store(true, Ordering::Relaxed);
+    }
+
+    fn print(&self, out: &mut impl Write, bytes: u64, path: &Path) {
+        let size = if self.human {
+            human_size(bytes)
+        } else {
+            bytes.div_ceil(self.block_size).to_string()
+        };
+        let _ = writeln!(out, "{}\t{}", size, path.display());
+    }
+
+    fn walk(&self, root: &str) -> Walk {
+        Walk::new(root)
+            .follow_root(self.dereference)
+            .follow_links(self.dereference)
+            .same_file_system(self.one_file_system)
+    }
+
+    /// Close the directories being walked which are `depth` deep or
+    /// deeper, so they aren't ancestors of the next file: print their usage
+    /// and add it to their parent, or to `total`.
+    fn close(
+        &self,
+        out: &mut impl Write,
+        open: &mut Vec<(DirEntry, u64)>,
+        depth: usize,
+        total: &mut u64,
+    ) {
+        while let Some((dir, usage)) = open.pop_if(|(dir, _)| dir.depth() >= depth) {
+            if dir.depth() <= self.max_depth {
+                self.print(out, usage, dir.path());
+            }
+            match open.last_mut() {
+                Some((_, parent)) => *parent += usage,
+                None => *total += usage,
+            }
+        }
+    }
+
+    /// Print the usage of the tree of `root`, and of its directories (and
+    /// files with `-a`) up to the maximum depth, after their contents.
+    /// Returns the usage of the tree.
+    fn tree(&self, out: &mut impl Write, root: &str) -> u64 {
+        let mut walk = self.walk(root);
+        // The directories being walked, with the usage found so far.
+        let mut open: Vec<(DirEntry, u64)> = Vec::new();
+        let mut total = 0;
+
+        while let Some(entry) = walk.next() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.error(&e);
+                    continue;
+                }
+            };
+
+            self.close(out, &mut open, entry.depth(), &mut total);
+            if self.excluded(entry.path()) || !self.first_seen(entry.metadata()) {
+                if entry.is_dir() {
+                    walk.skip_current_dir();
+                }
+                continue;
+            }
+
+            let size = self.size(entry.metadata());
+            if entry.is_dir() {
+                open.push((entry, size));
+                continue;
+            }
+
+            if entry.depth() == 0 || (self.all && entry.depth() <= self.max_depth) {
+                self.print(out, size, entry.path());
+            }
+            match open.last_mut() {
+                Some((_, usage)) => *usage += size,
+                None => total += size,
+            }
+        }
+
+        self.close(out, &mut open, 0, &mut total);
+        total
+    }
+
+    /// Get the usage of the tree of `root`, walking it with several
+    /// threads, since only the total is printed.
+    fn summary(&self, root: &str) -> Option<u64> {
+        let total = AtomicU64::new(0);
+        let found = AtomicBool::new(false);
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+        self.walk(root).for_each_parallel(threads, |entry| {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.error(&e);
+                    return false;
+                }
+            };
+
+            if self.excluded(entry.path()) || !self.first_seen(entry.metadata()) {
+                return false;
+            }
+            if entry.depth() == 0 {
+                found.store(true, Ordering::Relaxed);
+            }
+            total.fetch_add(self.size(entry.metadata()), Ordering::Relaxed);
+            true
+        });
+
+        found.into_inner().then(|| total.into_inner())
+    }
+}
+
+pub fn du(args: &[String]) {
+    let with_value = ["-d", "--max-depth", "--exclude"];
+    let Some((opts, paths)) = parse_options(args, &with_value) else {
+        println!("Invalid command");
+        std::process::exit(-280);
+    };
+
+    let mut du = Du {
+        apparent: false,
+        block_size: 1024,
+        human: false,
+        all: false,
+        max_depth: usize::MAX,
+        excludes: Vec::new(),
+        dereference: false,
+        one_file_system: false,
+        seen: Mutex::new(HashSet::new()),
+        failed: AtomicBool::new(false),
+    };
+    let mut summarize = false;
+    let mut grand_total = false;
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+        match opt.as_str() {
+            "-s" | "--summarize" => summarize = true,
+            "-h" | "--human-readable" => du.human = true,
+            "-a" | "--all" => du.all = true,
+            "-c" | "--total" => grand_total = true,
+            "-d" | "--max-depth" => {
+                du.max_depth = value.parse().unwrap_or_else(|_| {
+                    eprintln!("du: invalid maximum depth '{}'", value);
+                    std::process::exit(-280);
+                })
+            }
+            "-x" | "--one-file-system" => du.one_file_system = true,
+            "-b" | "--bytes" => {
+                du.apparent = true;
+                du.block_size = 1;
+            }
+            "--apparent-size" => du.apparent = true,
+            "--exclude" => du.excludes.push(value),
+            "-L" | "--dereference" => du.dereference = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-280);
+            }
+        }
+    }
+
+    if summarize && du.all {
+        eprintln!("du: cannot both summarize and show all entries");
+        std::process::exit(-280);
+    }
+    if summarize {
+        du.max_depth = 0;
+    }
+
+    let default = String::from(".");
+    let paths = if paths.is_empty() {
+        vec![&default]
+    } else {
+        paths
+    };
+
+    let stdout = std::io::stdout();
+    let mut out = std::io::BufWriter::new(stdout.lock());
+    let mut total = 0;
+
+    for path in paths {
+        // Only the totals are printed, so the order of the walk doesn't
+        // matter.
+        if du.max_depth == 0 {
+            if let Some(usage) = du.summary(path) {
+                du.print(&mut out, usage, Path::new(path));
+                total += usage;
+            }
+        } else {
+            total += du.tree(&mut out, path);
+        }
+    }
+
+    if grand_total {
+        du.print(&mut out, total, Path::new("total"));
+    }
+    let _ = out.flush();
+
+    if du.failed.into_inner() {
+        std::process::exit(-280);
+    }
+}
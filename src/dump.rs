@@ -0,0 +1,370 @@
+//! Reading binary input in blocks and formatting its bytes, shared by the
+//! commands that dump binary files (`od`, `hexdump`, `xxd`).
+
+use std::fmt::Write as _;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use crate::utils::*;
+
+/// The inputs of a dump, read one after the other as a single stream,
+/// from an offset and up to a length.
+pub struct Reader {
+    command: &'static str,
+    files: std::vec::IntoIter<String>,
+    input: Option<(String, Box<dyn Read>)>,
+    skip: u64,
+    limit: Option<u64>,
+    /// Set when an input couldn't be read.
+    pub failed: bool,
+}
+
+impl Reader {
+    pub fn new(command: &'static str, files: Vec<String>, skip: u64, limit: Option<u64>) -> Self {
+        Reader {
+            command,
+            files: files.into_iter(),
+            input: None,
+            skip,
+            limit,
+            failed: false,
+        }
+    }
+
+    fn report(&mut self, path: &str, e: &std::io::Error) {
+        eprintln!("{}: {}: {}", self.command, path, error_reason(e));
+        self.failed = true;
+    }
+
+    /// Open the next input, skipping the regular files which are entirely
+    /// before the offset, and seeking in the one containing it.
+    fn open_next(&mut self) -> bool {
+        while let Some(path) = self.files.next() {
+            if path == "-" {
+                self.input = Some((path, Box::new(std::io::stdin())));
+                return true;
+            }
+
+            let mut file = match std::fs::File::open(&path) {
+                Ok(file) => file,
+                Err(e) => {
+                    self.report(&path, &e);
+                    continue;
+                }
+            };
+
+            if self.skip > 0 {
+                if let Some(len) = file
+                    .metadata()
+                    .ok()
+                    .filter(|m| m.is_file())
+                    .map(|m| m.len())
+                {
+                    if len <= self.skip {
+                        self.skip -= len;
+                        continue;
+                    }
+
+                    if let Err(e) = file.seek(SeekFrom::Start(self.skip)) {
+                        self.report(&path, &e);
+                        continue;
+                    }
+                    self.skip = 0;
+                }
+            }
+
+            self.input = Some((path, Box::new(file)));
+            return true;
+        }
+
+        false
+    }
+
+    /// Fill `block` with the next bytes of the inputs. Returns how many
+    /// were read, which is less than the size of the block only at the end.
+    pub fn read(&mut self, block: &mut [u8]) -> usize {
+        let len = match self.limit {
+            Some(limit) => block.len().min(limit.try_into().unwrap_or(usize::MAX)),
+            None => block.len(),
+        };
+        let mut filled = 0;
+
+        while filled < len {
+            let Some((path, input)) = &mut self.input else {
+                if !self.open_next() {
+                    break;
+                }
+                continue;
+            };
+
+            let result = if self.skip > 0 {
+                // Inputs which can't seek are skipped by reading them.
+                match std::io::copy(&mut input.as_mut().take(self.skip), &mut std::io::sink()) {
+                    Ok(skipped) => {
+                        self.skip -= skipped;
+                        Ok(if self.skip > 0 { 0 } else { 1 })
+                    }
+                    Err(e) => Err(e),
+                }
+            } else {
+                input
+                    .read(&mut block[filled..len])
+                    .inspect(|&n| filled += n)
+            };
+
+            match result {
+                Ok(0) => self.input = None,
+                Ok(_) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    let path = path.clone();
+                    self.report(&path, &e);
+                    self.input = None;
+                }
+            }
+        }
+
+        if let Some(limit) = &mut self.limit {
+            *limit -= filled as u64;
+        }
+        filled
+    }
+
+    /// Get the number of bytes which couldn't be skipped, because the
+    /// inputs ended before the offset.
+    pub fn unskipped(&self) -> u64 {
+        self.skip
+    }
+}
+
+/// Parse an offset or a length: in hexadecimal with `0x`, in octal with
+/// a leading `0`, or else in decimal with the suffixes of `parse_size`.
+pub fn parse_offset(offset: &str) -> Option<u64> {
+    if let Some(hex) = offset
+        .strip_prefix("0x")
+        .or_else(|| offset.strip_prefix("0X"))
+    {
+        u64::from_str_radix(hex, 16).ok()
+    } else if offset.len() > 1 && offset.starts_with('0') {
+        u64::from_str_radix(&offset[1..], 8).ok()
+    } else {
+        parse_size(offset)
+    }
+}
+
+/// Get the character shown for `byte` in the text column of a dump.
+pub fn printable(byte: u8) -> char {
+    if (b' '..=b'~').contains(&byte) {
+        byte as char
+    } else {
+        '.'
+    }
+}
+
+/// Append `bytes` to `out` in hexadecimal, two digits each.
+pub fn write_hex(out: &mut String, bytes: &[u8], upper: bool) {
+    for byte in bytes {
+        let _ = if upper {
+            write!(out, "{:02X}", byte)
+        } else {
+            write!(out, "{:02x}", byte)
+        };
+    }
+}
+
+/// Replaces the blocks repeating the previous one with a single `*` line.
+pub struct Squeeze {
+    enabled: bool,
+    previous: Option<Vec<u8>>,
+    squeezing: bool,
+}
+
+impl Squeeze {
+    pub fn new(enabled: bool) -> Self {
+        Squeeze {
+            enabled,
+            previous: None,
+            squeezing: false,
+        }
+    }
+
+    /// Check if `block` repeats the previous one, and should be omitted.
+    /// The `*` line is written for the first block of a run.
+    pub fn repeats(&mut self, block: &[u8], out: &mut impl Write) -> std::io::Result<bool> {
+        if self.enabled && self.previous.as_deref() == Some(block) {
+            if !self.squeezing {
+                writeln!(out, "*")?;
+                self.squeezing = true;
+            }
+            return Ok(true);
+        }
+
+        self.squeezing = false;
+        self.previous = Some(block.to_vec());
+        Ok(false)
+    }
+}
+
+/// The names of the ASCII control characters, for `od -t a`.
+const NAMES: [&str; 33] = [
+    "nul", "soh", "stx", "etx", "eot", "enq", "ack", "bel", "bs", "ht", "nl", "vt", "ff", "cr",
+    "so", "si", "dle", "dc1", "dc2", "dc3", "dc4", "nak", "syn", "etb", "can", "em", "sub", "esc",
+    "fs", "gs", "rs", "us", "sp",
+];
+
+/// How the units of a block are shown, with their size in bytes.
+#[derive(Clone, Copy)]
+pub enum Format {
+    /// Named characters (`od -t a`).
+    Named,
+    /// Characters, or escapes (`od -t c`).
+    Char,
+    Signed(usize),
+    Unsigned(usize),
+    Octal(usize),
+    Hex(usize),
+    Float(usize),
+}
+
+impl Format {
+    pub fn size(self) -> usize {
+        match self {
+            Format::Named | Format::Char => 1,
+            Format::Signed(size)
+            | Format::Unsigned(size)
+            | Format::Octal(size)
+            | Format::Hex(size)
+            | Format::Float(size) => size,
+        }
+    }
+
+    /// Get the width of the widest unit.
+    pub fn width(self) -> usize {
+        match self {
+            Format::Named | Format::Char => 3,
+            Format::Signed(size) => [4, 6, 11, 20][size.trailing_zeros() as usize],
+            Format::Unsigned(size) => [3, 5, 10, 20][size.trailing_zeros() as usize],
+            Format::Octal(size) => [3, 6, 11, 22][size.trailing_zeros() as usize],
+            Format::Hex(size) => size * 2,
+            Format::Float(size) => {
+                if size == 4 {
+                    15
+                } else {
+                    24
+                }
+            }
+        }
+    }
+
+    /// Format the unit in `bytes`, which are in the native byte order.
+    pub fn format(self, bytes: &[u8]) -> String {
+        let unsigned = || match *bytes {
+            [a] => a as u64,
+            [a, b] => u16::from_ne_bytes([a, b]) as u64,
+            [a, b, c, d] => u32::from_ne_bytes([a, b, c, d]) as u64,
+            _ => u64::from_ne_bytes(bytes.try_into().unwrap()),
+        };
+
+        match self {
+            Format::Named => {
+                let byte = bytes[0] & 0x7f;
+                match NAMES.get(byte as usize) {
+                    Some(name) => name.to_string(),
+                    None if byte == 0x7f => "del".to_string(),
+                    None => (byte as char).to_string(),
+                }
+            }
+            Format::Char => match bytes[0] {
+                b'\0' => "\\0".to_string(),
+                0x07 => "\\a".to_string(),
+                0x08 => "\\b".to_string(),
+                0x0c => "\\f".to_string(),
+                b'\n' => "\\n".to_string(),
+                b'\r' => "\\r".to_string(),
+                b'\t' => "\\t".to_string(),
+                0x0b => "\\v".to_string(),
+                byte @ b' '..=b'~' => (byte as char).to_string(),
+                byte => format!("{:03o}", byte),
+            },
+            Format::Signed(size) => {
+                let shift = 64 - size * 8;
+                (((unsigned() << shift) as i64) >> shift).to_string()
+            }
+            Format::Unsigned(_) => unsigned().to_string(),
+            Format::Octal(_) => format!("{:0width$o}", unsigned(), width = self.width()),
+            Format::Hex(size) => format!("{:0width$x}", unsigned(), width = size * 2),
+            Format::Float(4) => {
+                let value = f32::from_ne_bytes(bytes.try_into().unwrap());
+                format_float(value as f64, value.is_subnormal(), 6, |precision| {
+                    let number = format!("{:.*e}", precision - 1, value);
+                    (number.parse() == Ok(value)).then_some(number)
+                })
+            }
+            Format::Float(_) => {
+                let value = f64::from_ne_bytes(bytes.try_into().unwrap());
+                format_float(value, value.is_subnormal(), 15, |precision| {
+                    let number = format!("{:.*e}", precision - 1, value);
+                    (number.parse() == Ok(value)).then_some(number)
+                })
+            }
+        }
+    }
+}
+
+/// Format a float like `printf("%.*g")`, with the lowest precision from
+/// `digits` (the number of significant digits of the type) for which
+/// `format(precision)` reads back as the same number. It's formatted in
+/// Rust's `{:.*e}` format.
+fn format_float(
+    value: f64,
+    subnormal: bool,
+    digits: usize,
+    format: impl Fn(usize) -> Option<String>,
+) -> String {
+    if value.is_nan() {
+        return if value.is_sign_negative() {
+            "-nan"
+        } else {
+            "nan"
+        }
+        .to_string();
+    } else if value.is_infinite() {
+        return if value < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+
+    let first = if subnormal { 1 } else { digits };
+    let number = (first..).find_map(&format).unwrap();
+    let (mantissa, exponent) = number.split_once('e').unwrap();
+    let exponent: i64 = exponent.parse().unwrap();
+    let (sign, mantissa) = match mantissa.strip_prefix('-') {
+        Some(mantissa) => ("-", mantissa),
+        None => ("", mantissa),
+    };
+    let figures: String = mantissa.chars().filter(|&c| c != '.').collect();
+    let precision = figures.len() as i64;
+    let figures = match figures.trim_end_matches('0') {
+        "" => "0",
+        figures => figures,
+    };
+
+    if exponent < -4 || exponent >= precision {
+        let (first, rest) = figures.split_at(1);
+        let point = if rest.is_empty() { "" } else { "." };
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        format!(
+            "{sign}{first}{point}{rest}e{exponent_sign}{:02}",
+            exponent.abs()
+        )
+    } else if exponent < 0 {
+        let zeros = "0".repeat((-exponent - 1) as usize);
+        format!("{sign}0.{zeros}{figures}")
+    } else {
+        let integer_len = exponent as usize + 1;
+        if figures.len() <= integer_len {
+            let zeros = "0".repeat(integer_len - figures.len());
+            format!("{sign}{figures}{zeros}")
+        } else {
+            let (integer, fraction) = figures.split_at(integer_len);
+            format!("{sign}{integer}.{fraction}")
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Splitting input into lines and lines into fields, shared by the
+//! commands that work on text records (`sort -k`, `uniq`, `join`, `cut`).
+
+use std::io::BufRead;
+
+/// Read a line (without its delimiter) into `line`.
+/// Returns false at the end of the input.
+pub fn read_line(
+    input: &mut dyn BufRead,
+    delimiter: u8,
+    line: &mut Vec<u8>,
+) -> std::io::Result<bool> {
+    line.clear();
+    if input.read_until(delimiter, line)? == 0 {
+        return Ok(false);
+    }
+
+    if line.last() == Some(&delimiter) {
+        line.pop();
+    }
+
+    Ok(true)
+}
+
+/// Check if `byte` is a blank (a space or a tab), which separates
+/// fields when there is no separator.
+pub fn is_blank(byte: u8) -> bool {
+    byte == b' ' || byte == b'\t'
+}
+
+/// Find where the field starting at `start` ends. Without a separator,
+/// fields are made of leading blanks followed by non-blank characters.
+pub fn field_end(line: &[u8], separator: Option<u8>, start: usize) -> usize {
+    let rest = &line[start..];
+
+    let len = match separator {
+        Some(separator) => rest.iter().position(|&b| b == separator),
+        None => {
+            let blanks = rest.iter().take_while(|&&b| is_blank(b)).count();
+            rest[blanks..]
+                .iter()
+                .position(|&b| is_blank(b))
+                .map(|len| blanks + len)
+        }
+    };
+
+    start + len.unwrap_or(rest.len())
+}
+
+/// Find where the field `field` (counted from 0) starts, or the end
+/// of the line if there are fewer fields.
+pub fn field_start(line: &[u8], separator: Option<u8>, field: usize) -> usize {
+    let mut pos = 0;
+
+    for _ in 0..field {
+        pos = field_end(line, separator, pos);
+        if pos == line.len() {
+            return pos;
+        }
+
+        // Skip the separator itself.
+        if separator.is_some() {
+            pos += 1;
+        }
+    }
+
+    pos
+}
+
+/// Skip the blanks at the start of `line[pos..]`.
+pub fn skip_blanks(line: &[u8], pos: usize) -> usize {
+    pos + line[pos..].iter().take_while(|&&b| is_blank(b)).count()
+}
+
+/// Split a line into its fields. With a separator, every occurrence of it
+/// ends a field, while without one the fields are the runs of non-blank
+/// characters, ignoring the blanks around them.
+pub fn split_fields(line: &[u8], separator: Option<u8>) -> Vec<&[u8]> {
+    match separator {
+        Some(separator) => line.split(|&b| b == separator).collect(),
+        None => line
+            .split(|&b| is_blank(b))
+            .filter(|field| !field.is_empty())
+            .collect(),
+    }
+}
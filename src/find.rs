@@ -0,0 +1,690 @@
+//! The `find` command: an expression tree evaluated for every file
+//! of a directory walk.
+
+use std::{
+    io::Write,
+    os::unix::prelude::{MetadataExt, OsStrExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+use crate::utils::*;
+use crate::walk::{DirEntry, Walk};
+
+/// How a number given to a test is compared (`+N`, `-N` or `N`).
+#[derive(Clone, Copy)]
+enum Comparison {
+    GreaterThan(i64),
+    LessThan(i64),
+    Equal(i64),
+}
+
+impl Comparison {
+    fn parse(arg: &str) -> Option<Self> {
+        if let Some(n) = arg.strip_prefix('+') {
+            Some(Comparison::GreaterThan(n.parse().ok()?))
+        } else if let Some(n) = arg.strip_prefix('-') {
+            Some(Comparison::LessThan(n.parse().ok()?))
+        } else {
+            Some(Comparison::Equal(arg.parse().ok()?))
+        }
+    }
+
+    fn matches(self, value: i64) -> bool {
+        match self {
+            Comparison::GreaterThan(n) => value > n,
+            Comparison::LessThan(n) => value < n,
+            Comparison::Equal(n) => value == n,
+        }
+    }
+}
+
+/// How the permissions are compared by `-perm`.
+enum PermMatch {
+    /// Exactly these permissions (`MODE`).
+    Exact(u32),
+    /// At least these permissions (`-MODE`).
+    All(u32),
+    /// Any of these permissions (`/MODE`).
+    Any(u32),
+}
+
+enum Test {
+    True,
+    False,
+    Name {
+        pattern: String,
+        ignore_case: bool,
+    },
+    Path {
+        pattern: String,
+        ignore_case: bool,
+    },
+    /// The file type letters accepted (`fdlpscb`).
+    Type(Vec<char>),
+    /// The comparison and the size of the unit, in bytes.
+    Size(Comparison, u64),
+    /// The comparison and the unit (in seconds) of the modification time.
+    Mtime(Comparison, i64),
+    Atime(Comparison, i64),
+    Newer(i64, i64),
+    Perm(PermMatch),
+    User(u32),
+    Group(u32),
+    Empty,
+}
+
+enum Action {
+    Print,
+    Print0,
+    Delete,
+    Prune,
+    /// The command (with `{}` replaced by the path). If the paths are
+    /// collected to run it fewer times (`+`), the index of the batch.
+    Exec {
+        command: Vec<String>,
+        batch: Option<usize>,
+    },
+}
+
+enum Expr {
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Test(Test),
+    Action(Action),
+}
+
+/// The options that affect the walk rather than the files.
+struct WalkOptions {
+    min_depth: usize,
+    max_depth: usize,
+    contents_first: bool,
+    breadth_first: bool,
+    same_file_system: bool,
+}
+
+/// Parses the tokens of an expression, by recursive descent:
+///
+/// ```text
+/// or   := and (-o and)*
+/// and  := not ([-a] not)*
+/// not  := (! | -not) not | ( or ) | primary
+/// ```
+struct Parser<'a> {
+    tokens: &'a [String],
+    pos: usize,
+    options: WalkOptions,
+    /// Set if any action was given, so `-print` isn't implied.
+    has_action: bool,
+    /// How many `-exec ... {} +` were given.
+    batches: usize,
+}
+
+/// Print an error about the expression and exit.
+fn invalid_expression(message: String) -> ! {
+    eprintln!("find: {}", message);
+    std::process::exit(-110);
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    fn next_arg(&mut self, predicate: &str) -> &str {
+        let Some(arg) = self.tokens.get(self.pos) else {
+            invalid_expression(format!("missing argument to '{}'", predicate));
+        };
+
+        self.pos += 1;
+        arg
+    }
+
+    fn parse_or(&mut self) -> Expr {
+        let mut expr = self.parse_and();
+
+        while let Some("-o" | "-or") = self.peek() {
+            self.pos += 1;
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()));
+        }
+
+        expr
+    }
+
+    fn parse_and(&mut self) -> Expr {
+        let mut expr = self.parse_not();
+
+        loop {
+            match self.peek() {
+                Some("-a" | "-and") => self.pos += 1,
+                None | Some("-o" | "-or" | ")") => return expr,
+                Some(_) => {}
+            }
+
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()));
+        }
+    }
+
+    fn parse_not(&mut self) -> Expr {
+        match self.peek() {
+            Some("!" | "-not") => {
+                self.pos += 1;
+                Expr::Not(Box::new(self.parse_not()))
+            }
+            Some("(") => {
+                self.pos += 1;
+                let expr = self.parse_or();
+
+                if self.peek() != Some(")") {
+                    invalid_expression("missing ')'".to_owned());
+                }
+                self.pos += 1;
+
+                expr
+            }
+            Some(_) => self.parse_primary(),
+            None => invalid_expression("expected an expression".to_owned()),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Expr {
+        let predicate = self.next_arg("").to_owned();
+
+        let test = match predicate.as_str() {
+            "-true" => Test::True,
+            "-false" => Test::False,
+            "-name" | "-iname" => Test::Name {
+                pattern: self.next_arg(&predicate).to_owned(),
+                ignore_case: predicate == "-iname",
+            },
+            "-path" | "-wholename" | "-ipath" | "-iwholename" => Test::Path {
+                pattern: self.next_arg(&predicate).to_owned(),
+                ignore_case: predicate.starts_with("-i"),
+            },
+            "-type" => {
+                let types = self.next_arg(&predicate);
+                let types = types.split(',').collect::<Vec<_>>();
+
+                if !types.iter().all(|t| t.len() == 1 && "fdlpscb".contains(*t)) {
+                    invalid_expression(format!("unknown argument to -type: {}", types.join(",")));
+                }
+
+                Test::Type(types.iter().filter_map(|t| t.chars().next()).collect())
+            }
+            "-size" => {
+                let arg = self.next_arg(&predicate);
+                let (number, unit) = match arg.char_indices().last() {
+                    Some((i, unit)) if unit.is_ascii_alphabetic() => (&arg[..i], unit),
+                    _ => (arg, 'b'),
+                };
+
+                let unit_size = match unit {
+                    'c' => 1,
+                    'w' => 2,
+                    'b' => 512,
+                    'k' => 1 << 10,
+                    'M' => 1 << 20,
+                    'G' => 1 << 30,
+                    _ => invalid_expression(format!("invalid -size unit '{}'", unit)),
+                };
+
+                let Some(comparison) = Comparison::parse(number) else {
+                    invalid_expression(format!("invalid argument '{}' to -size", arg));
+                };
+
+                Test::Size(comparison, unit_size)
+            }
+            "-mtime" | "-mmin" | "-atime" | "-amin" => {
+                let arg = self.next_arg(&predicate);
+                let Some(comparison) = Comparison::parse(arg) else {
+                    invalid_expression(format!("invalid argument '{}' to {}", arg, predicate));
+                };
+
+                let unit = if predicate.ends_with("min") {
+                    60
+                } else {
+                    86400
+                };
+                if predicate.starts_with("-m") {
+                    Test::Mtime(comparison, unit)
+                } else {
+                    Test::Atime(comparison, unit)
+                }
+            }
+            "-newer" => {
+                let file = self.next_arg(&predicate);
+                match std::fs::metadata(file) {
+                    Ok(metadata) => Test::Newer(metadata.mtime(), metadata.mtime_nsec()),
+                    Err(e) => invalid_expression(format!("'{}': {}", file, error_reason(&e))),
+                }
+            }
+            "-perm" => {
+                let arg = self.next_arg(&predicate);
+                let (kind, mode): (fn(u32) -> PermMatch, _) =
+                    if let Some(mode) = arg.strip_prefix('-') {
+                        (PermMatch::All, mode)
+                    } else if let Some(mode) = arg.strip_prefix('/') {
+                        (PermMatch::Any, mode)
+                    } else {
+                        (PermMatch::Exact, arg)
+                    };
+
+                // Symbolic modes are applied to no permissions at all.
+                let Some(mode) = parse_mode(mode) else {
+                    invalid_expression(format!("invalid mode '{}'", arg));
+                };
+
+                Test::Perm(kind(mode.apply(0, false, 0)))
+            }
+            "-user" => {
+                let user = self.next_arg(&predicate);
+                match resolve_user(user) {
+                    Some((uid, _)) => Test::User(uid),
+                    None => {
+                        invalid_expression(format!("'{}' is not the name of a known user", user))
+                    }
+                }
+            }
+            "-group" => {
+                let group = self.next_arg(&predicate);
+                match resolve_group(group) {
+                    Some(gid) => Test::Group(gid),
+                    None => invalid_expression(format!(
+                        "'{}' is not the name of an existing group",
+                        group
+                    )),
+                }
+            }
+            "-empty" => Test::Empty,
+            "-maxdepth" | "-mindepth" => {
+                let arg = self.next_arg(&predicate);
+                let Ok(depth) = arg.parse() else {
+                    invalid_expression(format!("invalid argument '{}' to {}", arg, predicate));
+                };
+
+                if predicate == "-maxdepth" {
+                    self.options.max_depth = depth;
+                } else {
+                    self.options.min_depth = depth;
+                }
+
+                // Options are always true.
+                Test::True
+            }
+            "-depth" => {
+                self.options.contents_first = true;
+                Test::True
+            }
+            "-bfs" => {
+                self.options.breadth_first = true;
+                Test::True
+            }
+            "-xdev" | "-mount" => {
+                self.options.same_file_system = true;
+                Test::True
+            }
+            _ => return Expr::Action(self.parse_action(&predicate)),
+        };
+
+        Expr::Test(test)
+    }
+
+    fn parse_action(&mut self, predicate: &str) -> Action {
+        let action = match predicate {
+            "-print" => Action::Print,
+            "-print0" => Action::Print0,
+            "-delete" => {
+                // Directories are deleted after their contents.
+                self.options.contents_first = true;
+                Action::Delete
+            }
+            // Pruning doesn't prevent the implicit '-print'.
+            "-prune" => return Action::Prune,
+            "-exec" => {
+                let mut command = Vec::new();
+
+                loop {
+                    let arg = self.next_arg(predicate).to_owned();
+
+                    if arg == ";" {
+                        break Action::Exec {
+                            command,
+                            batch: None,
+                        };
+                    }
+
+                    // '+' only ends the command right after '{}'.
+                    if arg == "+" && command.last().is_some_and(|last| last == "{}") {
+                        self.batches += 1;
+
+                        break Action::Exec {
+                            command,
+                            batch: Some(self.batches - 1),
+                        };
+                    }
+
+                    command.push(arg);
+                }
+            }
+            _ => invalid_expression(format!("unknown predicate '{}'", predicate)),
+        };
+
+        self.has_action = true;
+        action
+    }
+}
+
+/// The state of the evaluation, shared by all the files.
+struct Context {
+    now: i64,
+    /// Set by `-prune` to skip the current directory.
+    prune: bool,
+    /// The paths collected by each `-exec ... {} +`.
+    batches: Vec<Vec<PathBuf>>,
+    was_error: bool,
+}
+
+/// The letter used by `-type` for the type of a file.
+fn type_letter(metadata: &std::fs::Metadata) -> char {
+    use std::os::unix::fs::FileTypeExt;
+
+    let file_type = metadata.file_type();
+    if file_type.is_dir() {
+        'd'
+    } else if file_type.is_symlink() {
+        'l'
+    } else if file_type.is_fifo() {
+        'p'
+    } else if file_type.is_socket() {
+        's'
+    } else if file_type.is_char_device() {
+        'c'
+    } else if file_type.is_block_device() {
+        'b'
+    } else {
+        'f'
+    }
+}
+
+fn run_command(command: &[String], paths: &[&Path]) -> bool {
+    let Some((program, args)) = command.split_first() else {
+        return false;
+    };
+
+    let mut process = std::process::Command::new(program);
+    for arg in args {
+        if arg == "{}" {
+            process.args(paths);
+        } else {
+            // '{}' is also replaced inside other arguments.
+            process.arg(arg.replace("{}", &paths[0].to_string_lossy()));
+        }
+    }
+
+    // Flush what was printed before, so the output stays in order.
+    let _ = std::io::stdout().flush();
+
+    match process.status() {
+        Ok(status) => status.success(),
+        Err(e) => {
+            eprintln!("find: '{}': {}", program, error_reason(&e));
+            false
+        }
+    }
+}
+
+impl Test {
+    fn matches(&self, entry: &DirEntry, context: &Context) -> bool {
+        let metadata = entry.metadata();
+
+        match self {
+            Test::True => true,
+            Test::False => false,
+            Test::Name {
+                pattern,
+                ignore_case,
+            } => {
+                // The root is matched by its basename too ('/' has none).
+                let name = entry.path().file_name().unwrap_or(entry.path().as_os_str());
+                glob_match(pattern, &name.to_string_lossy(), *ignore_case)
+            }
+            Test::Path {
+                pattern,
+                ignore_case,
+            } => glob_match(pattern, &entry.path().to_string_lossy(), *ignore_case),
+            Test::Type(types) => types.contains(&type_letter(metadata)),
+            Test::Size(comparison, unit) => {
+                // The size is rounded up to the unit.
+                comparison.matches(metadata.len().div_ceil(*unit) as i64)
+            }
+            Test::Mtime(comparison, unit) => {
+                comparison.matches((context.now - metadata.mtime()).div_euclid(*unit))
+            }
+            Test::Atime(comparison, unit) => {
+                comparison.matches((context.now - metadata.atime()).div_euclid(*unit))
+            }
+            Test::Newer(secs, nanos) => (metadata.mtime(), metadata.mtime_nsec()) > (*secs, *nanos),
+            Test::Perm(perm_match) => {
+                let mode = metadata.permissions().mode() & 0o7777;
+                match perm_match {
+                    PermMatch::Exact(perms) => mode == *perms,
+                    PermMatch::All(perms) => mode & perms == *perms,
+                    PermMatch::Any(perms) => *perms == 0 || mode & perms != 0,
+                }
+            }
+            Test::User(uid) => metadata.uid() == *uid,
+            Test::Group(gid) => metadata.gid() == *gid,
+            Test::Empty => {
+                if metadata.is_dir() {
+                    std::fs::read_dir(entry.path()).is_ok_and(|mut dir| dir.next().is_none())
+                } else {
+                    metadata.is_file() && metadata.len() == 0
+                }
+            }
+        }
+    }
+}
+
+impl Action {
+    fn run(&self, entry: &DirEntry, context: &mut Context) -> bool {
+        let path = entry.path();
+
+        match self {
+            Action::Print | Action::Print0 => {
+                let terminator = if matches!(self, Action::Print) {
+                    b'\n'
+                } else {
+                    b'\0'
+                };
+                let mut stdout = std::io::stdout().lock();
+
+                let _ = stdout
+                    .write_all(path.as_os_str().as_bytes())
+                    .and_then(|_| stdout.write_all(&[terminator]));
+                true
+            }
+            Action::Delete => {
+                let ret_status = if entry.is_dir() {
+                    std::fs::remove_dir(path)
+                } else {
+                    std::fs::remove_file(path)
+                };
+
+                if let Err(e) = ret_status {
+                    eprintln!(
+                        "find: cannot delete '{}': {}",
+                        path.display(),
+                        error_reason(&e)
+                    );
+                    context.was_error = true;
+                    return false;
+                }
+
+                true
+            }
+            Action::Prune => {
+                context.prune = true;
+                true
+            }
+            Action::Exec {
+                command,
+                batch: None,
+            } => run_command(command, &[path]),
+            Action::Exec {
+                batch: Some(batch), ..
+            } => {
+                context.batches[*batch].push(path.to_owned());
+                true
+            }
+        }
+    }
+}
+
+impl Expr {
+    fn evaluate(&self, entry: &DirEntry, context: &mut Context) -> bool {
+        match self {
+            Expr::Not(expr) => !expr.evaluate(entry, context),
+            Expr::And(left, right) => {
+                left.evaluate(entry, context) && right.evaluate(entry, context)
+            }
+            Expr::Or(left, right) => {
+                left.evaluate(entry, context) || right.evaluate(entry, context)
+            }
+            Expr::Test(test) => test.matches(entry, context),
+            Expr::Action(action) => action.run(entry, context),
+        }
+    }
+
+    /// Run the commands of every `-exec ... {} +` with the collected paths.
+    fn flush_batches(&self, context: &mut Context) {
+        match self {
+            Expr::Not(expr) => expr.flush_batches(context),
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.flush_batches(context);
+                right.flush_batches(context);
+            }
+            Expr::Action(Action::Exec {
+                command,
+                batch: Some(batch),
+            }) => {
+                let paths = std::mem::take(&mut context.batches[*batch]);
+                let paths = paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+
+                // Keep the command lines reasonably short.
+                for chunk in paths.chunks(1024) {
+                    if !run_command(command, chunk) {
+                        context.was_error = true;
+                    }
+                }
+            }
+            Expr::Test(_) | Expr::Action(_) => {}
+        }
+    }
+}
+
+pub fn find(args: &[String]) {
+    // Symlink options come before the paths.
+    let mut follow_root = false;
+    let mut follow_links = false;
+    let mut i = 0;
+
+    while let Some(opt) = args.get(i) {
+        match opt.as_str() {
+            "-P" => (follow_root, follow_links) = (false, false),
+            "-H" => (follow_root, follow_links) = (true, false),
+            "-L" => (follow_root, follow_links) = (true, true),
+            _ => break,
+        }
+        i += 1;
+    }
+
+    // The paths end where the expression starts.
+    let paths_end = args[i..]
+        .iter()
+        .position(|arg| arg.starts_with('-') || arg == "!" || arg == "(")
+        .map_or(args.len(), |end| i + end);
+
+    let mut paths = args[i..paths_end]
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+    if paths.is_empty() {
+        paths.push(".");
+    }
+
+    let mut parser = Parser {
+        tokens: &args[paths_end..],
+        pos: 0,
+        options: WalkOptions {
+            min_depth: 0,
+            max_depth: usize::MAX,
+            contents_first: false,
+            breadth_first: false,
+            same_file_system: false,
+        },
+        has_action: false,
+        batches: 0,
+    };
+
+    let mut expr = if parser.tokens.is_empty() {
+        Expr::Test(Test::True)
+    } else {
+        parser.parse_or()
+    };
+
+    if let Some(token) = parser.peek() {
+        invalid_expression(format!("unexpected '{}'", token));
+    }
+
+    // Without actions, the matching files are printed.
+    if !parser.has_action {
+        expr = Expr::And(Box::new(expr), Box::new(Expr::Action(Action::Print)));
+    }
+
+    let options = parser.options;
+    let mut context = Context {
+        now: chrono::Local::now().timestamp(),
+        prune: false,
+        batches: vec![Vec::new(); parser.batches],
+        was_error: false,
+    };
+
+    for path in paths {
+        let mut walk = Walk::new(path)
+            .follow_root(follow_root)
+            .follow_links(follow_links)
+            .max_depth(options.max_depth)
+            .contents_first(options.contents_first)
+            // Contents first (needed by '-delete') wins over levels.
+            .breadth_first(options.breadth_first && !options.contents_first)
+            .same_file_system(options.same_file_system);
+
+        while let Some(entry) = walk.next() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    eprintln!("find: '{}': {}", e.path.display(), error_reason(&e.error));
+                    context.was_error = true;
+                    continue;
+                }
+            };
+
+            if entry.depth() < options.min_depth {
+                continue;
+            }
+
+            context.prune = false;
+            expr.evaluate(&entry, &mut context);
+
+            if context.prune {
+                walk.skip_current_dir();
+            }
+        }
+    }
+
+    expr.flush_batches(&mut context);
+
+    if context.was_error {
+        std::process::exit(-110);
+    }
+}
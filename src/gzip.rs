@@ -0,0 +1,724 @@
+//! The `gzip`, `gunzip` and `zcat` commands, compressing files in the
+//! gzip format (RFC 1952), or decompressing them.
+
+use std::fs::Metadata;
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::sync::mpsc::{Receiver, SyncSender};
+
+use crate::deflate::*;
+use crate::hash::{Crc32, Hasher};
+use crate::utils::*;
+use crate::walk::Walk;
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+/// The compression method of DEFLATE, the only one in use.
+const DEFLATED: u8 = 8;
+
+/// The flags of the header.
+const FHCRC: u8 = 2;
+const FEXTRA: u8 = 4;
+const FNAME: u8 = 8;
+const FCOMMENT: u8 = 16;
+
+/// The suffixes of compressed files, with the suffix of the file they
+/// decompress to.
+const SUFFIXES: [(&str, &str); 6] = [
+    (".gz", ""),
+    (".tgz", ".tar"),
+    ("-gz", ""),
+    (".z", ""),
+    ("-z", ""),
+    ("_z", ""),
+];
+
+fn data_error(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Get the name of the file decompressed from `path`, if it has one of
+/// the suffixes of compressed files.
+fn decompressed_name(path: &str) -> Option<String> {
+    SUFFIXES.iter().find_map(|(suffix, replacement)| {
+        path.strip_suffix(suffix)
+            .filter(|stem| !stem.is_empty() && !stem.ends_with('/'))
+            .map(|stem| format!("{}{}", stem, replacement))
+    })
+}
+
+/// Compresses the data written to it into a gzip member, with the name
+/// and modification time of the file in the header. The member is ended
+/// by `finish`.
+pub struct GzEncoder<W: Write> {
+    out: W,
+    deflater: Deflater,
+    crc: Crc32,
+    size: u32,
+    compressed: Vec<u8>,
+}
+
+impl<W: Write> GzEncoder<W> {
+    pub fn new(mut out: W, level: u32, name: Option<&str>, mtime: u32) -> std::io::Result<Self> {
+        let mut header = MAGIC.to_vec();
+        header.push(DEFLATED);
+        header.push(if name.is_some() { FNAME } else { 0 });
+        header.extend(mtime.to_le_bytes());
+        // The extra flags tell about the level, and the system is Unix.
+        header.push(match level {
+            1 => 4,
+            9 => 2,
+            _ => 0,
+        });
+        header.push(3);
+        if let Some(name) = name {
+            header.extend(name.as_bytes());
+            header.push(0);
+        }
+        out.write_all(&header)?;
+
+        Ok(GzEncoder {
+            out,
+            deflater: Deflater::new(level),
+            crc: Crc32::new(),
+            size: 0,
+            compressed: Vec::new(),
+        })
+    }
+
+    /// Write the end of the member, returning the output.
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.compressed.clear();
+        self.deflater.finish(&mut self.compressed);
+        self.compressed.extend(self.crc.value().to_le_bytes());
+        self.compressed.extend(self.size.to_le_bytes());
+        self.out.write_all(&self.compressed)?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write> Write for GzEncoder<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.crc.update(data);
+        // The size is stored modulo 2^32.
+        self.size = self.size.wrapping_add(data.len() as u32);
+        self.compressed.clear();
+        self.deflater.update(data, &mut self.compressed);
+        self.out.write_all(&self.compressed)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Compress `input` into a gzip member.
+fn compress(
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+    level: u32,
+    name: Option<&str>,
+    mtime: u32,
+) -> std::io::Result<()> {
+    let mut encoder = GzEncoder::new(out, level, name, mtime)?;
+    std::io::copy(input, &mut encoder)?;
+    encoder.finish().map(|_| ())
+}
+
+/// Read the header of a member, after its magic number. Returns its
+/// length, with the magic number.
+fn read_header(reader: &mut BitReader) -> std::io::Result<u64> {
+    let method = reader.byte()?;
+    if method != DEFLATED {
+        return Err(data_error(&format!(
+            "unknown method {} -- not supported",
+            method
+        )));
+    }
+    let flags = reader.byte()?;
+    if flags & 0xe0 != 0 {
+        return Err(data_error(&format!(
+            "has flags 0x{:x} -- not supported",
+            flags
+        )));
+    }
+
+    // The modification time, the extra flags and the system.
+    for _ in 0..6 {
+        reader.byte()?;
+    }
+    let mut len = 10;
+
+    if flags & FEXTRA != 0 {
+        let extra = reader.byte()? as u64 | (reader.byte()? as u64) << 8;
+        for _ in 0..extra {
+            reader.byte()?;
+        }
+        len += 2 + extra;
+    }
+
+    // The name and the comment end with a null byte.
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            len += 1;
+            while reader.byte()? != 0 {
+                len += 1;
+            }
+        }
+    }
+
+    if flags & FHCRC != 0 {
+        reader.byte()?;
+        reader.byte()?;
+        len += 2;
+    }
+
+    Ok(len)
+}
+
+/// Read the 32-bit little-endian number at the end of a member.
+fn read_u32(reader: &mut BitReader) -> std::io::Result<u32> {
+    let mut value = 0;
+    for i in 0..4 {
+        value |= (reader.byte()? as u32) << (8 * i);
+    }
+    Ok(value)
+}
+
+/// Passes the data decompressed through, computing its CRC and size.
+struct Checked<'a> {
+    out: &'a mut dyn Write,
+    crc: Crc32,
+    size: u32,
+}
+
+impl Write for Checked<'_> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        let len = self.out.write(data)?;
+        self.crc.update(&data[..len]);
+        self.size = self.size.wrapping_add(len as u32);
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Decompress the members of the gzip stream `input` into `out`. With
+/// `passthrough`, input which isn't in the gzip format is copied as is.
+/// Returns false if there's trailing garbage after the members (trailing
+/// zeros are ignored).
+fn decompress(
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+    passthrough: bool,
+) -> std::io::Result<bool> {
+    let start = input.fill_buf()?;
+    if !start.starts_with(&MAGIC) {
+        if passthrough {
+            std::io::copy(input, out)?;
+            return Ok(true);
+        } else if start.is_empty() {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        return Err(data_error("not in gzip format"));
+    }
+
+    let mut reader = BitReader::new(input);
+    reader.byte()?;
+    reader.byte()?;
+
+    loop {
+        read_header(&mut reader)?;
+
+        let mut checked = Checked {
+            out: &mut *out,
+            crc: Crc32::new(),
+            size: 0,
+        };
+        inflate(&mut reader, &mut checked)?;
+
+        if read_u32(&mut reader)? != checked.crc.value() {
+            return Err(data_error("invalid compressed data--crc error"));
+        }
+        if read_u32(&mut reader)? != checked.size {
+            return Err(data_error("invalid compressed data--length error"));
+        }
+
+        if reader.at_end()? {
+            return Ok(true);
+        }
+
+        // Another member, or garbage.
+        let first = reader.byte()?;
+        let second = if reader.at_end()? {
+            None
+        } else {
+            Some(reader.byte()?)
+        };
+        if (first, second) != (MAGIC[0], Some(MAGIC[1])) {
+            let mut zeros = first == 0 && second.unwrap_or(0) == 0;
+            while !reader.at_end()? {
+                zeros &= reader.byte()? == 0;
+            }
+            return Ok(zeros);
+        }
+    }
+}
+
+/// Sends the data written to it to a `ChannelReader`.
+struct ChannelWriter(SyncSender<std::io::Result<Vec<u8>>>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .send(Ok(data.to_vec()))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reads the data sent by a `ChannelWriter`, and the error which ended it.
+struct ChannelReader {
+    receiver: Receiver<std::io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.receiver.recv() {
+                Ok(chunk) => self.chunk = chunk?,
+                Err(_) => return Ok(0),
+            }
+            self.pos = 0;
+        }
+
+        let len = buffer.len().min(self.chunk.len() - self.pos);
+        buffer[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Decompress the gzip stream `input` in another thread, returning
+/// a reader of the data decompressed. An error is read after the data
+/// decompressed before it.
+pub fn decompressing_reader(input: impl Read + Send + 'static) -> impl Read {
+    let (sender, receiver) = std::sync::mpsc::sync_channel(4);
+
+    std::thread::spawn(move || {
+        let mut input = BufReader::with_capacity(1 << 16, input);
+        let mut out = ChannelWriter(sender.clone());
+        if let Err(e) = decompress(&mut input, &mut out, false) {
+            let _ = sender.send(Err(e));
+        }
+    });
+
+    ChannelReader {
+        receiver,
+        chunk: Vec::new(),
+        pos: 0,
+    }
+}
+
+/// The sizes of a compressed file, listed by `-l`.
+struct Sizes {
+    compressed: u64,
+    uncompressed: u64,
+    header: u64,
+}
+
+/// Get the sizes of a compressed stream, where the uncompressed size is
+/// the one in the trailer of the last member.
+fn sizes(input: &mut dyn BufRead) -> std::io::Result<Sizes> {
+    let mut reader = BitReader::new(input);
+    if reader.at_end()? {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    if [reader.byte()?, reader.byte()?] != MAGIC {
+        return Err(data_error("not in gzip format"));
+    }
+
+    // The header, the data and the trailer, whose last 4 bytes are kept.
+    let header = read_header(&mut reader)? + 8;
+    let mut compressed = header - 8;
+    let mut last = 0u32;
+    while !reader.at_end()? {
+        last = last >> 8 | (reader.byte()? as u32) << 24;
+        compressed += 1;
+    }
+
+    if compressed < header {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(Sizes {
+        compressed,
+        uncompressed: last as u64,
+        header,
+    })
+}
+
+/// Format the line of `-l` for a file.
+fn list_line(compressed: u64, uncompressed: u64, header: u64, name: &str) -> String {
+    let ratio = if uncompressed == 0 {
+        0.0
+    } else {
+        let saved = uncompressed as f64 - (compressed - header) as f64;
+        100.0 * saved / uncompressed as f64
+    };
+
+    format!(
+        "{:>19} {:>19} {:>5.1}% {}",
+        compressed, uncompressed, ratio, name
+    )
+}
+
+/// The settings of `gzip`, and how it went.
+struct Gzip {
+    command: &'static str,
+    level: u32,
+    decompress: bool,
+    stdout: bool,
+    keep: bool,
+    force: bool,
+    recursive: bool,
+    test: bool,
+    list: bool,
+    /// The totals of the files listed, for more than one.
+    listed: Vec<(u64, u64)>,
+    failed: bool,
+}
+
+impl Gzip {
+    fn warn(&mut self, message: std::fmt::Arguments) {
+        eprintln!("{}: {}", self.command, message);
+        self.failed = true;
+    }
+
+    /// Compress or decompress `input` (named `name`) into `out`.
+    fn convert(
+        &mut self,
+        input: &mut dyn BufRead,
+        out: &mut dyn Write,
+        name: &str,
+        metadata: Option<&Metadata>,
+    ) -> std::io::Result<()> {
+        if !self.decompress {
+            let base = metadata.and(Path::new(name).file_name());
+            let base = base.map(|base| base.to_string_lossy());
+            let mtime = metadata.map_or(0, |metadata| metadata.mtime() as u32);
+            return compress(input, out, self.level, base.as_deref(), mtime);
+        }
+
+        let passthrough = self.stdout && self.force;
+        if !decompress(input, out, passthrough)? {
+            out.flush()?;
+            self.warn(format_args!(
+                "{}: decompression OK, trailing garbage ignored",
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    /// List the sizes of the compressed `input`.
+    fn list(&mut self, input: &mut dyn BufRead, name: &str) -> std::io::Result<()> {
+        let sizes = sizes(input)?;
+
+        if self.listed.is_empty() {
+            println!(
+                "{:>19} {:>19}  ratio uncompressed_name",
+                "compressed", "uncompressed"
+            );
+        }
+        let output = decompressed_name(name).unwrap_or_else(|| name.to_string());
+        println!(
+            "{}",
+            list_line(sizes.compressed, sizes.uncompressed, sizes.header, &output)
+        );
+        self.listed.push((sizes.compressed, sizes.uncompressed));
+        Ok(())
+    }
+
+    /// Process the standard input, written to the standard output.
+    fn stdin(&mut self) {
+        if !self.decompress && !self.force && std::io::stdout().is_terminal() {
+            self.warn(format_args!(
+                "compressed data not written to a terminal. Use -f to force compression."
+            ));
+            return;
+        } else if self.decompress && !self.force && std::io::stdin().is_terminal() {
+            self.warn(format_args!(
+                "compressed data not read from a terminal. Use -f to force decompression."
+            ));
+            return;
+        }
+
+        let mut input = std::io::stdin().lock();
+        let result = if self.list {
+            self.list(&mut input, "-")
+        } else if self.test {
+            self.convert(&mut input, &mut std::io::sink(), "-", None)
+        } else {
+            let mut out = BufWriter::new(std::io::stdout().lock());
+            self.convert(&mut input, &mut out, "-", None)
+                .and_then(|_| out.flush())
+        };
+
+        if let Err(e) = result {
+            self.warn(format_args!("stdin: {}", error_reason(&e)));
+        }
+    }
+
+    /// Process an operand, which may be a directory with `-r`.
+    fn operand(&mut self, path: &str) {
+        if path == "-" {
+            self.stdin();
+            return;
+        }
+
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                self.warn(format_args!("{}: {}", path, error_reason(&e)));
+                return;
+            }
+        };
+
+        if metadata.is_file() {
+            self.file(path, &metadata, false);
+        } else if !metadata.is_dir() {
+            self.warn(format_args!(
+                "{} is not a directory or a regular file - ignored",
+                path
+            ));
+        } else if !self.recursive {
+            self.warn(format_args!("{} is a directory -- ignored", path));
+        } else {
+            for entry in Walk::new(path) {
+                match entry {
+                    Ok(entry) if entry.metadata().is_file() => {
+                        let path = entry.path().to_string_lossy();
+                        self.file(&path, entry.metadata(), true);
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        let path = e.path.display();
+                        self.warn(format_args!("{}: {}", path, error_reason(&e.error)));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Process a regular file. The files without a suitable suffix are
+    /// silently skipped when `walked` from a directory.
+    fn file(&mut self, path: &str, metadata: &Metadata, walked: bool) {
+        // The file written, unless it's the standard output.
+        let output = if self.stdout || self.test || self.list {
+            None
+        } else if self.decompress {
+            match decompressed_name(path) {
+                Some(output) => Some(output),
+                None if walked => return,
+                None => {
+                    self.warn(format_args!("{}: unknown suffix -- ignored", path));
+                    return;
+                }
+            }
+        } else if let Some((suffix, _)) = SUFFIXES.iter().find(|(suffix, _)| path.ends_with(suffix))
+        {
+            if !walked {
+                self.warn(format_args!(
+                    "{} already has {} suffix -- unchanged",
+                    path, suffix
+                ));
+            }
+            return;
+        } else {
+            Some(format!("{}.gz", path))
+        };
+
+        let mut input = match open_input(path) {
+            Ok(input) => input,
+            Err(e) => {
+                self.warn(format_args!("{}: {}", path, error_reason(&e)));
+                return;
+            }
+        };
+
+        let Some(output) = output else {
+            let result = if self.list {
+                self.list(&mut input, path)
+            } else if self.test {
+                self.convert(&mut input, &mut std::io::sink(), path, Some(metadata))
+            } else {
+                let mut out = BufWriter::new(std::io::stdout().lock());
+                self.convert(&mut input, &mut out, path, Some(metadata))
+                    .and_then(|_| out.flush())
+            };
+
+            if let Err(e) = result {
+                self.warn(format_args!("{}: {}", path, error_reason(&e)));
+            }
+            return;
+        };
+
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(!self.force)
+            .create(true)
+            .truncate(true)
+            .open(&output);
+        let file = match file {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                self.warn(format_args!("{} already exists; not overwritten", output));
+                return;
+            }
+            Err(e) => {
+                self.warn(format_args!("{}: {}", output, error_reason(&e)));
+                return;
+            }
+        };
+
+        let mut out = BufWriter::new(file);
+        let result = self
+            .convert(&mut input, &mut out, path, Some(metadata))
+            .and_then(|_| out.flush());
+        if let Err(e) = result {
+            self.warn(format_args!("{}: {}", path, error_reason(&e)));
+            let _ = std::fs::remove_file(&output);
+            return;
+        }
+
+        // The output gets the mode and times of the input, which is removed.
+        let file = out.get_ref();
+        let times = std::fs::FileTimes::new();
+        let times = match (metadata.accessed(), metadata.modified()) {
+            (Ok(accessed), Ok(modified)) => times.set_accessed(accessed).set_modified(modified),
+            _ => times,
+        };
+        if let Err(e) = file
+            .set_permissions(metadata.permissions())
+            .and_then(|_| file.set_times(times))
+        {
+            self.warn(format_args!("{}: {}", output, error_reason(&e)));
+        }
+
+        if !self.keep {
+            if let Err(e) = std::fs::remove_file(path) {
+                self.warn(format_args!("{}: {}", path, error_reason(&e)));
+            }
+        }
+    }
+}
+
+fn run(args: &[String], command: &'static str, code: i32, decompress: bool, stdout: bool) {
+    let Some((opts, files)) = parse_options(args, &[]) else {
+        eprintln!(
+            "Usage: {} [-1..-9] [-d] [-c] [-k] [-f] [-r] [-t] [-l] [FILE]...",
+            command
+        );
+        std::process::exit(code);
+    };
+
+    let mut gzip = Gzip {
+        command,
+        level: 6,
+        decompress,
+        stdout,
+        keep: false,
+        force: false,
+        recursive: false,
+        test: false,
+        list: false,
+        listed: Vec::new(),
+        failed: false,
+    };
+
+    for (opt, _) in opts {
+        match opt.as_str() {
+            "-1" | "-2" | "-3" | "-4" | "-5" | "-6" | "-7" | "-8" | "-9" => {
+                gzip.level = opt[1..].parse().unwrap();
+            }
+            "--fast" => gzip.level = 1,
+            "--best" => gzip.level = 9,
+            "-d" | "--decompress" | "--uncompress" => gzip.decompress = true,
+            "-c" | "--stdout" | "--to-stdout" => gzip.stdout = true,
+            "-k" | "--keep" => gzip.keep = true,
+            "-f" | "--force" => gzip.force = true,
+            "-r" | "--recursive" => gzip.recursive = true,
+            "-t" | "--test" => gzip.test = true,
+            "-l" | "--list" => gzip.list = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(code);
+            }
+        }
+    }
+
+    // Testing and listing are about compressed files.
+    gzip.decompress |= gzip.test || gzip.list;
+
+    if files.is_empty() {
+        gzip.stdin();
+    }
+    for file in files {
+        gzip.operand(file);
+    }
+
+    if gzip.listed.len() > 1 {
+        let compressed = gzip.listed.iter().map(|sizes| sizes.0).sum();
+        let uncompressed = gzip.listed.iter().map(|sizes| sizes.1).sum();
+        println!("{}", list_line(compressed, uncompressed, 0, "(totals)"));
+    }
+
+    if gzip.failed {
+        std::process::exit(code);
+    }
+}
+
+pub fn gzip(args: &[String]) {
+    run(args, "gzip", -250, false, false);
+}
+
+pub fn gunzip(args: &[String]) {
+    run(args, "gunzip", -251, true, false);
+}
+
+/// `zcat` decompresses like `gzip -dc`, through the output of `cat`.
+pub fn zcat(args: &[String]) {
+    let Some((opts, files)) = parse_options(args, &[]) else {
+        eprintln!("Usage: zcat [-f] [FILE]...");
+        std::process::exit(-252);
+    };
+
+    let mut force = false;
+    for (opt, _) in opts {
+        match opt.as_str() {
+            "-f" | "--force" => force = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-252);
+            }
+        }
+    }
+
+    cat_inputs("zcat", -252, &files, |input, out| {
+        if decompress(input, out, force)? {
+            Ok(())
+        } else {
+            Err(data_error("decompression OK, trailing garbage ignored"))
+        }
+    });
+}
@@ -0,0 +1,682 @@
+//! Hash functions for the checksum commands: MD5, SHA-1, SHA-256,
+//! SHA-512, BLAKE2b and the CRC of `cksum`, and the CRC-32 of `gzip`.
+
+/// A hash function, fed with `update` and read with `finish`.
+pub trait Hasher {
+    fn update(&mut self, data: &[u8]);
+    fn finish(&mut self) -> Vec<u8>;
+}
+
+/// The blocks of the Merkle–Damgård hashes (MD5 and SHA), buffered until
+/// they are complete.
+struct Blocks<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+    total: u64,
+}
+
+impl<const N: usize> Blocks<N> {
+    fn new() -> Self {
+        Blocks {
+            buffer: [0; N],
+            len: 0,
+            total: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8], mut compress: impl FnMut(&[u8; N])) {
+        self.total += data.len() as u64;
+
+        if self.len > 0 {
+            let n = (N - self.len).min(data.len());
+            self.buffer[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+
+            if self.len < N {
+                return;
+            }
+            compress(&self.buffer);
+            self.len = 0;
+        }
+
+        let mut blocks = data.chunks_exact(N);
+        for block in &mut blocks {
+            compress(block.try_into().unwrap());
+        }
+
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.len = rest.len();
+    }
+
+    /// Pad the message with a 1 bit and zeros, up to its `length` (in
+    /// bits, already encoded), which ends the last block.
+    fn pad(&mut self, length: &[u8], mut compress: impl FnMut(&[u8; N])) {
+        let total = self.total;
+        let zeros = (N - (self.len + 1 + length.len()) % N) % N;
+
+        self.update(&[0x80], &mut compress);
+        self.update(&vec![0; zeros], &mut compress);
+        self.update(length, &mut compress);
+        self.total = total;
+    }
+}
+
+const MD5_SHIFTS: [u32; 16] = [7, 12, 17, 22, 5, 9, 14, 20, 4, 11, 16, 23, 6, 10, 15, 21];
+
+const MD5_CONSTANTS: [u32; 64] = [
+    0xd76aa478, 0xe8c7b756, 0x242070db, 0xc1bdceee, 0xf57c0faf, 0x4787c62a, 0xa8304613, 0xfd469501,
+    0x698098d8, 0x8b44f7af, 0xffff5bb1, 0x895cd7be, 0x6b901122, 0xfd987193, 0xa679438e, 0x49b40821,
+    0xf61e2562, 0xc040b340, 0x265e5a51, 0xe9b6c7aa, 0xd62f105d, 0x02441453, 0xd8a1e681, 0xe7d3fbc8,
+    0x21e1cde6, 0xc33707d6, 0xf4d50d87, 0x455a14ed, 0xa9e3e905, 0xfcefa3f8, 0x676f02d9, 0x8d2a4c8a,
+    0xfffa3942, 0x8771f681, 0x6d9d6122, 0xfde5380c, 0xa4beea44, 0x4bdecfa9, 0xf6bb4b60, 0xbebfbc70,
+    0x289b7ec6, 0xeaa127fa, 0xd4ef3085, 0x04881d05, 0xd9d4d039, 0xe6db99e5, 0x1fa27cf8, 0xc4ac5665,
+    0xf4292244, 0x432aff97, 0xab9423a7, 0xfc93a039, 0x655b59c3, 0x8f0ccc92, 0xffeff47d, 0x85845dd1,
+    0x6fa87e4f, 0xfe2ce6e0, 0xa3014314, 0x4e0811a1, 0xf7537e82, 0xbd3af235, 0x2ad7d2bb, 0xeb86d391,
+];
+
+pub struct Md5 {
+    state: [u32; 4],
+    blocks: Blocks<64>,
+}
+
+impl Md5 {
+    pub fn new() -> Self {
+        Md5 {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476],
+            blocks: Blocks::new(),
+        }
+    }
+
+    fn compress(state: &mut [u32; 4], block: &[u8; 64]) {
+        let words: Vec<u32> = block
+            .chunks_exact(4)
+            .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+        let [mut a, mut b, mut c, mut d] = *state;
+
+        for i in 0..64 {
+            let (f, g) = match i / 16 {
+                0 => ((b & c) | (!b & d), i),
+                1 => ((d & b) | (!d & c), (5 * i + 1) % 16),
+                2 => (b ^ c ^ d, (3 * i + 5) % 16),
+                _ => (c ^ (b | !d), (7 * i) % 16),
+            };
+
+            let sum = a
+                .wrapping_add(f)
+                .wrapping_add(MD5_CONSTANTS[i])
+                .wrapping_add(words[g]);
+            a = d;
+            d = c;
+            c = b;
+            b = b.wrapping_add(sum.rotate_left(MD5_SHIFTS[i / 16 * 4 + i % 4]));
+        }
+
+        for (word, value) in state.iter_mut().zip([a, b, c, d]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+}
+
+impl Hasher for Md5 {
+    fn update(&mut self, data: &[u8]) {
+        self.blocks
+            .update(data, |block| Md5::compress(&mut self.state, block));
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let length = (self.blocks.total * 8).to_le_bytes();
+        self.blocks
+            .pad(&length, |block| Md5::compress(&mut self.state, block));
+        self.state
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect()
+    }
+}
+
+pub struct Sha1 {
+    state: [u32; 5],
+    blocks: Blocks<64>,
+}
+
+impl Sha1 {
+    pub fn new() -> Self {
+        Sha1 {
+            state: [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0],
+            blocks: Blocks::new(),
+        }
+    }
+
+    fn compress(state: &mut [u32; 5], block: &[u8; 64]) {
+        let mut words = [0u32; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..80 {
+            words[i] = (words[i - 3] ^ words[i - 8] ^ words[i - 14] ^ words[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = *state;
+        for (i, word) in words.iter().enumerate() {
+            let (f, k) = match i / 20 {
+                0 => ((b & c) | (!b & d), 0x5a827999),
+                1 => (b ^ c ^ d, 0x6ed9eba1),
+                2 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (word, value) in state.iter_mut().zip([a, b, c, d, e]) {
+            *word = word.wrapping_add(value);
+        }
+    }
+}
+
+impl Hasher for Sha1 {
+    fn update(&mut self, data: &[u8]) {
+        self.blocks
+            .update(data, |block| Sha1::compress(&mut self.state, block));
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let length = (self.blocks.total * 8).to_be_bytes();
+        self.blocks
+            .pad(&length, |block| Sha1::compress(&mut self.state, block));
+        self.state
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect()
+    }
+}
+
+const SHA256_CONSTANTS: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+pub struct Sha256 {
+    state: [u32; 8],
+    blocks: Blocks<64>,
+}
+
+impl Sha256 {
+    pub fn new() -> Self {
+        Sha256 {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            blocks: Blocks::new(),
+        }
+    }
+
+    fn compress(state: &mut [u32; 8], block: &[u8; 64]) {
+        let mut words = [0u32; 64];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(4)) {
+            *word = u32::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = words[i - 15].rotate_right(7)
+                ^ words[i - 15].rotate_right(18)
+                ^ (words[i - 15] >> 3);
+            let s1 = words[i - 2].rotate_right(17)
+                ^ words[i - 2].rotate_right(19)
+                ^ (words[i - 2] >> 10);
+            words[i] = words[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(words[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let mut v = *state;
+        for (word, constant) in words.iter().zip(SHA256_CONSTANTS) {
+            let [a, b, c, d, e, f, g, h] = v;
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(constant)
+                .wrapping_add(*word);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+
+            v = [
+                temp1.wrapping_add(temp2),
+                a,
+                b,
+                c,
+                d.wrapping_add(temp1),
+                e,
+                f,
+                g,
+            ];
+        }
+
+        for (word, value) in state.iter_mut().zip(v) {
+            *word = word.wrapping_add(value);
+        }
+    }
+}
+
+impl Hasher for Sha256 {
+    fn update(&mut self, data: &[u8]) {
+        self.blocks
+            .update(data, |block| Sha256::compress(&mut self.state, block));
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let length = (self.blocks.total * 8).to_be_bytes();
+        self.blocks
+            .pad(&length, |block| Sha256::compress(&mut self.state, block));
+        self.state
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect()
+    }
+}
+
+const SHA512_CONSTANTS: [u64; 80] = [
+    0x428a2f98d728ae22,
+    0x7137449123ef65cd,
+    0xb5c0fbcfec4d3b2f,
+    0xe9b5dba58189dbbc,
+    0x3956c25bf348b538,
+    0x59f111f1b605d019,
+    0x923f82a4af194f9b,
+    0xab1c5ed5da6d8118,
+    0xd807aa98a3030242,
+    0x12835b0145706fbe,
+    0x243185be4ee4b28c,
+    0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f,
+    0x80deb1fe3b1696b1,
+    0x9bdc06a725c71235,
+    0xc19bf174cf692694,
+    0xe49b69c19ef14ad2,
+    0xefbe4786384f25e3,
+    0x0fc19dc68b8cd5b5,
+    0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275,
+    0x4a7484aa6ea6e483,
+    0x5cb0a9dcbd41fbd4,
+    0x76f988da831153b5,
+    0x983e5152ee66dfab,
+    0xa831c66d2db43210,
+    0xb00327c898fb213f,
+    0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2,
+    0xd5a79147930aa725,
+    0x06ca6351e003826f,
+    0x142929670a0e6e70,
+    0x27b70a8546d22ffc,
+    0x2e1b21385c26c926,
+    0x4d2c6dfc5ac42aed,
+    0x53380d139d95b3df,
+    0x650a73548baf63de,
+    0x766a0abb3c77b2a8,
+    0x81c2c92e47edaee6,
+    0x92722c851482353b,
+    0xa2bfe8a14cf10364,
+    0xa81a664bbc423001,
+    0xc24b8b70d0f89791,
+    0xc76c51a30654be30,
+    0xd192e819d6ef5218,
+    0xd69906245565a910,
+    0xf40e35855771202a,
+    0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8,
+    0x1e376c085141ab53,
+    0x2748774cdf8eeb99,
+    0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63,
+    0x4ed8aa4ae3418acb,
+    0x5b9cca4f7763e373,
+    0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc,
+    0x78a5636f43172f60,
+    0x84c87814a1f0ab72,
+    0x8cc702081a6439ec,
+    0x90befffa23631e28,
+    0xa4506cebde82bde9,
+    0xbef9a3f7b2c67915,
+    0xc67178f2e372532b,
+    0xca273eceea26619c,
+    0xd186b8c721c0c207,
+    0xeada7dd6cde0eb1e,
+    0xf57d4f7fee6ed178,
+    0x06f067aa72176fba,
+    0x0a637dc5a2c898a6,
+    0x113f9804bef90dae,
+    0x1b710b35131c471b,
+    0x28db77f523047d84,
+    0x32caab7b40c72493,
+    0x3c9ebe0a15c9bebc,
+    0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6,
+    0x597f299cfc657e2a,
+    0x5fcb6fab3ad6faec,
+    0x6c44198c4a475817,
+];
+
+/// The initial state of SHA-512, which is also the one of BLAKE2b.
+const SHA512_STATE: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+pub struct Sha512 {
+    state: [u64; 8],
+    blocks: Blocks<128>,
+}
+
+impl Sha512 {
+    pub fn new() -> Self {
+        Sha512 {
+            state: SHA512_STATE,
+            blocks: Blocks::new(),
+        }
+    }
+
+    fn compress(state: &mut [u64; 8], block: &[u8; 128]) {
+        let mut words = [0u64; 80];
+        for (word, bytes) in words.iter_mut().zip(block.chunks_exact(8)) {
+            *word = u64::from_be_bytes(bytes.try_into().unwrap());
+        }
+        for i in 16..80 {
+            let s0 = words[i - 15].rotate_right(1)
+                ^ words[i - 15].rotate_right(8)
+                ^ (words[i - 15] >> 7);
+            let s1 =
+                words[i - 2].rotate_right(19) ^ words[i - 2].rotate_right(61) ^ (words[i - 2] >> 6);
+            words[i] = words[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(words[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let mut v = *state;
+        for (word, constant) in words.iter().zip(SHA512_CONSTANTS) {
+            let [a, b, c, d, e, f, g, h] = v;
+            let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+            let choice = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(choice)
+                .wrapping_add(constant)
+                .wrapping_add(*word);
+            let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+            let majority = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(majority);
+
+            v = [
+                temp1.wrapping_add(temp2),
+                a,
+                b,
+                c,
+                d.wrapping_add(temp1),
+                e,
+                f,
+                g,
+            ];
+        }
+
+        for (word, value) in state.iter_mut().zip(v) {
+            *word = word.wrapping_add(value);
+        }
+    }
+}
+
+impl Hasher for Sha512 {
+    fn update(&mut self, data: &[u8]) {
+        self.blocks
+            .update(data, |block| Sha512::compress(&mut self.state, block));
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let length = (self.blocks.total as u128 * 8).to_be_bytes();
+        self.blocks
+            .pad(&length, |block| Sha512::compress(&mut self.state, block));
+        self.state
+            .iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect()
+    }
+}
+
+const BLAKE2B_SIGMA: [[usize; 16]; 10] = [
+    [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+    [14, 10, 4, 8, 9, 15, 13, 6, 1, 12, 0, 2, 11, 7, 5, 3],
+    [11, 8, 12, 0, 5, 2, 15, 13, 10, 14, 3, 6, 7, 1, 9, 4],
+    [7, 9, 3, 1, 13, 12, 11, 14, 2, 6, 5, 10, 4, 0, 15, 8],
+    [9, 0, 5, 7, 2, 4, 10, 15, 14, 1, 11, 12, 6, 8, 3, 13],
+    [2, 12, 6, 10, 0, 11, 8, 3, 4, 13, 7, 5, 15, 14, 1, 9],
+    [12, 5, 1, 15, 14, 13, 4, 10, 0, 7, 6, 3, 9, 2, 8, 11],
+    [13, 11, 7, 14, 12, 1, 3, 9, 5, 0, 15, 4, 8, 6, 2, 10],
+    [6, 15, 14, 9, 11, 3, 0, 8, 12, 2, 13, 7, 1, 4, 10, 5],
+    [10, 2, 8, 4, 7, 6, 1, 5, 15, 11, 9, 14, 3, 12, 13, 0],
+];
+
+/// BLAKE2b, without a key, with a digest of up to 64 bytes.
+pub struct Blake2b {
+    state: [u64; 8],
+    buffer: [u8; 128],
+    len: usize,
+    total: u128,
+    digest_len: usize,
+}
+
+impl Blake2b {
+    pub fn new(digest_len: usize) -> Self {
+        let mut state = SHA512_STATE;
+        state[0] ^= 0x01010000 ^ digest_len as u64;
+
+        Blake2b {
+            state,
+            buffer: [0; 128],
+            len: 0,
+            total: 0,
+            digest_len,
+        }
+    }
+
+    fn compress(&mut self, last: bool) {
+        let words: Vec<u64> = self
+            .buffer
+            .chunks_exact(8)
+            .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+            .collect();
+
+        let mut v = [0u64; 16];
+        v[..8].copy_from_slice(&self.state);
+        v[8..].copy_from_slice(&SHA512_STATE);
+        v[12] ^= self.total as u64;
+        v[13] ^= (self.total >> 64) as u64;
+        if last {
+            v[14] = !v[14];
+        }
+
+        let mix = |v: &mut [u64; 16], [a, b, c, d]: [usize; 4], x: u64, y: u64| {
+            v[a] = v[a].wrapping_add(v[b]).wrapping_add(x);
+            v[d] = (v[d] ^ v[a]).rotate_right(32);
+            v[c] = v[c].wrapping_add(v[d]);
+            v[b] = (v[b] ^ v[c]).rotate_right(24);
+            v[a] = v[a].wrapping_add(v[b]).wrapping_add(y);
+            v[d] = (v[d] ^ v[a]).rotate_right(16);
+            v[c] = v[c].wrapping_add(v[d]);
+            v[b] = (v[b] ^ v[c]).rotate_right(63);
+        };
+
+        for round in 0..12 {
+            let s = &BLAKE2B_SIGMA[round % 10];
+            mix(&mut v, [0, 4, 8, 12], words[s[0]], words[s[1]]);
+            mix(&mut v, [1, 5, 9, 13], words[s[2]], words[s[3]]);
+            mix(&mut v, [2, 6, 10, 14], words[s[4]], words[s[5]]);
+            mix(&mut v, [3, 7, 11, 15], words[s[6]], words[s[7]]);
+            mix(&mut v, [0, 5, 10, 15], words[s[8]], words[s[9]]);
+            mix(&mut v, [1, 6, 11, 12], words[s[10]], words[s[11]]);
+            mix(&mut v, [2, 7, 8, 13], words[s[12]], words[s[13]]);
+            mix(&mut v, [3, 4, 9, 14], words[s[14]], words[s[15]]);
+        }
+
+        for i in 0..8 {
+            self.state[i] ^= v[i] ^ v[i + 8];
+        }
+    }
+}
+
+impl Hasher for Blake2b {
+    fn update(&mut self, mut data: &[u8]) {
+        // The last block is kept, since it's compressed differently.
+        while !data.is_empty() {
+            if self.len == self.buffer.len() {
+                self.total += self.len as u128;
+                self.compress(false);
+                self.len = 0;
+            }
+
+            let n = (self.buffer.len() - self.len).min(data.len());
+            self.buffer[self.len..self.len + n].copy_from_slice(&data[..n]);
+            self.len += n;
+            data = &data[n..];
+        }
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        self.total += self.len as u128;
+        self.buffer[self.len..].fill(0);
+        self.compress(true);
+
+        let digest: Vec<u8> = self
+            .state
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect();
+        digest[..self.digest_len].to_vec()
+    }
+}
+
+/// The CRC of `cksum` (POSIX), with the polynomial of Ethernet, computed
+/// on the data and then its length.
+pub struct Crc {
+    table: [u32; 256],
+    crc: u32,
+    total: u64,
+}
+
+impl Crc {
+    pub fn new() -> Self {
+        let mut table = [0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = (i as u32) << 24;
+            for _ in 0..8 {
+                crc = if crc & 0x80000000 != 0 {
+                    crc << 1 ^ 0x04c11db7
+                } else {
+                    crc << 1
+                };
+            }
+            *entry = crc;
+        }
+
+        Crc {
+            table,
+            crc: 0,
+            total: 0,
+        }
+    }
+
+    fn add(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = self.crc << 8 ^ self.table[((self.crc >> 24) as u8 ^ byte) as usize];
+        }
+    }
+}
+
+impl Hasher for Crc {
+    fn update(&mut self, data: &[u8]) {
+        self.add(data);
+        self.total += data.len() as u64;
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        let mut length = self.total;
+        while length > 0 {
+            self.add(&[length as u8]);
+            length >>= 8;
+        }
+
+        (!self.crc).to_be_bytes().to_vec()
+    }
+}
+
+/// The CRC-32 of `gzip` (and zlib), the reflected variant of the CRC of
+/// `cksum`.
+pub struct Crc32 {
+    table: [u32; 256],
+    crc: u32,
+}
+
+impl Crc32 {
+    pub fn new() -> Self {
+        let mut table = [0; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut crc = i as u32;
+            for _ in 0..8 {
+                crc = if crc & 1 != 0 {
+                    crc >> 1 ^ 0xedb88320
+                } else {
+                    crc >> 1
+                };
+            }
+            *entry = crc;
+        }
+
+        Crc32 { table, crc: !0 }
+    }
+
+    /// Get the CRC of the data so far.
+    pub fn value(&self) -> u32 {
+        !self.crc
+    }
+}
+
+impl Hasher for Crc32 {
+    fn update(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.crc = self.crc >> 8 ^ self.table[(self.crc as u8 ^ byte) as usize];
+        }
+    }
+
+    fn finish(&mut self) -> Vec<u8> {
+        self.value().to_be_bytes().to_vec()
+    }
+}
@@ -0,0 +1,316 @@
+//! The `head` command, and the reading of the beginning or the end
+//! of a stream, shared with `tail`.
+
+use std::{
+    collections::VecDeque,
+    io::{BufRead, Read, Seek, SeekFrom, Write},
+};
+
+use crate::utils::*;
+
+/// What is counted by `head` and `tail`.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Unit {
+    Lines,
+    Bytes,
+}
+
+/// Which part of the input is printed.
+#[derive(Clone, Copy)]
+pub enum Span {
+    /// The first N units (`head -n N`).
+    First(u64),
+    /// All but the last N units (`head -n -N`).
+    AllButLast(u64),
+    /// The last N units (`tail -n N`).
+    Last(u64),
+    /// From the Nth unit onwards, counting from 1 (`tail -n +N`).
+    From(u64),
+}
+
+/// Turn the obsolete `-N` syntax, only allowed as the first
+/// argument, into `-n N`, so it can be parsed.
+pub fn expand_obsolete_count(args: &[String]) -> Vec<String> {
+    match args.split_first() {
+        Some((first, rest)) => match first.strip_prefix('-') {
+            Some(count) if !count.is_empty() && count.bytes().all(|c| c.is_ascii_digit()) => {
+                let mut expanded = vec![String::from("-n"), count.to_owned()];
+                expanded.extend_from_slice(rest);
+                expanded
+            }
+            _ => args.to_vec(),
+        },
+        None => Vec::new(),
+    }
+}
+
+/// Print the header shown before each file when there are more of them.
+pub fn print_header(out: &mut impl Write, path: &str, first: bool) -> std::io::Result<()> {
+    let name = if path == "-" { "standard input" } else { path };
+
+    if first {
+        writeln!(out, "==> {} <==", name)
+    } else {
+        writeln!(out, "\n==> {} <==", name)
+    }
+}
+
+/// Read a unit (a line, or a chunk of bytes) from `input`, into `buffer`.
+/// Returns false at the end of the input.
+fn read_unit(input: &mut dyn BufRead, unit: Unit, buffer: &mut Vec<u8>) -> std::io::Result<bool> {
+    buffer.clear();
+
+    match unit {
+        Unit::Lines => Ok(input.read_until(b'\n', buffer)? != 0),
+        Unit::Bytes => {
+            let available = input.fill_buf()?;
+            buffer.extend_from_slice(available);
+            let len = available.len();
+            input.consume(len);
+
+            Ok(len != 0)
+        }
+    }
+}
+
+/// Print the requested span of a stream, reading it only once.
+pub fn print_span(
+    input: &mut dyn BufRead,
+    out: &mut impl Write,
+    unit: Unit,
+    span: Span,
+) -> std::io::Result<()> {
+    let mut buffer = Vec::new();
+
+    match (unit, span) {
+        (Unit::Bytes, Span::First(count)) => {
+            std::io::copy(&mut input.take(count), out)?;
+        }
+        (Unit::Lines, Span::First(count)) => {
+            for _ in 0..count {
+                if !read_unit(input, unit, &mut buffer)? {
+                    break;
+                }
+                out.write_all(&buffer)?;
+            }
+        }
+        (_, Span::From(start)) => {
+            // Skip the units before the start (counted from 1).
+            let mut skipped = 0;
+            while skipped + 1 < start {
+                if unit == Unit::Bytes {
+                    let to_skip = start - 1 - skipped;
+                    let skip = std::io::copy(&mut input.take(to_skip), &mut std::io::sink())?;
+                    skipped += skip;
+                    if skip < to_skip {
+                        return Ok(());
+                    }
+                } else {
+                    if !read_unit(input, unit, &mut buffer)? {
+                        return Ok(());
+                    }
+                    skipped += 1;
+                }
+            }
+
+            std::io::copy(input, out)?;
+        }
+        (_, Span::AllButLast(count) | Span::Last(count)) => {
+            let print_front = matches!(span, Span::AllButLast(_));
+
+            // Keep the last `count` units, printing (or dropping)
+            // whatever falls out of the window.
+            let mut window = VecDeque::<u8>::new();
+            let mut window_lines = 0;
+
+            while read_unit(input, unit, &mut buffer)? {
+                window.extend(&buffer);
+                if unit == Unit::Lines {
+                    window_lines += 1;
+                }
+
+                loop {
+                    let excess = match unit {
+                        Unit::Bytes => window.len().saturating_sub(count as usize),
+                        Unit::Lines if window_lines > count => window
+                            .iter()
+                            .position(|&c| c == b'\n')
+                            .map_or(0, |end| end + 1),
+                        Unit::Lines => 0,
+                    };
+
+                    if excess == 0 {
+                        break;
+                    }
+
+                    let dropped = window.drain(..excess).collect::<Vec<_>>();
+                    if print_front {
+                        out.write_all(&dropped)?;
+                    }
+
+                    if unit == Unit::Lines {
+                        window_lines -= 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+
+            if !print_front {
+                let (front, back) = window.as_slices();
+                out.write_all(front)?;
+                out.write_all(back)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Print the last `count` units of a regular file, reading it backwards
+/// from the end, instead of going through all of it.
+pub fn print_file_end(
+    file: &mut std::fs::File,
+    out: &mut impl Write,
+    unit: Unit,
+    count: u64,
+) -> std::io::Result<()> {
+    let len = file.metadata()?.len();
+
+    let start = match unit {
+        Unit::Bytes => len.saturating_sub(count),
+        Unit::Lines => {
+            let mut pos = len;
+            let mut newlines = 0;
+            let mut block = vec![0; 1 << 16];
+            let mut start = 0;
+
+            // A newline at the very end doesn't start a new line.
+            let mut skip_last = true;
+
+            'search: while pos > 0 {
+                let size = (pos as usize).min(block.len());
+                pos -= size as u64;
+                file.seek(SeekFrom::Start(pos))?;
+                file.read_exact(&mut block[..size])?;
+
+                for i in (0..size).rev() {
+                    if block[i] != b'\n' {
+                        skip_last = false;
+                        continue;
+                    }
+
+                    if std::mem::take(&mut skip_last) {
+                        continue;
+                    }
+
+                    newlines += 1;
+                    if newlines == count {
+                        start = pos + i as u64 + 1;
+                        break 'search;
+                    }
+                }
+            }
+
+            if count == 0 {
+                len
+            } else {
+                start
+            }
+        }
+    };
+
+    file.seek(SeekFrom::Start(start))?;
+    std::io::copy(file, out)?;
+
+    Ok(())
+}
+
+/// Parse the count given to `-n` or `-c`, with its sign.
+pub fn parse_count(count: &str) -> Option<(Option<char>, u64)> {
+    let sign = count.chars().next().filter(|c| *c == '+' || *c == '-');
+    let number = if sign.is_some() { &count[1..] } else { count };
+
+    Some((sign, parse_size(number)?))
+}
+
+pub fn head(args: &[String]) {
+    let args = expand_obsolete_count(args);
+    let Some((opts, files)) = parse_options(&args, &["-n", "--lines", "-c", "--bytes"]) else {
+        eprintln!("Usage: head [-n [-]N] [-c [-]N] [FILE]...");
+        std::process::exit(-130);
+    };
+
+    let mut unit = Unit::Lines;
+    let mut span = Span::First(10);
+    let mut headers = None;
+
+    for (opt, value) in opts {
+        match opt.as_str() {
+            "-n" | "--lines" | "-c" | "--bytes" => {
+                unit = if opt.starts_with("-n") || opt == "--lines" {
+                    Unit::Lines
+                } else {
+                    Unit::Bytes
+                };
+
+                span = match value.as_deref().and_then(parse_count) {
+                    Some((Some('-'), count)) => Span::AllButLast(count),
+                    Some((_, count)) => Span::First(count),
+                    None => {
+                        eprintln!("head: invalid number: '{}'", value.unwrap_or_default());
+                        std::process::exit(-130);
+                    }
+                };
+            }
+            "-q" | "--quiet" | "--silent" => headers = Some(false),
+            "-v" | "--verbose" => headers = Some(true),
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-130);
+            }
+        }
+    }
+
+    let stdin = String::from("-");
+    let files = if files.is_empty() {
+        vec![&stdin]
+    } else {
+        files
+    };
+    let headers = headers.unwrap_or(files.len() > 1);
+
+    let mut stdout = std::io::stdout().lock();
+    let mut was_error = false;
+
+    for (i, file) in files.iter().enumerate() {
+        let mut input = match open_input(file) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!(
+                    "head: cannot open '{}' for reading: {}",
+                    file,
+                    error_reason(&e)
+                );
+                was_error = true;
+                continue;
+            }
+        };
+
+        let printed = (if headers {
+            print_header(&mut stdout, file, i == 0)
+        } else {
+            Ok(())
+        })
+        .and_then(|_| print_span(&mut input, &mut stdout, unit, span));
+
+        if let Err(e) = printed {
+            eprintln!("head: error reading '{}': {}", file, error_reason(&e));
+            was_error = true;
+        }
+    }
+
+    if was_error {
+        std::process::exit(-130);
+    }
+}
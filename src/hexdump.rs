@@ -0,0 +1,135 @@
+//! The `hexdump` command, dumping files in hexadecimal.
+
+use std::io::{BufWriter, Write};
+
+use crate::dump::*;
+use crate::utils::*;
+
+fn usage() -> ! {
+    eprintln!("Usage: hexdump [-C] [-v] [-s OFFSET] [-n LENGTH] [FILE]...");
+    std::process::exit(-221);
+}
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("hexdump: {}", message);
+    std::process::exit(-221);
+}
+
+/// Format a line of the canonical format (`-C`): the offset, 16 bytes in
+/// hexadecimal in two groups of 8, then the bytes as text.
+fn canonical_line(offset: u64, bytes: &[u8]) -> String {
+    let mut line = format!("{:08x} ", offset);
+
+    for (i, &byte) in bytes.iter().enumerate() {
+        line.push(' ');
+        if i == 8 {
+            line.push(' ');
+        }
+        write_hex(&mut line, &[byte], false);
+    }
+
+    let hex_width = 16 * 3 + 1;
+    let padding = hex_width - bytes.len() * 3 - usize::from(bytes.len() > 8);
+    line.push_str(&" ".repeat(padding));
+    line.push_str("  |");
+    line.extend(bytes.iter().map(|&byte| printable(byte)));
+    line.push('|');
+    line
+}
+
+/// Format a line of the default format: the offset, then 8 words of
+/// 2 bytes in hexadecimal, each followed by a space, like the missing
+/// words at the end of the input.
+fn default_line(offset: u64, bytes: &[u8]) -> String {
+    let mut line = format!("{:07x} ", offset);
+
+    for word in bytes.chunks(2) {
+        let word = [word[0], word.get(1).copied().unwrap_or(0)];
+        line.push_str(&Format::Hex(2).format(&word));
+        line.push(' ');
+    }
+
+    format!("{:48}", line)
+}
+
+pub fn hexdump(args: &[String]) {
+    let with_value = ["-s", "-n"];
+    let Some((opts, files)) = parse_options(args, &with_value) else {
+        usage();
+    };
+
+    let mut canonical = false;
+    let mut squeeze = true;
+    let mut skip = 0;
+    let mut limit = None;
+
+    for (opt, value) in opts {
+        let number = || {
+            let value = value.clone().unwrap_or_default();
+            parse_offset(&value).unwrap_or_else(|| fail(format_args!("invalid number '{}'", value)))
+        };
+
+        match opt.as_str() {
+            "-C" | "--canonical" => canonical = true,
+            "-v" | "--no-squeezing" => squeeze = false,
+            "-s" | "--skip" => skip = number(),
+            "-n" | "--length" => limit = Some(number()),
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-221);
+            }
+        }
+    }
+
+    let files = if files.is_empty() {
+        vec!["-".to_string()]
+    } else {
+        files.into_iter().cloned().collect()
+    };
+    let mut reader = Reader::new("hexdump", files, skip, limit);
+    let mut squeeze = Squeeze::new(squeeze);
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut block = [0; 16];
+    let mut offset = skip;
+
+    let result = (|| -> std::io::Result<()> {
+        loop {
+            let len = reader.read(&mut block);
+            if len == 0 {
+                break;
+            }
+
+            if len == block.len() && squeeze.repeats(&block, &mut out)? {
+                offset += len as u64;
+                continue;
+            }
+
+            let line = if canonical {
+                canonical_line(offset, &block[..len])
+            } else {
+                default_line(offset, &block[..len])
+            };
+            writeln!(out, "{}", line)?;
+            offset += len as u64;
+        }
+
+        if offset > skip {
+            if canonical {
+                writeln!(out, "{:08x}", offset)?;
+            } else {
+                writeln!(out, "{:07x}", offset)?;
+            }
+        }
+
+        out.flush()
+    })();
+
+    if let Err(e) = result {
+        fail(format_args!("{}", error_reason(&e)));
+    }
+
+    if reader.failed {
+        std::process::exit(-221);
+    }
+}
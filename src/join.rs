@@ -0,0 +1,266 @@
+//! The `join` command, joining the lines of two sorted files
+//! that have equal join fields.
+
+use std::{
+    cmp::Ordering,
+    io::{BufRead, BufWriter, Write},
+};
+
+use crate::fields::*;
+use crate::utils::*;
+
+/// A field printed by `-o`: the join field, or a field of one of the files.
+enum OutputField {
+    Join,
+    Field(usize, usize),
+}
+
+/// Parse the output format of `-o`: a list of `0` (the join field) and
+/// `FILE.FIELD`, separated by commas or blanks.
+fn parse_format(format: &str) -> Option<Vec<OutputField>> {
+    format
+        .split([',', ' ', '\t'])
+        .filter(|spec| !spec.is_empty())
+        .map(|spec| match spec.split_once('.') {
+            None if spec == "0" => Some(OutputField::Join),
+            Some((file @ ("1" | "2"), field)) => match field.parse::<usize>() {
+                Ok(field) if field > 0 => {
+                    Some(OutputField::Field((file == "2") as usize, field - 1))
+                }
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// The settings of a join.
+struct Joiner {
+    /// The join field of each file.
+    fields: [usize; 2],
+    separator: Option<u8>,
+    ignore_case: bool,
+    /// Print the unpairable lines of each file (`-a`, `-v`).
+    unpaired: [bool; 2],
+    /// Print the joined lines (not with `-v`).
+    paired: bool,
+    format: Option<Vec<OutputField>>,
+    empty: Vec<u8>,
+}
+
+impl Joiner {
+    fn key<'a>(&self, file: usize, line: &'a [u8]) -> &'a [u8] {
+        split_fields(line, self.separator)
+            .get(self.fields[file])
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn compare_keys(&self, a: &[u8], b: &[u8]) -> Ordering {
+        if self.ignore_case {
+            a.iter()
+                .map(u8::to_ascii_lowercase)
+                .cmp(b.iter().map(u8::to_ascii_lowercase))
+        } else {
+            a.cmp(b)
+        }
+    }
+
+    /// Print a joined line, from the lines of both files (or just one
+    /// of them, if it's unpairable).
+    fn print(&self, out: &mut impl Write, lines: [Option<&[u8]>; 2]) -> std::io::Result<()> {
+        let fields = lines.map(|line| line.map(|line| split_fields(line, self.separator)));
+        let separator = [self.separator.unwrap_or(b' ')];
+
+        let join_field = (0..2)
+            .find_map(|file| fields[file].as_ref()?.get(self.fields[file]).copied())
+            .unwrap_or_default();
+
+        let mut output = Vec::new();
+        match &self.format {
+            Some(format) => {
+                for spec in format {
+                    let field = match *spec {
+                        OutputField::Join => Some(join_field),
+                        OutputField::Field(file, field) => fields[file]
+                            .as_ref()
+                            .and_then(|fields| fields.get(field).copied()),
+                    };
+                    output.push(field.unwrap_or(&self.empty));
+                }
+            }
+            None => {
+                output.push(join_field);
+                for file in 0..2 {
+                    let Some(fields) = &fields[file] else {
+                        continue;
+                    };
+
+                    output.extend(
+                        fields
+                            .iter()
+                            .enumerate()
+                            .filter(|&(i, _)| i != self.fields[file])
+                            .map(|(_, field)| *field),
+                    );
+                }
+            }
+        }
+
+        out.write_all(&output.join(&separator[..]))?;
+        out.write_all(b"\n")
+    }
+
+    /// Read the group of lines that have the same key as `line`, which
+    /// is then replaced with the first line after the group, if any.
+    fn read_group(
+        &self,
+        input: &mut dyn BufRead,
+        file: usize,
+        line: &mut Option<Vec<u8>>,
+    ) -> std::io::Result<Vec<Vec<u8>>> {
+        let mut group = Vec::from_iter(line.take());
+
+        loop {
+            let mut next = Vec::new();
+            if !read_line(input, b'\n', &mut next)? {
+                return Ok(group);
+            }
+
+            let same = self.compare_keys(self.key(file, &group[0]), self.key(file, &next))
+                == Ordering::Equal;
+            if !same {
+                *line = Some(next);
+                return Ok(group);
+            }
+
+            group.push(next);
+        }
+    }
+
+    /// Merge the two inputs, printing the joined lines as they're found.
+    fn join(&self, mut inputs: [Box<dyn BufRead>; 2], out: &mut impl Write) -> std::io::Result<()> {
+        let next_line = |input: &mut Box<dyn BufRead>| -> std::io::Result<Option<Vec<u8>>> {
+            let mut line = Vec::new();
+            Ok(read_line(input.as_mut(), b'\n', &mut line)?.then_some(line))
+        };
+
+        let mut lines = [next_line(&mut inputs[0])?, next_line(&mut inputs[1])?];
+
+        loop {
+            let order = match &lines {
+                [Some(a), Some(b)] => self.compare_keys(self.key(0, a), self.key(1, b)),
+                [Some(_), None] => Ordering::Less,
+                [None, Some(_)] => Ordering::Greater,
+                [None, None] => return Ok(()),
+            };
+
+            match order {
+                Ordering::Less | Ordering::Greater => {
+                    let file = (order == Ordering::Greater) as usize;
+                    let line = lines[file].take().unwrap_or_default();
+
+                    if self.unpaired[file] {
+                        let mut pair = [None, None];
+                        pair[file] = Some(line.as_slice());
+                        self.print(out, pair)?;
+                    }
+
+                    lines[file] = next_line(&mut inputs[file])?;
+                }
+                Ordering::Equal => {
+                    // Every line of a group is joined with every line of
+                    // the group with the same key in the other file.
+                    let group_a = self.read_group(inputs[0].as_mut(), 0, &mut lines[0])?;
+                    let group_b = self.read_group(inputs[1].as_mut(), 1, &mut lines[1])?;
+
+                    if self.paired {
+                        for a in &group_a {
+                            for b in &group_b {
+                                self.print(out, [Some(a), Some(b)])?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("join: {}", message);
+    std::process::exit(-162);
+}
+
+pub fn join(args: &[String]) {
+    let with_value = ["-1", "-2", "-j", "-t", "-a", "-v", "-o", "-e"];
+    let Some((opts, files)) = parse_options(args, &with_value) else {
+        eprintln!(
+            "Usage: join [-i] [-1 F] [-2 F] [-t C] [-a N] [-v N] [-o FMT] [-e S] FILE1 FILE2"
+        );
+        std::process::exit(-162);
+    };
+
+    let mut joiner = Joiner {
+        fields: [0, 0],
+        separator: None,
+        ignore_case: false,
+        unpaired: [false, false],
+        paired: true,
+        format: None,
+        empty: Vec::new(),
+    };
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+        let field = || match value.parse::<usize>() {
+            Ok(field) if field > 0 => field - 1,
+            _ => fail(format_args!("invalid field number: '{}'", value)),
+        };
+        let file = || match value.as_str() {
+            "1" => 0,
+            "2" => 1,
+            _ => fail(format_args!("invalid file number: '{}'", value)),
+        };
+
+        match opt.as_str() {
+            "-1" => joiner.fields[0] = field(),
+            "-2" => joiner.fields[1] = field(),
+            "-j" => joiner.fields = [field(); 2],
+            "-t" => match value.as_bytes() {
+                [separator] => joiner.separator = Some(*separator),
+                _ => fail(format_args!("multi-character tab '{}'", value)),
+            },
+            "-a" => joiner.unpaired[file()] = true,
+            "-v" => {
+                joiner.unpaired[file()] = true;
+                joiner.paired = false;
+            }
+            "-o" => match parse_format(&value) {
+                Some(format) => joiner.format = Some(format),
+                None => fail(format_args!("invalid field specifier: '{}'", value)),
+            },
+            "-e" => joiner.empty = value.into_bytes(),
+            "-i" | "--ignore-case" => joiner.ignore_case = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-162);
+            }
+        }
+    }
+
+    let [first, second] = files.as_slice() else {
+        fail(format_args!("expected two files"));
+    };
+
+    let inputs = [first, second].map(|path| match open_input(path) {
+        Ok(input) => input,
+        Err(e) => fail(format_args!("{}: {}", path, error_reason(&e))),
+    });
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    if let Err(e) = joiner.join(inputs, &mut out).and_then(|_| out.flush()) {
+        fail(format_args!("{}", error_reason(&e)));
+    }
+}
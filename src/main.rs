@@ -1,10 +1,49 @@
+mod ar;
+mod archive;
+mod awk;
+mod basenc;
+mod checksum;
+mod cmp;
+mod codec;
+mod comm;
+mod cpio;
+mod cut;
+mod deflate;
+mod df;
+mod diff;
+mod du;
+mod dump;
+mod fields;
+mod find;
+mod gzip;
+mod hash;
+mod head;
+mod hexdump;
+mod join;
+mod od;
+mod paste;
+mod patch;
+mod regex;
+mod sed;
+mod sort;
+mod tail;
+mod tar;
+mod tr;
+mod uniq;
 mod utils;
+mod walk;
+mod wc;
+mod xargs;
+mod xxd;
 
 use std::{
-    io::{BufRead, Write},
-    os::unix::prelude::{FileExt, PermissionsExt},
+    io::BufRead,
+    os::unix::prelude::{MetadataExt, OpenOptionsExt, OsStrExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
 };
 use utils::*;
+use walk::{DirEntry, Walk, WalkError};
 
 fn pwd() {
     if let Ok(cwd) = std::env::current_dir() {
@@ -81,14 +120,9 @@ fn grep(args: &[String]) {
 }
 
 fn cat(args: &[String]) {
-    for arg in args {
-        if let Ok(contents) = std::fs::read_to_string(arg) {
-            print!("{}", contents);
-        } else {
-            eprintln!("cat: {}: No such file or directory", arg);
-            std::process::exit(-20);
-        }
-    }
+    cat_inputs("cat", -20, args, |input, out| {
+        std::io::copy(input, out).map(|_| ())
+    });
 }
 
 fn mkdir(args: &[String]) {
@@ -113,12 +147,27 @@ fn mv(args: &[String]) {
 }
 
 fn ln(args: &[String]) {
-    let (opts, args) = extract_options(args);
+    let Some((opts, args)) = parse_options(args, &["-t", "--target-directory"]) else {
+        eprintln!("Usage: ln [OPTION]... SOURCE... DEST");
+        std::process::exit(-50);
+    };
     let mut symbolic = false;
+    let mut force = false;
+    let mut follow_dest = true;
+    let mut relative = false;
+    let mut verbose = false;
+    let mut no_target_dir = false;
+    let mut target_dir = None;
 
-    for opt in opts {
+    for (opt, value) in opts {
         match opt.as_str() {
             "-s" | "--symbolic" => symbolic = true,
+            "-f" | "--force" => force = true,
+            "-n" | "--no-dereference" => follow_dest = false,
+            "-r" | "--relative" => relative = true,
+            "-v" | "--verbose" => verbose = true,
+            "-T" | "--no-target-directory" => no_target_dir = true,
+            "-t" | "--target-directory" => target_dir = value,
             _ => {
                 println!("Invalid command");
                 std::process::exit(-1);
@@ -126,41 +175,255 @@ fn ln(args: &[String]) {
         }
     }
 
-    let [src, dst] = args.as_slice() else {
-        eprintln!("Usage: ln [OPTION]... SOURCE DEST");
+    if relative && !symbolic {
+        eprintln!("ln: cannot do --relative without --symbolic");
         std::process::exit(-50);
-    };
+    }
 
-    let ret_status = if symbolic {
-        std::os::unix::fs::symlink(src, dst)
-    } else {
-        std::fs::hard_link(src, dst)
+    // Pair every source with the path of the link to be created.
+    let links = match (target_dir, args.as_slice()) {
+        (Some(_), _) if no_target_dir => {
+            eprintln!("ln: cannot combine --target-directory and --no-target-directory");
+            std::process::exit(-50);
+        }
+        (_, []) => {
+            eprintln!("Usage: ln [OPTION]... SOURCE... DEST");
+            std::process::exit(-50);
+        }
+        (Some(dir), srcs) => link_into_dir(srcs, Path::new(&dir)),
+        (None, [src, dst]) if no_target_dir => vec![(Path::new(src.as_str()), dst.into())],
+        (None, _) if no_target_dir => {
+            eprintln!("ln: extra operand after '{}'", args[1]);
+            std::process::exit(-50);
+        }
+        // A single source is linked into the current directory.
+        (None, [src]) => link_into_dir(&[src], Path::new(".")),
+        (None, [src, dst]) => {
+            let src = Path::new(src.as_str());
+            match resolve_dest(src, Path::new(dst.as_str()), follow_dest) {
+                Ok(dst) => vec![(src, dst)],
+                Err(e) => {
+                    eprintln!("ln: failed to access '{}': {}", dst, error_reason(&e));
+                    std::process::exit(-50);
+                }
+            }
+        }
+        (None, [srcs @ .., dir]) => {
+            let dir = Path::new(dir.as_str());
+            if !dir.is_dir() {
+                eprintln!("ln: target '{}' is not a directory", dir.display());
+                std::process::exit(-50);
+            }
+
+            link_into_dir(srcs, dir)
+        }
     };
 
-    if ret_status.is_err() {
-        eprintln!("ln: cannot link '{}' to '{}'", src, dst);
+    let mut was_error = false;
+
+    for (src, dst) in links {
+        if force {
+            // Removing the destination would lose the only copy of the file.
+            if !symbolic && same_file(src, &dst) {
+                eprintln!(
+                    "ln: '{}' and '{}' are the same file",
+                    src.display(),
+                    dst.display()
+                );
+                was_error = true;
+                continue;
+            }
+
+            // Never replace a directory, just like GNU ln.
+            match std::fs::symlink_metadata(&dst) {
+                Ok(metadata) if !metadata.is_dir() => {
+                    if let Err(e) = std::fs::remove_file(&dst) {
+                        eprintln!(
+                            "ln: cannot remove '{}': {}",
+                            dst.display(),
+                            error_reason(&e)
+                        );
+                        was_error = true;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let target = if relative {
+            match relative_link_target(src, &dst) {
+                Ok(target) => target,
+                Err(e) => {
+                    eprintln!(
+                        "ln: failed to access '{}': {}",
+                        src.display(),
+                        error_reason(&e)
+                    );
+                    was_error = true;
+                    continue;
+                }
+            }
+        } else {
+            src.to_owned()
+        };
+
+        let ret_status = if symbolic {
+            std::os::unix::fs::symlink(&target, &dst)
+        } else {
+            std::fs::hard_link(&target, &dst)
+        };
+
+        match ret_status {
+            Ok(()) if verbose => {
+                let arrow = if symbolic { "->" } else { "=>" };
+                println!("'{}' {} '{}'", dst.display(), arrow, target.display());
+            }
+            Ok(()) => {}
+            Err(e) => {
+                let kind = if symbolic {
+                    "symbolic link"
+                } else {
+                    "hard link"
+                };
+                eprintln!(
+                    "ln: failed to create {} '{}': {}",
+                    kind,
+                    dst.display(),
+                    error_reason(&e)
+                );
+                was_error = true;
+            }
+        }
+    }
+
+    if was_error {
         std::process::exit(-50);
     }
 }
 
+/// Pair every source with a link named as its basename inside `dir`.
+fn link_into_dir<'a>(srcs: &[&'a String], dir: &Path) -> Vec<(&'a Path, PathBuf)> {
+    srcs.iter()
+        .map(|src| {
+            let src = Path::new(src.as_str());
+            let Some(basename) = src.file_name() else {
+                eprintln!("ln: failed to get basename of '{}'", src.display());
+                std::process::exit(-50);
+            };
+
+            (src, dir.join(basename))
+        })
+        .collect()
+}
+
+/// Check if `src` and `dst` are links to the same file.
+fn same_file(src: &Path, dst: &Path) -> bool {
+    match (std::fs::symlink_metadata(src), std::fs::symlink_metadata(dst)) {
+        (Ok(src), Ok(dst)) => (src.dev(), src.ino()) == (dst.dev(), dst.ino()),
+        _ => false,
+    }
+}
+
+/// Compute the target of a symlink at `link` pointing to `src`, relative to
+/// the directory of the link. The directories are canonicalized, but
+/// `src` itself isn't dereferenced.
+fn relative_link_target(src: &Path, link: &Path) -> std::io::Result<PathBuf> {
+    let canonical_parent = |path: &Path| match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize(),
+        _ => std::env::current_dir(),
+    };
+
+    let mut src_abs = canonical_parent(src)?;
+    if let Some(name) = src.file_name() {
+        src_abs.push(name);
+    }
+
+    Ok(relative_path(&canonical_parent(link)?, &src_abs))
+}
+
 fn rmdir(args: &[String]) {
+    let Some((opts, args)) = parse_options(args, &[]) else {
+        unreachable!("no option takes a value");
+    };
+    let mut parents = false;
+    let mut ignore_non_empty = false;
+    let mut verbose = false;
+
+    for (opt, _) in opts {
+        match opt.as_str() {
+            "-p" | "--parents" => parents = true,
+            "--ignore-fail-on-non-empty" => ignore_non_empty = true,
+            "-v" | "--verbose" => verbose = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-60);
+            }
+        }
+    }
+
+    if args.is_empty() {
+        eprintln!("Usage: rmdir [OPTION]... DIRECTORY...");
+        std::process::exit(-60);
+    }
+
+    let mut was_error = false;
+
     for arg in args {
-        if std::fs::remove_dir(arg).is_err() {
-            eprintln!("rmdir: failed to remove '{}'", arg);
-            std::process::exit(-60);
+        let mut dir = std::path::Path::new(arg.as_str());
+
+        loop {
+            if verbose {
+                println!("rmdir: removing directory, '{}'", dir.display());
+            }
+
+            if let Err(e) = std::fs::remove_dir(dir) {
+                // A non-empty directory isn't an error with
+                // '--ignore-fail-on-non-empty', but it still
+                // ends the chain of parents to be removed.
+                if !(ignore_non_empty && e.kind() == std::io::ErrorKind::DirectoryNotEmpty) {
+                    eprintln!(
+                        "rmdir: failed to remove '{}': {}",
+                        dir.display(),
+                        error_reason(&e)
+                    );
+                    was_error = true;
+                }
+
+                break;
+            }
+
+            if !parents {
+                break;
+            }
+
+            // With '-p', continue with each parent given in the
+            // path ('a/b/c' removes 'a/b/c', 'a/b' and 'a').
+            match dir.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() && parent.parent().is_some() => {
+                    dir = parent
+                }
+                _ => break,
+            }
         }
     }
+
+    if was_error {
+        std::process::exit(-60);
+    }
 }
 
 fn rm(args: &[String]) {
     let (opts, files) = extract_options(args);
     let mut recursive = false;
     let mut rmdir = false;
+    let mut one_file_system = false;
 
     for opt in opts {
         match opt.as_str() {
             "-r" | "-R" | "--recursive" => recursive = true,
             "-d" | "--dir" => rmdir = true,
+            "--one-file-system" => one_file_system = true,
             _ => {
                 println!("Invalid command");
                 std::process::exit(-70);
@@ -168,7 +431,7 @@ fn rm(args: &[String]) {
         }
     }
 
-    if files.len() == 0 {
+    if files.is_empty() {
         println!("Invalid command");
         std::process::exit(-1);
     }
@@ -193,7 +456,10 @@ fn rm(args: &[String]) {
 
         let ret_status = if metadata.is_dir() {
             if recursive {
-                std::fs::remove_dir_all(file)
+                was_error |= !remove_tree(Path::new(file.as_str()), one_file_system);
+
+                // The errors are signaled while removing the contents.
+                Ok(())
             } else if rmdir {
                 std::fs::remove_dir(file)
             } else {
@@ -218,6 +484,59 @@ fn rm(args: &[String]) {
     }
 }
 
+/// Remove a directory and its contents, without following symlinks.
+/// Returns false if anything couldn't be removed.
+fn remove_tree(root: &Path, one_file_system: bool) -> bool {
+    let root_dev = std::fs::symlink_metadata(root).map_or(0, |metadata| metadata.dev());
+    let mut success = true;
+
+    for entry in Walk::new(root)
+        .follow_root(false)
+        .contents_first(true)
+        .same_file_system(one_file_system)
+    {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!(
+                    "rm: cannot remove '{}': {}",
+                    e.path.display(),
+                    error_reason(&e.error)
+                );
+                success = false;
+                continue;
+            }
+        };
+
+        // Its contents weren't walked, so it can't be removed.
+        if one_file_system && entry.metadata().dev() != root_dev {
+            eprintln!(
+                "rm: skipping '{}', since it's on a different device",
+                entry.path().display()
+            );
+            success = false;
+            continue;
+        }
+
+        let ret_status = if entry.is_dir() {
+            std::fs::remove_dir(entry.path())
+        } else {
+            std::fs::remove_file(entry.path())
+        };
+
+        if let Err(e) = ret_status {
+            eprintln!(
+                "rm: cannot remove '{}': {}",
+                entry.path().display(),
+                error_reason(&e)
+            );
+            success = false;
+        }
+    }
+
+    success
+}
+
 fn ls(args: &[String]) {
     let (opts, args) = extract_options(args);
     let mut recursive = false;
@@ -238,11 +557,11 @@ fn ls(args: &[String]) {
 
     // ls with no dirs lists current directory.
     if args.is_empty() {
-        list_file(&String::from("."), all, recursive, long);
+        list_file(Path::new("."), all, recursive, long);
     }
 
     for arg in args {
-        list_file(arg, all, recursive, long);
+        list_file(Path::new(arg.as_str()), all, recursive, long);
     }
 }
 
@@ -265,27 +584,11 @@ fn cp(args: &[String]) {
         std::process::exit(-90);
     };
 
-    let actual_dest = match std::fs::metadata(dest) {
-        // If the destination exists and is a directory,
-        // we copy the source *inside* it and it will be
-        // named as the *basename* of the source.
-        Ok(metadata) => {
-            if metadata.is_dir() {
-                // The basename is the last thing after a slash.
-                let Some(basename) = src.rsplit("/").next() else {
-                    eprintln!("cp: failed to get basename of '{}'", src);
-                    std::process::exit(-90);
-                };
-
-                format!("{}/{}", dest, basename)
-            } else {
-                dest.to_string()
-            }
-        }
-        // If dest doesn't exist, the destination is a file.
-        Err(e) if e.kind() == std::io::ErrorKind::NotFound => dest.to_string(),
-        Err(_) => {
-            eprintln!("cp: failed to access '{}'", dest);
+    let src = Path::new(src.as_str());
+    let actual_dest = match resolve_dest(src, Path::new(dest.as_str()), true) {
+        Ok(actual_dest) => actual_dest,
+        Err(e) => {
+            eprintln!("cp: failed to access '{}': {}", dest, error_reason(&e));
             std::process::exit(-90);
         }
     };
@@ -294,132 +597,755 @@ fn cp(args: &[String]) {
         if file_metadata.is_dir() {
             if recursive {
                 if std::fs::create_dir(&actual_dest).is_err() {
-                    eprintln!("cp: failed to create directory '{}'", actual_dest);
+                    eprintln!("cp: failed to create directory '{}'", actual_dest.display());
                     std::process::exit(-90);
                 }
-                copy_dir(src, &actual_dest, &String::from("."));
-                return;
+                copy_dir(src, &actual_dest);
             } else {
-                eprintln!("cp: omitting directory '{}'", src);
-                std::process::exit(-90);
-            }
-        } else {
-            if std::fs::copy(src, &actual_dest).is_err() {
-                eprintln!("cp: failed to move {} to {}", src, actual_dest);
+                eprintln!("cp: omitting directory '{}'", src.display());
                 std::process::exit(-90);
             }
+        } else if std::fs::copy(src, &actual_dest).is_err() {
+            eprintln!(
+                "cp: failed to move {} to {}",
+                src.display(),
+                actual_dest.display()
+            );
+            std::process::exit(-90);
         }
     } else {
-        eprintln!("cp: failed to access '{}'", src);
+        eprintln!("cp: failed to access '{}'", src.display());
         std::process::exit(-90);
     }
 }
 
 fn touch(args: &[String]) {
-    let (opts, args) = extract_options(args);
+    let Some((opts, args)) =
+        parse_options(args, &["-d", "--date", "-t", "-r", "--reference", "--time"])
+    else {
+        eprintln!("Usage: touch [OPTION]... FILE...");
+        std::process::exit(-100);
+    };
     let mut only_access = false;
     let mut only_modification = false;
     let mut create = true;
+    let mut follow = true;
 
-    for opt in opts {
-        match opt.as_str() {
-            "-a" => only_access = true,
-            "-c" | "--no-creat" => create = false,
-            "-m" => only_modification = true,
-            _ => {}
-        }
-    }
+    // By default, both times are set to the current time.
+    let now = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: libc::UTIME_NOW,
+    };
+    let mut times = [now, now];
 
-    for path in args {
-        match std::fs::File::options()
-            .read(true)
-            .append(true)
-            .create(create)
-            .open(path)
-        {
-            Ok(mut file) => {
-                if !only_access {
-                    let Ok(metadata) = file.metadata() else {
-                        eprintln!("touch: failed to read '{}' metadata", path);
-                        std::process::exit(-100);
-                    };
+    for (opt, value) in opts {
+        match (opt.as_str(), value) {
+            ("-a", _) => only_access = true,
+            ("-c" | "--no-create", _) => create = false,
+            ("-m", _) => only_modification = true,
+            ("-h" | "--no-dereference", _) => {
+                follow = false;
+                create = false;
+            }
+            ("--time", Some(word)) => match word.as_str() {
+                "access" | "atime" | "use" => only_access = true,
+                "modify" | "mtime" => only_modification = true,
+                _ => {
+                    eprintln!("touch: invalid argument '{}' for '--time'", word);
+                    std::process::exit(-100);
+                }
+            },
+            ("-d" | "--date", Some(date)) => {
+                let Some(date) = parse_date(&date) else {
+                    eprintln!("touch: invalid date format '{}'", date);
+                    std::process::exit(-100);
+                };
+
+                times = [to_timespec(&date); 2];
+            }
+            ("-t", Some(stamp)) => {
+                let Some(date) = parse_touch_stamp(&stamp) else {
+                    eprintln!("touch: invalid date format '{}'", stamp);
+                    std::process::exit(-100);
+                };
 
-                    let file_len = metadata.len();
+                times = [to_timespec(&date); 2];
+            }
+            ("-r" | "--reference", Some(reference)) => {
+                let metadata = if follow {
+                    std::fs::metadata(&reference)
+                } else {
+                    std::fs::symlink_metadata(&reference)
+                };
 
-                    // Force a mtime modification by writing a dummy char.
-                    if file.write_all(&[b'\0']).is_err() {
-                        eprintln!("touch: failed to modify mtime of '{}'", path);
+                let metadata = match metadata {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        eprintln!(
+                            "touch: failed to get attributes of '{}': {}",
+                            reference,
+                            error_reason(&e)
+                        );
                         std::process::exit(-100);
                     }
+                };
 
-                    // Restore initial length of the file (removing the added char).
-                    if file.set_len(file_len).is_err() {
-                        eprintln!("touch: failed to restore '{}'", path);
-                        std::process::exit(-100);
-                    };
+                times = [
+                    libc::timespec {
+                        tv_sec: metadata.atime(),
+                        tv_nsec: metadata.atime_nsec(),
+                    },
+                    libc::timespec {
+                        tv_sec: metadata.mtime(),
+                        tv_nsec: metadata.mtime_nsec(),
+                    },
+                ];
+            }
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-100);
+            }
+        }
+    }
+
+    // Leave alone the time that wasn't selected ('-a' and '-m' together
+    // mean both, just like neither of them).
+    if only_access && !only_modification {
+        times[1].tv_nsec = libc::UTIME_OMIT;
+    }
+    if only_modification && !only_access {
+        times[0].tv_nsec = libc::UTIME_OMIT;
+    }
+
+    let mut was_error = false;
+
+    for path in args {
+        if create {
+            // Create the file if it's missing, without touching its contents.
+            // Opening an existing FIFO mustn't wait for a reader.
+            let created = std::fs::File::options()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(path);
+
+            // A directory (or an unwritable file) is fine, as long as it exists.
+            if let Err(e) = created {
+                if std::fs::symlink_metadata(path).is_err() {
+                    eprintln!("touch: cannot touch '{}': {}", path, error_reason(&e));
+                    was_error = true;
+                    continue;
                 }
+            }
+        }
 
-                // Read file contents to force atime modification.
-                if !only_modification {
-                    let mut temp_buffer = [b'\0'];
+        if let Err(e) = set_file_times(Path::new(path.as_str()), &times, follow) {
+            // The file doesn't exist and wasn't created ('-c'), as intended.
+            if !create && e.kind() == std::io::ErrorKind::NotFound {
+                continue;
+            }
 
-                    if file.read_at(&mut temp_buffer, 0).is_err() {
-                        eprintln!("touch: failed to modify atime of '{}'", path);
-                        std::process::exit(-100);
+            eprintln!("touch: setting times of '{}': {}", path, error_reason(&e));
+            was_error = true;
+        }
+    }
+
+    if was_error {
+        std::process::exit(-100);
+    }
+}
+
+/// Convert a date to the representation used by `utimensat`.
+fn to_timespec(date: &chrono::DateTime<chrono::Local>) -> libc::timespec {
+    libc::timespec {
+        tv_sec: date.timestamp(),
+        tv_nsec: date.timestamp_subsec_nanos() as libc::c_long,
+    }
+}
+
+/// Set the access and modification times (in this order) of `path`.
+/// If `follow` is unset, the times of a symlink itself are changed.
+fn set_file_times(path: &Path, times: &[libc::timespec; 2], follow: bool) -> std::io::Result<()> {
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return Err(std::io::ErrorKind::InvalidInput.into());
+    };
+    let flags = if follow { 0 } else { libc::AT_SYMLINK_NOFOLLOW };
+
+    // SAFETY: `c_path` is a valid C string and `times` points to two timespecs.
+    let ret = unsafe { libc::utimensat(libc::AT_FDCWD, c_path.as_ptr(), times.as_ptr(), flags) };
+
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// How `chmod` reports the files it processes.
+#[derive(PartialEq)]
+enum Verbosity {
+    Quiet,
+    Changes,
+    Verbose,
+}
+
+fn chmod(args: &[String]) {
+    let mut recursive = false;
+    let mut verbosity = Verbosity::Quiet;
+    let mut silent = false;
+    let mut reference = None;
+    let mut mode = None;
+    let mut files = Vec::new();
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--recursive" => recursive = true,
+            "--verbose" => verbosity = Verbosity::Verbose,
+            "--changes" => verbosity = Verbosity::Changes,
+            "--silent" | "--quiet" => silent = true,
+            "--" => {
+                // The mode may follow, if it looks like an option ('-w').
+                if mode.is_none() && reference.is_none() {
+                    mode = args.next();
+                }
+                files.extend(args.by_ref());
+            }
+            // Grouped short flags ('-Rv'). They can't be confused with
+            // modes, because none of these letters is valid in a mode.
+            _ if arg.len() > 1 && arg[1..].chars().all(|c| "Rvcf".contains(c)) => {
+                for flag in arg[1..].chars() {
+                    match flag {
+                        'R' => recursive = true,
+                        'v' => verbosity = Verbosity::Verbose,
+                        'c' => verbosity = Verbosity::Changes,
+                        _ => silent = true,
                     }
                 }
             }
-            // If this error is returned, the file doesn't exist and wasn't
-            // created (-c option). Show a message but don't return an
-            // error because this is intended.
-            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
-                println!("'{}' already exists.", path);
+            _ if arg.starts_with("--reference=") => {
+                reference = arg.strip_prefix("--reference=");
             }
-            _ => {
-                eprintln!("touch: failed to open '{}'", path);
-                std::process::exit(-100);
+            // Modes such as '-w' look like options, so the first unknown
+            // option is tried as a mode (if none was given already).
+            _ if arg.starts_with('-') && (mode.is_some() || reference.is_some()) => {
+                println!("Invalid command");
+                std::process::exit(-25);
             }
+            _ if mode.is_none() && reference.is_none() => mode = Some(arg),
+            _ => files.push(arg),
         }
     }
-}
 
-fn chmod(args: &[String]) {
-    let [mode, path] = args else {
-        eprintln!("Usage: chmod MODE FILE");
+    // With '--reference', the mode operand is just another file.
+    if let (Some(_), Some(file)) = (reference, mode) {
+        files.insert(0, file);
+        mode = None;
+    }
+
+    if files.is_empty() || (mode.is_none() && reference.is_none()) {
+        eprintln!("Usage: chmod [OPTION]... MODE[,MODE]... FILE...");
+        std::process::exit(-25);
+    }
+
+    let mode_change = match (reference, mode) {
+        // The mode is copied exactly, like an octal mode with 5 digits.
+        (Some(reference), _) => match std::fs::metadata(reference) {
+            Ok(metadata) => ModeChange::Octal(metadata.permissions().mode() & 0o7777, 5),
+            Err(e) => {
+                eprintln!(
+                    "chmod: failed to get attributes of '{}': {}",
+                    reference,
+                    error_reason(&e)
+                );
+                std::process::exit(-25);
+            }
+        },
+        (None, Some(mode)) => {
+            let Some(mode_change) = parse_mode(mode) else {
+                eprintln!("chmod: invalid mode: '{}'", mode);
+                std::process::exit(-25);
+            };
+
+            mode_change
+        }
+        (None, None) => {
+            eprintln!("Usage: chmod [OPTION]... MODE[,MODE]... FILE...");
+            std::process::exit(-25);
+        }
+    };
+
+    let umask = current_umask();
+    let mut was_error = false;
+
+    // Returns false if the mode couldn't be changed.
+    let change_mode = |path: &Path, metadata: &std::fs::Metadata| {
+        let old_mode = metadata.permissions().mode() & 0o7777;
+        let new_mode = mode_change.apply(old_mode, metadata.is_dir(), umask);
+
+        if old_mode != new_mode {
+            let new_perm = std::fs::Permissions::from_mode(new_mode);
+            if let Err(e) = std::fs::set_permissions(path, new_perm) {
+                if !silent {
+                    eprintln!(
+                        "chmod: changing permissions of '{}': {}",
+                        path.display(),
+                        error_reason(&e)
+                    );
+                }
+                return false;
+            }
+        }
+
+        if old_mode != new_mode && verbosity != Verbosity::Quiet {
+            println!(
+                "mode of '{}' changed from {:04o} ({}) to {:04o} ({})",
+                path.display(),
+                old_mode,
+                format_mode(old_mode),
+                new_mode,
+                format_mode(new_mode)
+            );
+        } else if verbosity == Verbosity::Verbose {
+            println!(
+                "mode of '{}' retained as {:04o} ({})",
+                path.display(),
+                old_mode,
+                format_mode(old_mode)
+            );
+        }
+
+        true
+    };
+
+    for file in files {
+        let path = Path::new(file.as_str());
+
+        let metadata = match std::fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                if !silent {
+                    eprintln!("chmod: cannot access '{}': {}", file, error_reason(&e));
+                }
+                was_error = true;
+                continue;
+            }
+        };
+
+        was_error |= !change_mode(path, &metadata);
+
+        if recursive && metadata.is_dir() {
+            // Symlinks found in the directories are skipped, because their
+            // permissions can't be changed and they might lead outside.
+            was_error |= !change_tree(
+                path,
+                false,
+                verbosity == Verbosity::Quiet,
+                "chmod",
+                |entry| {
+                    entry.metadata().file_type().is_symlink()
+                        || change_mode(entry.path(), entry.metadata())
+                },
+            );
+        }
+    }
+
+    if was_error {
         std::process::exit(-25);
+    }
+}
+
+/// Call `change` for everything inside `dir`, recursively, following
+/// symlinks only if `follow_links` is set. When `parallel` is set (nothing
+/// is printed, so the order doesn't matter), the tree is walked using
+/// multiple threads. Returns false if any change failed.
+fn change_tree(
+    dir: &Path,
+    follow_links: bool,
+    parallel: bool,
+    command: &str,
+    change: impl Fn(&DirEntry) -> bool + Sync,
+) -> bool {
+    let success = AtomicBool::new(true);
+
+    let visit = |entry: Result<DirEntry, WalkError>| {
+        match entry {
+            // The directory itself was already changed.
+            Ok(entry) if entry.depth() == 0 => {}
+            Ok(entry) => {
+                if !change(&entry) {
+                    success.store(false, Ordering::Relaxed);
+                }
+            }
+            Err(e) => {
+                eprintln!(
+                    "{}: cannot access '{}': {}",
+                    command,
+                    e.path.display(),
+                    error_reason(&e.error)
+                );
+                success.store(false, Ordering::Relaxed);
+            }
+        }
+
+        true
     };
 
-    // Try to parse mode as an octal number. If this fails,
-    // parse as "symbolic mode" (u+rwx).
-    let new_mode = if let Ok(mode) = u32::from_str_radix(mode, 8) {
-        mode
+    let walk = Walk::new(dir).follow_links(follow_links);
+    if parallel {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        walk.for_each_parallel(threads, visit);
     } else {
-        let Some((mode, add_perms)) = convert_mode(mode) else {
-            println!("Invalid command");
-            std::process::exit(-1);
+        walk.for_each(|entry| {
+            visit(entry);
+        });
+    }
+
+    success.into_inner()
+}
+
+/// How symlinks are followed by `chown -R` (`-P`, `-H` and `-L`).
+#[derive(PartialEq)]
+enum Traversal {
+    /// Never follow symlinks.
+    Physical,
+    /// Follow only the symlinks given as arguments.
+    CommandLine,
+    /// Follow every symlink.
+    Logical,
+}
+
+/// The ownership change made by `chown` and `chgrp`.
+struct OwnerChange {
+    uid: Option<u32>,
+    gid: Option<u32>,
+    /// Only files owned by this user and group are changed (`--from`).
+    from: (Option<u32>, Option<u32>),
+    verbosity: Verbosity,
+    silent: bool,
+    /// Whether the messages are about the group only (`chgrp`).
+    only_group: bool,
+}
+
+impl OwnerChange {
+    /// Change the owner of `path` (or of the symlink itself if `follow` is
+    /// unset). Returns its metadata, as it was before the change.
+    fn apply(&self, path: &Path, follow: bool) -> Option<std::fs::Metadata> {
+        let command = if self.only_group { "chgrp" } else { "chown" };
+        let metadata = if follow {
+            std::fs::metadata(path)
+        } else {
+            std::fs::symlink_metadata(path)
         };
 
-        if let Ok(metadata) = std::fs::metadata(path) {
-            let current_mode = metadata.permissions().mode();
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                if !self.silent {
+                    eprintln!(
+                        "{}: cannot access '{}': {}",
+                        command,
+                        path.display(),
+                        error_reason(&e)
+                    );
+                }
+                return None;
+            }
+        };
+
+        let (old_uid, old_gid) = (metadata.uid(), metadata.gid());
+        let matches_from = self.from.0.is_none_or(|uid| uid == old_uid)
+            && self.from.1.is_none_or(|gid| gid == old_gid);
+
+        let (new_uid, new_gid) = if matches_from {
+            (self.uid.unwrap_or(old_uid), self.gid.unwrap_or(old_gid))
+        } else {
+            (old_uid, old_gid)
+        };
+
+        let changed = (new_uid, new_gid) != (old_uid, old_gid);
+        if changed {
+            let ret_status = if follow {
+                std::os::unix::fs::chown(path, Some(new_uid), Some(new_gid))
+            } else {
+                std::os::unix::fs::lchown(path, Some(new_uid), Some(new_gid))
+            };
+
+            if let Err(e) = ret_status {
+                if !self.silent {
+                    let what = if self.only_group {
+                        "group"
+                    } else {
+                        "ownership"
+                    };
+                    eprintln!(
+                        "{}: changing {} of '{}': {}",
+                        command,
+                        what,
+                        path.display(),
+                        error_reason(&e)
+                    );
+                }
+                return None;
+            }
+        }
 
-            if add_perms {
-                current_mode | mode
+        let owner = |uid, gid| {
+            let group = id_name("/etc/group", gid);
+            if self.only_group {
+                group
             } else {
-                current_mode & !mode
+                format!("{}:{}", id_name("/etc/passwd", uid), group)
             }
+        };
+        let what = if self.only_group {
+            "group"
         } else {
-            eprintln!("chmod: failed to access '{}'", path);
-            std::process::exit(-25);
+            "ownership"
+        };
+
+        if changed && self.verbosity != Verbosity::Quiet {
+            println!(
+                "changed {} of '{}' from {} to {}",
+                what,
+                path.display(),
+                owner(old_uid, old_gid),
+                owner(new_uid, new_gid)
+            );
+        } else if !changed && self.verbosity == Verbosity::Verbose {
+            println!(
+                "{} of '{}' retained as {}",
+                what,
+                path.display(),
+                owner(old_uid, old_gid)
+            );
+        }
+
+        Some(metadata)
+    }
+}
+
+/// Parse an owner given as `[USER][:[GROUP]]` into a user and a group id.
+/// `USER:` means the login group of the user. Returns `None` if the user
+/// or the group don't exist.
+fn parse_owner(spec: &str) -> Option<(Option<u32>, Option<u32>)> {
+    // The old `USER.GROUP` syntax is accepted too, unless
+    // the dot is part of the user name.
+    let separated = spec.split_once(':').or_else(|| {
+        resolve_user(spec)
+            .is_none()
+            .then(|| spec.split_once('.'))
+            .flatten()
+    });
+
+    let Some((user, group)) = separated else {
+        if spec.is_empty() {
+            return Some((None, None));
         }
+
+        return Some((Some(resolve_user(spec)?.0), None));
     };
 
-    let new_perm = std::fs::Permissions::from_mode(new_mode);
-    if std::fs::set_permissions(path, new_perm).is_err() {
-        eprintln!("chmod: failed to set permissions for '{}'", path);
-        std::process::exit(-25);
+    let (uid, login_gid) = match user {
+        "" => (None, None),
+        user => {
+            let (uid, login_gid) = resolve_user(user)?;
+            (Some(uid), login_gid)
+        }
+    };
+
+    let gid = match group {
+        "" if uid.is_some() => Some(login_gid?),
+        "" => None,
+        group => Some(resolve_group(group)?),
+    };
+
+    Some((uid, gid))
+}
+
+fn chown(args: &[String], only_group: bool) {
+    let command = if only_group { "chgrp" } else { "chown" };
+    let Some((opts, args)) = parse_options(args, &["--from", "--reference"]) else {
+        println!("Invalid command");
+        std::process::exit(-26);
+    };
+    let mut recursive = false;
+    let mut dereference = None;
+    let mut traversal = Traversal::Physical;
+    let mut verbosity = Verbosity::Quiet;
+    let mut silent = false;
+    let mut from = (None, None);
+    let mut reference = None;
+
+    for (opt, value) in opts {
+        match (opt.as_str(), value) {
+            ("-R" | "--recursive", _) => recursive = true,
+            ("-h" | "--no-dereference", _) => dereference = Some(false),
+            ("--dereference", _) => dereference = Some(true),
+            ("-H", _) => traversal = Traversal::CommandLine,
+            ("-L", _) => traversal = Traversal::Logical,
+            ("-P", _) => traversal = Traversal::Physical,
+            ("-v" | "--verbose", _) => verbosity = Verbosity::Verbose,
+            ("-c" | "--changes", _) => verbosity = Verbosity::Changes,
+            ("-f" | "--silent" | "--quiet", _) => silent = true,
+            ("--from", Some(owner)) => {
+                let Some(owner) = parse_owner(&owner) else {
+                    eprintln!("{}: invalid user: '{}'", command, owner);
+                    std::process::exit(-26);
+                };
+
+                from = owner;
+            }
+            ("--reference", Some(file)) => reference = Some(file),
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-26);
+            }
+        }
+    }
+
+    let (uid, gid, files) = match (reference, args.split_first()) {
+        (Some(reference), _) => match std::fs::metadata(&reference) {
+            Ok(metadata) if only_group => (None, Some(metadata.gid()), args.as_slice()),
+            Ok(metadata) => (Some(metadata.uid()), Some(metadata.gid()), args.as_slice()),
+            Err(e) => {
+                eprintln!(
+                    "{}: failed to get attributes of '{}': {}",
+                    command,
+                    reference,
+                    error_reason(&e)
+                );
+                std::process::exit(-26);
+            }
+        },
+        (None, Some((group, files))) if only_group => {
+            let Some(gid) = resolve_group(group) else {
+                eprintln!("chgrp: invalid group: '{}'", group);
+                std::process::exit(-26);
+            };
+
+            (None, Some(gid), files)
+        }
+        (None, Some((owner, files))) => {
+            let Some((uid, gid)) = parse_owner(owner) else {
+                eprintln!("chown: invalid user: '{}'", owner);
+                std::process::exit(-26);
+            };
+
+            (uid, gid, files)
+        }
+        (None, None) => (None, None, args.as_slice()),
+    };
+
+    if files.is_empty() {
+        if only_group {
+            eprintln!("Usage: chgrp [OPTION]... GROUP FILE...");
+        } else {
+            eprintln!("Usage: chown [OPTION]... [OWNER][:[GROUP]] FILE...");
+        }
+        std::process::exit(-26);
+    }
+
+    // When recursing without following symlinks, the links themselves
+    // are changed, like with '-h'.
+    let follow_args = match dereference {
+        Some(dereference) => dereference,
+        None => !recursive || traversal != Traversal::Physical,
+    };
+
+    let verbosity_quiet = verbosity == Verbosity::Quiet;
+    let change = OwnerChange {
+        uid,
+        gid,
+        from,
+        verbosity,
+        silent,
+        only_group,
+    };
+    let mut was_error = false;
+
+    for file in files {
+        let path = Path::new(file.as_str());
+        let Some(metadata) = change.apply(path, follow_args) else {
+            was_error = true;
+            continue;
+        };
+
+        if recursive && metadata.is_dir() {
+            let follow_links = traversal == Traversal::Logical;
+            was_error |= !change_tree(path, follow_links, verbosity_quiet, command, |entry| {
+                change.apply(entry.path(), follow_links).is_some()
+            });
+        }
+    }
+
+    if was_error {
+        std::process::exit(-26);
     }
 }
 
+/// Find the function implementing the command `name`.
+fn applet(name: &str) -> Option<fn(&[String])> {
+    let run: fn(&[String]) = match name {
+        "pwd" => |_| pwd(),
+        "echo" => echo,
+        "grep" => grep,
+        "cat" => cat,
+        "mkdir" => mkdir,
+        "mv" => mv,
+        "ln" => ln,
+        "rmdir" => rmdir,
+        "rm" => rm,
+        "ls" => ls,
+        "cp" => cp,
+        "touch" => touch,
+        "chmod" => chmod,
+        "chown" => |args| chown(args, false),
+        "chgrp" => |args| chown(args, true),
+        "find" => find::find,
+        "head" => head::head,
+        "tail" => tail::tail,
+        "wc" => wc::wc,
+        "sort" => sort::sort,
+        "uniq" => uniq::uniq,
+        "comm" => comm::comm,
+        "join" => join::join,
+        "cut" => cut::cut,
+        "paste" => paste::paste,
+        "tr" => tr::tr,
+        "sed" => sed::sed,
+        "awk" => awk::awk,
+        "diff" => diff::diff,
+        "patch" => patch::patch,
+        "cmp" => cmp::cmp,
+        "od" => od::od,
+        "hexdump" => hexdump::hexdump,
+        "xxd" => xxd::xxd,
+        "md5sum" => checksum::md5sum,
+        "sha1sum" => checksum::sha1sum,
+        "sha256sum" => checksum::sha256sum,
+        "sha512sum" => checksum::sha512sum,
+        "b2sum" => checksum::b2sum,
+        "cksum" => checksum::cksum,
+        "base64" => basenc::base64,
+        "base32" => basenc::base32,
+        "basenc" => basenc::basenc,
+        "gzip" => gzip::gzip,
+        "gunzip" => gzip::gunzip,
+        "zcat" => gzip::zcat,
+        "tar" => tar::tar,
+        "cpio" => cpio::cpio,
+        "ar" => ar::ar,
+        "du" => du::du,
+        "df" => df::df,
+        "xargs" => xargs::xargs,
+        _ => return None,
+    };
+
+    Some(run)
+}
+
 fn main() {
     let args = std::env::args().collect::<Vec<String>>();
 
@@ -427,24 +1353,11 @@ fn main() {
     let (rustybox_exec, rustybox_command) = args.split_first().unwrap();
 
     if let Some((command, args)) = rustybox_command.split_first() {
-        match command.as_str() {
-            "pwd" => pwd(),
-            "echo" => echo(args),
-            "grep" => grep(args),
-            "cat" => cat(args),
-            "mkdir" => mkdir(args),
-            "mv" => mv(args),
-            "ln" => ln(args),
-            "rmdir" => rmdir(args),
-            "rm" => rm(args),
-            "ls" => ls(args),
-            "cp" => cp(args),
-            "touch" => touch(args),
-            "chmod" => chmod(args),
-            _ => {
-                println!("Invalid command");
-                std::process::exit(-1)
-            }
+        if let Some(run) = applet(command) {
+            run(args);
+        } else {
+            println!("Invalid command");
+            std::process::exit(-1)
         }
     } else {
         eprintln!("Usage: {} COMMAND [ARGS]...", rustybox_exec);
@@ -0,0 +1,302 @@
+//! The `od` command, dumping files in octal and other formats.
+
+use std::fmt::Write as _;
+use std::io::{BufWriter, Write};
+
+use crate::dump::*;
+use crate::utils::*;
+
+fn usage() -> ! {
+    eprintln!("Usage: od [-A RADIX] [-t TYPE]... [-j SKIP] [-N COUNT] [-w[N]] [-v] [FILE]...");
+    std::process::exit(-220);
+}
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("od: {}", message);
+    std::process::exit(-220);
+}
+
+/// An output format, and the padding aligning its units with the ones
+/// of the other formats.
+struct Spec {
+    format: Format,
+    /// Whether the bytes are also shown as text (the `z` suffix).
+    text: bool,
+    pad: usize,
+}
+
+/// Parse the size of a `-t` type, as a number of bytes or a C type
+/// letter (one of `letters`, for the sizes 1, 2, 4 and 8).
+fn parse_type_size(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    letters: [Option<char>; 4],
+) -> Option<Option<usize>> {
+    if let Some(i) = letters
+        .iter()
+        .position(|&letter| letter.is_some() && chars.peek() == letter.as_ref())
+    {
+        chars.next();
+        return Some(Some(1 << i));
+    }
+
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(char::is_ascii_digit) {
+        digits.push(digit);
+    }
+
+    if digits.is_empty() {
+        Some(None)
+    } else {
+        digits.parse().ok().map(Some)
+    }
+}
+
+/// Parse the types of a `-t` option, which may follow each other (`-t x1c`).
+fn parse_types(types: &str, specs: &mut Vec<Spec>) -> Option<()> {
+    let mut chars = types.chars().peekable();
+
+    while let Some(kind) = chars.next() {
+        let format = match kind {
+            'a' => Format::Named,
+            'c' => Format::Char,
+            'd' | 'o' | 'u' | 'x' => {
+                let letters = [Some('C'), Some('S'), Some('I'), Some('L')];
+                let size = parse_type_size(&mut chars, letters)?.unwrap_or(4);
+                if ![1, 2, 4, 8].contains(&size) {
+                    return None;
+                }
+
+                match kind {
+                    'd' => Format::Signed(size),
+                    'o' => Format::Octal(size),
+                    'u' => Format::Unsigned(size),
+                    _ => Format::Hex(size),
+                }
+            }
+            'f' => {
+                let letters = [None, None, Some('F'), Some('D')];
+                let size = parse_type_size(&mut chars, letters)?.unwrap_or(8);
+                if ![4, 8].contains(&size) {
+                    return None;
+                }
+
+                Format::Float(size)
+            }
+            _ => return None,
+        };
+
+        let text = chars.next_if_eq(&'z').is_some();
+        specs.push(Spec {
+            format,
+            text,
+            pad: 0,
+        });
+    }
+
+    Some(())
+}
+
+fn parse_number(option: &str, number: &str) -> u64 {
+    parse_offset(number)
+        .unwrap_or_else(|| fail(format_args!("invalid {} argument '{}'", option, number)))
+}
+
+/// Write the lines showing the first `len` bytes of `block`, one per format.
+/// A unit partly after the end of the input is padded with zeros.
+fn write_block(
+    out: &mut impl Write,
+    address: &str,
+    indent: usize,
+    specs: &[Spec],
+    block: &[u8],
+    len: usize,
+) -> std::io::Result<()> {
+    let mut line = String::new();
+
+    for (i, spec) in specs.iter().enumerate() {
+        line.clear();
+        if i == 0 {
+            line.push_str(address);
+        } else {
+            line.push_str(&" ".repeat(indent));
+        }
+
+        // The padding is spread over the units, the first ones getting
+        // the most, and the units after the end of the input are blank.
+        let size = spec.format.size();
+        let units = block.len() / size;
+        let blank = (block.len() - len) / size;
+        let mut pad = spec.pad;
+
+        for unit in (blank + 1..=units).rev() {
+            let next_pad = spec.pad * (unit - 1) / units;
+            let start = (units - unit) * size;
+            let value = spec.format.format(&block[start..start + size]);
+            let _ = write!(line, " {:>1$}", value, pad - next_pad + spec.format.width());
+            pad = next_pad;
+        }
+
+        if spec.text {
+            let blank_width = blank * (spec.format.width() + 1) + pad;
+            line.push_str(&" ".repeat(blank_width));
+            line.push_str("  >");
+            line.extend(block[..len].iter().map(|&byte| printable(byte)));
+            line.push('<');
+        }
+
+        writeln!(out, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+pub fn od(args: &[String]) {
+    // The width is optional, and only attached to `-w`.
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| match arg.strip_prefix("-w") {
+            Some("") => "--width=32".to_string(),
+            Some(width) => format!("--width={}", width),
+            None => arg.clone(),
+        })
+        .collect();
+
+    let with_value = [
+        "-A",
+        "-j",
+        "-N",
+        "-t",
+        "--address-radix",
+        "--skip-bytes",
+        "--read-bytes",
+        "--format",
+    ];
+    let Some((opts, files)) = parse_options(&args, &with_value) else {
+        usage();
+    };
+
+    let mut radix = 'o';
+    let mut skip = 0;
+    let mut limit = None;
+    let mut width = 16;
+    let mut squeeze = true;
+    let mut specs = Vec::new();
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+
+        let types = match opt.as_str() {
+            "-A" | "--address-radix" => {
+                radix = match value.as_str() {
+                    "d" | "o" | "x" | "n" => value.chars().next().unwrap(),
+                    _ => fail(format_args!("invalid output address radix '{}'", value)),
+                };
+                continue;
+            }
+            "-j" | "--skip-bytes" => {
+                skip = parse_number("-j", &value);
+                continue;
+            }
+            "-N" | "--read-bytes" => {
+                limit = Some(parse_number("-N", &value));
+                continue;
+            }
+            "--width" => {
+                width = parse_number("-w", &value) as usize;
+                continue;
+            }
+            "-v" | "--output-duplicates" => {
+                squeeze = false;
+                continue;
+            }
+            "-t" | "--format" => value.as_str(),
+            "-a" => "a",
+            "-b" => "o1",
+            "-c" => "c",
+            "-d" => "u2",
+            "-f" => "f4",
+            "-i" => "d4",
+            "-l" => "d8",
+            "-o" => "o2",
+            "-s" => "d2",
+            "-x" => "x2",
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-220);
+            }
+        };
+
+        if parse_types(types, &mut specs).is_none() {
+            fail(format_args!("invalid type string '{}'", types));
+        }
+    }
+
+    if specs.is_empty() {
+        parse_types("o2", &mut specs);
+    }
+    if width == 0 || specs.iter().any(|spec| width % spec.format.size() != 0) {
+        fail(format_args!("invalid -w argument '{}'", width));
+    }
+
+    // Align the units of all the formats on the widest line.
+    let line_width = |spec: &Spec| width / spec.format.size() * (spec.format.width() + 1);
+    let widest = specs.iter().map(line_width).max().unwrap_or(0);
+    for spec in &mut specs {
+        spec.pad = widest - line_width(spec);
+    }
+
+    let address = |offset: u64| match radix {
+        'd' => format!("{:07}", offset),
+        'o' => format!("{:07o}", offset),
+        'x' => format!("{:06x}", offset),
+        _ => String::new(),
+    };
+    let indent = address(0).len();
+
+    let files = if files.is_empty() {
+        vec!["-".to_string()]
+    } else {
+        files.into_iter().cloned().collect()
+    };
+    let mut reader = Reader::new("od", files, skip, limit);
+    let mut squeeze = Squeeze::new(squeeze);
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut block = vec![0; width];
+    let mut offset = skip;
+
+    let result = (|| -> std::io::Result<()> {
+        loop {
+            let len = reader.read(&mut block);
+            if len == 0 {
+                break;
+            }
+
+            block[len..].fill(0);
+            if len == width && squeeze.repeats(&block, &mut out)? {
+                offset += len as u64;
+                continue;
+            }
+            write_block(&mut out, &address(offset), indent, &specs, &block, len)?;
+            offset += len as u64;
+        }
+
+        if reader.unskipped() > 0 {
+            out.flush()?;
+            fail(format_args!("cannot skip past end of combined input"));
+        }
+        if radix != 'n' {
+            writeln!(out, "{}", address(offset))?;
+        }
+
+        out.flush()
+    })();
+
+    if let Err(e) = result {
+        fail(format_args!("{}", error_reason(&e)));
+    }
+
+    if reader.failed {
+        std::process::exit(-220);
+    }
+}
@@ -0,0 +1,179 @@
+//! The `paste` command, merging the lines of files.
+
+use std::io::{BufRead, BufWriter, Write};
+
+use crate::fields::*;
+use crate::utils::*;
+
+/// Parse the delimiter list of `-d`, used in turn. Besides the usual
+/// escapes, `\0` stands for no delimiter at all.
+fn parse_delimiters(list: &str) -> Vec<Option<u8>> {
+    let list = list.as_bytes();
+    let mut delimiters = Vec::new();
+    let mut i = 0;
+
+    while i < list.len() {
+        if list[i] == b'\\' && list.get(i + 1) == Some(&b'0') {
+            delimiters.push(None);
+            i += 2;
+        } else if list[i] == b'\\' {
+            let (byte, len) = unescape(&list[i..]);
+            delimiters.push(Some(byte));
+            i += len;
+        } else {
+            delimiters.push(Some(list[i]));
+            i += 1;
+        }
+    }
+
+    delimiters
+}
+
+/// Paste the lines of each file into a single line (`-s`).
+fn paste_serial(
+    input: &mut dyn BufRead,
+    delimiters: &[Option<u8>],
+    terminator: u8,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let mut line = Vec::new();
+    let mut count = 0;
+
+    while read_line(input, terminator, &mut line)? {
+        if count > 0 {
+            if let Some(delimiter) = delimiters[(count - 1) % delimiters.len()] {
+                out.write_all(&[delimiter])?;
+            }
+        }
+        out.write_all(&line)?;
+        count += 1;
+    }
+
+    out.write_all(&[terminator])
+}
+
+/// Paste the lines of all the files side by side. A file given more than
+/// once (`-`) has its lines spread between its columns.
+fn paste_parallel(
+    inputs: &mut [Option<Box<dyn BufRead>>],
+    columns: &[usize],
+    delimiters: &[Option<u8>],
+    terminator: u8,
+    out: &mut impl Write,
+) -> std::io::Result<()> {
+    let mut line = Vec::new();
+    let mut output = Vec::new();
+
+    loop {
+        output.clear();
+        let mut any_line = false;
+
+        for (column, &input) in columns.iter().enumerate() {
+            if column > 0 {
+                output.extend(delimiters[(column - 1) % delimiters.len()]);
+            }
+
+            let Some(reader) = &mut inputs[input] else {
+                continue;
+            };
+
+            if read_line(reader.as_mut(), terminator, &mut line)? {
+                output.extend_from_slice(&line);
+                any_line = true;
+            } else {
+                inputs[input] = None;
+            }
+        }
+
+        if !any_line {
+            return Ok(());
+        }
+
+        out.write_all(&output)?;
+        out.write_all(&[terminator])?;
+    }
+}
+
+pub fn paste(args: &[String]) {
+    let Some((opts, files)) = parse_options(args, &["-d", "--delimiters"]) else {
+        eprintln!("Usage: paste [-s] [-z] [-d LIST] [FILE]...");
+        std::process::exit(-171);
+    };
+
+    let mut delimiters = vec![Some(b'\t')];
+    let mut serial = false;
+    let mut terminator = b'\n';
+
+    for (opt, value) in opts {
+        match opt.as_str() {
+            "-d" | "--delimiters" => {
+                delimiters = parse_delimiters(&value.unwrap_or_default());
+                if delimiters.is_empty() {
+                    delimiters.push(None);
+                }
+            }
+            "-s" | "--serial" => serial = true,
+            "-z" | "--zero-terminated" => terminator = b'\0',
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-171);
+            }
+        }
+    }
+
+    let stdin = String::from("-");
+    let files = if files.is_empty() {
+        vec![&stdin]
+    } else {
+        files
+    };
+
+    let mut out = BufWriter::new(std::io::stdout().lock());
+    let mut was_error = false;
+
+    let result = if serial {
+        files.iter().try_for_each(|file| match open_input(file) {
+            Ok(mut input) => paste_serial(input.as_mut(), &delimiters, terminator, &mut out),
+            Err(e) => {
+                eprintln!("paste: {}: {}", file, error_reason(&e));
+                was_error = true;
+                Ok(())
+            }
+        })
+    } else {
+        // The standard input is opened once, even if it's given more times.
+        let mut inputs = Vec::new();
+        let mut stdin_input = None;
+        let mut columns = Vec::new();
+
+        for file in &files {
+            if file.as_str() == "-" {
+                if let Some(input) = stdin_input {
+                    columns.push(input);
+                    continue;
+                }
+                stdin_input = Some(inputs.len());
+            }
+
+            columns.push(inputs.len());
+            match open_input(file) {
+                Ok(input) => inputs.push(Some(input)),
+                Err(e) => {
+                    eprintln!("paste: {}: {}", file, error_reason(&e));
+                    std::process::exit(-171);
+                }
+            }
+        }
+
+        paste_parallel(&mut inputs, &columns, &delimiters, terminator, &mut out)
+    };
+
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        eprintln!("paste: {}", error_reason(&e));
+        was_error = true;
+    }
+
+    if was_error {
+        std::process::exit(-171);
+    }
+}
@@ -0,0 +1,571 @@
+//! The `patch` command, applying unified diffs like those made by
+//! `diff -u`. Hunks are searched around their expected line, and allowed to
+//! drop some context (fuzz); those which can't be applied are saved in a
+//! reject file.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use crate::diff::Lines;
+use crate::utils::*;
+
+/// How many context lines a hunk may drop at each end.
+const MAX_FUZZ: usize = 2;
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("patch: **** {}", message);
+    std::process::exit(-201);
+}
+
+struct Hunk {
+    /// The first line of each side, from the header.
+    old_start: usize,
+    new_start: usize,
+    /// The lines, with their kind: `' '`, `'-'` or `'+'`.
+    lines: Vec<(u8, Vec<u8>)>,
+    /// The last line of each side has no newline.
+    old_missing_newline: bool,
+    new_missing_newline: bool,
+    /// The lines of the hunk as written in the patch, for the reject file.
+    text: Vec<u8>,
+}
+
+impl Hunk {
+    fn side(&self, removed: u8) -> Vec<&[u8]> {
+        self.lines
+            .iter()
+            .filter(|(kind, _)| *kind != removed)
+            .map(|(_, line)| line.as_slice())
+            .collect()
+    }
+
+    fn old_lines(&self) -> Vec<&[u8]> {
+        self.side(b'+')
+    }
+
+    fn new_lines(&self) -> Vec<&[u8]> {
+        self.side(b'-')
+    }
+
+    /// The number of context lines before and after the changes.
+    fn context(&self) -> (usize, usize) {
+        let leading = self
+            .lines
+            .iter()
+            .take_while(|(kind, _)| *kind == b' ')
+            .count();
+        let trailing = self
+            .lines
+            .iter()
+            .rev()
+            .take_while(|(kind, _)| *kind == b' ')
+            .count();
+        (leading, trailing)
+    }
+
+    fn reverse(&mut self) {
+        for (kind, _) in &mut self.lines {
+            *kind = match *kind {
+                b'-' => b'+',
+                b'+' => b'-',
+                kind => kind,
+            };
+        }
+        std::mem::swap(&mut self.old_start, &mut self.new_start);
+        std::mem::swap(&mut self.old_missing_newline, &mut self.new_missing_newline);
+    }
+}
+
+/// The changes to a file.
+struct FilePatch {
+    old_name: String,
+    new_name: String,
+    /// The `---` and `+++` lines, for the reject file.
+    header: Vec<u8>,
+    hunks: Vec<Hunk>,
+}
+
+/// Parse a line like `@@ -1,3 +1,4 @@`, returning the start and length of
+/// each side.
+fn parse_hunk_header(line: &str) -> Option<((usize, usize), (usize, usize))> {
+    let mut parts = line.strip_prefix("@@ ")?.split(' ');
+
+    let range = |part: Option<&str>, sign: char| -> Option<(usize, usize)> {
+        let part = part?.strip_prefix(sign)?;
+        match part.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((part.parse().ok()?, 1)),
+        }
+    };
+
+    let old = range(parts.next(), '-')?;
+    let new = range(parts.next(), '+')?;
+    (parts.next() == Some("@@")).then_some((old, new))
+}
+
+/// Get the file name of a `---` or `+++` line, without its timestamp.
+fn header_name(line: &[u8]) -> String {
+    let name = &line[4..];
+    let name = match name.iter().position(|&byte| byte == b'\t') {
+        Some(tab) => &name[..tab],
+        None => name,
+    };
+    String::from_utf8_lossy(name).trim_end().to_owned()
+}
+
+/// Parse the file patches of a unified diff. Anything between them (like
+/// the `diff` command lines) is ignored.
+fn parse_patch(data: &[u8]) -> Vec<FilePatch> {
+    let lines = Lines::new(data.to_vec());
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines.line(i);
+
+        if line.starts_with(b"--- ")
+            && i + 1 < lines.len()
+            && lines.line(i + 1).starts_with(b"+++ ")
+        {
+            let new_line = lines.line(i + 1);
+            let mut header = [line, b"\n", new_line, b"\n"].concat();
+            header.retain(|&byte| byte != b'\r');
+
+            patches.push(FilePatch {
+                old_name: header_name(line),
+                new_name: header_name(new_line),
+                header,
+                hunks: Vec::new(),
+            });
+            i += 2;
+            continue;
+        }
+
+        let Some(patch) = patches.last_mut().filter(|_| line.starts_with(b"@@ ")) else {
+            i += 1;
+            continue;
+        };
+
+        let header = String::from_utf8_lossy(line);
+        let Some(((old_start, mut old_left), (new_start, mut new_left))) =
+            parse_hunk_header(&header)
+        else {
+            fail(format_args!(
+                "malformed patch at line {}: {}",
+                i + 1,
+                header
+            ));
+        };
+
+        let mut hunk = Hunk {
+            old_start,
+            new_start,
+            lines: Vec::new(),
+            old_missing_newline: false,
+            new_missing_newline: false,
+            text: Vec::new(),
+        };
+        i += 1;
+
+        while i < lines.len() {
+            let line = lines.line(i);
+
+            if line.starts_with(b"\\") {
+                // The previous line has no newline.
+                match hunk.lines.last() {
+                    Some((b'-', _)) => hunk.old_missing_newline = true,
+                    Some((b'+', _)) => hunk.new_missing_newline = true,
+                    _ => {
+                        hunk.old_missing_newline = true;
+                        hunk.new_missing_newline = true;
+                    }
+                }
+            } else if old_left == 0 && new_left == 0 {
+                break;
+            } else {
+                // Some tools strip the blank of empty context lines.
+                let (kind, text) = match line.split_first() {
+                    None => (b' ', &[][..]),
+                    Some((&kind, text)) => (kind, text),
+                };
+
+                match kind {
+                    b' ' if old_left > 0 && new_left > 0 => {
+                        old_left -= 1;
+                        new_left -= 1;
+                    }
+                    b'-' if old_left > 0 => old_left -= 1,
+                    b'+' if new_left > 0 => new_left -= 1,
+                    _ => fail(format_args!(
+                        "malformed patch at line {}: {}",
+                        i + 1,
+                        String::from_utf8_lossy(line)
+                    )),
+                }
+                hunk.lines.push((kind, text.to_vec()));
+            }
+
+            hunk.text.extend_from_slice(line);
+            hunk.text.push(b'\n');
+            i += 1;
+        }
+
+        if old_left > 0 || new_left > 0 {
+            fail(format_args!("unexpected end of hunk at line {}", i));
+        }
+        patch.hunks.push(hunk);
+    }
+
+    patches.retain(|patch| !patch.hunks.is_empty());
+    patches
+}
+
+/// Remove the first `strip` components of a path, or all the directories
+/// if `strip` isn't given.
+fn strip_path(name: &str, strip: Option<usize>) -> String {
+    match strip {
+        None => name.rsplit('/').next().unwrap_or(name).to_owned(),
+        Some(strip) => {
+            let mut rest = name;
+            for _ in 0..strip {
+                match rest.find('/') {
+                    Some(slash) => rest = rest[slash + 1..].trim_start_matches('/'),
+                    None => return name.to_owned(),
+                }
+            }
+            rest.to_owned()
+        }
+    }
+}
+
+/// Find where `pattern` occurs in `lines`, nearest to `expected`, and not
+/// before `min`.
+fn find_pattern(
+    lines: &[Vec<u8>],
+    pattern: &[&[u8]],
+    expected: usize,
+    min: usize,
+    anchor: Anchor,
+) -> Option<usize> {
+    let max = lines.len().checked_sub(pattern.len())?;
+    let matches = |pos: usize| {
+        pos >= min
+            && pos <= max
+            && pattern
+                .iter()
+                .zip(&lines[pos..])
+                .all(|(a, b)| *a == b.as_slice())
+    };
+
+    match (anchor.start, anchor.end) {
+        (true, true) => return (max == 0 && matches(0)).then_some(0),
+        (true, false) => return matches(0).then_some(0),
+        (false, true) => return matches(max).then_some(max),
+        (false, false) => {}
+    }
+
+    let expected = expected.clamp(min.min(max), max);
+    for distance in 0..=lines.len() {
+        if matches(expected + distance) {
+            return Some(expected + distance);
+        }
+        if distance <= expected && matches(expected - distance) {
+            return Some(expected - distance);
+        }
+        if expected + distance > max && distance > expected {
+            break;
+        }
+    }
+
+    None
+}
+
+/// Whether a hunk must match at the start or at the end of the file.
+#[derive(Clone, Copy)]
+struct Anchor {
+    start: bool,
+    end: bool,
+}
+
+struct Patcher {
+    strip: Option<usize>,
+    reverse: bool,
+    dry_run: bool,
+    failed: bool,
+}
+
+impl Patcher {
+    /// Choose the file to patch among the names of the patch.
+    fn target(&self, patch: &FilePatch) -> Option<PathBuf> {
+        let names = [&patch.old_name, &patch.new_name]
+            .into_iter()
+            .filter(|name| name.as_str() != "/dev/null")
+            .map(|name| PathBuf::from(strip_path(name, self.strip)))
+            .collect::<Vec<_>>();
+
+        names
+            .iter()
+            .find(|name| name.exists())
+            .or(names.last())
+            .cloned()
+    }
+
+    fn apply(&mut self, mut patch: FilePatch, target: Option<&Path>) -> std::io::Result<()> {
+        if self.reverse {
+            for hunk in &mut patch.hunks {
+                hunk.reverse();
+            }
+            std::mem::swap(&mut patch.old_name, &mut patch.new_name);
+        }
+
+        let Some(path) = target.map(Path::to_owned).or_else(|| self.target(&patch)) else {
+            fail(format_args!("can't find the file to patch"));
+        };
+        // A side of `/dev/null`, or of a single empty hunk at line 0 (as
+        // `diff -N` makes them), means that the file is created or deleted.
+        let empty_side = |removed: u8| {
+            let [hunk] = patch.hunks.as_slice() else {
+                return false;
+            };
+            let start = if removed == b'+' {
+                hunk.old_start
+            } else {
+                hunk.new_start
+            };
+            start == 0 && hunk.side(removed).is_empty()
+        };
+        let creating = patch.old_name == "/dev/null" || empty_side(b'+');
+        let deleting = patch.new_name == "/dev/null" || empty_side(b'-');
+
+        let verb = if self.dry_run { "checking" } else { "patching" };
+        println!("{} file {}", verb, path.display());
+
+        let original = match std::fs::read(&path) {
+            Ok(data) => Some(data),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && creating => None,
+            Err(e) => {
+                eprintln!("patch: can't read {}: {}", path.display(), error_reason(&e));
+                self.failed = true;
+                return Ok(());
+            }
+        };
+        let original = Lines::new(original.unwrap_or_default());
+        let mut lines = (0..original.len())
+            .map(|i| original.line(i).to_vec())
+            .collect::<Vec<_>>();
+        let mut missing_newline = original.missing_newline;
+
+        // `delta` is the number of lines added by the hunks applied, and
+        // `offset` how far from their expected line the last one matched.
+        let (mut delta, mut offset) = (0isize, 0isize);
+        let mut min = 0;
+        let mut rejects = Vec::new();
+
+        for (number, hunk) in patch.hunks.iter().enumerate() {
+            let (old, new) = (hunk.old_lines(), hunk.new_lines());
+            let (leading, trailing) = hunk.context();
+            let base = hunk.old_start.saturating_sub(usize::from(!old.is_empty())) as isize + delta;
+
+            let mut applied = None;
+            // A side with less context than the other is at an end of the
+            // file, until enough context is dropped.
+            let context = leading.max(trailing);
+            for fuzz in 0..=MAX_FUZZ {
+                let top = (fuzz + leading).saturating_sub(context);
+                let bottom = (fuzz + trailing).saturating_sub(context);
+                let anchor = Anchor {
+                    start: fuzz + leading < context && hunk.old_start <= 1,
+                    end: fuzz + trailing < context,
+                };
+
+                let pattern = &old[top..old.len() - bottom];
+                let expected = (base + offset + top as isize).max(0) as usize;
+                if let Some(pos) = find_pattern(&lines, pattern, expected, min, anchor) {
+                    applied = Some((pos, top, bottom, fuzz));
+                    break;
+                }
+            }
+
+            let Some((pos, top, bottom, fuzz)) = applied else {
+                println!(
+                    "Hunk #{} FAILED at {}.",
+                    number + 1,
+                    hunk.old_start as isize + delta
+                );
+                rejects.push((hunk, delta));
+                continue;
+            };
+
+            let start = pos - top;
+            offset = start as isize - base;
+            let replaced = old.len() - top - bottom;
+            let replacement = new[top..new.len() - bottom]
+                .iter()
+                .map(|line| line.to_vec());
+            let at_end = pos + replaced == lines.len();
+            lines.splice(pos..pos + replaced, replacement);
+
+            if at_end && bottom == 0 {
+                missing_newline = hunk.new_missing_newline;
+            }
+            delta += new.len() as isize - old.len() as isize;
+            min = pos + new.len() - top - bottom;
+
+            let line = if new.is_empty() { start } else { start + 1 };
+            let mut message = format!("Hunk #{} succeeded at {}", number + 1, line);
+            if fuzz > 0 {
+                message.push_str(&format!(" with fuzz {}", fuzz));
+            }
+            if offset != 0 {
+                let plural = if offset.abs() == 1 { "" } else { "s" };
+                message.push_str(&format!(" (offset {} line{})", offset, plural));
+            }
+            if fuzz > 0 || offset != 0 {
+                println!("{}.", message);
+            }
+        }
+
+        if !rejects.is_empty() {
+            self.failed = true;
+            let total = patch.hunks.len();
+            let plural = if total == 1 { "" } else { "s" };
+            let summary = format!("{} out of {} hunk{} FAILED", rejects.len(), total, plural);
+
+            if self.dry_run {
+                println!("{}", summary);
+            } else {
+                let mut reject_path = path.clone().into_os_string();
+                reject_path.push(".rej");
+                println!(
+                    "{} -- saving rejects to file {}",
+                    summary,
+                    reject_path.to_string_lossy()
+                );
+
+                let mut text = patch.header.clone();
+                // The line numbers of the rejected hunks account for the
+                // lines added and removed by the hunks applied before.
+                for (hunk, delta) in rejects {
+                    let range = |start: usize, len: usize| match len {
+                        1 => format!("{}", start as isize + delta),
+                        _ => format!("{},{}", start as isize + delta, len),
+                    };
+                    let header = format!(
+                        "@@ -{} +{} @@\n",
+                        range(hunk.old_start, hunk.old_lines().len()),
+                        range(hunk.new_start, hunk.new_lines().len())
+                    );
+                    text.extend_from_slice(header.as_bytes());
+                    text.extend_from_slice(&hunk.text);
+                }
+                std::fs::write(&reject_path, text)?;
+            }
+        }
+
+        if self.dry_run {
+            return Ok(());
+        }
+
+        if deleting && lines.is_empty() {
+            match std::fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+
+            // The directories left empty are removed too.
+            for dir in path.ancestors().skip(1) {
+                if dir.as_os_str().is_empty() || std::fs::remove_dir(dir).is_err() {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+
+        let mut data = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            data.extend_from_slice(line);
+            if i + 1 < lines.len() || !missing_newline {
+                data.push(b'\n');
+            }
+        }
+        write_file(&path, "patch", &data)
+    }
+}
+
+pub fn patch(args: &[String]) {
+    let with_value = ["-p", "-i", "--strip", "--input"];
+    let Some((opts, operands)) = parse_options(args, &with_value) else {
+        eprintln!("Usage: patch [-pN] [-R] [--dry-run] [-i PATCHFILE] [FILE [PATCHFILE]]");
+        std::process::exit(-201);
+    };
+
+    let mut patcher = Patcher {
+        strip: None,
+        reverse: false,
+        dry_run: false,
+        failed: false,
+    };
+    let mut input = None;
+
+    for (opt, value) in opts {
+        match opt.as_str() {
+            "-p" | "--strip" => match value.as_deref().map(str::parse) {
+                Some(Ok(strip)) => patcher.strip = Some(strip),
+                _ => fail(format_args!(
+                    "strip count {} is not a number",
+                    value.unwrap_or_default()
+                )),
+            },
+            "-i" | "--input" => input = value,
+            "-R" | "--reverse" => patcher.reverse = true,
+            "--dry-run" => patcher.dry_run = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-201);
+            }
+        }
+    }
+
+    let target = match operands.as_slice() {
+        [] => None,
+        [file] => Some(Path::new(file.as_str())),
+        [file, patch_file] if input.is_none() => {
+            input = Some(patch_file.to_string());
+            Some(Path::new(file.as_str()))
+        }
+        _ => {
+            eprintln!("Usage: patch [-pN] [-R] [--dry-run] [-i PATCHFILE] [FILE [PATCHFILE]]");
+            std::process::exit(-201);
+        }
+    };
+
+    let mut data = Vec::new();
+    let result = match input.as_deref() {
+        None | Some("-") => std::io::stdin().lock().read_to_end(&mut data).map(|_| ()),
+        Some(path) => std::fs::read(path).map(|contents| data = contents),
+    };
+    if let Err(e) = result {
+        fail(format_args!(
+            "can't open patch file {}: {}",
+            input.unwrap_or_default(),
+            error_reason(&e)
+        ));
+    }
+
+    let patches = parse_patch(&data);
+    if patches.is_empty() {
+        fail(format_args!("Only garbage was found in the patch input."));
+    }
+
+    for patch in patches {
+        let path = patch.new_name.clone();
+        if let Err(e) = patcher.apply(patch, target) {
+            eprintln!("patch: {}: {}", path, error_reason(&e));
+            patcher.failed = true;
+        }
+    }
+
+    let _ = std::io::stdout().flush();
+    std::process::exit(i32::from(patcher.failed));
+}
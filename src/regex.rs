@@ -0,0 +1,823 @@
+//! POSIX regular expressions (basic and extended), matched by backtracking.
+//! They are used by `grep`, `sed` and `awk`.
+
+use crate::utils::char_class_matches;
+
+/// The position of each group in the matched text (in characters), the
+/// whole match being the group 0. Groups that didn't take part are `None`.
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+/// A member of a bracket expression.
+#[derive(Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Class(String),
+}
+
+/// A node of a compiled expression.
+enum Node {
+    Char(char),
+    Any,
+    Set(Vec<ClassItem>, bool),
+    /// `^`, matching at the start of the text.
+    Start,
+    /// `$`, matching at the end of the text.
+    End,
+    /// `\b` (or `\B`, if negated), matching at the edge of a word.
+    WordBoundary(bool),
+    /// `\<`, matching at the start of a word.
+    WordStart,
+    /// `\>`, matching at the end of a word.
+    WordEnd,
+    Group(Box<Node>, usize),
+    Concat(Vec<Node>),
+    Alternation(Vec<Node>),
+    Repeat(Box<Node>, usize, Option<usize>),
+    Backref(usize),
+}
+
+/// The text being matched.
+struct Input<'a> {
+    chars: &'a [char],
+    ignore_case: bool,
+}
+
+/// Check if `chr` can be part of a word.
+fn is_word_char(chr: char) -> bool {
+    chr.is_alphanumeric() || chr == '_'
+}
+
+impl Input<'_> {
+    fn chars_equal(&self, a: char, b: char) -> bool {
+        a == b || (self.ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+    }
+
+    fn word_before(&self, pos: usize) -> bool {
+        pos > 0 && is_word_char(self.chars[pos - 1])
+    }
+
+    fn word_after(&self, pos: usize) -> bool {
+        self.chars.get(pos).is_some_and(|&chr| is_word_char(chr))
+    }
+
+    /// Check if the single character instruction `inst` matches `chr`.
+    fn char_matches(&self, inst: &Inst, chr: char) -> bool {
+        match inst {
+            Inst::Char(expected) => self.chars_equal(*expected, chr),
+            Inst::Any => true,
+            Inst::Set(items, negated) => {
+                let in_set = |chr: char| {
+                    items.iter().any(|item| match item {
+                        ClassItem::Char(c) => *c == chr,
+                        ClassItem::Range(first, last) => (*first..=*last).contains(&chr),
+                        ClassItem::Class(class) => char_class_matches(class, chr),
+                    })
+                };
+
+                let matched = in_set(chr)
+                    || (self.ignore_case
+                        && (chr.to_lowercase().any(in_set) || chr.to_uppercase().any(in_set)));
+                matched != *negated
+            }
+            _ => false,
+        }
+    }
+}
+
+/// An instruction of a compiled expression, run by backtracking.
+enum Inst {
+    Char(char),
+    Any,
+    Set(Vec<ClassItem>, bool),
+    /// A single character matched between `min` and `max` times, as many
+    /// as possible.
+    Run(Box<Inst>, usize, usize),
+    Start,
+    End,
+    WordBoundary(bool),
+    WordStart,
+    WordEnd,
+    Backref(usize),
+    /// Remember where the group starts.
+    Open(usize),
+    /// Capture the group, from where it was opened.
+    Close(usize),
+    /// Continue at the first target, then at the second one if it fails.
+    Split(usize, usize),
+    Jump(usize),
+    /// Remember where an iteration of a repetition starts, in a register.
+    Mark(usize),
+    /// Fail if the iteration started at the register matched nothing, as
+    /// it would repeat forever.
+    Progress(usize),
+    Match,
+}
+
+/// Where to resume when a path fails, or what to undo on the way there.
+enum Backtrack {
+    /// Continue at an instruction, at a position in the text.
+    Resume(usize, usize),
+    /// Retry the run of the instruction, started at a position, with one
+    /// character less than matched.
+    Run(usize, usize, usize),
+    /// Restore a capture.
+    Capture(usize, Option<(usize, usize)>),
+    /// Restore a register.
+    Register(usize, usize),
+}
+
+/// The states (an instruction at a position) already tried, as bits. They
+/// grow with the positions reached, as most searches stop early.
+struct Visited {
+    bits: Vec<u64>,
+    /// The first position of the text that can be tried.
+    start: usize,
+    /// The number of instructions.
+    width: usize,
+}
+
+impl Visited {
+    /// Mark a state as tried. Returns false if it was already.
+    fn insert(&mut self, pc: usize, pos: usize) -> bool {
+        let bit = (pos - self.start) * self.width + pc;
+        let (word, mask) = (bit / 64, 1 << (bit % 64));
+        if word >= self.bits.len() {
+            self.bits.resize((word + 1).max(2 * self.bits.len()), 0);
+        }
+
+        let new = self.bits[word] & mask == 0;
+        self.bits[word] |= mask;
+        new
+    }
+}
+
+/// Compile a single character node.
+fn atom(node: &Node) -> Option<Inst> {
+    match node {
+        Node::Char(chr) => Some(Inst::Char(*chr)),
+        Node::Any => Some(Inst::Any),
+        Node::Set(items, negated) => Some(Inst::Set(items.clone(), *negated)),
+        _ => None,
+    }
+}
+
+/// Compiles the nodes of an expression to instructions.
+struct Compiler {
+    program: Vec<Inst>,
+    /// The registers used, the first ones being the starts of the groups.
+    registers: usize,
+}
+
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.program.push(inst);
+        self.program.len() - 1
+    }
+
+    /// Point the second target of the split at `split` to the end of the
+    /// program.
+    fn patch_split(&mut self, split: usize) {
+        let end = self.program.len();
+        if let Inst::Split(_, second) = &mut self.program[split] {
+            *second = end;
+        }
+    }
+
+    fn compile(&mut self, node: &Node) {
+        match node {
+            Node::Char(_) | Node::Any | Node::Set(..) => {
+                if let Some(atom) = atom(node) {
+                    self.push(atom);
+                }
+            }
+            Node::Start => {
+                self.push(Inst::Start);
+            }
+            Node::End => {
+                self.push(Inst::End);
+            }
+            Node::WordBoundary(negated) => {
+                self.push(Inst::WordBoundary(*negated));
+            }
+            Node::WordStart => {
+                self.push(Inst::WordStart);
+            }
+            Node::WordEnd => {
+                self.push(Inst::WordEnd);
+            }
+            Node::Backref(index) => {
+                self.push(Inst::Backref(*index));
+            }
+            Node::Group(inner, index) => {
+                self.push(Inst::Open(*index));
+                self.compile(inner);
+                self.push(Inst::Close(*index));
+            }
+            Node::Concat(nodes) => {
+                for node in nodes {
+                    self.compile(node);
+                }
+            }
+            Node::Alternation(alternatives) => {
+                let mut jumps = Vec::new();
+
+                for (i, alternative) in alternatives.iter().enumerate() {
+                    if i + 1 == alternatives.len() {
+                        self.compile(alternative);
+                        break;
+                    }
+
+                    let split = self.push(Inst::Split(self.program.len() + 1, 0));
+                    self.compile(alternative);
+                    jumps.push(self.push(Inst::Jump(0)));
+                    self.patch_split(split);
+                }
+
+                let end = self.program.len();
+                for jump in jumps {
+                    self.program[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat(inner, min, max) => {
+                let max = max.unwrap_or(usize::MAX);
+
+                // Single characters are repeated in a single step.
+                if let Some(atom) = atom(inner) {
+                    self.push(Inst::Run(Box::new(atom), *min, max));
+                    return;
+                }
+
+                for _ in 0..*min {
+                    self.compile(inner);
+                }
+
+                let mark = self.registers;
+                self.registers += 1;
+                let optional = |compiler: &mut Self| {
+                    let split = compiler.push(Inst::Split(compiler.program.len() + 1, 0));
+                    compiler.push(Inst::Mark(mark));
+                    compiler.compile(inner);
+                    compiler.push(Inst::Progress(mark));
+                    split
+                };
+
+                if max == usize::MAX {
+                    let split = optional(self);
+                    self.push(Inst::Jump(split));
+                    self.patch_split(split);
+                } else {
+                    // Each optional iteration is tried after the previous
+                    // one matched.
+                    let splits = (*min..max).map(|_| optional(self)).collect::<Vec<_>>();
+                    for split in splits {
+                        self.patch_split(split);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Parses an expression, in the basic or the extended syntax.
+struct Parser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    extended: bool,
+    groups: usize,
+    pattern: &'a str,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("invalid regular expression '{}': {}", self.pattern, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    /// Check if an alternation operator (`|`, or `\|` in basic
+    /// expressions) comes next.
+    fn at_alternation(&self) -> bool {
+        if self.extended {
+            self.peek() == Some('|')
+        } else {
+            self.peek() == Some('\\') && self.peek_at(1) == Some('|')
+        }
+    }
+
+    /// Check if the end of a group comes next.
+    fn at_group_end(&self) -> bool {
+        if self.extended {
+            self.peek() == Some(')')
+        } else {
+            self.peek() == Some('\\') && self.peek_at(1) == Some(')')
+        }
+    }
+
+    fn parse_alternation(&mut self, depth: usize) -> Result<Node, String> {
+        let mut alternatives = vec![self.parse_concat(depth)?];
+
+        while self.at_alternation() {
+            self.pos += if self.extended { 1 } else { 2 };
+            alternatives.push(self.parse_concat(depth)?);
+        }
+
+        Ok(if alternatives.len() == 1 {
+            alternatives.pop().unwrap_or(Node::Concat(Vec::new()))
+        } else {
+            Node::Alternation(alternatives)
+        })
+    }
+
+    fn parse_concat(&mut self, depth: usize) -> Result<Node, String> {
+        let mut nodes = Vec::new();
+        let start = self.pos;
+
+        while self.pos < self.chars.len() && !self.at_alternation() {
+            if self.at_group_end() {
+                if depth > 0 {
+                    break;
+                }
+                if self.extended {
+                    return Err(self.error("unmatched ) or \\)"));
+                }
+            }
+
+            let atom_start = self.pos == start;
+            let atom = self.parse_atom(depth, atom_start)?;
+            let atom = self.parse_quantifiers(atom)?;
+            nodes.push(atom);
+        }
+
+        Ok(if nodes.len() == 1 {
+            nodes.pop().unwrap_or(Node::Concat(Vec::new()))
+        } else {
+            Node::Concat(nodes)
+        })
+    }
+
+    /// Apply the quantifiers following an atom.
+    fn parse_quantifiers(&mut self, mut atom: Node) -> Result<Node, String> {
+        loop {
+            let (min, max) = match (self.peek(), self.peek_at(1)) {
+                (Some('*'), _) => {
+                    self.pos += 1;
+                    (0, None)
+                }
+                (Some('+'), _) if self.extended => {
+                    self.pos += 1;
+                    (1, None)
+                }
+                (Some('?'), _) if self.extended => {
+                    self.pos += 1;
+                    (0, Some(1))
+                }
+                (Some('\\'), Some('+')) if !self.extended => {
+                    self.pos += 2;
+                    (1, None)
+                }
+                (Some('\\'), Some('?')) if !self.extended => {
+                    self.pos += 2;
+                    (0, Some(1))
+                }
+                (Some('{'), _) if self.extended => {
+                    self.pos += 1;
+                    self.parse_interval()?
+                }
+                (Some('\\'), Some('{')) if !self.extended => {
+                    self.pos += 2;
+                    self.parse_interval()?
+                }
+                _ => return Ok(atom),
+            };
+
+            atom = Node::Repeat(Box::new(atom), min, max);
+        }
+    }
+
+    /// Parse the bounds of `{min,max}`, after the opening brace.
+    fn parse_interval(&mut self) -> Result<(usize, Option<usize>), String> {
+        let number = |parser: &mut Self| -> Option<usize> {
+            let start = parser.pos;
+            while parser.peek().is_some_and(|c| c.is_ascii_digit()) {
+                parser.pos += 1;
+            }
+            parser.chars[start..parser.pos]
+                .iter()
+                .collect::<String>()
+                .parse()
+                .ok()
+        };
+
+        let min = number(self).unwrap_or(0);
+        let max = if self.peek() == Some(',') {
+            self.pos += 1;
+            number(self)
+        } else {
+            Some(min)
+        };
+
+        let closed = if self.extended {
+            self.peek() == Some('}')
+        } else {
+            self.peek() == Some('\\') && self.peek_at(1) == Some('}')
+        };
+        if !closed || max.is_some_and(|max| max < min) {
+            return Err(self.error("invalid content of \\{\\}"));
+        }
+        self.pos += if self.extended { 1 } else { 2 };
+
+        Ok((min, max))
+    }
+
+    fn parse_atom(&mut self, depth: usize, at_start: bool) -> Result<Node, String> {
+        let chr = self.chars[self.pos];
+        self.pos += 1;
+
+        let node = match chr {
+            '.' => Node::Any,
+            '[' => self.parse_bracket()?,
+            '^' if self.extended || at_start => Node::Start,
+            // In basic expressions, `$` is an anchor only at the end.
+            '$' if self.extended
+                || self.pos == self.chars.len()
+                || self.at_group_end()
+                || self.at_alternation() =>
+            {
+                Node::End
+            }
+            // A leading `*` is literal.
+            '*' if at_start => Node::Char('*'),
+            '(' if self.extended => self.parse_group(depth)?,
+            '*' | '+' | '?' | '{' if self.extended => {
+                return Err(self.error("nothing to repeat"));
+            }
+            '\\' => {
+                let Some(escaped) = self.peek() else {
+                    return Err(self.error("trailing backslash"));
+                };
+                self.pos += 1;
+
+                match escaped {
+                    '(' if !self.extended => self.parse_group(depth)?,
+                    '1'..='9' => {
+                        let index = escaped as usize - '0' as usize;
+                        if index > self.groups {
+                            return Err(self.error("invalid back reference"));
+                        }
+                        Node::Backref(index)
+                    }
+                    'n' => Node::Char('\n'),
+                    't' => Node::Char('\t'),
+                    'w' | 'W' => Node::Set(
+                        vec![
+                            ClassItem::Class(String::from("alnum")),
+                            ClassItem::Char('_'),
+                        ],
+                        escaped == 'W',
+                    ),
+                    's' | 'S' => Node::Set(
+                        vec![ClassItem::Class(String::from("space"))],
+                        escaped == 'S',
+                    ),
+                    'b' => Node::WordBoundary(false),
+                    'B' => Node::WordBoundary(true),
+                    '<' => Node::WordStart,
+                    '>' => Node::WordEnd,
+                    '`' => Node::Start,
+                    '\'' => Node::End,
+                    chr => Node::Char(chr),
+                }
+            }
+            chr => Node::Char(chr),
+        };
+
+        Ok(node)
+    }
+
+    /// Parse a group, after its opening parenthesis.
+    fn parse_group(&mut self, depth: usize) -> Result<Node, String> {
+        self.groups += 1;
+        let index = self.groups;
+
+        let inner = self.parse_alternation(depth + 1)?;
+        if !self.at_group_end() {
+            return Err(self.error("unmatched ( or \\("));
+        }
+        self.pos += if self.extended { 1 } else { 2 };
+
+        Ok(Node::Group(Box::new(inner), index))
+    }
+
+    /// Parse a bracket expression, after its opening bracket.
+    fn parse_bracket(&mut self) -> Result<Node, String> {
+        let negated = self.peek() == Some('^');
+        if negated {
+            self.pos += 1;
+        }
+
+        let mut items = Vec::new();
+        let mut first = true;
+
+        loop {
+            let Some(chr) = self.peek() else {
+                return Err(self.error("unmatched [, [^, [:, [., or [="));
+            };
+
+            // A leading `]` is literal.
+            if chr == ']' && !first {
+                self.pos += 1;
+                break;
+            }
+            first = false;
+
+            if chr == '[' && matches!(self.peek_at(1), Some(':' | '=' | '.')) {
+                let kind = self.chars[self.pos + 1];
+                let rest = &self.chars[self.pos + 2..];
+                let Some(end) = rest.windows(2).position(|w| w[0] == kind && w[1] == ']') else {
+                    return Err(self.error("unmatched [, [^, [:, [., or [="));
+                };
+
+                let name = rest[..end].iter().collect::<String>();
+                self.pos += end + 4;
+
+                if kind == ':' {
+                    if !crate::utils::CHAR_CLASSES.contains(&name.as_str()) {
+                        return Err(self.error("invalid character class"));
+                    }
+                    items.push(ClassItem::Class(name));
+                } else {
+                    items.extend(name.chars().map(ClassItem::Char));
+                }
+                continue;
+            }
+
+            self.pos += 1;
+
+            if self.peek() == Some('-') && self.peek_at(1).is_some_and(|c| c != ']') {
+                let last = self.chars[self.pos + 1];
+                self.pos += 2;
+
+                if last < chr {
+                    return Err(self.error("invalid range end"));
+                }
+                items.push(ClassItem::Range(chr, last));
+            } else {
+                items.push(ClassItem::Char(chr));
+            }
+        }
+
+        Ok(Node::Set(items, negated))
+    }
+}
+
+/// A compiled regular expression.
+pub struct Regex {
+    program: Vec<Inst>,
+    registers: usize,
+    /// Whether the expression can only match at the start of the text.
+    anchored: bool,
+    /// Whether the states tried can be remembered, which is only possible
+    /// without back-references.
+    memoize: bool,
+    groups: usize,
+    ignore_case: bool,
+}
+
+impl Regex {
+    /// Compile an expression in the basic syntax (as for `grep` and `sed`),
+    /// or in the extended one (as for `grep -E`, `sed -E` and `awk`).
+    pub fn new(pattern: &str, extended: bool, ignore_case: bool) -> Result<Self, String> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            extended,
+            groups: 0,
+            pattern,
+        };
+
+        let root = parser.parse_alternation(0)?;
+        if parser.pos < parser.chars.len() {
+            return Err(parser.error("unmatched ) or \\)"));
+        }
+
+        let anchored = matches!(&root, Node::Start)
+            || matches!(&root, Node::Concat(nodes) if matches!(nodes.first(), Some(Node::Start)));
+
+        let mut compiler = Compiler {
+            program: Vec::new(),
+            registers: parser.groups + 1,
+        };
+        compiler.compile(&root);
+        compiler.push(Inst::Match);
+
+        let memoize = !compiler
+            .program
+            .iter()
+            .any(|inst| matches!(inst, Inst::Backref(_)));
+
+        Ok(Regex {
+            program: compiler.program,
+            registers: compiler.registers,
+            anchored,
+            memoize,
+            groups: parser.groups,
+            ignore_case,
+        })
+    }
+
+    /// The number of groups in the expression.
+    pub fn groups(&self) -> usize {
+        self.groups
+    }
+
+    /// Find the leftmost match in `chars`, starting the search at `start`.
+    /// Of the matches starting there, the longest is chosen, as POSIX asks.
+    pub fn find_at(&self, chars: &[char], start: usize) -> Option<Captures> {
+        self.search(chars, start, true)
+    }
+
+    /// Run the program at `pos`, calling `accept` with the end of each match
+    /// found until it returns true. The paths are tried in order of
+    /// preference, the state to backtrack to being kept on a stack instead of
+    /// the call stack, so long texts can't overflow it.
+    fn run(
+        &self,
+        input: &Input,
+        pos: usize,
+        caps: &mut Captures,
+        mut visited: Option<&mut Visited>,
+        accept: &mut dyn FnMut(usize, &mut Captures) -> bool,
+    ) -> bool {
+        let chars = input.chars;
+        let mut stack = Vec::new();
+        let mut registers = vec![0; self.registers];
+        let (mut pc, mut pos) = (0, pos);
+
+        loop {
+            // A state tried already can't lead to a match that wasn't found,
+            // since its outcome only depends on the instruction and the
+            // position (there are no back-references).
+            let tried = visited
+                .as_deref_mut()
+                .is_some_and(|visited| !visited.insert(pc, pos));
+
+            let matched = match &self.program[pc] {
+                _ if tried => false,
+                inst @ (Inst::Char(_) | Inst::Any | Inst::Set(..)) => {
+                    let matched = pos < chars.len() && input.char_matches(inst, chars[pos]);
+                    pos += 1;
+                    matched
+                }
+                Inst::Run(atom, min, max) => {
+                    let mut count = 0;
+                    while count < *max
+                        && pos + count < chars.len()
+                        && input.char_matches(atom, chars[pos + count])
+                    {
+                        count += 1;
+                    }
+
+                    if count > *min {
+                        stack.push(Backtrack::Run(pc, pos, count));
+                    }
+                    pos += count;
+                    count >= *min
+                }
+                Inst::Start => pos == 0,
+                Inst::End => pos == chars.len(),
+                Inst::WordBoundary(negated) => {
+                    (input.word_before(pos) != input.word_after(pos)) != *negated
+                }
+                Inst::WordStart => !input.word_before(pos) && input.word_after(pos),
+                Inst::WordEnd => input.word_before(pos) && !input.word_after(pos),
+                Inst::Backref(index) => match caps.get(*index).copied().flatten() {
+                    Some((start, end)) => {
+                        let len = end - start;
+                        let matched = pos + len <= chars.len()
+                            && (0..len)
+                                .all(|i| input.chars_equal(chars[start + i], chars[pos + i]));
+                        pos += len;
+                        matched
+                    }
+                    None => false,
+                },
+                Inst::Open(index) | Inst::Mark(index) => {
+                    stack.push(Backtrack::Register(*index, registers[*index]));
+                    registers[*index] = pos;
+                    true
+                }
+                Inst::Close(index) => {
+                    stack.push(Backtrack::Capture(*index, caps[*index]));
+                    caps[*index] = Some((registers[*index], pos));
+                    true
+                }
+                Inst::Progress(mark) => pos != registers[*mark],
+                Inst::Split(first, second) => {
+                    stack.push(Backtrack::Resume(*second, pos));
+                    pc = *first;
+                    continue;
+                }
+                Inst::Jump(target) => {
+                    pc = *target;
+                    continue;
+                }
+                Inst::Match => {
+                    if accept(pos, caps) {
+                        return true;
+                    }
+                    false
+                }
+            };
+
+            if matched {
+                pc += 1;
+                continue;
+            }
+
+            loop {
+                match stack.pop() {
+                    None => return false,
+                    Some(Backtrack::Resume(target, start)) => {
+                        (pc, pos) = (target, start);
+                        break;
+                    }
+                    Some(Backtrack::Run(run, start, count)) => {
+                        let Inst::Run(_, min, _) = &self.program[run] else {
+                            unreachable!("only runs are retried");
+                        };
+                        if count - 1 > *min {
+                            stack.push(Backtrack::Run(run, start, count - 1));
+                        }
+                        (pc, pos) = (run + 1, start + count - 1);
+                        break;
+                    }
+                    Some(Backtrack::Capture(index, value)) => caps[index] = value,
+                    Some(Backtrack::Register(index, value)) => registers[index] = value,
+                }
+            }
+        }
+    }
+
+    /// Find a match, the first one found by backtracking if `longest`
+    /// isn't set, which is enough to know if there's one.
+    fn search(&self, chars: &[char], start: usize, longest: bool) -> Option<Captures> {
+        let input = Input {
+            chars,
+            ignore_case: self.ignore_case,
+        };
+
+        // The states tried from a start are skipped from the next ones:
+        // they can't lead to a match, since none was found.
+        let mut visited = self.memoize.then(|| Visited {
+            bits: Vec::new(),
+            start,
+            width: self.program.len(),
+        });
+
+        for pos in start..=chars.len() {
+            let mut caps = vec![None; self.groups + 1];
+            let mut best: Option<Captures> = None;
+            let mut accept = |end, caps: &mut Captures| {
+                if best.as_ref().is_some_and(|best| best[0].unwrap().1 >= end) {
+                    return false;
+                }
+
+                caps[0] = Some((pos, end));
+                best = Some(caps.clone());
+
+                // Nothing can be longer than a match up to the end.
+                !longest || end == chars.len()
+            };
+            self.run(&input, pos, &mut caps, visited.as_mut(), &mut accept);
+
+            if best.is_some() {
+                return best;
+            }
+
+            // An expression anchored at the start can only match there.
+            if self.anchored {
+                break;
+            }
+        }
+
+        None
+    }
+
+    /// Check if the expression matches anywhere in `chars`.
+    pub fn matches(&self, chars: &[char]) -> bool {
+        self.search(chars, 0, false).is_some()
+    }
+
+    /// Check if the expression matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        let chars = text.chars().collect::<Vec<_>>();
+        self.matches(&chars)
+    }
+}
@@ -0,0 +1,1545 @@
+//! The `sed` command, a stream editor.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufWriter, Write};
+use std::os::unix::prelude::{MetadataExt, OsStrExt};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::regex::{Captures, Regex};
+use crate::utils::*;
+
+/// Invalid UTF-8 bytes are kept as characters of this private use range,
+/// so that they come out unchanged.
+const RAW_BYTES: u32 = 0x10ff00;
+
+/// Decode `bytes` into characters.
+fn decode(bytes: &[u8], chars: &mut Vec<char>) {
+    for chunk in bytes.utf8_chunks() {
+        chars.extend(chunk.valid().chars());
+        chars.extend(
+            chunk
+                .invalid()
+                .iter()
+                .map(|&b| char::from_u32(RAW_BYTES + b as u32).unwrap()),
+        );
+    }
+}
+
+/// Encode characters back into bytes.
+fn encode(chars: &[char], bytes: &mut Vec<u8>) {
+    let mut buffer = [0; 4];
+
+    for &chr in chars {
+        if chr as u32 >= RAW_BYTES {
+            bytes.push((chr as u32 - RAW_BYTES) as u8);
+        } else {
+            bytes.extend_from_slice(chr.encode_utf8(&mut buffer).as_bytes());
+        }
+    }
+}
+
+/// An address selecting lines.
+enum Address {
+    Line(usize),
+    /// `$`, the last line.
+    Last,
+    Match(Rc<Regex>),
+    /// `first~step`, every `step` lines from `first`.
+    Step(usize, usize),
+}
+
+/// The end of a range of lines.
+enum RangeEnd {
+    Address(Address),
+    /// `addr1,+N`, the `N` lines after the first one.
+    Count(usize),
+    /// `addr1,~N`, up to a line that is a multiple of `N`.
+    Multiple(usize),
+}
+
+/// The branching commands.
+enum Jump {
+    /// `b`, always.
+    Always,
+    /// `t`, if there was a substitution.
+    IfSubstituted,
+    /// `T`, if there was none.
+    UnlessSubstituted,
+}
+
+/// A part of the replacement of `s`.
+enum Piece {
+    Text(Vec<char>),
+    /// `&` (the group 0) or `\1` to `\9`.
+    Group(usize),
+    /// `\U` or `\L`, converting the case of what follows up to `\E`, or
+    /// `\u` or `\l`, converting the next character only.
+    Case(char),
+}
+
+/// The `s` command.
+struct Substitution {
+    regex: Rc<Regex>,
+    replacement: Vec<Piece>,
+    global: bool,
+    /// Only the `N`th match (and the next ones if global) is replaced.
+    occurrence: usize,
+    print: bool,
+    write: Option<usize>,
+}
+
+enum Action {
+    /// `{`, with the index of the command after the matching `}`.
+    Block(usize),
+    EndBlock,
+    Substitute(Box<Substitution>),
+    Translate(HashMap<char, char>),
+    Delete,
+    DeleteFirst,
+    Print,
+    PrintFirst,
+    Next,
+    AppendNext,
+    Hold,
+    HoldAppend,
+    Get,
+    GetAppend,
+    Exchange,
+    Zap,
+    Append(Vec<u8>),
+    Insert(Vec<u8>),
+    Change(Vec<u8>),
+    ReadFile(String),
+    WriteFile(usize),
+    Quit(i32),
+    QuitSilently(i32),
+    LineNumber,
+    List(Option<usize>),
+    Label,
+    /// A branch to the command at the index.
+    Branch(Jump, usize),
+}
+
+struct Command {
+    first: Option<Address>,
+    last: Option<RangeEnd>,
+    negated: bool,
+    action: Action,
+}
+
+/// Parse a script into commands. Branches and blocks are resolved to the
+/// index of the command they lead to.
+struct ScriptParser<'a> {
+    chars: Vec<char>,
+    pos: usize,
+    extended: bool,
+    last_regex: Option<Rc<Regex>>,
+    commands: Vec<Command>,
+    /// The commands opening the blocks not closed yet.
+    blocks: Vec<usize>,
+    labels: HashMap<String, usize>,
+    /// The branches, with the label they lead to.
+    branches: Vec<(usize, String)>,
+    /// The files written by `w`, with their index.
+    write_files: &'a mut Vec<String>,
+}
+
+impl ScriptParser<'_> {
+    fn fail(&self, message: &str) -> ! {
+        eprintln!("sed: -e expression #1, char {}: {}", self.pos, message);
+        std::process::exit(-180);
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let chr = self.peek();
+        if chr.is_some() {
+            self.pos += 1;
+        }
+        chr
+    }
+
+    /// Skip the whitespace, except newlines, which end commands.
+    fn skip_spaces(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace() && c != '\n') {
+            self.pos += 1;
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+
+        let digits = self.chars[start..self.pos].iter().collect::<String>();
+        match digits.parse() {
+            Ok(number) => Some(number),
+            Err(_) if digits.is_empty() => None,
+            Err(_) => self.fail("invalid number"),
+        }
+    }
+
+    /// Read the text up to the delimiter, for a regex or a replacement.
+    /// `\` followed by the delimiter stands for the delimiter, and the
+    /// other escapes are kept. In a regex, a bracket expression can
+    /// contain the delimiter. `unterminated` is the error if the delimiter
+    /// is missing.
+    fn delimited(&mut self, delimiter: char, regex: bool, unterminated: &str) -> Vec<char> {
+        let mut text = Vec::new();
+
+        loop {
+            let Some(chr) = self.next() else {
+                self.fail(unterminated);
+            };
+
+            match chr {
+                _ if chr == delimiter => return text,
+                '\\' => match self.next() {
+                    Some(chr) if chr == delimiter => text.push(chr),
+                    Some('\n') if regex => self.fail(unterminated),
+                    Some(chr) => text.extend(['\\', chr]),
+                    None => self.fail(unterminated),
+                },
+                '\n' if regex => self.fail(unterminated),
+                '[' if regex => {
+                    text.push(chr);
+
+                    // `]` is a member if it comes first.
+                    let start = self.pos;
+                    while let Some(chr) = self.next() {
+                        text.push(chr);
+
+                        if chr == '[' && matches!(self.peek(), Some(':' | '.' | '=')) {
+                            let kind = self.next().unwrap();
+                            text.push(kind);
+                            while let Some(chr) = self.next() {
+                                text.push(chr);
+                                if chr == kind && self.peek() == Some(']') {
+                                    text.push(self.next().unwrap());
+                                    break;
+                                }
+                            }
+                        } else if chr == ']'
+                            && self.pos - 1 > start
+                            && !(self.pos - 1 == start + 1 && self.chars[start] == '^')
+                        {
+                            break;
+                        }
+                    }
+                }
+                _ => text.push(chr),
+            }
+        }
+    }
+
+    /// Compile a regex, an empty one standing for the last regex used.
+    fn regex(&mut self, pattern: &[char], ignore_case: bool) -> Rc<Regex> {
+        if pattern.is_empty() {
+            match &self.last_regex {
+                Some(regex) => return regex.clone(),
+                None => self.fail("no previous regular expression"),
+            }
+        }
+
+        let pattern = pattern.iter().collect::<String>();
+        let regex = match Regex::new(&pattern, self.extended, ignore_case) {
+            Ok(regex) => Rc::new(regex),
+            Err(message) => self.fail(&message),
+        };
+
+        self.last_regex = Some(regex.clone());
+        regex
+    }
+
+    fn address(&mut self) -> Option<Address> {
+        match self.peek()? {
+            '$' => {
+                self.pos += 1;
+                Some(Address::Last)
+            }
+            '/' | '\\' => {
+                let delimiter = match self.next()? {
+                    '\\' => self
+                        .next()
+                        .unwrap_or_else(|| self.fail("unexpected end of script")),
+                    chr => chr,
+                };
+                let pattern = self.delimited(delimiter, true, "unterminated address regex");
+
+                let mut ignore_case = false;
+                while let Some(flag @ ('I' | 'M')) = self.peek() {
+                    ignore_case |= flag == 'I';
+                    self.pos += 1;
+                }
+
+                Some(Address::Match(self.regex(&pattern, ignore_case)))
+            }
+            _ => {
+                let line = self.number()?;
+                if self.peek() == Some('~') {
+                    self.pos += 1;
+                    let step = self.number().unwrap_or(0);
+                    return Some(Address::Step(line, step));
+                }
+
+                Some(Address::Line(line))
+            }
+        }
+    }
+
+    /// Read the text of `a`, `i` and `c`: the rest of the line, or the next
+    /// lines after `a\`, a backslash at the end of a line continuing it.
+    fn text(&mut self) -> Vec<u8> {
+        self.skip_spaces();
+
+        if self.peek() == Some('\\') {
+            self.pos += 1;
+            self.skip_spaces();
+            if self.peek() == Some('\n') {
+                self.pos += 1;
+            }
+        }
+
+        let mut text = Vec::new();
+        while let Some(chr) = self.next() {
+            match chr {
+                '\n' => break,
+                '\\' => match self.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some(chr) => text.push(chr),
+                    None => {}
+                },
+                _ => text.push(chr),
+            }
+        }
+
+        let mut bytes = Vec::new();
+        encode(&text, &mut bytes);
+        bytes
+    }
+
+    /// Read the argument of a command up to the end of the line, like a
+    /// file name.
+    fn rest_of_line(&mut self) -> String {
+        self.skip_spaces();
+
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != '\n') {
+            self.pos += 1;
+        }
+
+        self.chars[start..self.pos].iter().collect()
+    }
+
+    /// Read a label, ending at a semicolon or the end of the line.
+    fn label(&mut self) -> String {
+        self.skip_spaces();
+
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c != '\n' && c != ';') {
+            self.pos += 1;
+        }
+
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .trim_end()
+            .to_owned()
+    }
+
+    fn write_file(&mut self) -> usize {
+        let path = self.rest_of_line();
+        if path.is_empty() {
+            self.fail("missing filename in r/R/w/W commands");
+        }
+
+        match self.write_files.iter().position(|file| *file == path) {
+            Some(index) => index,
+            None => {
+                self.write_files.push(path);
+                self.write_files.len() - 1
+            }
+        }
+    }
+
+    fn substitution(&mut self) -> Substitution {
+        let Some(delimiter) = self.next().filter(|&c| c != '\n' && c != '\\') else {
+            self.fail("unterminated `s' command");
+        };
+
+        let unterminated = "unterminated `s' command";
+        let pattern = self.delimited(delimiter, true, unterminated);
+        let replacement = self.delimited(delimiter, false, unterminated);
+
+        let mut substitution = Substitution {
+            regex: Rc::new(Regex::new("", false, false).unwrap()),
+            replacement: Vec::new(),
+            global: false,
+            occurrence: 1,
+            print: false,
+            write: None,
+        };
+        let mut ignore_case = false;
+
+        loop {
+            match self.peek() {
+                Some('g') => substitution.global = true,
+                Some('p') => substitution.print = true,
+                Some('i' | 'I') => ignore_case = true,
+                Some('m' | 'M') => {}
+                Some('w') => {
+                    self.pos += 1;
+                    substitution.write = Some(self.write_file());
+                    break;
+                }
+                Some('0'..='9') => {
+                    substitution.occurrence = match self.number() {
+                        Some(0) => self.fail("number option to `s' command may not be zero"),
+                        number => number.unwrap(),
+                    };
+                    continue;
+                }
+                _ => break,
+            }
+            self.pos += 1;
+        }
+
+        substitution.regex = self.regex(&pattern, ignore_case);
+
+        let mut text = Vec::new();
+        let mut chars = replacement.into_iter();
+        while let Some(chr) = chars.next() {
+            let piece = match chr {
+                '&' => Piece::Group(0),
+                '\\' => match chars.next() {
+                    Some(digit @ '0'..='9') => {
+                        let group = digit as usize - '0' as usize;
+                        if group > substitution.regex.groups() {
+                            self.fail(&format!(
+                                "invalid reference \\{} on `s' command's RHS",
+                                group
+                            ));
+                        }
+                        Piece::Group(group)
+                    }
+                    Some(case @ ('U' | 'L' | 'u' | 'l' | 'E')) => Piece::Case(case),
+                    Some('n') => {
+                        text.push('\n');
+                        continue;
+                    }
+                    Some('t') => {
+                        text.push('\t');
+                        continue;
+                    }
+                    Some(chr) => {
+                        text.push(chr);
+                        continue;
+                    }
+                    None => continue,
+                },
+                _ => {
+                    text.push(chr);
+                    continue;
+                }
+            };
+
+            if !text.is_empty() {
+                substitution
+                    .replacement
+                    .push(Piece::Text(std::mem::take(&mut text)));
+            }
+            substitution.replacement.push(piece);
+        }
+
+        if !text.is_empty() {
+            substitution.replacement.push(Piece::Text(text));
+        }
+
+        substitution
+    }
+
+    fn translation(&mut self) -> HashMap<char, char> {
+        let Some(delimiter) = self.next().filter(|&c| c != '\n' && c != '\\') else {
+            self.fail("unterminated `y' command");
+        };
+
+        let mut sets = [Vec::new(), Vec::new()];
+        for set in &mut sets {
+            let mut chars = self
+                .delimited(delimiter, false, "unterminated `y' command")
+                .into_iter();
+            while let Some(chr) = chars.next() {
+                set.push(match chr {
+                    '\\' => match chars.next() {
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some(chr) => chr,
+                        None => '\\',
+                    },
+                    _ => chr,
+                });
+            }
+        }
+
+        if sets[0].len() != sets[1].len() {
+            self.fail("strings for `y' command are different lengths");
+        }
+
+        sets[0]
+            .iter()
+            .copied()
+            .zip(sets[1].iter().copied())
+            .collect()
+    }
+
+    /// Parse the command after its address.
+    fn action(&mut self, name: char) -> Action {
+        match name {
+            '{' => {
+                self.blocks.push(self.commands.len());
+                Action::Block(0)
+            }
+            '}' => {
+                let Some(start) = self.blocks.pop() else {
+                    self.fail("unexpected `}'");
+                };
+                let end = self.commands.len() + 1;
+                self.commands[start].action = Action::Block(end);
+                Action::EndBlock
+            }
+            's' => Action::Substitute(Box::new(self.substitution())),
+            'y' => Action::Translate(self.translation()),
+            'd' => Action::Delete,
+            'D' => Action::DeleteFirst,
+            'p' => Action::Print,
+            'P' => Action::PrintFirst,
+            'n' => Action::Next,
+            'N' => Action::AppendNext,
+            'h' => Action::Hold,
+            'H' => Action::HoldAppend,
+            'g' => Action::Get,
+            'G' => Action::GetAppend,
+            'x' => Action::Exchange,
+            'z' => Action::Zap,
+            'a' => Action::Append(self.text()),
+            'i' => Action::Insert(self.text()),
+            'c' => Action::Change(self.text()),
+            'r' => Action::ReadFile(self.rest_of_line()),
+            'w' => Action::WriteFile(self.write_file()),
+            'q' | 'Q' => {
+                self.skip_spaces();
+                let code = self.number().unwrap_or(0) as i32;
+                if name == 'q' {
+                    Action::Quit(code)
+                } else {
+                    Action::QuitSilently(code)
+                }
+            }
+            '=' => Action::LineNumber,
+            'l' => {
+                self.skip_spaces();
+                Action::List(self.number())
+            }
+            ':' => {
+                let label = self.label();
+                if label.is_empty() {
+                    self.fail("\":\" lacks a label");
+                }
+                self.labels.insert(label, self.commands.len());
+                Action::Label
+            }
+            'b' | 't' | 'T' => {
+                let label = self.label();
+                self.branches.push((self.commands.len(), label));
+
+                let jump = match name {
+                    'b' => Jump::Always,
+                    't' => Jump::IfSubstituted,
+                    _ => Jump::UnlessSubstituted,
+                };
+                Action::Branch(jump, 0)
+            }
+            _ => self.fail(&format!("unknown command: `{}'", name)),
+        }
+    }
+
+    fn parse(mut self) -> Vec<Command> {
+        loop {
+            while self.peek().is_some_and(|c| c.is_whitespace() || c == ';') {
+                self.pos += 1;
+            }
+
+            let Some(chr) = self.peek() else {
+                break;
+            };
+
+            if chr == '#' {
+                while self.next().is_some_and(|c| c != '\n') {}
+                continue;
+            }
+
+            let first = self.address();
+            let mut last = None;
+            if first.is_some() && self.peek() == Some(',') {
+                self.pos += 1;
+                self.skip_spaces();
+
+                last = match self.peek() {
+                    Some(kind @ ('+' | '~')) => {
+                        self.pos += 1;
+                        let Some(count) = self.number() else {
+                            self.fail("expected address after `,'");
+                        };
+                        Some(if kind == '+' {
+                            RangeEnd::Count(count)
+                        } else {
+                            RangeEnd::Multiple(count)
+                        })
+                    }
+                    _ => match self.address() {
+                        Some(address) => Some(RangeEnd::Address(address)),
+                        None => self.fail("unexpected `,'"),
+                    },
+                };
+            }
+
+            if matches!(first, Some(Address::Line(0)))
+                && !matches!(last, Some(RangeEnd::Address(Address::Match(_))))
+            {
+                self.fail("invalid usage of line address 0");
+            }
+
+            self.skip_spaces();
+            let mut negated = false;
+            while self.peek() == Some('!') {
+                negated = true;
+                self.pos += 1;
+                self.skip_spaces();
+            }
+
+            let Some(name) = self.next() else {
+                self.fail("missing command");
+            };
+
+            if first.is_some() && matches!(name, ':' | '}') {
+                self.fail(&format!("{} doesn't want any addresses", name));
+            }
+
+            let action = self.action(name);
+            self.commands.push(Command {
+                first,
+                last,
+                negated,
+                action,
+            });
+
+            // A command ends at a newline, a semicolon, a block end or a
+            // comment.
+            self.skip_spaces();
+            match self.peek() {
+                None | Some('\n' | ';' | '}' | '#') => {}
+                Some(_) if name == '{' => {}
+                Some(_) => self.fail("extra characters after command"),
+            }
+        }
+
+        if !self.blocks.is_empty() {
+            self.fail("unmatched `{'");
+        }
+
+        for (index, label) in std::mem::take(&mut self.branches) {
+            let target = if label.is_empty() {
+                self.commands.len()
+            } else {
+                match self.labels.get(&label) {
+                    Some(&target) => target,
+                    None => self.fail(&format!("can't find label for jump to `{}'", label)),
+                }
+            };
+
+            if let Action::Branch(_, destination) = &mut self.commands[index].action {
+                *destination = target;
+            }
+        }
+
+        self.commands
+    }
+}
+
+/// The lines of the input files, read as a single stream.
+struct Input {
+    files: std::vec::IntoIter<String>,
+    file: String,
+    reader: Option<Box<dyn BufRead>>,
+    /// The line after the current one, read to know if it's the last.
+    lookahead: Option<Option<(Vec<u8>, bool)>>,
+    delimiter: u8,
+    was_error: bool,
+}
+
+impl Input {
+    fn new(files: Vec<String>, delimiter: u8) -> Self {
+        Input {
+            files: files.into_iter(),
+            file: String::new(),
+            reader: None,
+            lookahead: None,
+            delimiter,
+            was_error: false,
+        }
+    }
+
+    /// Read the next line, with whether it was terminated.
+    fn read(&mut self) -> Option<(Vec<u8>, bool)> {
+        loop {
+            if let Some(reader) = &mut self.reader {
+                let mut line = Vec::new();
+                match reader.read_until(self.delimiter, &mut line) {
+                    Ok(0) => {}
+                    Ok(_) => {
+                        let terminated = line.last() == Some(&self.delimiter);
+                        if terminated {
+                            line.pop();
+                        }
+                        return Some((line, terminated));
+                    }
+                    Err(e) => {
+                        eprintln!("sed: read error on {}: {}", self.file, error_reason(&e));
+                        self.was_error = true;
+                    }
+                }
+                self.reader = None;
+            }
+
+            self.file = self.files.next()?;
+            match open_input(&self.file) {
+                Ok(reader) => self.reader = Some(reader),
+                Err(e) => {
+                    eprintln!("sed: can't read {}: {}", self.file, error_reason(&e));
+                    self.was_error = true;
+                }
+            }
+        }
+    }
+
+    fn next_line(&mut self) -> Option<(Vec<u8>, bool)> {
+        match self.lookahead.take() {
+            Some(line) => line,
+            None => self.read(),
+        }
+    }
+
+    fn is_last(&mut self) -> bool {
+        if self.lookahead.is_none() {
+            self.lookahead = Some(self.read());
+        }
+
+        matches!(self.lookahead, Some(None))
+    }
+}
+
+/// An output, which knows if its last line wasn't terminated, so that a
+/// terminator is added before writing more.
+struct Output {
+    out: Box<dyn Write>,
+    missing_terminator: bool,
+}
+
+impl Output {
+    fn new(out: Box<dyn Write>) -> Self {
+        Output {
+            out,
+            missing_terminator: false,
+        }
+    }
+
+    fn write_line(&mut self, line: &[u8], terminator: Option<u8>) -> std::io::Result<()> {
+        if self.missing_terminator {
+            self.out.write_all(b"\n")?;
+        }
+
+        self.out.write_all(line)?;
+        if let Some(terminator) = terminator {
+            self.out.write_all(&[terminator])?;
+        }
+        self.missing_terminator = terminator.is_none();
+
+        Ok(())
+    }
+}
+
+/// Text queued by `a` and `r`, printed at the end of the cycle.
+enum Queued {
+    Text(Vec<u8>),
+    File(String),
+}
+
+/// The state of the editor, kept from a file to the next one.
+struct Editor {
+    commands: Vec<Command>,
+    quiet: bool,
+    delimiter: u8,
+    line_wrap: usize,
+    hold: Vec<char>,
+    /// For the ranges being matched, the line they end at, or `usize::MAX`
+    /// if that depends on an address.
+    ranges: Vec<Option<usize>>,
+    write_files: Vec<Option<Output>>,
+    queued: Vec<Queued>,
+    line: usize,
+}
+
+impl Editor {
+    fn address_matches(&self, address: &Address, space: &[char], input: &mut Input) -> bool {
+        match *address {
+            Address::Line(line) => self.line == line,
+            Address::Last => input.is_last(),
+            Address::Match(ref regex) => regex.matches(space),
+            Address::Step(first, step) => {
+                self.line >= first && (self.line - first).is_multiple_of(step)
+            }
+        }
+    }
+
+    /// Check if the command at `index` applies to the current line.
+    fn selected(
+        &mut self,
+        index: usize,
+        command: &Command,
+        space: &[char],
+        input: &mut Input,
+    ) -> bool {
+        let Some(first) = &command.first else {
+            return !command.negated;
+        };
+
+        let Some(last) = &command.last else {
+            return self.address_matches(first, space, input) != command.negated;
+        };
+
+        let matched = if let Some(end) = self.ranges[index] {
+            let ended = match last {
+                RangeEnd::Address(address @ (Address::Last | Address::Match(_))) => {
+                    self.address_matches(address, space, input)
+                }
+                _ => self.line >= end,
+            };
+            if ended {
+                self.ranges[index] = None;
+            }
+            true
+        } else if matches!(first, Address::Line(0)) && self.line == 1 {
+            // `0,/re/` can end on the first line.
+            if !self.address_matches(&command.first_end().unwrap(), space, input) {
+                self.ranges[index] = Some(usize::MAX);
+            }
+            true
+        } else if self.address_matches(first, space, input) {
+            let line = self.line;
+            self.ranges[index] = match *last {
+                RangeEnd::Address(Address::Line(end)) => (end > line).then_some(end),
+                RangeEnd::Address(Address::Last) => (!input.is_last()).then_some(usize::MAX),
+                RangeEnd::Address(_) => Some(usize::MAX),
+                RangeEnd::Count(count) => (count > 0).then_some(line + count),
+                RangeEnd::Multiple(multiple) => (multiple > 0 && !line.is_multiple_of(multiple))
+                    .then(|| line.next_multiple_of(multiple)),
+            };
+            true
+        } else {
+            false
+        };
+
+        matched != command.negated
+    }
+
+    /// Run the `s` command. Returns whether there was a substitution.
+    fn substitute(&self, substitution: &Substitution, space: &mut Vec<char>) -> bool {
+        let mut result = Vec::new();
+        let mut copied = 0;
+        let mut start = 0;
+        let mut count = 0;
+        let mut replaced = false;
+        let mut previous_end = None;
+
+        while start <= space.len() {
+            let Some(caps) = substitution.regex.find_at(space, start) else {
+                break;
+            };
+            let (match_start, match_end) = caps[0].unwrap();
+
+            // An empty match right after the previous match isn't one.
+            if match_start == match_end && previous_end == Some(match_start) {
+                start = match_start + 1;
+                continue;
+            }
+            previous_end = Some(match_end);
+
+            count += 1;
+            if count >= substitution.occurrence {
+                result.extend_from_slice(&space[copied..match_start]);
+                self.replace(&substitution.replacement, space, &caps, &mut result);
+                copied = match_end;
+                replaced = true;
+
+                if !substitution.global {
+                    break;
+                }
+            }
+
+            start = if match_end > match_start {
+                match_end
+            } else {
+                match_end + 1
+            };
+        }
+
+        if replaced {
+            result.extend_from_slice(&space[copied..]);
+            *space = result;
+        }
+
+        replaced
+    }
+
+    fn replace(
+        &self,
+        replacement: &[Piece],
+        space: &[char],
+        caps: &Captures,
+        result: &mut Vec<char>,
+    ) {
+        let mut case = 'E';
+        let mut next_case = None;
+
+        for piece in replacement {
+            let text = match piece {
+                Piece::Text(text) => text.as_slice(),
+                Piece::Group(group) => match caps[*group] {
+                    Some((start, end)) => &space[start..end],
+                    None => &[],
+                },
+                Piece::Case(chr @ ('u' | 'l')) => {
+                    next_case = Some(*chr);
+                    continue;
+                }
+                Piece::Case(chr) => {
+                    case = *chr;
+                    next_case = None;
+                    continue;
+                }
+            };
+
+            for &chr in text {
+                match next_case.take().unwrap_or(case) {
+                    'U' | 'u' => result.extend(chr.to_uppercase()),
+                    'L' | 'l' => result.extend(chr.to_lowercase()),
+                    _ => result.push(chr),
+                }
+            }
+        }
+    }
+
+    /// Write the pattern space in the unambiguous form of `l`.
+    fn list(&self, space: &[char], width: usize, out: &mut Output) -> std::io::Result<()> {
+        let mut bytes = Vec::new();
+        encode(space, &mut bytes);
+
+        let mut listed = Vec::new();
+        let mut column = 0;
+        for &b in &bytes {
+            let escaped = match b {
+                b'\\' => "\\\\".to_owned(),
+                0x07 => "\\a".to_owned(),
+                0x08 => "\\b".to_owned(),
+                0x0c => "\\f".to_owned(),
+                b'\n' => "\\n".to_owned(),
+                b'\r' => "\\r".to_owned(),
+                b'\t' => "\\t".to_owned(),
+                0x0b => "\\v".to_owned(),
+                b' '..=b'~' => (b as char).to_string(),
+                _ => format!("\\{:03o}", b),
+            };
+
+            // Long lines are wrapped, ending with a backslash.
+            if width > 1 && column + escaped.len() > width - 1 {
+                listed.extend_from_slice(b"\\\n");
+                column = 0;
+            }
+
+            listed.extend_from_slice(escaped.as_bytes());
+            column += escaped.len();
+        }
+        listed.push(b'$');
+
+        out.write_line(&listed, Some(b'\n'))
+    }
+
+    /// Print the text queued by `a` and `r`.
+    fn flush_queued(&mut self, out: &mut Output) -> std::io::Result<()> {
+        for queued in std::mem::take(&mut self.queued) {
+            match queued {
+                Queued::Text(text) => out.write_line(&text, Some(b'\n'))?,
+                Queued::File(path) => {
+                    // A file that can't be read is silently ignored.
+                    if let Ok(contents) = std::fs::read(&path) {
+                        out.write_line(&contents, None)?;
+                        out.missing_terminator = !contents.is_empty() && !contents.ends_with(b"\n");
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_space(
+        &mut self,
+        space: &[char],
+        terminated: bool,
+        out: &mut Output,
+    ) -> std::io::Result<()> {
+        let mut bytes = Vec::new();
+        encode(space, &mut bytes);
+        out.write_line(&bytes, terminated.then_some(self.delimiter))
+    }
+
+    /// Run the script over the lines of `input`. Returns the exit code if
+    /// the script quit.
+    fn run(&mut self, input: &mut Input, out: &mut Output) -> std::io::Result<Option<i32>> {
+        // The commands are set apart while the state changes.
+        let commands = std::mem::take(&mut self.commands);
+        let result = self.run_commands(&commands, input, out);
+        self.commands = commands;
+
+        result
+    }
+
+    fn run_commands(
+        &mut self,
+        commands: &[Command],
+        input: &mut Input,
+        out: &mut Output,
+    ) -> std::io::Result<Option<i32>> {
+        let mut space = Vec::new();
+        let mut terminated = true;
+        let mut next_cycle_reads = true;
+        let mut substituted = false;
+
+        loop {
+            if next_cycle_reads {
+                let Some((line, line_terminated)) = input.next_line() else {
+                    return Ok(None);
+                };
+
+                space.clear();
+                decode(&line, &mut space);
+                terminated = line_terminated;
+                self.line += 1;
+                substituted = false;
+            }
+            next_cycle_reads = true;
+
+            let mut autoprint = !self.quiet;
+            let mut quit = None;
+            let mut index = 0;
+
+            while index < commands.len() {
+                let command = &commands[index];
+                if !self.selected(index, command, &space, input) {
+                    index = match command.action {
+                        Action::Block(end) => end,
+                        _ => index + 1,
+                    };
+                    continue;
+                }
+
+                index += 1;
+
+                match &command.action {
+                    Action::Block(_) | Action::EndBlock | Action::Label => {}
+                    Action::Substitute(substitution) => {
+                        if self.substitute(substitution, &mut space) {
+                            substituted = true;
+
+                            if substitution.print {
+                                self.write_space(&space, terminated, out)?;
+                            }
+                            if let Some(file) = substitution.write {
+                                self.write_to_file(file, &space, out)?;
+                            }
+                        }
+                    }
+                    Action::Translate(map) => {
+                        for chr in &mut space {
+                            if let Some(&mapped) = map.get(chr) {
+                                *chr = mapped;
+                            }
+                        }
+                    }
+                    Action::Delete => {
+                        autoprint = false;
+                        break;
+                    }
+                    Action::DeleteFirst => {
+                        autoprint = false;
+                        if let Some(newline) = space.iter().position(|&c| c == '\n') {
+                            // The cycle restarts without reading a line.
+                            space.drain(..=newline);
+                            next_cycle_reads = false;
+                        }
+                        break;
+                    }
+                    Action::Print => self.write_space(&space, terminated, out)?,
+                    Action::PrintFirst => match space.iter().position(|&c| c == '\n') {
+                        Some(end) => self.write_space(&space[..end], true, out)?,
+                        None => self.write_space(&space, terminated, out)?,
+                    },
+                    Action::Next | Action::AppendNext => {
+                        // Without a next line, the script ends, printing
+                        // the pattern space, as GNU sed does.
+                        if input.is_last() {
+                            break;
+                        }
+
+                        let append = matches!(command.action, Action::AppendNext);
+                        if !append && !self.quiet {
+                            self.write_space(&space, terminated, out)?;
+                        }
+                        self.flush_queued(out)?;
+
+                        let (line, line_terminated) = input.next_line().unwrap();
+                        if append {
+                            space.push('\n');
+                        } else {
+                            space.clear();
+                        }
+                        decode(&line, &mut space);
+                        terminated = line_terminated;
+                        self.line += 1;
+                    }
+                    Action::Hold => self.hold.clone_from(&space),
+                    Action::HoldAppend => {
+                        self.hold.push('\n');
+                        self.hold.extend_from_slice(&space);
+                    }
+                    Action::Get => space.clone_from(&self.hold),
+                    Action::GetAppend => {
+                        space.push('\n');
+                        space.extend_from_slice(&self.hold);
+                    }
+                    Action::Exchange => std::mem::swap(&mut space, &mut self.hold),
+                    Action::Zap => space.clear(),
+                    Action::Append(text) => self.queued.push(Queued::Text(text.clone())),
+                    Action::Insert(text) => out.write_line(text, Some(b'\n'))?,
+                    Action::Change(text) => {
+                        // In a range, the text replaces all the lines.
+                        if command.negated
+                            || command.last.is_none()
+                            || self.ranges[index - 1].is_none()
+                        {
+                            out.write_line(text, Some(b'\n'))?;
+                        }
+                        autoprint = false;
+                        break;
+                    }
+                    Action::ReadFile(path) => self.queued.push(Queued::File(path.clone())),
+                    Action::WriteFile(file) => self.write_to_file(*file, &space, out)?,
+                    Action::Quit(code) => {
+                        quit = Some(*code);
+                        break;
+                    }
+                    Action::QuitSilently(code) => {
+                        autoprint = false;
+                        quit = Some(*code);
+                        break;
+                    }
+                    Action::LineNumber => {
+                        out.write_line(self.line.to_string().as_bytes(), Some(b'\n'))?
+                    }
+                    Action::List(width) => {
+                        self.list(&space, width.unwrap_or(self.line_wrap), out)?;
+                    }
+                    Action::Branch(jump, target) => {
+                        let taken = match jump {
+                            Jump::Always => true,
+                            Jump::IfSubstituted => substituted,
+                            Jump::UnlessSubstituted => !substituted,
+                        };
+                        if !matches!(jump, Jump::Always) {
+                            substituted = false;
+                        }
+                        if taken {
+                            index = *target;
+                        }
+                    }
+                }
+            }
+
+            if autoprint {
+                self.write_space(&space, terminated, out)?;
+            }
+            self.flush_queued(out)?;
+
+            if quit.is_some() {
+                return Ok(quit);
+            }
+        }
+    }
+
+    /// Write the pattern space to a file of `w`, `/dev/stdout` being the output.
+    fn write_to_file(
+        &mut self,
+        file: usize,
+        space: &[char],
+        out: &mut Output,
+    ) -> std::io::Result<()> {
+        let mut bytes = Vec::new();
+        encode(space, &mut bytes);
+
+        match &mut self.write_files[file] {
+            Some(file) => file.write_line(&bytes, Some(self.delimiter)),
+            None => out.write_line(&bytes, Some(self.delimiter)),
+        }
+    }
+}
+
+impl Command {
+    /// The address ending the range `0,/re/`.
+    fn first_end(&self) -> Option<Address> {
+        match &self.last {
+            Some(RangeEnd::Address(Address::Match(regex))) => Some(Address::Match(regex.clone())),
+            _ => None,
+        }
+    }
+}
+
+/// The name of the backup of `path`, made from `suffix`, in which `*`
+/// stands for the file name.
+fn backup_path(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+
+    if !suffix.contains('*') {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(suffix);
+        return backup.into();
+    }
+
+    let backup = suffix.replace('*', &name);
+    if backup.contains('/') {
+        PathBuf::from(backup)
+    } else {
+        path.with_file_name(backup)
+    }
+}
+
+/// Edit a file in place: the output goes to a temporary file, renamed
+/// over the file at the end, so that the file is never partially written.
+fn edit_in_place(
+    editor: &mut Editor,
+    path: &str,
+    suffix: Option<&str>,
+) -> Result<Option<i32>, String> {
+    let file_path = Path::new(path);
+    let metadata = std::fs::metadata(file_path)
+        .map_err(|e| format!("can't read {}: {}", path, error_reason(&e)))?;
+    if !metadata.is_file() {
+        return Err(format!("couldn't edit {}: not a regular file", path));
+    }
+
+    let dir = match file_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let (temp, temp_path) = create_temp_file(dir, "sed").map_err(|e| {
+        format!(
+            "couldn't open temporary file {}/sedXXXXXX: {}",
+            dir.display(),
+            error_reason(&e)
+        )
+    })?;
+
+    let result = (|| -> std::io::Result<Option<i32>> {
+        temp.set_permissions(metadata.permissions())?;
+
+        // The owner is kept if possible.
+        let temp_path_c = std::ffi::CString::new(temp_path.as_os_str().as_bytes()).unwrap();
+        // SAFETY: the path is a valid C string.
+        unsafe { libc::chown(temp_path_c.as_ptr(), metadata.uid(), metadata.gid()) };
+
+        let mut input = Input::new(vec![path.to_owned()], editor.delimiter);
+        let mut out = Output::new(Box::new(BufWriter::new(temp)));
+
+        // Line numbers restart for every file.
+        editor.line = 0;
+        let quit = editor.run(&mut input, &mut out)?;
+        out.out.flush()?;
+
+        if let Some(suffix) = suffix {
+            let backup = backup_path(file_path, suffix);
+            let _ = std::fs::remove_file(&backup);
+            if std::fs::hard_link(file_path, &backup).is_err() {
+                std::fs::copy(file_path, &backup)?;
+            }
+        }
+
+        std::fs::rename(&temp_path, file_path)?;
+        Ok(quit)
+    })();
+
+    result.map_err(|e| {
+        let _ = std::fs::remove_file(&temp_path);
+        format!("couldn't edit {}: {}", path, error_reason(&e))
+    })
+}
+
+fn usage() -> ! {
+    eprintln!("Usage: sed [-n] [-E] [-s] [-z] [-i[SUFFIX]] [-l N] {{SCRIPT | -e SCRIPT... | -f FILE...}} [FILE]...");
+    std::process::exit(-180);
+}
+
+pub fn sed(args: &[String]) {
+    let mut scripts = Vec::new();
+    let mut quiet = false;
+    let mut extended = false;
+    let mut separate = false;
+    let mut in_place = None;
+    let mut delimiter = b'\n';
+    let mut line_wrap = 70;
+    let mut files = Vec::new();
+
+    let add_script_file = |path: &str, scripts: &mut Vec<String>| {
+        let contents = if path == "-" {
+            std::io::read_to_string(std::io::stdin())
+        } else {
+            std::fs::read_to_string(path)
+        };
+
+        match contents {
+            Ok(contents) => {
+                scripts.push(contents.strip_suffix('\n').unwrap_or(&contents).to_owned())
+            }
+            Err(e) => {
+                eprintln!("sed: couldn't open file {}: {}", path, error_reason(&e));
+                std::process::exit(-180);
+            }
+        }
+    };
+
+    // The options are parsed here, as `-i` takes an optional value.
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            files.extend(args.by_ref().cloned());
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, value) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_owned())),
+                None => (long, None),
+            };
+            let mut value = || {
+                value
+                    .clone()
+                    .or_else(|| args.next().cloned())
+                    .unwrap_or_else(|| usage())
+            };
+
+            match name {
+                "quiet" | "silent" => quiet = true,
+                "regexp-extended" => extended = true,
+                "separate" => separate = true,
+                "null-data" | "zero-terminated" => delimiter = b'\0',
+                "in-place" => {
+                    in_place = Some(long.split_once('=').map(|(_, suffix)| suffix.to_owned()))
+                }
+                "expression" => scripts.push(value()),
+                "file" => add_script_file(&value(), &mut scripts),
+                "line-length" => line_wrap = value().parse().unwrap_or_else(|_| usage()),
+                "posix" | "unbuffered" | "debug" | "sandbox" => {}
+                _ => {
+                    println!("Invalid command");
+                    std::process::exit(-180);
+                }
+            }
+            continue;
+        }
+
+        let Some(flags) = arg.strip_prefix('-').filter(|flags| !flags.is_empty()) else {
+            files.push(arg.clone());
+            continue;
+        };
+
+        for (i, flag) in flags.char_indices() {
+            let rest = &flags[i + 1..];
+            let mut value = || {
+                if rest.is_empty() {
+                    args.next().cloned().unwrap_or_else(|| usage())
+                } else {
+                    rest.to_owned()
+                }
+            };
+
+            match flag {
+                'n' => quiet = true,
+                'E' | 'r' => extended = true,
+                's' => separate = true,
+                'z' => delimiter = b'\0',
+                'u' => {}
+                'i' => {
+                    // The rest of the group is the suffix.
+                    in_place = Some((!rest.is_empty()).then(|| rest.to_owned()));
+                    break;
+                }
+                'e' => {
+                    scripts.push(value());
+                    break;
+                }
+                'f' => {
+                    add_script_file(&value(), &mut scripts);
+                    break;
+                }
+                'l' => {
+                    line_wrap = value().parse().unwrap_or_else(|_| usage());
+                    break;
+                }
+                _ => {
+                    println!("Invalid command");
+                    std::process::exit(-180);
+                }
+            }
+        }
+    }
+
+    // Without `-e` nor `-f`, the script is the first operand.
+    if scripts.is_empty() {
+        if files.is_empty() {
+            usage();
+        }
+        scripts.push(files.remove(0));
+    }
+
+    let mut write_paths = Vec::new();
+    let parser = ScriptParser {
+        chars: scripts.join("\n").chars().collect(),
+        pos: 0,
+        extended,
+        last_regex: None,
+        commands: Vec::new(),
+        blocks: Vec::new(),
+        labels: HashMap::new(),
+        branches: Vec::new(),
+        write_files: &mut write_paths,
+    };
+    let commands = parser.parse();
+
+    // The files of `w` are created before any input is read.
+    let write_files = write_paths
+        .iter()
+        .map(|path| match path.as_str() {
+            "/dev/stdout" => None,
+            "/dev/stderr" => Some(Output::new(Box::new(std::io::stderr()))),
+            _ => match File::create(path) {
+                Ok(file) => Some(Output::new(Box::new(BufWriter::new(file)))),
+                Err(e) => {
+                    eprintln!("sed: couldn't open file {}: {}", path, error_reason(&e));
+                    std::process::exit(-180);
+                }
+            },
+        })
+        .collect();
+
+    let mut editor = Editor {
+        ranges: commands.iter().map(|_| None).collect(),
+        commands,
+        quiet,
+        delimiter,
+        line_wrap,
+        hold: Vec::new(),
+        write_files,
+        queued: Vec::new(),
+        line: 0,
+    };
+
+    if files.is_empty() {
+        if in_place.is_some() {
+            eprintln!("sed: no input files");
+            std::process::exit(-180);
+        }
+        files.push(String::from("-"));
+    }
+
+    let mut was_error = false;
+    let mut quit = None;
+
+    if let Some(suffix) = &in_place {
+        for file in &files {
+            match edit_in_place(&mut editor, file, suffix.as_deref()) {
+                Ok(code) => quit = code,
+                Err(message) => {
+                    eprintln!("sed: {}", message);
+                    was_error = true;
+                }
+            }
+
+            if quit.is_some() {
+                break;
+            }
+        }
+    } else {
+        let mut out = Output::new(Box::new(BufWriter::new(std::io::stdout().lock())));
+
+        // With `-s`, every file is a separate stream, with its own lines.
+        let streams = if separate {
+            files.into_iter().map(|file| vec![file]).collect()
+        } else {
+            vec![files]
+        };
+
+        for stream in streams {
+            let mut input = Input::new(stream, delimiter);
+            editor.line = 0;
+
+            let result = editor.run(&mut input, &mut out);
+            was_error |= input.was_error;
+
+            match result {
+                Ok(code) => quit = code,
+                Err(e) => {
+                    eprintln!("sed: couldn't write: {}", error_reason(&e));
+                    std::process::exit(-180);
+                }
+            }
+
+            if quit.is_some() {
+                break;
+            }
+        }
+
+        if let Err(e) = out.out.flush() {
+            eprintln!("sed: couldn't flush stdout: {}", error_reason(&e));
+            was_error = true;
+        }
+    }
+
+    for file in editor.write_files.iter_mut().flatten() {
+        if let Err(e) = file.out.flush() {
+            eprintln!("sed: couldn't flush: {}", error_reason(&e));
+            was_error = true;
+        }
+    }
+
+    if was_error {
+        std::process::exit(-180);
+    }
+    if let Some(code) = quit {
+        std::process::exit(code);
+    }
+}
@@ -0,0 +1,1042 @@
+//! The `sort` command. Inputs that don't fit in the buffer are sorted in
+//! runs, which are spilled to temporary files and merged at the end.
+
+use std::{
+    cmp::Ordering,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Seek, Write},
+    ops::Range,
+    os::unix::prelude::MetadataExt,
+    path::{Path, PathBuf},
+};
+
+use crate::fields::*;
+use crate::utils::*;
+
+/// How many runs are merged at once. With more of them, they're merged
+/// in more passes, so there aren't too many files open.
+const MAX_MERGE: usize = 32;
+
+/// How the keys are compared.
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    Text,
+    /// `-n`: decimal numbers, of any size.
+    Numeric,
+    /// `-g`: floating point numbers.
+    General,
+    /// `-h`: numbers with a unit suffix, like `2K` or `1G`.
+    Human,
+    /// `-V`: version numbers.
+    Version,
+    /// `-M`: month names.
+    Month,
+}
+
+/// The ordering options of a key, or of the whole line.
+#[derive(Clone, Copy, PartialEq)]
+struct KeyOptions {
+    kind: Kind,
+    reverse: bool,
+    fold_case: bool,
+    ignore_blanks: bool,
+}
+
+impl KeyOptions {
+    const DEFAULT: KeyOptions = KeyOptions {
+        kind: Kind::Text,
+        reverse: false,
+        fold_case: false,
+        ignore_blanks: false,
+    };
+
+    /// Apply the option given by the letter `opt` (as in `-k2n` or `-n`).
+    /// Returns false if it's not an ordering option.
+    fn set(&mut self, opt: char) -> bool {
+        match opt {
+            'n' => self.kind = Kind::Numeric,
+            'g' => self.kind = Kind::General,
+            'h' => self.kind = Kind::Human,
+            'V' => self.kind = Kind::Version,
+            'M' => self.kind = Kind::Month,
+            'r' => self.reverse = true,
+            'f' => self.fold_case = true,
+            'b' => self.ignore_blanks = true,
+            _ => return false,
+        }
+
+        true
+    }
+}
+
+/// A sort key given by `-k FIELD[.CHAR][OPTS][,FIELD[.CHAR][OPTS]]`,
+/// with fields and characters counted from 0.
+struct Key {
+    start_field: usize,
+    start_char: usize,
+    /// The last field, and the last character in it (`None` for
+    /// the end of the field). Without it, the key ends with the line.
+    end: Option<(usize, Option<usize>)>,
+    options: KeyOptions,
+}
+
+/// Parse a key definition, taking the global ordering options
+/// if it has none of its own.
+fn parse_key(key: &str, global: KeyOptions) -> Option<Key> {
+    let (start, end) = match key.split_once(',') {
+        Some((start, end)) => (start, Some(end)),
+        None => (key, None),
+    };
+
+    let mut options = KeyOptions::DEFAULT;
+    let mut has_options = false;
+
+    // Split a position into its numbers and the ordering options after them.
+    let mut parse_position = |position: &str| -> Option<(usize, Option<usize>)> {
+        let numbers_end = position
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(position.len());
+        let (numbers, opts) = position.split_at(numbers_end);
+
+        for opt in opts.chars() {
+            if !options.set(opt) {
+                return None;
+            }
+            has_options = true;
+        }
+
+        match numbers.split_once('.') {
+            Some((field, chr)) => Some((field.parse().ok()?, Some(chr.parse().ok()?))),
+            None => Some((numbers.parse().ok()?, None)),
+        }
+    };
+
+    let (start_field, start_char) = parse_position(start)?;
+    let end = match end.map(&mut parse_position) {
+        Some(Some((0, _))) => return None,
+        // The character 0 of the last field means its end.
+        Some(Some((field, chr))) => Some((field - 1, chr.filter(|&chr| chr != 0))),
+        Some(None) => return None,
+        None => None,
+    };
+
+    if start_field == 0 || start_char == Some(0) {
+        return None;
+    }
+
+    Some(Key {
+        start_field: start_field - 1,
+        start_char: start_char.unwrap_or(1) - 1,
+        end,
+        options: if has_options { options } else { global },
+    })
+}
+
+/// Split a decimal number into its sign, and its integer and fractional
+/// digits, without insignificant zeros.
+fn split_number(key: &[u8]) -> (bool, &[u8], &[u8]) {
+    let key = &key[skip_blanks(key, 0)..];
+    let (negative, key) = match key.first() {
+        Some(b'-') => (true, &key[1..]),
+        _ => (false, key),
+    };
+
+    let int_len = key.iter().take_while(|b| b.is_ascii_digit()).count();
+    let int = &key[..int_len];
+    let frac = match key.get(int_len) {
+        Some(b'.') => {
+            let frac = &key[int_len + 1..];
+            &frac[..frac.iter().take_while(|b| b.is_ascii_digit()).count()]
+        }
+        _ => &key[..0],
+    };
+
+    let int = &int[int.iter().take_while(|&&b| b == b'0').count()..];
+    let frac = &frac[..frac.len() - frac.iter().rev().take_while(|&&b| b == b'0').count()];
+
+    // There is no negative zero.
+    let negative = negative && !(int.is_empty() && frac.is_empty());
+    (negative, int, frac)
+}
+
+/// Compare two decimal numbers, digit by digit, so they can have any size.
+fn compare_numbers(a: &[u8], b: &[u8]) -> Ordering {
+    let (a_negative, a_int, a_frac) = split_number(a);
+    let (b_negative, b_int, b_frac) = split_number(b);
+
+    let magnitude = a_int
+        .len()
+        .cmp(&b_int.len())
+        .then_with(|| a_int.cmp(b_int))
+        .then_with(|| a_frac.cmp(b_frac));
+
+    match (a_negative, b_negative) {
+        (false, false) => magnitude,
+        (true, true) => magnitude.reverse(),
+        (true, false) => Ordering::Less,
+        (false, true) => Ordering::Greater,
+    }
+}
+
+/// Compare numbers with unit suffixes: first by their sign,
+/// then by their unit, and then by their value.
+fn compare_human(a: &[u8], b: &[u8]) -> Ordering {
+    let parse = |key: &[u8]| {
+        let (negative, int, frac) = split_number(key);
+        let sign = if int.is_empty() && frac.is_empty() {
+            0
+        } else if negative {
+            -1
+        } else {
+            1
+        };
+
+        let key = &key[skip_blanks(key, 0)..];
+        let number_len = key
+            .iter()
+            .enumerate()
+            .take_while(|&(i, &b)| b.is_ascii_digit() || b == b'.' || (i == 0 && b == b'-'))
+            .count();
+        let unit = key
+            .get(number_len)
+            .and_then(|&unit| {
+                b"KMGTPEZYRQ"
+                    .iter()
+                    .position(|&u| u == unit.to_ascii_uppercase())
+            })
+            .map_or(0, |unit| unit + 1);
+
+        (sign, unit)
+    };
+
+    let (a_sign, a_unit) = parse(a);
+    let (b_sign, b_unit) = parse(b);
+
+    let units = if a_sign < 0 {
+        b_unit.cmp(&a_unit)
+    } else {
+        a_unit.cmp(&b_unit)
+    };
+
+    a_sign
+        .cmp(&b_sign)
+        .then(units)
+        .then_with(|| compare_numbers(a, b))
+}
+
+/// Parse the floating point number at the start of `key`, like `strtod`.
+fn parse_float(key: &[u8]) -> Option<f64> {
+    let key = &key[skip_blanks(key, 0)..];
+    let mut len = 0;
+
+    let digits = |from: usize| {
+        key[from..]
+            .iter()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+
+    if matches!(key.first(), Some(b'-' | b'+')) {
+        len += 1;
+    }
+
+    let text = std::str::from_utf8(&key[len..key.len().min(len + 8)]).unwrap_or_default();
+    let text = text.to_ascii_lowercase();
+    for special in ["infinity", "inf", "nan"] {
+        if text.starts_with(special) {
+            return std::str::from_utf8(&key[..len + special.len()])
+                .ok()?
+                .parse()
+                .ok();
+        }
+    }
+
+    let int_len = digits(len);
+    len += int_len;
+    let mut frac_len = 0;
+    if key.get(len) == Some(&b'.') {
+        frac_len = digits(len + 1);
+        len += 1 + frac_len;
+    }
+
+    if int_len == 0 && frac_len == 0 {
+        return None;
+    }
+
+    if matches!(key.get(len), Some(b'e' | b'E')) {
+        let sign = matches!(key.get(len + 1), Some(b'-' | b'+')) as usize;
+        let exponent_len = digits(len + 1 + sign);
+        if exponent_len > 0 {
+            len += 1 + sign + exponent_len;
+        }
+    }
+
+    std::str::from_utf8(&key[..len]).ok()?.parse().ok()
+}
+
+/// Compare floating point numbers. Keys that aren't numbers come first,
+/// then NaNs, and then the numbers.
+fn compare_floats(a: &[u8], b: &[u8]) -> Ordering {
+    let rank = |number: Option<f64>| match number {
+        None => 0,
+        Some(number) if number.is_nan() => 1,
+        Some(_) => 2,
+    };
+
+    let (a, b) = (parse_float(a), parse_float(b));
+    match (a, b) {
+        (Some(a), Some(b)) if !a.is_nan() && !b.is_nan() => a.total_cmp(&b),
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+/// Get the month (from 1 to 12) whose name `key` starts with, or 0.
+fn month(key: &[u8]) -> usize {
+    const MONTHS: [&[u8]; 12] = [
+        b"JAN", b"FEB", b"MAR", b"APR", b"MAY", b"JUN", b"JUL", b"AUG", b"SEP", b"OCT", b"NOV",
+        b"DEC",
+    ];
+
+    let key = &key[skip_blanks(key, 0)..];
+    key.get(..3)
+        .and_then(|name| {
+            MONTHS
+                .iter()
+                .position(|month| month.eq_ignore_ascii_case(name))
+        })
+        .map_or(0, |month| month + 1)
+}
+
+/// Compare version numbers, like Debian's `dpkg` does: the digits are
+/// compared as numbers, the other characters as text, except that letters
+/// come before other characters and `~` comes before anything, even the end.
+fn compare_versions(a: &[u8], b: &[u8]) -> Ordering {
+    let order = |chr: Option<&u8>| -> i32 {
+        match chr {
+            None => 0,
+            Some(b'~') => -1,
+            Some(chr) if chr.is_ascii_digit() => 0,
+            Some(chr) if chr.is_ascii_alphabetic() => *chr as i32,
+            Some(chr) => *chr as i32 + 256,
+        }
+    };
+
+    let (mut i, mut j) = (0, 0);
+
+    while i < a.len() || j < b.len() {
+        // Compare the text up to the next digits.
+        while (i < a.len() && !a[i].is_ascii_digit()) || (j < b.len() && !b[j].is_ascii_digit()) {
+            let (a_order, b_order) = (order(a.get(i)), order(b.get(j)));
+            if a_order != b_order {
+                return a_order.cmp(&b_order);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        while a.get(i) == Some(&b'0') {
+            i += 1;
+        }
+        while b.get(j) == Some(&b'0') {
+            j += 1;
+        }
+
+        // A longer number is bigger, otherwise the first digit that
+        // differs decides it.
+        let mut first_diff = Ordering::Equal;
+        while a.get(i).is_some_and(u8::is_ascii_digit) && b.get(j).is_some_and(u8::is_ascii_digit) {
+            if first_diff == Ordering::Equal {
+                first_diff = a[i].cmp(&b[j]);
+            }
+            i += 1;
+            j += 1;
+        }
+
+        if a.get(i).is_some_and(u8::is_ascii_digit) {
+            return Ordering::Greater;
+        }
+        if b.get(j).is_some_and(u8::is_ascii_digit) {
+            return Ordering::Less;
+        }
+        if first_diff != Ordering::Equal {
+            return first_diff;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Compare text, optionally ignoring the case.
+fn compare_text(a: &[u8], b: &[u8], fold_case: bool) -> Ordering {
+    if !fold_case {
+        return a.cmp(b);
+    }
+
+    a.iter()
+        .map(u8::to_ascii_uppercase)
+        .cmp(b.iter().map(u8::to_ascii_uppercase))
+}
+
+/// Everything that decides the order of the lines.
+pub struct Comparator {
+    keys: Vec<Key>,
+    /// The options used for the whole line, if there are no keys.
+    global: KeyOptions,
+    separator: Option<u8>,
+    stable: bool,
+    unique: bool,
+}
+
+impl Comparator {
+    /// Get the part of `line` covered by `key`.
+    fn key<'a>(&self, key: &Key, line: &'a [u8]) -> &'a [u8] {
+        let blanks = key.options.ignore_blanks;
+
+        let mut start = field_start(line, self.separator, key.start_field);
+        if blanks {
+            start = skip_blanks(line, start);
+        }
+        let start = (start + key.start_char).min(line.len());
+
+        let end = match key.end {
+            None => line.len(),
+            Some((field, chr)) => {
+                let field_start = field_start(line, self.separator, field);
+                let field_end = field_end(line, self.separator, field_start);
+
+                match chr {
+                    None => field_end,
+                    Some(chr) => {
+                        let from = if blanks {
+                            skip_blanks(line, field_start)
+                        } else {
+                            field_start
+                        };
+                        (from + chr).min(line.len())
+                    }
+                }
+            }
+        };
+
+        &line[start..end.max(start)]
+    }
+
+    /// Compare the keys of two lines, or the lines themselves
+    /// if there are no keys.
+    fn compare_keys(&self, a: &[u8], b: &[u8]) -> Ordering {
+        let whole_line = [Key {
+            start_field: 0,
+            start_char: 0,
+            end: None,
+            options: self.global,
+        }];
+        let keys = if self.keys.is_empty() {
+            &whole_line[..]
+        } else {
+            &self.keys
+        };
+
+        for key in keys {
+            let (mut a, mut b) = (self.key(key, a), self.key(key, b));
+            let options = key.options;
+
+            if options.ignore_blanks {
+                a = &a[skip_blanks(a, 0)..];
+                b = &b[skip_blanks(b, 0)..];
+            }
+
+            let order = match options.kind {
+                Kind::Text => compare_text(a, b, options.fold_case),
+                Kind::Numeric => compare_numbers(a, b),
+                Kind::General => compare_floats(a, b),
+                Kind::Human => compare_human(a, b),
+                Kind::Version => compare_versions(a, b),
+                Kind::Month => month(a).cmp(&month(b)),
+            };
+
+            let order = if options.reverse {
+                order.reverse()
+            } else {
+                order
+            };
+
+            if order != Ordering::Equal {
+                return order;
+            }
+        }
+
+        Ordering::Equal
+    }
+
+    /// Compare two lines. When their keys are equal, the whole lines are
+    /// compared, unless the sort is stable or only unique lines are kept.
+    pub fn compare(&self, a: &[u8], b: &[u8]) -> Ordering {
+        let order = self.compare_keys(a, b);
+        if order != Ordering::Equal || self.stable || self.unique {
+            return order;
+        }
+
+        if self.global.reverse {
+            b.cmp(a)
+        } else {
+            a.cmp(b)
+        }
+    }
+}
+
+/// Writes the sorted lines, leaving out the duplicates with `-u`.
+struct Output<'a, W: Write> {
+    out: W,
+    delimiter: u8,
+    comparator: &'a Comparator,
+    last: Option<Vec<u8>>,
+}
+
+impl<W: Write> Output<'_, W> {
+    fn write(&mut self, line: &[u8]) -> std::io::Result<()> {
+        if self.comparator.unique {
+            if let Some(last) = &mut self.last {
+                if self.comparator.compare_keys(last, line) == Ordering::Equal {
+                    return Ok(());
+                }
+                last.clear();
+                last.extend_from_slice(line);
+            } else {
+                self.last = Some(line.to_vec());
+            }
+        }
+
+        self.out.write_all(line)?;
+        self.out.write_all(&[self.delimiter])
+    }
+}
+
+/// Create an anonymous temporary file in `dir`, deleted as soon as
+/// it's closed.
+fn temp_file(dir: &Path) -> std::io::Result<File> {
+    let (file, path) = create_temp_file(dir, "rustybox-sort")?;
+    std::fs::remove_file(path)?;
+
+    Ok(file)
+}
+
+/// Lines read into a single buffer, to be sorted in memory.
+struct Chunk {
+    data: Vec<u8>,
+    lines: Vec<Range<usize>>,
+}
+
+impl Chunk {
+    /// How much memory the chunk takes.
+    fn size(&self) -> usize {
+        self.data.len() + self.lines.len() * std::mem::size_of::<Range<usize>>()
+    }
+
+    /// Sort the lines, splitting them between `threads` threads.
+    fn sort(&mut self, comparator: &Comparator, threads: usize) {
+        let data = &self.data;
+        let compare = |a: &Range<usize>, b: &Range<usize>| {
+            comparator.compare(&data[a.clone()], &data[b.clone()])
+        };
+
+        let part_len = self.lines.len().div_ceil(threads.max(1)).max(1);
+        if threads <= 1 || self.lines.len() < 2 * part_len.max(1024) {
+            self.lines.sort_by(compare);
+            return;
+        }
+
+        std::thread::scope(|scope| {
+            for part in self.lines.chunks_mut(part_len) {
+                scope.spawn(move || part.sort_by(compare));
+            }
+        });
+
+        // Merge the sorted parts, two by two, preferring the earlier
+        // part on ties, so the sort stays stable.
+        let mut parts = self
+            .lines
+            .chunks(part_len)
+            .map(<[_]>::to_vec)
+            .collect::<Vec<_>>();
+
+        while parts.len() > 1 {
+            let mut merged = Vec::with_capacity(parts.len().div_ceil(2));
+            let mut pairs = parts.into_iter();
+
+            while let Some(left) = pairs.next() {
+                let Some(right) = pairs.next() else {
+                    merged.push(left);
+                    break;
+                };
+
+                let mut out = Vec::with_capacity(left.len() + right.len());
+                let (mut left, mut right) =
+                    (left.into_iter().peekable(), right.into_iter().peekable());
+                while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+                    if compare(r, l) == Ordering::Less {
+                        out.extend(right.next());
+                    } else {
+                        out.extend(left.next());
+                    }
+                }
+                out.extend(left);
+                out.extend(right);
+
+                merged.push(out);
+            }
+
+            parts = merged;
+        }
+
+        self.lines = parts.pop().unwrap_or_default();
+    }
+}
+
+/// Merge sorted inputs, writing each line in order with `write`.
+/// On ties, the earlier input comes first.
+fn merge(
+    mut inputs: Vec<Box<dyn BufRead>>,
+    comparator: &Comparator,
+    delimiter: u8,
+    mut write: impl FnMut(&[u8]) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut lines = vec![Vec::new(); inputs.len()];
+
+    // A binary heap of the inputs, by their current lines.
+    let mut heap = Vec::new();
+    for (i, input) in inputs.iter_mut().enumerate() {
+        if read_line(input.as_mut(), delimiter, &mut lines[i])? {
+            heap.push(i);
+        }
+    }
+
+    let less = |lines: &[Vec<u8>], a: usize, b: usize| {
+        comparator.compare(&lines[a], &lines[b]).then(a.cmp(&b)) == Ordering::Less
+    };
+
+    let sift_down = |heap: &mut [usize], lines: &[Vec<u8>], mut pos: usize| loop {
+        let mut smallest = pos;
+        for child in [2 * pos + 1, 2 * pos + 2] {
+            if child < heap.len() && less(lines, heap[child], heap[smallest]) {
+                smallest = child;
+            }
+        }
+
+        if smallest == pos {
+            return;
+        }
+        heap.swap(pos, smallest);
+        pos = smallest;
+    };
+
+    for pos in (0..heap.len() / 2).rev() {
+        sift_down(&mut heap, &lines, pos);
+    }
+
+    while let Some(&first) = heap.first() {
+        write(&lines[first])?;
+
+        if !read_line(inputs[first].as_mut(), delimiter, &mut lines[first])? {
+            heap.swap_remove(0);
+        }
+        sift_down(&mut heap, &lines, 0);
+    }
+
+    Ok(())
+}
+
+/// The settings of a sort.
+struct Sorter {
+    comparator: Comparator,
+    delimiter: u8,
+    buffer_size: usize,
+    threads: usize,
+    temp_dir: PathBuf,
+    /// The sorted runs spilled to temporary files, by level: when a level
+    /// has `MAX_MERGE` runs, they're merged into a run of the next one. The
+    /// runs of a level are older than those of the levels below, so the
+    /// lines stay in input order for `-s` and `-u`.
+    levels: Vec<Vec<File>>,
+}
+
+impl Sorter {
+    /// Sort a chunk and write it to a new run.
+    fn spill(&mut self, chunk: &mut Chunk) -> std::io::Result<()> {
+        chunk.sort(&self.comparator, self.threads);
+
+        let mut file = temp_file(&self.temp_dir)?;
+        let mut out = Output {
+            out: BufWriter::new(&mut file),
+            delimiter: self.delimiter,
+            comparator: &self.comparator,
+            last: None,
+        };
+        for line in &chunk.lines {
+            out.write(&chunk.data[line.clone()])?;
+        }
+        out.out.flush()?;
+        drop(out);
+
+        file.rewind()?;
+        self.add_run(file)?;
+
+        chunk.data.clear();
+        chunk.lines.clear();
+
+        Ok(())
+    }
+
+    /// Add a run to the first level, merging the levels that get full.
+    fn add_run(&mut self, mut run: File) -> std::io::Result<()> {
+        for level in 0.. {
+            if level == self.levels.len() {
+                self.levels.push(Vec::new());
+            }
+
+            self.levels[level].push(run);
+            if self.levels[level].len() < MAX_MERGE {
+                break;
+            }
+
+            let group = std::mem::take(&mut self.levels[level]);
+            run = self.merge_runs(group)?;
+        }
+
+        Ok(())
+    }
+
+    /// Merge runs into a new one.
+    fn merge_runs(&self, runs: Vec<File>) -> std::io::Result<File> {
+        let mut file = temp_file(&self.temp_dir)?;
+        let mut out = BufWriter::new(&mut file);
+        let inputs = runs
+            .into_iter()
+            .map(|run| Box::new(BufReader::new(run)) as Box<dyn BufRead>)
+            .collect();
+
+        merge(inputs, &self.comparator, self.delimiter, |line| {
+            out.write_all(line)?;
+            out.write_all(&[self.delimiter])
+        })?;
+        out.flush()?;
+        drop(out);
+
+        file.rewind()?;
+        Ok(file)
+    }
+
+    fn has_runs(&self) -> bool {
+        self.levels.iter().any(|level| !level.is_empty())
+    }
+}
+
+/// Check that a file is sorted, reporting the first line out of order
+/// (unless `quiet`). Returns false if it's not.
+fn check(
+    input: &mut dyn BufRead,
+    name: &str,
+    comparator: &Comparator,
+    delimiter: u8,
+    quiet: bool,
+) -> std::io::Result<bool> {
+    let mut last = Vec::new();
+    let mut line = Vec::new();
+    let mut number = 0;
+
+    while read_line(input, delimiter, &mut line)? {
+        number += 1;
+
+        if number > 1 {
+            let order = comparator.compare(&last, &line);
+            if order == Ordering::Greater || (comparator.unique && order == Ordering::Equal) {
+                if !quiet {
+                    eprintln!(
+                        "sort: {}:{}: disorder: {}",
+                        name,
+                        number,
+                        String::from_utf8_lossy(&line)
+                    );
+                }
+                return Ok(false);
+            }
+        }
+
+        std::mem::swap(&mut last, &mut line);
+    }
+
+    Ok(true)
+}
+
+/// Parse the buffer size given to `-S`: a number of KiB by default, or
+/// with a suffix (`b` for bytes, `%` for a percentage of the memory).
+fn parse_buffer_size(size: &str) -> Option<usize> {
+    if let Some(percent) = size.strip_suffix('%') {
+        // SAFETY: `sysconf` has no preconditions.
+        let pages = unsafe { libc::sysconf(libc::_SC_PHYS_PAGES) };
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        let memory = (pages.max(0) as u64).saturating_mul(page_size.max(0) as u64);
+
+        let percent = percent
+            .parse::<f64>()
+            .ok()
+            .filter(|p| (0.0..=100.0).contains(p))?;
+        return Some((memory as f64 * percent / 100.0) as usize);
+    }
+
+    let size = match size.strip_suffix('b') {
+        Some(bytes) => bytes.parse().ok()?,
+        None if size.bytes().all(|b| b.is_ascii_digit()) => size.parse::<u64>().ok()? * 1024,
+        None => parse_size(size)?,
+    };
+
+    usize::try_from(size).ok()
+}
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("sort: {}", message);
+    std::process::exit(-150);
+}
+
+pub fn sort(args: &[String]) {
+    let with_value = [
+        "-k",
+        "--key",
+        "-t",
+        "--field-separator",
+        "-o",
+        "--output",
+        "-S",
+        "--buffer-size",
+        "-T",
+        "--temporary-directory",
+        "--parallel",
+    ];
+    let Some((opts, files)) = parse_options(args, &with_value) else {
+        eprintln!("Usage: sort [-bcCfghMmnrsuVz] [-k KEY]... [-t SEP] [-o FILE] [FILE]...");
+        std::process::exit(-150);
+    };
+
+    let mut global = KeyOptions::DEFAULT;
+    let mut key_defs = Vec::new();
+    let mut separator = None;
+    let mut output = None;
+    let mut stable = false;
+    let mut unique = false;
+    let mut check_mode = None;
+    let mut merge_only = false;
+    let mut delimiter = b'\n';
+    let mut buffer_size = 256 << 20;
+    let mut temp_dir = std::env::var_os("TMPDIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("/tmp"));
+    let mut threads = 1;
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+
+        match opt.as_str() {
+            "-k" | "--key" => key_defs.push(value),
+            "-t" | "--field-separator" => match value.as_bytes() {
+                [separator_byte] => separator = Some(*separator_byte),
+                b"\\0" => separator = Some(b'\0'),
+                _ => fail(format_args!("invalid field separator '{}'", value)),
+            },
+            "-o" | "--output" => output = Some(value),
+            "-S" | "--buffer-size" => match parse_buffer_size(&value) {
+                Some(size) => buffer_size = size.max(1),
+                None => fail(format_args!("invalid buffer size '{}'", value)),
+            },
+            "-T" | "--temporary-directory" => temp_dir = PathBuf::from(value),
+            "--parallel" => match value.parse::<usize>() {
+                // Like GNU sort, don't use more threads than processors.
+                Ok(count) if count > 0 => {
+                    let processors = std::thread::available_parallelism().map_or(1, usize::from);
+                    threads = count.min(processors);
+                }
+                _ => fail(format_args!("invalid number of threads '{}'", value)),
+            },
+            "-s" | "--stable" => stable = true,
+            "-u" | "--unique" => unique = true,
+            "-c" | "--check" => check_mode = Some(false),
+            "-C" => check_mode = Some(true),
+            "-m" | "--merge" => merge_only = true,
+            "-z" | "--zero-terminated" => delimiter = b'\0',
+            "--numeric-sort" => global.kind = Kind::Numeric,
+            "--general-numeric-sort" => global.kind = Kind::General,
+            "--human-numeric-sort" => global.kind = Kind::Human,
+            "--version-sort" => global.kind = Kind::Version,
+            "--month-sort" => global.kind = Kind::Month,
+            "--reverse" => global.reverse = true,
+            "--ignore-case" => global.fold_case = true,
+            "--ignore-leading-blanks" => global.ignore_blanks = true,
+            _ => {
+                let mut chars = opt.chars();
+                let flag = match (chars.next(), chars.next(), chars.next()) {
+                    (Some('-'), Some(flag), None) => flag,
+                    _ => '\0',
+                };
+
+                if !global.set(flag) {
+                    println!("Invalid command");
+                    std::process::exit(-150);
+                }
+            }
+        }
+    }
+
+    let keys = key_defs
+        .iter()
+        .map(|key| {
+            parse_key(key, global).unwrap_or_else(|| fail(format_args!("invalid key '{}'", key)))
+        })
+        .collect();
+
+    let comparator = Comparator {
+        keys,
+        global,
+        separator,
+        stable,
+        unique,
+    };
+
+    let stdin = String::from("-");
+    let files = if files.is_empty() {
+        vec![&stdin]
+    } else {
+        files
+    };
+
+    let open = |file: &String| -> Box<dyn BufRead> {
+        open_input(file)
+            .unwrap_or_else(|e| fail(format_args!("cannot read: {}: {}", file, error_reason(&e))))
+    };
+
+    if let Some(quiet) = check_mode {
+        if files.len() > 1 {
+            fail(format_args!(
+                "extra operand '{}' not allowed with -c",
+                files[1]
+            ));
+        }
+
+        match check(
+            open(files[0]).as_mut(),
+            files[0],
+            &comparator,
+            delimiter,
+            quiet,
+        ) {
+            Ok(true) => return,
+            Ok(false) => std::process::exit(1),
+            Err(e) => fail(format_args!("{}: {}", files[0], error_reason(&e))),
+        }
+    }
+
+    let mut sorter = Sorter {
+        comparator,
+        delimiter,
+        buffer_size,
+        threads,
+        temp_dir,
+        levels: Vec::new(),
+    };
+
+    let mut chunk = Chunk {
+        data: Vec::new(),
+        lines: Vec::new(),
+    };
+
+    let spill_error = |e: std::io::Error| -> ! {
+        fail(format_args!(
+            "cannot create temporary file: {}",
+            error_reason(&e)
+        ))
+    };
+
+    let output_id = output
+        .as_ref()
+        .and_then(|output| std::fs::metadata(output).ok())
+        .map(|metadata| (metadata.dev(), metadata.ino()));
+
+    // The inputs to merge: either the files themselves (with `-m`),
+    // or the sorted runs of their lines.
+    let mut inputs = Vec::new();
+
+    for file in &files {
+        let mut input = open(file);
+
+        // The output can only be one of the inputs once it was read.
+        let is_output = output_id.is_some()
+            && std::fs::metadata(file)
+                .is_ok_and(|metadata| Some((metadata.dev(), metadata.ino())) == output_id);
+
+        if merge_only && !is_output {
+            inputs.push(input);
+            continue;
+        }
+
+        let mut line = Vec::new();
+        loop {
+            match read_line(input.as_mut(), delimiter, &mut line) {
+                Ok(true) => {}
+                Ok(false) => break,
+                Err(e) => fail(format_args!("read failed: {}: {}", file, error_reason(&e))),
+            }
+
+            let start = chunk.data.len();
+            chunk.data.extend_from_slice(&line);
+            chunk.lines.push(start..chunk.data.len());
+
+            if chunk.size() >= sorter.buffer_size {
+                sorter.spill(&mut chunk).unwrap_or_else(|e| spill_error(e));
+            }
+        }
+
+        if merge_only {
+            // Keep the lines of the output file apart, as they're sorted already.
+            sorter.spill(&mut chunk).unwrap_or_else(|e| spill_error(e));
+        }
+    }
+
+    if sorter.has_runs() && !chunk.lines.is_empty() {
+        sorter.spill(&mut chunk).unwrap_or_else(|e| spill_error(e));
+    }
+
+    let out: Box<dyn Write> = match &output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => fail(format_args!("open failed: {}: {}", path, error_reason(&e))),
+        },
+        None => Box::new(std::io::stdout().lock()),
+    };
+
+    let mut out = Output {
+        out: BufWriter::with_capacity(1 << 16, out),
+        delimiter,
+        comparator: &sorter.comparator,
+        last: None,
+    };
+
+    let written = if !sorter.has_runs() && inputs.is_empty() {
+        chunk.sort(&sorter.comparator, sorter.threads);
+        chunk
+            .lines
+            .iter()
+            .try_for_each(|line| out.write(&chunk.data[line.clone()]))
+    } else {
+        // The oldest runs are in the highest levels.
+        let runs = std::mem::take(&mut sorter.levels);
+        inputs.extend(
+            runs.into_iter()
+                .rev()
+                .flatten()
+                .map(|run| Box::new(BufReader::new(run)) as Box<dyn BufRead>),
+        );
+        merge(inputs, out.comparator, delimiter, |line| out.write(line))
+    };
+
+    if let Err(e) = written.and_then(|_| out.out.flush()) {
+        fail(format_args!("write failed: {}", error_reason(&e)));
+    }
+}
@@ -0,0 +1,388 @@
+//! The `tail` command, printing the end of files and following
+//! what is appended to them.
+
+use std::{
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::prelude::MetadataExt,
+};
+
+use crate::head::*;
+use crate::utils::*;
+
+/// A file followed by `tail -f`.
+struct Followed {
+    path: String,
+    file: Option<std::fs::File>,
+    /// How much of the file was printed.
+    pos: u64,
+    /// The identity of the opened file, to notice when it's replaced.
+    id: (u64, u64),
+}
+
+impl Followed {
+    fn open(path: &str) -> Option<Self> {
+        let file = std::fs::File::open(path).ok()?;
+        let metadata = file.metadata().ok()?;
+
+        Some(Followed {
+            path: path.to_owned(),
+            file: Some(file),
+            pos: 0,
+            id: (metadata.dev(), metadata.ino()),
+        })
+    }
+}
+
+/// Watches the followed files with inotify, falling back to polling.
+struct Watcher {
+    /// `None` if inotify isn't available.
+    fd: Option<std::os::fd::OwnedFd>,
+    interval: std::time::Duration,
+}
+
+impl Watcher {
+    fn new(interval: std::time::Duration) -> Self {
+        use std::os::fd::FromRawFd;
+
+        // SAFETY: no pointers are involved.
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+
+        Watcher {
+            // SAFETY: the file descriptor was just created and is owned here.
+            fd: (fd >= 0).then(|| unsafe { std::os::fd::OwnedFd::from_raw_fd(fd) }),
+            interval,
+        }
+    }
+
+    /// Watch for changes of `path` and for files appearing in
+    /// its directory (when it's rotated).
+    fn watch(&self, path: &str) {
+        use std::os::fd::AsRawFd;
+
+        let Some(fd) = &self.fd else {
+            return;
+        };
+
+        let dir = match std::path::Path::new(path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => std::path::Path::new("."),
+        };
+
+        let watches = [
+            (
+                path.as_bytes().to_vec(),
+                libc::IN_MODIFY | libc::IN_ATTRIB | libc::IN_DELETE_SELF | libc::IN_MOVE_SELF,
+            ),
+            (
+                dir.as_os_str().as_encoded_bytes().to_vec(),
+                libc::IN_CREATE | libc::IN_MOVED_TO,
+            ),
+        ];
+
+        for (path, mask) in watches {
+            let Ok(path) = std::ffi::CString::new(path) else {
+                continue;
+            };
+
+            // SAFETY: `path` is a valid C string.
+            unsafe { libc::inotify_add_watch(fd.as_raw_fd(), path.as_ptr(), mask) };
+        }
+    }
+
+    /// Wait until a watched file changes, or for the polling interval.
+    fn wait(&self) {
+        use std::os::fd::AsRawFd;
+
+        let Some(fd) = &self.fd else {
+            std::thread::sleep(self.interval);
+            return;
+        };
+
+        let mut poll_fd = libc::pollfd {
+            fd: fd.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        // The interval is still used, to check the writer and
+        // the files that couldn't be watched.
+        let timeout = self.interval.as_millis().min(i32::MAX as u128) as i32;
+        // SAFETY: `poll_fd` is a single valid pollfd.
+        unsafe { libc::poll(&mut poll_fd, 1, timeout) };
+
+        // The events themselves don't matter, all the files are checked.
+        let mut events = [0u8; 4096];
+        // SAFETY: `events` is a valid buffer of the given size.
+        while unsafe { libc::read(fd.as_raw_fd(), events.as_mut_ptr().cast(), events.len()) } > 0 {}
+    }
+}
+
+/// Check if the process `pid` is still running.
+fn process_alive(pid: libc::pid_t) -> bool {
+    // SAFETY: signal 0 only checks if the process exists.
+    let ret = unsafe { libc::kill(pid, 0) };
+    ret == 0 || std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+/// Print what was appended to the followed files, forever (or until
+/// the process `pid` ends). With `by_name`, files that are replaced
+/// (rotated) or that reappear are reopened.
+fn follow(
+    mut files: Vec<Followed>,
+    out: &mut impl Write,
+    by_name: bool,
+    headers: bool,
+    pid: Option<libc::pid_t>,
+    watcher: Watcher,
+) {
+    for file in &files {
+        watcher.watch(&file.path);
+    }
+
+    let mut last_printed = files.len().saturating_sub(1);
+
+    loop {
+        // Check the writer before reading, so nothing written
+        // before it ended is missed.
+        let writer_ended = pid.is_some_and(|pid| !process_alive(pid));
+
+        for (i, followed) in files.iter_mut().enumerate() {
+            if by_name {
+                let current = std::fs::metadata(&followed.path)
+                    .ok()
+                    .map(|metadata| (metadata.dev(), metadata.ino()));
+
+                match (current, &followed.file) {
+                    (Some(id), Some(_)) if id == followed.id => {}
+                    (Some(_), had_file) => {
+                        if let Some(reopened) = Followed::open(&followed.path) {
+                            let message = if had_file.is_some() {
+                                "has been replaced;  following new file"
+                            } else {
+                                "has appeared;  following new file"
+                            };
+                            eprintln!("tail: '{}' {}", followed.path, message);
+
+                            *followed = reopened;
+                            watcher.watch(&followed.path);
+                        }
+                    }
+                    (None, Some(_)) => {
+                        eprintln!("tail: '{}' has become inaccessible", followed.path);
+                        followed.file = None;
+                    }
+                    (None, None) => {}
+                }
+            }
+
+            let Some(file) = &mut followed.file else {
+                continue;
+            };
+
+            let Ok(len) = file.metadata().map(|metadata| metadata.len()) else {
+                continue;
+            };
+
+            if len < followed.pos {
+                eprintln!("tail: {}: file truncated", followed.path);
+                followed.pos = 0;
+            }
+
+            if len == followed.pos {
+                continue;
+            }
+
+            if headers && i != last_printed {
+                let _ = print_header(out, &followed.path, false);
+                last_printed = i;
+            }
+
+            let copied = file
+                .seek(SeekFrom::Start(followed.pos))
+                .and_then(|_| std::io::copy(&mut file.take(len - followed.pos), out));
+
+            if let Ok(copied) = copied {
+                followed.pos += copied;
+            }
+        }
+
+        if out.flush().is_err() || writer_ended {
+            return;
+        }
+
+        watcher.wait();
+    }
+}
+
+pub fn tail(args: &[String]) {
+    let args = expand_obsolete_count(args);
+    let with_value = [
+        "-n",
+        "--lines",
+        "-c",
+        "--bytes",
+        "--pid",
+        "-s",
+        "--sleep-interval",
+    ];
+    let Some((opts, files)) = parse_options(&args, &with_value) else {
+        eprintln!("Usage: tail [-f|-F] [-n [+]N] [-c [+]N] [FILE]...");
+        std::process::exit(-131);
+    };
+
+    let mut unit = Unit::Lines;
+    let mut span = Span::Last(10);
+    let mut headers = None;
+    let mut follow_mode = None;
+    let mut pid = None;
+    let mut interval = std::time::Duration::from_secs(1);
+
+    for (opt, value) in opts {
+        match opt.as_str() {
+            "-n" | "--lines" | "-c" | "--bytes" => {
+                unit = if opt.starts_with("-n") || opt == "--lines" {
+                    Unit::Lines
+                } else {
+                    Unit::Bytes
+                };
+
+                span = match value.as_deref().and_then(parse_count) {
+                    Some((Some('+'), count)) => Span::From(count),
+                    Some((_, count)) => Span::Last(count),
+                    None => {
+                        eprintln!("tail: invalid number: '{}'", value.unwrap_or_default());
+                        std::process::exit(-131);
+                    }
+                };
+            }
+            "-f" => follow_mode = Some(false),
+            "-F" => follow_mode = Some(true),
+            "--follow" => match value.as_deref() {
+                None | Some("descriptor") => follow_mode = Some(false),
+                Some("name") => follow_mode = Some(true),
+                Some(mode) => {
+                    eprintln!("tail: invalid argument '{}' for '--follow'", mode);
+                    std::process::exit(-131);
+                }
+            },
+            "--pid" => match value.as_deref().map(str::parse) {
+                Some(Ok(value)) => pid = Some(value),
+                _ => {
+                    eprintln!("tail: invalid PID");
+                    std::process::exit(-131);
+                }
+            },
+            "-s" | "--sleep-interval" => match value.as_deref().map(str::parse::<f64>) {
+                Some(Ok(seconds)) if seconds >= 0.0 => {
+                    interval = std::time::Duration::from_secs_f64(seconds);
+                }
+                _ => {
+                    eprintln!("tail: invalid number of seconds");
+                    std::process::exit(-131);
+                }
+            },
+            "-q" | "--quiet" | "--silent" => headers = Some(false),
+            "-v" | "--verbose" => headers = Some(true),
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-131);
+            }
+        }
+    }
+
+    let stdin = String::from("-");
+    let files = if files.is_empty() {
+        vec![&stdin]
+    } else {
+        files
+    };
+    let headers = headers.unwrap_or(files.len() > 1);
+
+    let mut stdout = std::io::stdout().lock();
+    let mut was_error = false;
+    let mut followed = Vec::new();
+
+    for (i, path) in files.iter().enumerate() {
+        let printed = if path.as_str() == "-" {
+            let mut input = std::io::stdin().lock();
+            (if headers {
+                print_header(&mut stdout, path, i == 0)
+            } else {
+                Ok(())
+            })
+            .and_then(|_| print_span(&mut input, &mut stdout, unit, span))
+        } else {
+            match std::fs::File::open(path) {
+                Ok(mut file) => {
+                    let is_file = file.metadata().is_ok_and(|metadata| metadata.is_file());
+                    let printed = (if headers {
+                        print_header(&mut stdout, path, i == 0)
+                    } else {
+                        Ok(())
+                    })
+                    .and_then(|_| match span {
+                        Span::Last(count) if is_file => {
+                            print_file_end(&mut file, &mut stdout, unit, count)
+                        }
+                        _ => print_span(
+                            &mut std::io::BufReader::new(&mut file),
+                            &mut stdout,
+                            unit,
+                            span,
+                        ),
+                    });
+
+                    if let (Some(_), Some(mut opened)) = (follow_mode, Followed::open(path)) {
+                        // Follow from where the printing stopped.
+                        opened.pos = file.stream_position().unwrap_or(0);
+                        followed.push(opened);
+                    }
+
+                    printed
+                }
+                Err(e) => {
+                    eprintln!(
+                        "tail: cannot open '{}' for reading: {}",
+                        path,
+                        error_reason(&e)
+                    );
+                    was_error = true;
+
+                    // With '-F', wait for the file to appear.
+                    if follow_mode == Some(true) {
+                        followed.push(Followed {
+                            path: path.to_string(),
+                            file: None,
+                            pos: 0,
+                            id: (0, 0),
+                        });
+                    }
+
+                    Ok(())
+                }
+            }
+        };
+
+        if let Err(e) = printed {
+            eprintln!("tail: error reading '{}': {}", path, error_reason(&e));
+            was_error = true;
+        }
+    }
+
+    if let Some(by_name) = follow_mode {
+        if !followed.is_empty() {
+            follow(
+                followed,
+                &mut stdout,
+                by_name,
+                headers,
+                pid,
+                Watcher::new(interval),
+            );
+        }
+    }
+
+    if was_error {
+        std::process::exit(-131);
+    }
+}
@@ -0,0 +1,786 @@
+//! The `tar` command, creating (`-c`), listing (`-t`) or extracting (`-x`)
+//! archives in the ustar format. Names and links too long for it are
+//! stored in pax extended headers, and the long names of GNU tar are read
+//! too.
+
+use std::fs::Metadata;
+use std::io::{BufWriter, IsTerminal, Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use crate::archive::*;
+use crate::gzip::{decompressing_reader, GzEncoder};
+use crate::utils::*;
+
+const BLOCK: usize = 512;
+/// Archives are padded to records of 20 blocks, like GNU tar does.
+const RECORD: u64 = 20 * BLOCK as u64;
+
+fn usage() -> ! {
+    eprintln!("Usage: tar -c|-x|-t [-v] [-z] [-p] [-f ARCHIVE] [-C DIR] [--exclude=PATTERN] [--strip-components=N] [FILE]...");
+    std::process::exit(-260);
+}
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("tar: {}", message);
+    std::process::exit(-260);
+}
+
+fn data_error(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Get the text of a header field, up to its first null byte.
+fn text(field: &[u8]) -> String {
+    let end = field
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..end]).into_owned()
+}
+
+/// Parse a numeric field, in octal, or in base 256 (GNU) when its first
+/// bit is set.
+fn number(field: &[u8]) -> Option<u64> {
+    if field[0] & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .try_fold((field[0] & 0x7f) as u64, |value, &byte| {
+                value.checked_mul(256)?.checked_add(byte as u64)
+            });
+    }
+
+    let text = text(field);
+    let text = text.trim_matches(' ');
+    if text.is_empty() {
+        Some(0)
+    } else {
+        u64::from_str_radix(text, 8).ok()
+    }
+}
+
+fn put_text(field: &mut [u8], value: &str) {
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value.as_bytes()[..len]);
+}
+
+/// Write a numeric field in octal, ended with a null byte, or in base 256
+/// if it doesn't fit.
+fn put_number(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    let octal = format!("{:0width$o}", value, width = digits);
+
+    if octal.len() <= digits {
+        field[..digits].copy_from_slice(octal.as_bytes());
+        field[digits] = 0;
+    } else {
+        let mut value = value;
+        for byte in field.iter_mut().rev() {
+            *byte = value as u8;
+            value >>= 8;
+        }
+        field[0] = 0x80;
+    }
+}
+
+/// Compute the checksum of a header, where the checksum field counts as
+/// spaces.
+fn checksum(header: &[u8; BLOCK]) -> u64 {
+    let sum: u64 = header.iter().map(|&byte| byte as u64).sum();
+    let field: u64 = header[148..156].iter().map(|&byte| byte as u64).sum();
+    sum - field + 8 * b' ' as u64
+}
+
+/// Split a name into the prefix and name fields of a header, if it fits.
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 {
+        return Some(("", name));
+    }
+
+    name.match_indices('/')
+        .map(|(i, _)| (&name[..i], &name[i + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && !name.is_empty() && name.len() <= 100)
+}
+
+/// Format a record of a pax header, which starts with its own length.
+fn pax_record(key: &str, value: &str) -> String {
+    let len = key.len() + value.len() + 3;
+    let mut total = len;
+    loop {
+        let next = len + total.to_string().len();
+        if next == total {
+            break;
+        }
+        total = next;
+    }
+
+    format!("{} {}={}\n", total, key, value)
+}
+
+/// Parse the records of a pax header.
+fn parse_pax(data: &[u8]) -> Vec<(String, String)> {
+    let mut records = Vec::new();
+    let mut rest = data;
+
+    while let Some(space) = rest.iter().position(|&byte| byte == b' ') {
+        let len = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|len| len.parse::<usize>().ok());
+        let Some(len) = len.filter(|&len| len > space && len <= rest.len()) else {
+            break;
+        };
+
+        let record = &rest[space + 1..len];
+        let record = record.strip_suffix(b"\n").unwrap_or(record);
+        if let Some(equal) = record.iter().position(|&byte| byte == b'=') {
+            records.push((
+                String::from_utf8_lossy(&record[..equal]).into_owned(),
+                String::from_utf8_lossy(&record[equal + 1..]).into_owned(),
+            ));
+        }
+        rest = &rest[len..];
+    }
+
+    records
+}
+
+/// Build a header for `entry`, with the given name fields and type.
+fn header(entry: &Entry, prefix: &str, name: &str, link: &str, kind: u8) -> [u8; BLOCK] {
+    let mut header = [0; BLOCK];
+    put_text(&mut header[0..100], name);
+    put_number(&mut header[100..108], entry.mode as u64);
+    put_number(&mut header[108..116], entry.uid as u64);
+    put_number(&mut header[116..124], entry.gid as u64);
+    put_number(&mut header[124..136], entry.size);
+    put_number(&mut header[136..148], entry.mtime.max(0) as u64);
+    header[156] = kind;
+    put_text(&mut header[157..257], link);
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    put_text(&mut header[265..297], &entry.user);
+    put_text(&mut header[297..329], &entry.group);
+    if let Kind::CharDevice | Kind::BlockDevice = entry.kind {
+        put_number(&mut header[329..337], entry.device.0 as u64);
+        put_number(&mut header[337..345], entry.device.1 as u64);
+    }
+    put_text(&mut header[345..500], prefix);
+
+    let sum = format!("{:06o}\0 ", checksum(&header));
+    header[148..156].copy_from_slice(sum.as_bytes());
+    header
+}
+
+/// Parse a header into a member.
+fn parse_header(header: &[u8; BLOCK]) -> std::io::Result<Entry> {
+    let invalid = || data_error("Damaged archive header");
+    let field = |range: std::ops::Range<usize>| number(&header[range]).ok_or_else(invalid);
+
+    // The prefix is only in POSIX headers; GNU ones have times there.
+    let mut name = text(&header[0..100]);
+    let prefix = text(&header[345..500]);
+    if &header[257..263] == b"ustar\0" && !prefix.is_empty() {
+        name = format!("{}/{}", prefix, name);
+    }
+
+    let link = text(&header[157..257]);
+    let kind = match header[156] {
+        b'1' => Kind::HardLink(link),
+        b'2' => Kind::Symlink(link),
+        b'3' => Kind::CharDevice,
+        b'4' => Kind::BlockDevice,
+        b'5' => Kind::Directory,
+        b'6' => Kind::Fifo,
+        // Old archives mark directories with a slash.
+        _ if name.ends_with('/') => Kind::Directory,
+        _ => Kind::File,
+    };
+
+    Ok(Entry {
+        name,
+        kind,
+        mode: field(100..108)? as u32 & 0o7777,
+        uid: field(108..116)? as u32,
+        gid: field(116..124)? as u32,
+        user: text(&header[265..297]),
+        group: text(&header[297..329]),
+        size: field(124..136)?,
+        mtime: field(136..148)? as i64,
+        // Other types may have anything in the device numbers.
+        device: (
+            number(&header[329..337]).unwrap_or(0) as u32,
+            number(&header[337..345]).unwrap_or(0) as u32,
+        ),
+    })
+}
+
+/// Read a block, returning false at the end of the input.
+fn read_block(input: &mut dyn Read, block: &mut [u8; BLOCK]) -> std::io::Result<bool> {
+    let mut len = 0;
+    while len < BLOCK {
+        match input.read(&mut block[len..]) {
+            Ok(0) if len == 0 => return Ok(false),
+            Ok(0) => return Err(data_error("Unexpected EOF in archive")),
+            Ok(read) => len += read,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(true)
+}
+
+/// Skip `len` bytes of the archive.
+fn skip(input: &mut dyn Read, len: u64) -> std::io::Result<()> {
+    if std::io::copy(&mut input.take(len), &mut std::io::sink())? < len {
+        return Err(data_error("Unexpected EOF in archive"));
+    }
+    Ok(())
+}
+
+/// Get the size of the padding of `len` bytes of data to a block.
+fn padding(len: u64) -> u64 {
+    len.next_multiple_of(BLOCK as u64) - len
+}
+
+/// Read the members of an archive, calling `visit` with each one and
+/// a reader of its data. The long names and links of pax and GNU headers
+/// are applied to the member which follows them.
+fn read_archive(
+    input: &mut dyn Read,
+    mut visit: impl FnMut(&Entry, &mut dyn Read),
+) -> std::io::Result<()> {
+    let mut block = [0; BLOCK];
+    let mut first = true;
+    let mut records = Vec::new();
+    let mut long_name = None;
+    let mut long_link = None;
+
+    // The archive ends with zero blocks, or just ends.
+    while read_block(input, &mut block)? && block.iter().any(|&byte| byte != 0) {
+        if number(&block[148..156]) != Some(checksum(&block)) {
+            return Err(data_error(if first {
+                "This does not look like a tar archive"
+            } else {
+                "Skipping to next header"
+            }));
+        }
+        first = false;
+
+        let entry = parse_header(&block)?;
+        if let b'x' | b'g' | b'L' | b'K' = block[156] {
+            let mut data = Vec::new();
+            input.take(entry.size).read_to_end(&mut data)?;
+            if (data.len() as u64) < entry.size {
+                return Err(data_error("Unexpected EOF in archive"));
+            }
+            skip(input, padding(entry.size))?;
+
+            match block[156] {
+                b'x' => records = parse_pax(&data),
+                b'L' => long_name = Some(text(&data)),
+                b'K' => long_link = Some(text(&data)),
+                // The global pax headers are ignored.
+                _ => {}
+            }
+            continue;
+        }
+
+        let mut entry = entry;
+        let mut link = long_link.take();
+        if let Some(name) = long_name.take() {
+            entry.name = name;
+        }
+        for (key, value) in std::mem::take(&mut records) {
+            match key.as_str() {
+                "path" => entry.name = value,
+                "linkpath" => link = Some(value),
+                "uname" => entry.user = value,
+                "gname" => entry.group = value,
+                "size" => entry.size = value.parse().map_err(|_| data_error("Bad pax size"))?,
+                "uid" => entry.uid = value.parse().unwrap_or(entry.uid),
+                "gid" => entry.gid = value.parse().unwrap_or(entry.gid),
+                "mtime" => {
+                    let seconds = value.split('.').next().unwrap_or_default();
+                    entry.mtime = seconds.parse().unwrap_or(entry.mtime);
+                }
+                _ => {}
+            }
+        }
+        if let (Some(link), Kind::HardLink(target) | Kind::Symlink(target)) =
+            (link, &mut entry.kind)
+        {
+            *target = link;
+        }
+
+        // Only regular files have data.
+        let size = if entry.kind == Kind::File {
+            entry.size
+        } else {
+            0
+        };
+        let mut data = input.take(size);
+        visit(&entry, &mut data);
+        let rest = data.limit();
+        skip(input, rest + padding(size))?;
+    }
+
+    Ok(())
+}
+
+/// Writes the members of an archive.
+struct Writer<W: Write> {
+    out: W,
+    written: u64,
+}
+
+impl<W: Write> Writer<W> {
+    fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.out.write_all(data)?;
+        self.written += data.len() as u64;
+        Ok(())
+    }
+
+    /// Write zeros up to the end of the block.
+    fn pad(&mut self) -> std::io::Result<()> {
+        let len = padding(self.written);
+        self.write(&[0; BLOCK][..len as usize])
+    }
+
+    /// Write the header of `entry`, preceded by a pax header with the
+    /// attributes which don't fit in it.
+    fn header(&mut self, entry: &Entry) -> std::io::Result<()> {
+        let (link, kind) = match &entry.kind {
+            Kind::File => ("", b'0'),
+            Kind::HardLink(target) => (target.as_str(), b'1'),
+            Kind::Symlink(target) => (target.as_str(), b'2'),
+            Kind::CharDevice => ("", b'3'),
+            Kind::BlockDevice => ("", b'4'),
+            Kind::Directory => ("", b'5'),
+            Kind::Fifo => ("", b'6'),
+        };
+
+        let split = split_name(&entry.name);
+        let mut records = String::new();
+        if split.is_none() {
+            records.push_str(&pax_record("path", &entry.name));
+        }
+        if link.len() > 100 {
+            records.push_str(&pax_record("linkpath", link));
+        }
+        if entry.user.len() > 31 {
+            records.push_str(&pax_record("uname", &entry.user));
+        }
+        if entry.group.len() > 31 {
+            records.push_str(&pax_record("gname", &entry.group));
+        }
+
+        if !records.is_empty() {
+            let base = Path::new(&entry.name).file_name().unwrap_or_default();
+            let pax = Entry {
+                name: format!("./PaxHeaders/{}", base.to_string_lossy()),
+                kind: Kind::File,
+                mode: 0o644,
+                size: records.len() as u64,
+                ..entry.clone()
+            };
+            self.write(&header(&pax, "", &pax.name, "", b'x'))?;
+            self.write(records.as_bytes())?;
+            self.pad()?;
+        }
+
+        let (prefix, name) = split.unwrap_or(("", &entry.name));
+        self.write(&header(entry, prefix, name, link, kind))
+    }
+
+    /// Write the data of a member of `size` bytes from `input`. If there's
+    /// less, it's padded with zeros. Returns the size read.
+    fn data(&mut self, input: &mut dyn Read, size: u64) -> std::io::Result<u64> {
+        let mut buffer = vec![0; 1 << 16];
+        let mut read = 0;
+
+        while read < size {
+            let len = (size - read).min(buffer.len() as u64) as usize;
+            let len = match input.read(&mut buffer[..len]) {
+                Ok(0) => break,
+                Ok(len) => len,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            self.write(&buffer[..len])?;
+            read += len as u64;
+        }
+
+        let mut missing = size - read;
+        while missing > 0 {
+            let len = missing.min(BLOCK as u64);
+            self.write(&[0; BLOCK][..len as usize])?;
+            missing -= len;
+        }
+        self.pad()?;
+        Ok(read)
+    }
+
+    /// End the archive with two zero blocks, padded to a record.
+    fn finish(mut self) -> std::io::Result<W> {
+        self.write(&[0; 2 * BLOCK])?;
+        let padding = self.written.next_multiple_of(RECORD) - self.written;
+        self.write(&vec![0; padding as usize])?;
+        Ok(self.out)
+    }
+}
+
+/// Format a member like `tar -tv` does.
+fn long_listing(entry: &Entry) -> String {
+    let user = match entry.user.as_str() {
+        "" => entry.uid.to_string(),
+        user => user.to_string(),
+    };
+    let group = match entry.group.as_str() {
+        "" => entry.gid.to_string(),
+        group => group.to_string(),
+    };
+    let owner = format!("{}/{}", user, group);
+
+    let size = match entry.kind {
+        Kind::CharDevice | Kind::BlockDevice => format!("{},{}", entry.device.0, entry.device.1),
+        _ => entry.size.to_string(),
+    };
+    let date = chrono::DateTime::from_timestamp(entry.mtime, 0)
+        .map(|date| {
+            date.with_timezone(&chrono::Local)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        })
+        .unwrap_or_default();
+
+    let mut line = format!(
+        "{}{} {} {:>width$} {} {}",
+        entry.type_char(),
+        format_mode(entry.mode),
+        owner,
+        size,
+        date,
+        entry.name,
+        width = 18usize.saturating_sub(owner.len()),
+    );
+    match &entry.kind {
+        Kind::Symlink(target) => line.push_str(&format!(" -> {}", target)),
+        Kind::HardLink(target) => line.push_str(&format!(" link to {}", target)),
+        _ => {}
+    }
+    line
+}
+
+#[derive(PartialEq)]
+enum Mode {
+    Create,
+    Extract,
+    List,
+}
+
+/// The settings of `tar`.
+struct Tar {
+    verbose: bool,
+    excludes: Vec<String>,
+    preserve: bool,
+    strip: usize,
+    failed: bool,
+}
+
+impl Tar {
+    /// Check if a member is excluded, by its name or its last component.
+    fn excluded(&self, name: &str) -> bool {
+        let name = name.trim_end_matches('/');
+        let base = name.rsplit('/').next().unwrap_or(name);
+
+        self.excludes
+            .iter()
+            .any(|pattern| glob_match(pattern, name, false) || glob_match(pattern, base, false))
+    }
+
+    /// Write the archive of `files` into `out`. `archive` is the file of the
+    /// archive, which isn't archived itself.
+    fn create<W: Write>(
+        &mut self,
+        out: W,
+        files: &[&String],
+        archive: Option<&Metadata>,
+        to_stdout: bool,
+    ) -> std::io::Result<W> {
+        let mut writer = Writer {
+            out: BufWriter::new(out),
+            written: 0,
+        };
+        let mut failed = false;
+        let verbose = self.verbose;
+
+        let ok = walk_members(
+            "tar",
+            files,
+            |name| self.excluded(name),
+            |mut entry, path, metadata| {
+                if archive.is_some_and(|archive| {
+                    (archive.dev(), archive.ino()) == (metadata.dev(), metadata.ino())
+                }) {
+                    eprintln!("tar: {}: file is the archive; not dumped", path.display());
+                    return;
+                }
+
+                let mut input = None;
+                if entry.kind == Kind::File {
+                    match std::fs::File::open(path) {
+                        Ok(file) => input = Some(file),
+                        Err(e) => {
+                            eprintln!("tar: {}: Cannot open: {}", path.display(), error_reason(&e));
+                            failed = true;
+                            return;
+                        }
+                    }
+                } else if entry.kind == Kind::Directory && !entry.name.ends_with('/') {
+                    entry.name.push('/');
+                }
+
+                if verbose && to_stdout {
+                    eprintln!("{}", entry.name);
+                } else if verbose {
+                    println!("{}", entry.name);
+                }
+
+                let result = writer.header(&entry).and_then(|_| match &mut input {
+                    Some(file) => writer.data(file, entry.size),
+                    None => Ok(0),
+                });
+                match result {
+                    Ok(read) if read < entry.size => {
+                        eprintln!(
+                            "tar: {}: File shrank by {} bytes; padding with zeros",
+                            path.display(),
+                            entry.size - read
+                        );
+                        failed = true;
+                    }
+                    Ok(_) => {}
+                    Err(e) => fail(format_args!("{}", error_reason(&e))),
+                }
+            },
+        );
+
+        self.failed |= failed || !ok;
+        let out = writer.finish()?;
+        out.into_inner().map_err(|e| e.into_error())
+    }
+}
+
+pub fn tar(args: &[String]) {
+    // The first argument can be the flags without a dash (`tar cvf`).
+    let mut args = args.to_vec();
+    if let Some(first) = args.first_mut().filter(|first| !first.starts_with('-')) {
+        first.insert(0, '-');
+    }
+
+    let with_value = [
+        "-f",
+        "--file",
+        "-C",
+        "--directory",
+        "--exclude",
+        "--strip-components",
+    ];
+    let Some((opts, files)) = parse_options(&args, &with_value) else {
+        usage();
+    };
+
+    let mut mode = None;
+    let mut archive = String::from("-");
+    let mut directory = None;
+    let mut gzip = false;
+    let mut tar = Tar {
+        verbose: false,
+        excludes: Vec::new(),
+        preserve: false,
+        strip: 0,
+        failed: false,
+    };
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+        let new_mode = match opt.as_str() {
+            "-c" | "--create" => Mode::Create,
+            "-x" | "--extract" | "--get" => Mode::Extract,
+            "-t" | "--list" => Mode::List,
+            "-v" | "--verbose" => {
+                tar.verbose = true;
+                continue;
+            }
+            "-z" | "--gzip" | "--gunzip" => {
+                gzip = true;
+                continue;
+            }
+            "-p" | "--preserve-permissions" | "--same-permissions" => {
+                tar.preserve = true;
+                continue;
+            }
+            "-f" | "--file" => {
+                archive = value;
+                continue;
+            }
+            "-C" | "--directory" => {
+                directory = Some(value);
+                continue;
+            }
+            "--exclude" => {
+                tar.excludes.push(value);
+                continue;
+            }
+            "--strip-components" => {
+                tar.strip = value.parse().unwrap_or_else(|_| {
+                    fail(format_args!("invalid number of components: '{}'", value))
+                });
+                continue;
+            }
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-260);
+            }
+        };
+
+        if mode.as_ref().is_some_and(|mode| *mode != new_mode) {
+            usage();
+        }
+        mode = Some(new_mode);
+    }
+
+    let Some(mode) = mode else {
+        usage();
+    };
+
+    // The archive is opened before changing directories.
+    if mode == Mode::Create {
+        if files.is_empty() {
+            fail(format_args!("Cowardly refusing to create an empty archive"));
+        }
+
+        let (out, metadata): (Box<dyn Write>, _) = if archive == "-" {
+            if std::io::stdout().is_terminal() {
+                fail(format_args!(
+                    "Refusing to write archive contents to terminal"
+                ));
+            }
+            (Box::new(std::io::stdout().lock()), None)
+        } else {
+            match std::fs::File::create(&archive) {
+                Ok(file) => {
+                    let metadata = file.metadata().ok();
+                    (Box::new(file), metadata)
+                }
+                Err(e) => fail(format_args!(
+                    "{}: Cannot open: {}",
+                    archive,
+                    error_reason(&e)
+                )),
+            }
+        };
+
+        change_directory(directory.as_deref());
+        let to_stdout = archive == "-";
+        let result = if gzip {
+            GzEncoder::new(out, 6, None, 0)
+                .and_then(|encoder| tar.create(encoder, &files, metadata.as_ref(), to_stdout))
+                .and_then(|encoder| encoder.finish())
+                .and_then(|mut out| out.flush())
+        } else {
+            tar.create(out, &files, metadata.as_ref(), to_stdout)
+                .and_then(|mut out| out.flush())
+        };
+
+        if let Err(e) = result {
+            fail(format_args!("{}: {}", archive, error_reason(&e)));
+        }
+    } else {
+        let input: std::io::Result<Box<dyn Read>> = match (gzip, archive.as_str()) {
+            (true, "-") => Ok(Box::new(decompressing_reader(std::io::stdin()))),
+            (true, path) => std::fs::File::open(path)
+                .map(|file| Box::new(decompressing_reader(file)) as Box<dyn Read>),
+            (false, path) => open_input(path).map(|input| Box::new(input) as Box<dyn Read>),
+        };
+        let mut input = input.unwrap_or_else(|e| {
+            fail(format_args!(
+                "{}: Cannot open: {}",
+                archive,
+                error_reason(&e)
+            ))
+        });
+
+        change_directory(directory.as_deref());
+        let mut extractor = Extractor::new("tar", tar.preserve, tar.strip);
+        let mut found = vec![false; files.len()];
+
+        let result = read_archive(&mut input, |entry, data| {
+            let name = entry.name.trim_end_matches('/');
+            if tar.excluded(name) {
+                return;
+            }
+
+            // The members can be selected by their name or directory.
+            if !files.is_empty() {
+                let selected = files.iter().position(|file| {
+                    let file = file.trim_end_matches('/');
+                    name == file
+                        || name
+                            .strip_prefix(file)
+                            .is_some_and(|rest| rest.starts_with('/'))
+                });
+                match selected {
+                    Some(i) => found[i] = true,
+                    None => return,
+                }
+            }
+
+            if mode == Mode::List {
+                if tar.verbose {
+                    println!("{}", long_listing(entry));
+                } else {
+                    println!("{}", entry.name);
+                }
+                return;
+            }
+
+            if tar.verbose {
+                println!("{}", entry.name);
+            }
+            extractor.extract(entry, data);
+        });
+
+        extractor.finish();
+        tar.failed |= extractor.failed;
+
+        if let Err(e) = result {
+            fail(format_args!("{}", error_reason(&e)));
+        }
+
+        for (file, found) in files.iter().zip(found) {
+            if !found {
+                eprintln!("tar: {}: Not found in archive", file);
+                tar.failed = true;
+            }
+        }
+    }
+
+    if tar.failed {
+        std::process::exit(-260);
+    }
+}
+
+/// Change to the directory of `-C`.
+fn change_directory(directory: Option<&str>) {
+    if let Some(directory) = directory {
+        if let Err(e) = std::env::set_current_dir(directory) {
+            fail(format_args!(
+                "{}: Cannot open: {}",
+                directory,
+                error_reason(&e)
+            ));
+        }
+    }
+}
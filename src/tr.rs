@@ -0,0 +1,270 @@
+//! The `tr` command, translating, deleting or squeezing characters.
+
+use std::io::{Read, Write};
+
+use crate::utils::*;
+
+/// A part of a set, once its syntax is decoded.
+enum Part {
+    Bytes(Vec<u8>),
+    /// `[c*]`: the character repeated as many times as needed for
+    /// the set to be as long as the first one.
+    Fill(u8),
+}
+
+/// Decode the character at the start of `set`, which may be escaped.
+/// Returns the character and its length.
+fn set_char(set: &[u8]) -> (u8, usize) {
+    if set[0] == b'\\' {
+        unescape(set)
+    } else {
+        (set[0], 1)
+    }
+}
+
+/// Parse a set: characters, escapes, ranges (`a-z`), classes
+/// (`[:upper:]`), equivalence classes (`[=c=]`) and repeats (`[c*N]`).
+fn parse_set(set: &str) -> Result<Vec<Part>, String> {
+    let set = set.as_bytes();
+    let mut parts = Vec::new();
+    let mut bytes = Vec::new();
+    let mut i = 0;
+
+    while i < set.len() {
+        let rest = &set[i..];
+
+        if rest.starts_with(b"[:") {
+            if let Some(end) = rest.windows(2).position(|w| w == b":]") {
+                let class = String::from_utf8_lossy(&rest[2..end]);
+                if !CHAR_CLASSES.contains(&class.as_ref()) {
+                    return Err(format!("invalid character class '{}'", class));
+                }
+
+                // Only the ASCII characters are classified, as in the "C" locale.
+                bytes.extend((0..=0x7f).filter(|&b: &u8| char_class_matches(&class, b as char)));
+                i += end + 2;
+                continue;
+            }
+        }
+
+        if rest.starts_with(b"[=") {
+            if let Some(end) = rest[2..].windows(2).position(|w| w == b"=]") {
+                if end > 0 {
+                    let (chr, len) = set_char(&rest[2..]);
+                    if len == end {
+                        bytes.push(chr);
+                        i += end + 4;
+                        continue;
+                    }
+                }
+            }
+        }
+
+        if rest.len() > 3 && rest[0] == b'[' {
+            let (chr, len) = set_char(&rest[1..]);
+            if rest.get(1 + len) == Some(&b'*') {
+                if let Some(end) = rest[2 + len..].iter().position(|&b| b == b']') {
+                    let count = std::str::from_utf8(&rest[2 + len..2 + len + end]).unwrap_or("x");
+                    let count = match count {
+                        "" => Some(0),
+                        _ if count.starts_with('0') => usize::from_str_radix(count, 8).ok(),
+                        _ => count.parse().ok(),
+                    };
+                    let Some(count) = count else {
+                        return Err(format!(
+                            "invalid repeat count in '{}'",
+                            String::from_utf8_lossy(&rest[..3 + len + end])
+                        ));
+                    };
+
+                    if count == 0 {
+                        parts.push(Part::Bytes(std::mem::take(&mut bytes)));
+                        parts.push(Part::Fill(chr));
+                    } else {
+                        bytes.extend(std::iter::repeat_n(chr, count));
+                    }
+
+                    i += 3 + len + end;
+                    continue;
+                }
+            }
+        }
+
+        let (chr, len) = set_char(rest);
+        i += len;
+
+        // A range, unless the dash is the last character.
+        if set.get(i) == Some(&b'-') && i + 1 < set.len() {
+            let (last, last_len) = set_char(&set[i + 1..]);
+            if last < chr {
+                return Err(format!(
+                    "range-endpoints of '{}-{}' are in reverse collating sequence order",
+                    chr as char, last as char
+                ));
+            }
+
+            bytes.extend(chr..=last);
+            i += 1 + last_len;
+            continue;
+        }
+
+        bytes.push(chr);
+    }
+
+    parts.push(Part::Bytes(bytes));
+    Ok(parts)
+}
+
+/// Expand the parts of a set, filling `[c*]` up to `len` characters.
+fn expand_set(parts: Vec<Part>, len: usize) -> Vec<u8> {
+    let fixed = parts
+        .iter()
+        .map(|part| match part {
+            Part::Bytes(bytes) => bytes.len(),
+            Part::Fill(_) => 0,
+        })
+        .sum::<usize>();
+
+    let mut filled = false;
+    let mut set = Vec::new();
+    for part in parts {
+        match part {
+            Part::Bytes(bytes) => set.extend(bytes),
+            // Only the first `[c*]` is filled.
+            Part::Fill(chr) if !filled => {
+                set.extend(std::iter::repeat_n(chr, len.saturating_sub(fixed)));
+                filled = true;
+            }
+            Part::Fill(_) => {}
+        }
+    }
+
+    set
+}
+
+/// Exit with an error message.
+fn fail(message: &str) -> ! {
+    eprintln!("tr: {}", message);
+    std::process::exit(-172);
+}
+
+pub fn tr(args: &[String]) {
+    let Some((opts, sets)) = parse_options(args, &[]) else {
+        fail("missing operand");
+    };
+
+    let mut complement = false;
+    let mut delete = false;
+    let mut squeeze = false;
+
+    for (opt, _) in opts {
+        match opt.as_str() {
+            "-c" | "-C" | "--complement" => complement = true,
+            "-d" | "--delete" => delete = true,
+            "-s" | "--squeeze-repeats" => squeeze = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-172);
+            }
+        }
+    }
+
+    let translate = !delete && sets.len() == 2;
+    let expected = match (delete, squeeze) {
+        (true, true) => 2,
+        (true, false) => 1,
+        (false, true) => sets.len().clamp(1, 2),
+        (false, false) => 2,
+    };
+    if sets.len() != expected {
+        fail(if sets.len() < expected {
+            "missing operand"
+        } else {
+            "extra operand"
+        });
+    }
+
+    let parse = |set: &String| parse_set(set).unwrap_or_else(|message| fail(&message));
+
+    let mut set1 = expand_set(parse(sets[0]), 0);
+    if complement {
+        let mut members = [false; 256];
+        for &b in &set1 {
+            members[b as usize] = true;
+        }
+        set1 = (0..=255).filter(|&b| !members[b as usize]).collect();
+    }
+
+    let set2 = sets.get(1).map(|set| expand_set(parse(set), set1.len()));
+
+    // What happens to every byte.
+    let mut map = [0u8; 256];
+    for (b, mapped) in map.iter_mut().enumerate() {
+        *mapped = b as u8;
+    }
+    let mut deleted = [false; 256];
+    let mut squeezed = [false; 256];
+
+    if translate {
+        let set2 = set2.as_deref().unwrap_or_default();
+        let Some(&last) = set2.last() else {
+            fail("when not truncating set1, string2 must be non-empty");
+        };
+
+        // The second set is extended with its last character.
+        for (i, &b) in set1.iter().enumerate() {
+            map[b as usize] = *set2.get(i).unwrap_or(&last);
+        }
+    }
+
+    if delete {
+        for &b in &set1 {
+            deleted[b as usize] = true;
+        }
+    }
+
+    if squeeze {
+        // The characters squeezed are those of the last set.
+        let squeeze_set = match &set2 {
+            Some(set2) if delete || translate => set2,
+            _ => &set1,
+        };
+        for &b in squeeze_set {
+            squeezed[b as usize] = true;
+        }
+    }
+
+    let mut input = std::io::stdin().lock();
+    let mut out = std::io::stdout().lock();
+    let mut buffer = vec![0; 1 << 16];
+    let mut output = Vec::with_capacity(buffer.len());
+    let mut last = None;
+
+    loop {
+        let len = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => fail(&format!("read error: {}", error_reason(&e))),
+        };
+
+        output.clear();
+        for &b in &buffer[..len] {
+            if deleted[b as usize] {
+                continue;
+            }
+
+            let b = map[b as usize];
+            if squeezed[b as usize] && last == Some(b) {
+                continue;
+            }
+
+            output.push(b);
+            last = Some(b);
+        }
+
+        if let Err(e) = out.write_all(&output) {
+            fail(&format!("write error: {}", error_reason(&e)));
+        }
+    }
+}
@@ -0,0 +1,159 @@
+//! The `uniq` command, filtering adjacent repeated lines.
+
+use std::io::{BufWriter, Write};
+
+use crate::fields::*;
+use crate::utils::*;
+
+/// Which part of the lines is compared.
+struct Comparison {
+    skip_fields: usize,
+    skip_chars: usize,
+    max_chars: usize,
+    ignore_case: bool,
+}
+
+impl Comparison {
+    /// Get the part of `line` that is compared.
+    fn key<'a>(&self, line: &'a [u8]) -> &'a [u8] {
+        let start = field_start(line, None, self.skip_fields);
+        let start = (start + self.skip_chars).min(line.len());
+        let end = start.saturating_add(self.max_chars).min(line.len());
+
+        &line[start..end]
+    }
+
+    fn equal(&self, a: &[u8], b: &[u8]) -> bool {
+        let (a, b) = (self.key(a), self.key(b));
+
+        if self.ignore_case {
+            a.eq_ignore_ascii_case(b)
+        } else {
+            a == b
+        }
+    }
+}
+
+pub fn uniq(args: &[String]) {
+    let with_value = [
+        "-f",
+        "--skip-fields",
+        "-s",
+        "--skip-chars",
+        "-w",
+        "--check-chars",
+    ];
+    let Some((opts, files)) = parse_options(args, &with_value) else {
+        eprintln!("Usage: uniq [-c|-d|-u|-i|-z] [-f N] [-s N] [-w N] [INPUT [OUTPUT]]");
+        std::process::exit(-160);
+    };
+
+    let mut comparison = Comparison {
+        skip_fields: 0,
+        skip_chars: 0,
+        max_chars: usize::MAX,
+        ignore_case: false,
+    };
+    let mut count = false;
+    let mut only_repeated = false;
+    let mut only_unique = false;
+    let mut delimiter = b'\n';
+
+    for (opt, value) in opts {
+        let number = || match value.as_deref().map(str::parse) {
+            Some(Ok(number)) => number,
+            _ => {
+                eprintln!("uniq: invalid number for '{}'", opt);
+                std::process::exit(-160);
+            }
+        };
+
+        match opt.as_str() {
+            "-f" | "--skip-fields" => comparison.skip_fields = number(),
+            "-s" | "--skip-chars" => comparison.skip_chars = number(),
+            "-w" | "--check-chars" => comparison.max_chars = number(),
+            "-i" | "--ignore-case" => comparison.ignore_case = true,
+            "-c" | "--count" => count = true,
+            "-d" | "--repeated" => only_repeated = true,
+            "-u" | "--unique" => only_unique = true,
+            "-z" | "--zero-terminated" => delimiter = b'\0',
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-160);
+            }
+        }
+    }
+
+    let (input, output) = match files.as_slice() {
+        [] => ("-", None),
+        [input] => (input.as_str(), None),
+        [input, output] => (input.as_str(), Some(output.as_str())),
+        [_, _, extra, ..] => {
+            eprintln!("uniq: extra operand '{}'", extra);
+            std::process::exit(-160);
+        }
+    };
+
+    let mut input = match open_input(input) {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("uniq: {}: {}", input, error_reason(&e));
+            std::process::exit(-160);
+        }
+    };
+
+    let out: Box<dyn Write> = match output {
+        Some(path) if path != "-" => match std::fs::File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => {
+                eprintln!("uniq: {}: {}", path, error_reason(&e));
+                std::process::exit(-160);
+            }
+        },
+        _ => Box::new(std::io::stdout().lock()),
+    };
+    let mut out = BufWriter::new(out);
+
+    // Print the first line of a group of `repeats` equal lines.
+    let mut print_group = |line: &[u8], repeats: u64| -> std::io::Result<()> {
+        if (only_repeated && repeats == 1) || (only_unique && repeats > 1) {
+            return Ok(());
+        }
+
+        if count {
+            write!(out, "{:>7} ", repeats)?;
+        }
+        out.write_all(line)?;
+        out.write_all(&[delimiter])
+    };
+
+    let mut group = Vec::new();
+    let mut line = Vec::new();
+    let mut repeats = 0;
+
+    let result = (|| -> std::io::Result<()> {
+        while read_line(input.as_mut(), delimiter, &mut line)? {
+            if repeats > 0 && comparison.equal(&group, &line) {
+                repeats += 1;
+                continue;
+            }
+
+            if repeats > 0 {
+                print_group(&group, repeats)?;
+            }
+            std::mem::swap(&mut group, &mut line);
+            repeats = 1;
+        }
+
+        if repeats > 0 {
+            print_group(&group, repeats)?;
+        }
+
+        Ok(())
+    })();
+
+    if let Err(e) = result.and_then(|_| out.flush()) {
+        eprintln!("uniq: {}", error_reason(&e));
+        std::process::exit(-160);
+    }
+}
@@ -1,105 +1,634 @@
 //! Utilitary functions for commands.
 
+use crate::regex::Regex;
+use crate::walk::Walk;
+use chrono::{Datelike, TimeZone};
 use std::{
-    io::BufRead,
-    os::unix::prelude::{MetadataExt, PermissionsExt},
+    io::{BufRead, Write},
+    os::unix::prelude::{MetadataExt, OsStrExt, OsStringExt, PermissionsExt},
+    path::{Path, PathBuf},
 };
 
-/// A clojure that returns true if current character matches the token.
-/// Its inputs are the string to match and the current position in the string.
-type TokenValidator = Box<dyn Fn(&Vec<char>, &mut usize) -> bool>;
-
-type Pattern = Vec<TokenValidator>;
-
 /// Split args into options (flags) and arguments.
 pub fn extract_options(args: &[String]) -> (Vec<&String>, Vec<&String>) {
-    args.iter().partition(|arg| arg.starts_with("-"))
-}
-
-/// Compile a pattern into a list of clojures.
-pub fn compile_expr(pattern_str: &String) -> Pattern {
-    let mut pattern = Pattern::new();
-
-    for token in pattern_str.chars() {
-        let validator: TokenValidator = match token {
-            '^' => Box::new(|_, i| *i == 0),
-            '$' => Box::new(|s, i| *i == s.len()),
-            '*' => {
-                // Take the previous clojure and create a
-                // new one that matches it 0 or more times.
-                let Some(prev_validator) = pattern.pop() else {
-                    eprintln!("grep: invalid pattern '{}'", pattern_str);
-                    std::process::exit(-100);
-                };
+    args.iter().partition(|arg| arg.starts_with('-'))
+}
+
+/// Options parsed by `parse_options`, as `(name, value)` pairs.
+pub type Options = Vec<(String, Option<String>)>;
 
-                Box::new(move |s, i| {
-                    // Match the previous clojure as many times as possible.
-                    while prev_validator(s, i) && *i < s.len() {}
+/// Split args into options and arguments, like `extract_options`, but also
+/// split grouped short flags (`-sf` into `-s` and `-f`). The options listed
+/// in `with_value` take a value, either attached (`-tDIR`, `--target=DIR`)
+/// or as the next argument. A lone `-` is an argument, and everything after
+/// `--` is an argument too. Returns `None` if an option misses its value.
+pub fn parse_options<'a>(
+    args: &'a [String],
+    with_value: &[&str],
+) -> Option<(Options, Vec<&'a String>)> {
+    let mut opts = Options::new();
+    let mut operands = Vec::new();
+    let mut args = args.iter();
 
-                    // Go back to the character that didn't match the
-                    // wildcard so it can be matched with the next token.
-                    *i -= 1;
-                    true
-                })
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            operands.extend(args);
+            break;
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            if let Some((name, value)) = long.split_once('=') {
+                opts.push((format!("--{}", name), Some(value.to_owned())));
+            } else if with_value.contains(&arg.as_str()) {
+                opts.push((arg.to_owned(), Some(args.next()?.to_owned())));
+            } else {
+                opts.push((arg.to_owned(), None));
             }
-            '.' => Box::new(|_, i| {
-                *i += 1;
-
-                // Match anything.
-                true
-            }),
-            chr => Box::new(move |s, i| {
-                *i += 1;
-                s.get(*i - 1) == Some(&chr)
-            }),
-        };
+        } else if let Some(short) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            for (i, flag) in short.char_indices() {
+                let name = format!("-{}", flag);
 
-        pattern.push(validator);
+                if with_value.contains(&name.as_str()) {
+                    // The rest of the group is the value (`-tDIR`).
+                    let rest = &short[i + flag.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next()?.to_owned()
+                    } else {
+                        rest.to_owned()
+                    };
+
+                    opts.push((name, Some(value)));
+                    break;
+                }
+
+                opts.push((name, None));
+            }
+        } else {
+            operands.push(arg);
+        }
     }
 
-    pattern
+    Some((opts, operands))
 }
 
-/// Try to match a pattern starting at the beginning of a string.
-fn match_substr(pattern: &Pattern, string: &Vec<char>, start_pos: usize) -> bool {
-    let mut pattern_cursor = pattern.iter();
-    let mut i = start_pos;
+/// Find where the options end and the operands start, for commands whose
+/// operands are another command, with its own options (`xargs rm -f`).
+/// The options listed in `with_value` take a value, like for `parse_options`.
+pub fn operands_start(args: &[String], with_value: &[&str]) -> usize {
+    let mut i = 0;
 
-    loop {
-        let Some(token_action) = pattern_cursor.next() else {
-            // Pattern ended.
-            return true;
-        };
+    while let Some(arg) = args.get(i) {
+        i += 1;
 
-        if !token_action(string, &mut i) {
-            // Pattern didn't match.
+        if arg == "--" {
             break;
         }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            if !long.contains('=') && with_value.contains(&arg.as_str()) {
+                i += 1;
+            }
+        } else if let Some(short) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            // Only a value option at the end of the group takes the next
+            // argument (`-tn 5`), otherwise the value is attached (`-n5`).
+            if let Some((pos, flag)) = short
+                .char_indices()
+                .find(|(_, flag)| with_value.contains(&format!("-{}", flag).as_str()))
+            {
+                if pos + flag.len_utf8() == short.len() {
+                    i += 1;
+                }
+            }
+        } else {
+            return i - 1;
+        }
+    }
+
+    i.min(args.len())
+}
+
+/// Describe an I/O error like `strerror` does, without
+/// the "(os error N)" suffix added by the standard library.
+pub fn error_reason(err: &std::io::Error) -> String {
+    let message = err.to_string();
+
+    match message.find(" (os error") {
+        Some(end) => message[..end].to_owned(),
+        None => message,
+    }
+}
+
+/// Open a file for streaming its contents, or the standard input for `-`.
+pub fn open_input(path: &str) -> std::io::Result<Box<dyn BufRead>> {
+    if path == "-" {
+        return Ok(Box::new(std::io::stdin().lock()));
+    }
+
+    let file = std::fs::File::open(path)?;
+    Ok(Box::new(std::io::BufReader::with_capacity(1 << 16, file)))
+}
+
+/// Copy each input (the standard input for `-`, or if there are none) to
+/// the standard output with `copy`, like `cat`. Exits with `code` at the
+/// first error.
+pub fn cat_inputs(
+    command: &str,
+    code: i32,
+    paths: &[impl AsRef<str>],
+    copy: impl Fn(&mut dyn BufRead, &mut dyn Write) -> std::io::Result<()>,
+) {
+    let stdin = ["-"];
+    let paths: Vec<&str> = if paths.is_empty() {
+        stdin.to_vec()
+    } else {
+        paths.iter().map(|path| path.as_ref()).collect()
+    };
+
+    let mut stdout = std::io::stdout().lock();
+
+    for path in paths {
+        let mut input = match open_input(path) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("{}: {}: {}", command, path, error_reason(&e));
+                std::process::exit(code);
+            }
+        };
+
+        if let Err(e) = copy(&mut input, &mut stdout) {
+            eprintln!("{}: {}: {}", command, path, error_reason(&e));
+            std::process::exit(code);
+        }
     }
+}
+
+/// Decode the escape sequence at the start of `escape`, which starts with
+/// a backslash: `\\`, `\a`, `\b`, `\f`, `\n`, `\r`, `\t`, `\v` or up to three
+/// octal digits. Returns the byte and the length of the sequence. A lone
+/// or unknown escape stands for the backslash or the character itself.
+pub fn unescape(escape: &[u8]) -> (u8, usize) {
+    let octal_len = escape[1..]
+        .iter()
+        .take(3)
+        .take_while(|b| (b'0'..=b'7').contains(b))
+        .count();
+    if octal_len > 0 {
+        let value = escape[1..=octal_len]
+            .iter()
+            .fold(0u32, |value, digit| value * 8 + (digit - b'0') as u32);
+        return ((value & 0xff) as u8, 1 + octal_len);
+    }
+
+    match escape.get(1) {
+        None => (b'\\', 1),
+        Some(b'a') => (0x07, 2),
+        Some(b'b') => (0x08, 2),
+        Some(b'f') => (0x0c, 2),
+        Some(b'n') => (b'\n', 2),
+        Some(b'r') => (b'\r', 2),
+        Some(b't') => (b'\t', 2),
+        Some(b'v') => (0x0b, 2),
+        Some(&chr) => (chr, 2),
+    }
+}
+
+/// Create a new file in `dir`, with a unique name starting with `prefix`.
+pub fn create_temp_file(dir: &Path, prefix: &str) -> std::io::Result<(std::fs::File, PathBuf)> {
+    use std::os::fd::FromRawFd;
+
+    let template = dir.join(format!("{}XXXXXX", prefix));
+    let template = std::ffi::CString::new(template.into_os_string().into_encoded_bytes())
+        .map_err(std::io::Error::other)?;
+    let mut template = template.into_bytes_with_nul();
+
+    // SAFETY: `template` is a valid, writable C string.
+    let fd = unsafe { libc::mkstemp(template.as_mut_ptr().cast()) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    // SAFETY: the file descriptor was just created and is owned here.
+    let file = unsafe { std::fs::File::from_raw_fd(fd) };
+
+    template.pop();
+    let path = PathBuf::from(std::ffi::OsString::from_vec(template));
+
+    Ok((file, path))
+}
+
+/// Replace the contents of a file, through a temporary file renamed over
+/// it, keeping its permissions and owner. The temporary file's name starts
+/// with `prefix`.
+pub fn write_file(path: &Path, prefix: &str, data: &[u8]) -> std::io::Result<()> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    std::fs::create_dir_all(dir)?;
+
+    let metadata = std::fs::metadata(path).ok();
+    let (mut temp, temp_path) = create_temp_file(dir, prefix)?;
+
+    let result = (|| -> std::io::Result<()> {
+        match &metadata {
+            Some(metadata) => {
+                temp.set_permissions(metadata.permissions())?;
+
+                let temp_path_c = std::ffi::CString::new(temp_path.as_os_str().as_bytes()).unwrap();
+                // SAFETY: the path is a valid C string.
+                unsafe { libc::chown(temp_path_c.as_ptr(), metadata.uid(), metadata.gid()) };
+            }
+            None => {
+                // A new file gets the usual permissions, not the private
+                // ones of temporary files.
+                let mode = 0o666 & !current_umask();
+                std::fs::set_permissions(&temp_path, std::fs::Permissions::from_mode(mode))?;
+            }
+        }
+
+        temp.write_all(data)?;
+        std::fs::rename(&temp_path, path)
+    })();
 
-    // Pattern didn't match or string
-    // ended before the pattern.
-    false
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// Parse a size with an optional multiplier suffix: `b` (512), `K` or `KiB`
+/// (1024), `KB` (1000), and likewise `M`, `G`, `T`, `P` and `E`.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let digits_end = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (number, suffix) = size.split_at(digits_end);
+
+    let number = if number.is_empty() && !suffix.is_empty() {
+        1
+    } else {
+        number.parse::<u64>().ok()?
+    };
+
+    let multiplier = match suffix {
+        "" => 1,
+        "b" => 512,
+        _ => {
+            let mut suffix = suffix.chars();
+            let power = "KMGTPE".find(suffix.next()?.to_ascii_uppercase())? as u32 + 1;
+            let base: u64 = match suffix.as_str() {
+                "" | "iB" => 1024,
+                "B" => 1000,
+                _ => return None,
+            };
+
+            base.checked_pow(power)?
+        }
+    };
+
+    number.checked_mul(multiplier)
+}
+
+/// Format a size in bytes for humans, like `ls -h` does: rounded up to
+/// powers of 1024 (`K`, `M`, `G`...), with a decimal below 10 (`1.5K`,
+/// `12M`). Sizes below 1024 are written as they are.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: &[u8] = b"KMGTPE";
+    let bytes = bytes as u128;
+
+    let mut unit = 0;
+    let mut scale = 1;
+    while unit < UNITS.len() && bytes >= scale * 1024 {
+        scale *= 1024;
+        unit += 1;
+    }
+    if unit == 0 {
+        return bytes.to_string();
+    }
+
+    let tenths = (bytes * 10).div_ceil(scale);
+    if tenths < 100 {
+        return format!("{}.{}{}", tenths / 10, tenths % 10, UNITS[unit - 1] as char);
+    }
+
+    let whole = bytes.div_ceil(scale);
+    if whole >= 1024 && unit < UNITS.len() {
+        format!("1.0{}", UNITS[unit] as char)
+    } else {
+        format!("{}{}", whole, UNITS[unit - 1] as char)
+    }
+}
+
+/// Load the character type settings of the locale from the environment,
+/// and check if its characters are encoded in UTF-8. Otherwise, every byte
+/// is a character, as in the "C" locale.
+pub fn locale_is_utf8() -> bool {
+    // SAFETY: both strings are valid C strings, and the returned one is
+    // only read before calling into the locale functions again.
+    unsafe {
+        libc::setlocale(libc::LC_CTYPE, c"".as_ptr());
+        let codeset = std::ffi::CStr::from_ptr(libc::nl_langinfo(libc::CODESET));
+        let codeset = codeset.to_string_lossy().to_ascii_uppercase();
+
+        codeset == "UTF-8" || codeset == "UTF8"
+    }
+}
+
+/// Compile a `grep` pattern (a basic regular expression).
+pub fn compile_expr(pattern_str: &str) -> Regex {
+    match Regex::new(pattern_str, false, false) {
+        Ok(pattern) => pattern,
+        Err(message) => {
+            eprintln!("grep: {}", message);
+            std::process::exit(-100);
+        }
+    }
 }
 
 /// Try to match a pattern against a substring of `string`.
-pub fn match_expr(pattern: &Pattern, string: &String) -> bool {
-    // Convert to Vec<char> for O(1) random access (because
-    // String contains variable size chars).
-    let chars = string.chars().collect::<Vec<_>>();
-
-    for i in 0..chars.len() {
-        if match_substr(&pattern, &chars, i) {
-            return true;
+pub fn match_expr(pattern: &Regex, string: &str) -> bool {
+    pattern.is_match(string)
+}
+
+/// Check if `name` matches a shell wildcard pattern, like `fnmatch` does:
+/// `*` matches any string, `?` any character, `[...]` a set of characters
+/// (`[!...]` or `[^...]` negated, with ranges like `a-z`) and `\` escapes.
+pub fn glob_match(pattern: &str, name: &str, ignore_case: bool) -> bool {
+    let normalize = |s: &str| {
+        if ignore_case {
+            s.to_lowercase().chars().collect::<Vec<_>>()
+        } else {
+            s.chars().collect::<Vec<_>>()
+        }
+    };
+
+    glob_match_chars(&normalize(pattern), &normalize(name))
+}
+
+fn glob_match_chars(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to resume after the last '*', if the match fails.
+    let mut backtrack = None;
+
+    while n < name.len() {
+        let step = match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+                continue;
+            }
+            Some('?') => Some(1),
+            Some('[') => match_bracket(&pattern[p..], name[n]),
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == name[n]).then_some(2),
+            Some(&chr) => (chr == name[n]).then_some(1),
+            None => None,
+        };
+
+        match (step, backtrack) {
+            (Some(len), _) => {
+                p += len;
+                n += 1;
+            }
+            // Let the last '*' consume one more character.
+            (None, Some((star_p, star_n))) => {
+                backtrack = Some((star_p, star_n + 1));
+                p = star_p + 1;
+                n = star_n + 1;
+            }
+            (None, None) => return false,
         }
     }
 
-    false
+    pattern[p..].iter().all(|&c| c == '*')
 }
 
-/// Search the name of a user or group by its id in the file `path`.
-/// The file must be formatted like `/etc/passwd` or `/etc/group`.
-fn search_id_name(path: &str, target_id: u32) -> Option<String> {
+/// Match `chr` against the bracket expression at the start of `pattern`.
+/// Returns the length of the expression if it matches.
+fn match_bracket(pattern: &[char], chr: char) -> Option<usize> {
+    let mut i = 1;
+    let negated = matches!(pattern.get(i), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+
+    let mut matched = false;
+    let mut first = true;
+
+    loop {
+        match pattern.get(i) {
+            // An unterminated bracket is a literal '['.
+            None => return (chr == '[').then_some(1),
+            Some(']') if !first => break,
+            Some('[') if pattern.get(i + 1) == Some(&':') => {
+                let rest = pattern[i + 2..].iter().collect::<String>();
+                let Some(end) = rest.find(":]") else {
+                    return (chr == '[').then_some(1);
+                };
+
+                matched |= char_class_matches(&rest[..end], chr);
+                i += 2 + rest[..end].chars().count() + 2;
+            }
+            Some(&start) => {
+                if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|&c| c != ']')
+                {
+                    matched |= (start..=pattern[i + 2]).contains(&chr);
+                    i += 3;
+                } else {
+                    matched |= start == chr;
+                    i += 1;
+                }
+            }
+        }
+
+        first = false;
+    }
+
+    (matched != negated).then_some(i + 1)
+}
+
+/// The names of the POSIX character classes.
+pub const CHAR_CLASSES: [&str; 12] = [
+    "alnum", "alpha", "blank", "cntrl", "digit", "graph", "lower", "print", "punct", "space",
+    "upper", "xdigit",
+];
+
+/// Check if `chr` is part of a POSIX character class (such as `upper`).
+pub fn char_class_matches(class: &str, chr: char) -> bool {
+    match class {
+        "alnum" => chr.is_alphanumeric(),
+        "alpha" => chr.is_alphabetic(),
+        "blank" => chr == ' ' || chr == '\t',
+        "cntrl" => chr.is_control(),
+        "digit" => chr.is_ascii_digit(),
+        "graph" => !chr.is_control() && !chr.is_whitespace(),
+        "lower" => chr.is_lowercase(),
+        "print" => !chr.is_control(),
+        "punct" => chr.is_ascii_punctuation(),
+        "space" => chr.is_whitespace(),
+        "upper" => chr.is_uppercase(),
+        "xdigit" => chr.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
+
+/// Find where `src` ends up when copied or linked to `dest`. If `dest` is an
+/// existing directory, `src` is placed *inside* it, named as its basename.
+/// With `follow` unset, a symlink to a directory isn't treated as one.
+pub fn resolve_dest(src: &Path, dest: &Path, follow: bool) -> std::io::Result<PathBuf> {
+    let metadata = if follow {
+        std::fs::metadata(dest)
+    } else {
+        std::fs::symlink_metadata(dest)
+    };
+
+    match metadata {
+        Ok(metadata) if metadata.is_dir() => {
+            let Some(basename) = src.file_name() else {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "failed to get basename",
+                ));
+            };
+
+            Ok(dest.join(basename))
+        }
+        Ok(_) => Ok(dest.to_owned()),
+        // If dest doesn't exist, the destination is a file.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(dest.to_owned()),
+        // A dangling or looping symlink is not a directory either.
+        Err(_) if std::fs::symlink_metadata(dest).is_ok() => Ok(dest.to_owned()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Compute the path that leads from the directory `from` to `to`.
+/// Both paths must be absolute and normalized.
+pub fn relative_path(from: &Path, to: &Path) -> PathBuf {
+    let from = from.components().collect::<Vec<_>>();
+    let to = to.components().collect::<Vec<_>>();
+
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+
+    let mut path = PathBuf::new();
+    for _ in common..from.len() {
+        path.push("..");
+    }
+    for component in &to[common..] {
+        path.push(component);
+    }
+
+    if path.as_os_str().is_empty() {
+        path.push(".");
+    }
+
+    path
+}
+
+/// Build a local date, picking the earliest one if it's ambiguous (DST).
+fn local_date(date: chrono::NaiveDateTime) -> Option<chrono::DateTime<chrono::Local>> {
+    chrono::Local.from_local_datetime(&date).earliest()
+}
+
+/// Parse a date given in one of the common formats, like `date -d` does.
+/// Accepts `now`, `today`, `yesterday`, `tomorrow`, `@SECONDS`, RFC 3339,
+/// RFC 2822 and ISO 8601-like dates, with optional time and time zone.
+pub fn parse_date(date_str: &str) -> Option<chrono::DateTime<chrono::Local>> {
+    let date_str = date_str.trim();
+    let now = chrono::Local::now();
+
+    match date_str {
+        "now" => return Some(now),
+        "today" => return local_date(now.date_naive().and_hms_opt(0, 0, 0)?),
+        "yesterday" => return Some(now - chrono::Duration::days(1)),
+        "tomorrow" => return Some(now + chrono::Duration::days(1)),
+        _ => {}
+    }
+
+    if let Some(seconds) = date_str.strip_prefix('@') {
+        let (secs, nanos) = match seconds.split_once('.') {
+            Some((secs, fraction)) => {
+                // Pad (or cut) the fraction to nanoseconds.
+                let fraction = format!("{:0<9}", fraction);
+                (secs.parse().ok()?, fraction.get(..9)?.parse().ok()?)
+            }
+            None => (seconds.parse().ok()?, 0),
+        };
+
+        return chrono::Local.timestamp_opt(secs, nanos).single();
+    }
+
+    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(date_str) {
+        return Some(date.into());
+    }
+    if let Ok(date) = chrono::DateTime::parse_from_rfc2822(date_str) {
+        return Some(date.into());
+    }
+
+    let date_str = date_str.replacen('T', " ", 1);
+
+    for format in [
+        "%Y-%m-%d %H:%M:%S%.f %z",
+        "%Y-%m-%d %H:%M:%S%.f%z",
+        "%Y-%m-%d %H:%M %z",
+    ] {
+        if let Ok(date) = chrono::DateTime::parse_from_str(&date_str, format) {
+            return Some(date.into());
+        }
+    }
+
+    for format in [
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+        "%Y/%m/%d %H:%M:%S",
+    ] {
+        if let Ok(date) = chrono::NaiveDateTime::parse_from_str(&date_str, format) {
+            return local_date(date);
+        }
+    }
+
+    for format in ["%Y-%m-%d", "%Y/%m/%d", "%d %b %Y", "%b %d %Y"] {
+        if let Ok(date) = chrono::NaiveDate::parse_from_str(&date_str, format) {
+            return local_date(date.and_hms_opt(0, 0, 0)?);
+        }
+    }
+
+    None
+}
+
+/// Parse a timestamp formatted as `[[CC]YY]MMDDhhmm[.ss]` (`touch -t`).
+/// A missing year means the current one, while a two digit year
+/// means 1969-1999 for values from 69 and 2000-2068 otherwise.
+pub fn parse_touch_stamp(stamp: &str) -> Option<chrono::DateTime<chrono::Local>> {
+    let (digits, seconds) = match stamp.split_once('.') {
+        Some((digits, seconds)) if seconds.len() == 2 => (digits, seconds.parse().ok()?),
+        Some(_) => return None,
+        None => (stamp, 0),
+    };
+
+    if !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+
+    let field = |start: usize| digits[start..start + 2].parse::<u32>().ok();
+
+    let (year, rest) = match digits.len() {
+        8 => (chrono::Local::now().year(), 0),
+        10 => {
+            let year = field(0)? as i32;
+            (if year >= 69 { 1900 + year } else { 2000 + year }, 2)
+        }
+        12 => (digits[..4].parse().ok()?, 4),
+        _ => return None,
+    };
+
+    let date = chrono::NaiveDate::from_ymd_opt(year, field(rest)?, field(rest + 2)?)?;
+    local_date(date.and_hms_opt(field(rest + 4)?, field(rest + 6)?, seconds)?)
+}
+
+/// Search the entry of a user or group for which `matches(name, id)` holds
+/// in the file `path`, returning all of its fields. The file must be
+/// formatted like `/etc/passwd` or `/etc/group`.
+fn search_id_entry(path: &str, matches: impl Fn(&str, u32) -> bool) -> Option<Vec<String>> {
     let Ok(file) = std::fs::File::open(path) else {
         return None;
     };
@@ -109,13 +638,10 @@ fn search_id_name(path: &str, target_id: u32) -> Option<String> {
             return None;
         };
 
-        let mut fields = line.split(':');
+        let fields = line.split(':').collect::<Vec<_>>();
 
         // `name` and `id` are fields 0 and 2.
-        let name = fields.next();
-        let id = fields.nth(1);
-
-        let (Some(name), Some(id)) = (name, id) else {
+        let (Some(name), Some(id)) = (fields.first(), fields.get(2)) else {
             return None;
         };
 
@@ -123,24 +649,63 @@ fn search_id_name(path: &str, target_id: u32) -> Option<String> {
             return None;
         };
 
-        if id == target_id {
-            return Some(name.to_owned());
+        if matches(name, id) {
+            return Some(fields.into_iter().map(str::to_owned).collect());
         }
     }
 
     None
 }
 
-fn print_file_info(path_prefix: &String, path: &String, long: bool) {
+/// Search the name of a user or group by its id in the file `path`.
+/// The file must be formatted like `/etc/passwd` or `/etc/group`.
+pub fn search_id_name(path: &str, target_id: u32) -> Option<String> {
+    let mut fields = search_id_entry(path, |_, id| id == target_id)?;
+
+    Some(fields.swap_remove(0))
+}
+
+/// Get the name of a user or group, or its id if it has no name.
+pub fn id_name(path: &str, id: u32) -> String {
+    search_id_name(path, id).unwrap_or_else(|| id.to_string())
+}
+
+/// Find the id of a user by its name (or the name itself if it's numeric),
+/// along with the id of its login group, if it's known (by name or by id).
+pub fn resolve_user(name: &str) -> Option<(u32, Option<u32>)> {
+    match search_id_entry("/etc/passwd", |user, _| user == name) {
+        // The login group is the 4th field.
+        Some(fields) => Some((fields[2].parse().ok()?, fields.get(3)?.parse().ok())),
+        None => {
+            let uid = name.parse().ok()?;
+            // A numeric user still has a login group if its id is known.
+            let login_gid = search_id_entry("/etc/passwd", |_, id| id == uid)
+                .and_then(|fields| fields.get(3)?.parse().ok());
+            Some((uid, login_gid))
+        }
+    }
+}
+
+/// Find the id of a group by its name (or the name itself if it's numeric).
+pub fn resolve_group(name: &str) -> Option<u32> {
+    match search_id_entry("/etc/group", |group, _| group == name) {
+        Some(fields) => fields[2].parse().ok(),
+        None => name.parse().ok(),
+    }
+}
+
+/// Print the file found at `actual_path`, showing it as `path`.
+fn print_file_info(actual_path: &Path, path: &str, long: bool) {
     if !long {
         println!("{}", path);
         return;
     }
 
-    let actual_path = path_prefix.clone() + "/" + path;
-
-    let Ok(metadata) = std::fs::metadata(&actual_path) else {
-        eprintln!("ls: failed reading metadata for '{}'", actual_path);
+    let Ok(metadata) = std::fs::metadata(actual_path) else {
+        eprintln!(
+            "ls: failed reading metadata for '{}'",
+            actual_path.display()
+        );
         std::process::exit(-80);
     };
 
@@ -157,17 +722,7 @@ fn print_file_info(path_prefix: &String, path: &String, long: bool) {
         '-'
     });
 
-    for group_mask in (0..3).rev() {
-        let group_perm = file_mode >> (group_mask * 3);
-
-        for (i, chr) in "rwx".chars().enumerate() {
-            if group_perm & (1 << (2 - i)) != 0 {
-                formatted_mode.push(chr);
-            } else {
-                formatted_mode.push('-');
-            }
-        }
-    }
+    formatted_mode.push_str(&format_mode(file_mode));
 
     let Some(owner) = search_id_name("/etc/passwd", metadata.uid()) else {
         std::process::exit(-80);
@@ -200,161 +755,313 @@ fn print_file_info(path_prefix: &String, path: &String, long: bool) {
     );
 }
 
-fn list_dir(path: &String, all: bool, recursive: bool, long: bool) {
-    if recursive {
-        println!("{}:", path);
-    }
-
-    // If '-a' is set, list current and parent directories as well.
-    if all {
-        print_file_info(path, &String::from("."), long);
-        print_file_info(path, &String::from(".."), long);
-    }
-
-    let Ok(contents) = std::fs::read_dir(&path) else {
-        eprintln!("ls: failed reading files from '{}'", path);
-        std::process::exit(-80);
-    };
+fn list_dir(path: &Path, all: bool, recursive: bool, long: bool) {
+    let max_depth = if recursive { usize::MAX } else { 1 };
+    let mut walk = Walk::new(path).max_depth(max_depth);
 
-    for entry in contents {
-        let Ok(entry) = entry else {
-            eprintln!("ls: failed reading files from '{}'", path);
-            std::process::exit(-80);
+    while let Some(entry) = walk.next() {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("ls: failed reading files from '{}'", e.path.display());
+                std::process::exit(-80);
+            }
         };
 
-        if let Some(file_name) = entry.file_name().to_str() {
+        if entry.depth() > 0 {
+            let Some(file_name) = entry.path().file_name().and_then(|name| name.to_str()) else {
+                eprintln!(
+                    "ls: unsupported filename encoding in '{}'",
+                    entry.path().display()
+                );
+                std::process::exit(-80);
+            };
+
             // Skip hidden files unless '-a' option is present.
             if file_name.starts_with('.') && !all {
+                walk.skip_current_dir();
                 continue;
             }
 
-            print_file_info(path, &file_name.to_owned(), long);
+            print_file_info(entry.path(), file_name, long);
+        }
 
-            let Ok(file_type) = entry.file_type() else {
-                eprintln!("ls: failed retrieving metadata of '{}/{}'", path, file_name);
-                std::process::exit(-80);
-            };
+        // The directory is entered next (either the root, or
+        // any directory if '-R' option is present).
+        if entry.is_dir() && (entry.depth() == 0 || recursive) {
+            if recursive {
+                println!("{}:", entry.path().display());
+            }
 
-            // Recurse into directories if '-r' option is present.
-            if file_type.is_dir() && recursive {
-                list_dir(&format!("{}/{}", path, file_name), all, recursive, long);
+            // If '-a' is set, list current and parent directories as well.
+            if all {
+                print_file_info(&entry.path().join("."), ".", long);
+                print_file_info(&entry.path().join(".."), "..", long);
             }
-        } else {
-            eprintln!("ls: unsupported filename encoding in '{}'", path);
-            std::process::exit(-80);
         }
     }
 }
 
 /// List contents of a file or a directory.
-pub fn list_file(path: &String, all: bool, recursive: bool, long: bool) {
+pub fn list_file(path: &Path, all: bool, recursive: bool, long: bool) {
     if let Ok(file_metadata) = std::fs::metadata(path) {
         if file_metadata.is_file() {
-            print_file_info(&String::from(""), path, long);
+            print_file_info(path, &path.to_string_lossy(), long);
             return;
         }
     } else {
-        eprintln!("ls: failed reading metadata for '{}'", path);
+        eprintln!("ls: failed reading metadata for '{}'", path.display());
         std::process::exit(-80);
     }
 
     list_dir(path, all, recursive, long);
 }
 
-/// Copy contents of 'src_root/dir/' to 'dest_root/dir/'.
-pub fn copy_dir(src_root: &String, dest_root: &String, dir: &String) {
-    let full_path = format!("{}/{}", src_root, dir);
-    let Ok(contents) = std::fs::read_dir(&full_path) else {
-        eprintln!("ls: failed reading files from '{}'", full_path);
-        std::process::exit(-90);
-    };
+/// Copy the contents of the directory `src` to `dest`, which must exist.
+/// Symlinks are followed, but loops made by them are reported.
+pub fn copy_dir(src: &Path, dest: &Path) {
+    for entry in Walk::new(src).follow_links(true) {
+        let entry = match entry {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!(
+                    "cp: failed reading '{}': {}",
+                    e.path.display(),
+                    error_reason(&e.error)
+                );
+                std::process::exit(-90);
+            }
+        };
 
-    for entry in contents {
-        let Ok(entry) = entry else {
-            eprintln!("ls: failed reading files from '{}'", full_path);
-            std::process::exit(-90);
+        let Ok(relative_path) = entry.path().strip_prefix(src) else {
+            continue;
         };
 
-        if let Some(file_name) = entry.file_name().to_str() {
-            let full_file_name = format!("{}/{}", full_path, file_name);
-            let file_name = format!("{}/{}", dir, file_name);
-            let full_dest_name = format!("{}/{}", dest_root, file_name);
+        // The root itself already exists.
+        if entry.depth() == 0 {
+            continue;
+        }
 
-            let Ok(metadata) = std::fs::metadata(&full_file_name) else {
-                eprintln!("ls: failed reading metadata of '{}'", full_file_name);
+        let full_dest_name = dest.join(relative_path);
+
+        if entry.is_dir() {
+            if std::fs::create_dir(&full_dest_name).is_err() {
+                eprintln!(
+                    "cp: failed to create directory '{}'",
+                    full_dest_name.display()
+                );
                 std::process::exit(-90);
+            }
+        } else if std::fs::copy(entry.path(), &full_dest_name).is_err() {
+            eprintln!(
+                "cp: failed to move {} to {}",
+                entry.path().display(),
+                full_dest_name.display()
+            );
+            std::process::exit(-90);
+        }
+    }
+}
+
+/// The permissions that a symbolic mode clause operates on.
+pub enum ModePerms {
+    /// A set of literals (`rwxXst`).
+    Literals(Vec<char>),
+    /// The current permissions of a class (`u`, `g` or `o`).
+    Copy(char),
+}
+
+/// A single action of a symbolic mode clause, such as `+x` in `u+x`.
+pub struct ModeAction {
+    operator: char,
+    perms: ModePerms,
+}
+
+/// A comma-separated clause of a symbolic mode, such as `go-w`.
+pub struct ModeClause {
+    /// The bits of the affected classes, or `None` if no class
+    /// was given, meaning all of them, except the umask.
+    who: Option<u32>,
+    actions: Vec<ModeAction>,
+}
+
+/// A mode transformation, parsed from an octal or a symbolic mode.
+pub enum ModeChange {
+    /// The digits of the mode and their count.
+    Octal(u32, usize),
+    Symbolic(Vec<ModeClause>),
+}
+
+/// Parse a mode given either as an octal number (with up to 4 digits,
+/// the first one being the special bits) or in "symbolic mode", as
+/// POSIX describes it: comma separated `[ugoa]*([-+=]([rwxXst]*|[ugo]))+`.
+/// Returns `None` if `mode_str` is invalid.
+pub fn parse_mode(mode_str: &str) -> Option<ModeChange> {
+    if !mode_str.is_empty() && mode_str.bytes().all(|c| (b'0'..=b'7').contains(&c)) {
+        let mode = u32::from_str_radix(mode_str, 8).ok()?;
+
+        return (mode <= 0o7777).then_some(ModeChange::Octal(mode, mode_str.len()));
+    }
+
+    let mut clauses = Vec::new();
+
+    for clause in mode_str.split(',') {
+        let mut chars = clause.chars().peekable();
+        let mut who = None;
+
+        while let Some(c) = chars.next_if(|c| "ugoa".contains(*c)) {
+            *who.get_or_insert(0) |= match c {
+                'u' => 0o4700,
+                'g' => 0o2070,
+                'o' => 0o1007,
+                _ => 0o7777,
             };
+        }
 
-            if metadata.is_dir() {
-                if std::fs::create_dir(&full_dest_name).is_err() {
-                    eprintln!("cp: failed to create directory '{}'", full_dest_name);
-                    std::process::exit(-90);
-                }
+        let mut actions = Vec::new();
 
-                copy_dir(src_root, dest_root, &file_name);
-            } else {
-                if std::fs::copy(&full_file_name, &full_dest_name).is_err() {
-                    eprintln!(
-                        "cp: failed to move {} to {}",
-                        full_file_name, full_dest_name
-                    );
-                    std::process::exit(-90);
+        while let Some(operator) = chars.next_if(|c| "+-=".contains(*c)) {
+            let perms = match chars.next_if(|c| "ugo".contains(*c)) {
+                Some(class) => ModePerms::Copy(class),
+                None => {
+                    let mut literals = Vec::new();
+                    while let Some(c) = chars.next_if(|c| "rwxXst".contains(*c)) {
+                        literals.push(c);
+                    }
+
+                    ModePerms::Literals(literals)
                 }
-            }
-        } else {
-            eprintln!("ls: unsupported filename encoding in '{}'", full_path);
-            std::process::exit(-90);
+            };
+
+            actions.push(ModeAction { operator, perms });
+        }
+
+        // A clause needs at least one action and nothing after them.
+        if actions.is_empty() || chars.next().is_some() {
+            return None;
         }
+
+        clauses.push(ModeClause { who, actions });
     }
+
+    Some(ModeChange::Symbolic(clauses))
 }
 
-/// Converts permision literals from "symbolic mode" into bits.
-/// Also return whether they are to be added or removed.
-/// Returns `None` if `mode_str` is invalid.
-pub fn convert_mode(mode_str: &String) -> Option<(u32, bool)> {
-    let mut user_mask = 0o000;
-    let mut mode_mask = 0o000;
+impl ModeChange {
+    /// Compute the permission bits resulting from applying
+    /// the change to a file having the permissions `mode`.
+    pub fn apply(&self, mode: u32, is_dir: bool, umask: u32) -> u32 {
+        let mut mode = mode & 0o7777;
 
-    let mut modes = mode_str.split_inclusive(['+', '-']);
-    let users = modes.next();
-    let perms = modes.next();
+        let clauses = match self {
+            // Like GNU chmod, directories keep their set-user-ID and
+            // set-group-ID bits, unless the mode has more than 4 digits
+            // (`00755`).
+            ModeChange::Octal(new_mode, digits) if is_dir && *digits <= 4 => {
+                return new_mode | (mode & 0o6000);
+            }
+            ModeChange::Octal(new_mode, _) => return *new_mode,
+            ModeChange::Symbolic(clauses) => clauses,
+        };
 
-    let (Some(user_mode), Some(perm_mode)) = (users, perms) else {
-        return None;
-    };
+        for clause in clauses {
+            let who = clause.who.unwrap_or(0o7777);
+            // Without an explicit class, the umask bits are left alone.
+            let allowed = match clause.who {
+                Some(_) => who,
+                None => who & !umask,
+            };
 
-    let mut user_mode = user_mode.chars();
-    let perm_mode = perm_mode.chars();
+            for action in &clause.actions {
+                let perms = match &action.perms {
+                    ModePerms::Copy(class) => {
+                        let shift = match class {
+                            'u' => 6,
+                            'g' => 3,
+                            _ => 0,
+                        };
 
-    // Check if these permissions are to be added or removed. The control
-    // character will be the last of `user_mode` (because of `split_inclusive`).
-    let Some(change_mode) = user_mode.next_back() else {
-        return None;
-    };
-    let add_perms = change_mode == '+';
-
-    for c in user_mode {
-        match c {
-            'u' => user_mask |= 0o700,
-            'g' => user_mask |= 0o070,
-            'o' => user_mask |= 0o007,
-            'a' => user_mask |= 0o777,
-            _ => {
-                return None;
+                        // Replicate the class permissions to every class.
+                        ((mode >> shift) & 0o7) * 0o111
+                    }
+                    ModePerms::Literals(literals) => {
+                        literals.iter().fold(0, |perms, c| {
+                            perms
+                                | match c {
+                                    'r' => 0o444,
+                                    'w' => 0o222,
+                                    'x' => 0o111,
+                                    // Execute only for directories or
+                                    // files already executable by someone.
+                                    'X' if is_dir || mode & 0o111 != 0 => 0o111,
+                                    's' => 0o6000,
+                                    't' => 0o1000,
+                                    _ => 0,
+                                }
+                        })
+                    }
+                };
+
+                match action.operator {
+                    '+' => mode |= perms & allowed,
+                    '-' => mode &= !(perms & allowed),
+                    _ => {
+                        // Directories keep their special bits on '='
+                        // unless they are explicitly mentioned.
+                        let cleared = if is_dir { who & 0o1777 } else { who };
+                        mode = (mode & !cleared) | (perms & allowed);
+                    }
+                }
             }
         }
+
+        mode
     }
+}
+
+/// Get the file mode creation mask of the process.
+pub fn current_umask() -> u32 {
+    // `umask` can only be read by setting it, so it has to be restored.
+    // SAFETY: `umask` can't fail.
+    let umask = unsafe { libc::umask(0o022) };
+    unsafe { libc::umask(umask) };
 
-    for c in perm_mode {
-        match c {
-            'r' => mode_mask |= 0o444,
-            'w' => mode_mask |= 0o222,
-            'x' => mode_mask |= 0o111,
-            _ => {
-                return None;
+    umask as u32
+}
+
+/// Format permission bits like `ls -l` does (`rwxr-sr-t`).
+pub fn format_mode(mode: u32) -> String {
+    let mut formatted_mode = String::new();
+
+    for group_mask in (0..3).rev() {
+        let group_perm = mode >> (group_mask * 3);
+
+        for (i, chr) in "rwx".chars().enumerate() {
+            if group_perm & (1 << (2 - i)) != 0 {
+                formatted_mode.push(chr);
+            } else {
+                formatted_mode.push('-');
             }
         }
+
+        // The special bits replace the execute permission ('s' or 't'),
+        // being uppercase if it isn't set.
+        let (special_bit, special_chr) = match group_mask {
+            2 => (0o4000, 's'),
+            1 => (0o2000, 's'),
+            _ => (0o1000, 't'),
+        };
+
+        if mode & special_bit != 0 {
+            let executable = group_perm & 1 != 0;
+            formatted_mode.pop();
+            formatted_mode.push(if executable {
+                special_chr
+            } else {
+                special_chr.to_ascii_uppercase()
+            });
+        }
     }
 
-    Some((user_mask & mode_mask, add_perms))
+    formatted_mode
 }
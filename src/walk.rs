@@ -0,0 +1,409 @@
+//! Recursive directory traversal, shared by the commands that need it.
+
+use std::{
+    collections::VecDeque,
+    fs::Metadata,
+    os::unix::prelude::MetadataExt,
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex},
+};
+
+/// A file found while walking a directory tree.
+#[derive(Clone)]
+pub struct DirEntry {
+    path: PathBuf,
+    depth: usize,
+    metadata: Metadata,
+}
+
+impl DirEntry {
+    /// The path of the file, starting with the root of the walk.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// How deep the file is in the tree (the root being at depth 0).
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// The metadata of the file, or of the symlink itself if it
+    /// isn't followed.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.metadata.is_dir()
+    }
+
+    /// The identity of the file, used to detect loops.
+    fn id(&self) -> (u64, u64) {
+        (self.metadata.dev(), self.metadata.ino())
+    }
+}
+
+/// An error encountered while walking a directory tree.
+pub struct WalkError {
+    pub path: PathBuf,
+    pub error: std::io::Error,
+}
+
+/// A directory whose contents are being walked.
+struct Frame {
+    /// `None` if the directory couldn't be read or was fully walked.
+    entries: Option<std::fs::ReadDir>,
+    path: PathBuf,
+    depth: usize,
+    /// The directory itself, to be returned after its contents
+    /// when walking contents first.
+    dir: Option<DirEntry>,
+    /// The identities of the directory and of its ancestors.
+    ids: Vec<(u64, u64)>,
+}
+
+/// The settings of a walk.
+#[derive(Clone)]
+struct Options {
+    follow_root: bool,
+    follow_links: bool,
+    max_depth: usize,
+    contents_first: bool,
+    breadth_first: bool,
+    same_file_system: bool,
+}
+
+impl Options {
+    /// Get the metadata of `path`, following it if needed. A dangling
+    /// symlink is returned as is, even if it had to be followed.
+    fn read_entry(&self, path: PathBuf, depth: usize) -> Result<DirEntry, WalkError> {
+        let follow = if depth == 0 {
+            self.follow_root || self.follow_links
+        } else {
+            self.follow_links
+        };
+
+        let metadata = if follow {
+            std::fs::metadata(&path).or_else(|_| std::fs::symlink_metadata(&path))
+        } else {
+            std::fs::symlink_metadata(&path)
+        };
+
+        match metadata {
+            Ok(metadata) => Ok(DirEntry {
+                path,
+                depth,
+                metadata,
+            }),
+            Err(error) => Err(WalkError { path, error }),
+        }
+    }
+
+    /// Check if the contents of `entry` should be walked.
+    fn should_enter(&self, entry: &DirEntry, root_dev: u64) -> bool {
+        entry.is_dir()
+            && entry.depth < self.max_depth
+            && (!self.same_file_system || entry.metadata.dev() == root_dev)
+    }
+}
+
+/// Start reading the contents of `dir`, whose ancestors are `ids`.
+/// If `dir` is one of its own ancestors, the symlinks made a loop.
+fn open_dir(
+    dir: &DirEntry,
+    mut ids: Vec<(u64, u64)>,
+) -> (Result<std::fs::ReadDir, WalkError>, Vec<(u64, u64)>) {
+    let id = dir.id();
+
+    let entries = if ids.contains(&id) {
+        Err(std::io::Error::other("File system loop detected"))
+    } else {
+        std::fs::read_dir(&dir.path)
+    };
+    ids.push(id);
+
+    let entries = entries.map_err(|error| WalkError {
+        path: dir.path.clone(),
+        error,
+    });
+
+    (entries, ids)
+}
+
+/// An iterator over a directory tree, returning the root first. By default,
+/// the tree is walked depth-first, returning each directory before its
+/// contents. Loops made by symlinks are detected by comparing each
+/// directory with its ancestors, so they are reported instead of followed.
+pub struct Walk {
+    root: Option<PathBuf>,
+    root_dev: u64,
+    options: Options,
+    stack: Vec<Frame>,
+    /// The directories left to walk breadth-first, with their ancestors.
+    queue: VecDeque<(DirEntry, Vec<(u64, u64)>)>,
+    /// The last directory returned, to be entered on the next step.
+    pending: Option<DirEntry>,
+}
+
+impl Walk {
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Walk {
+            root: Some(root.as_ref().to_owned()),
+            root_dev: 0,
+            options: Options {
+                follow_root: true,
+                follow_links: false,
+                max_depth: usize::MAX,
+                contents_first: false,
+                breadth_first: false,
+                same_file_system: false,
+            },
+            stack: Vec::new(),
+            queue: VecDeque::new(),
+            pending: None,
+        }
+    }
+
+    /// Follow every symlink (default: false).
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.options.follow_links = follow;
+        self
+    }
+
+    /// Follow the root if it's a symlink (default: true).
+    pub fn follow_root(mut self, follow: bool) -> Self {
+        self.options.follow_root = follow;
+        self
+    }
+
+    /// Don't descend into directories deeper than `depth`.
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = depth;
+        self
+    }
+
+    /// Return the contents of a directory before the directory itself
+    /// (default: false). It has no effect when walking breadth-first.
+    pub fn contents_first(mut self, contents_first: bool) -> Self {
+        self.options.contents_first = contents_first;
+        self
+    }
+
+    /// Return every file of a level before going deeper (default: false).
+    pub fn breadth_first(mut self, breadth_first: bool) -> Self {
+        self.options.breadth_first = breadth_first;
+        self
+    }
+
+    /// Don't descend into directories from other file systems than the
+    /// one of the root (default: false).
+    pub fn same_file_system(mut self, same_file_system: bool) -> Self {
+        self.options.same_file_system = same_file_system;
+        self
+    }
+
+    /// Don't descend into the directory that was just returned.
+    pub fn skip_current_dir(&mut self) {
+        if !self.is_contents_first() {
+            self.pending = None;
+        }
+    }
+
+    fn is_contents_first(&self) -> bool {
+        self.options.contents_first && !self.options.breadth_first
+    }
+
+    /// Start walking the contents of `dir`, whose ancestors are `ids`.
+    fn enter_dir(&mut self, dir: DirEntry, ids: Vec<(u64, u64)>) -> Result<(), WalkError> {
+        let (entries, ids) = open_dir(&dir, ids);
+
+        let (entries, result) = match entries {
+            Ok(entries) => (Some(entries), Ok(())),
+            Err(e) => (None, Err(e)),
+        };
+
+        self.stack.push(Frame {
+            entries,
+            path: dir.path.clone(),
+            depth: dir.depth,
+            dir: self.is_contents_first().then_some(dir),
+            ids,
+        });
+
+        result
+    }
+
+    /// Walk the tree using `threads` threads, calling `visit` for every file
+    /// (and error) found, in no particular order, except that directories
+    /// are visited before their contents. If `visit` returns false for
+    /// a directory, it isn't entered. Walking contents first and
+    /// breadth-first are not supported.
+    pub fn for_each_parallel<F>(self, threads: usize, visit: F)
+    where
+        F: Fn(Result<DirEntry, WalkError>) -> bool + Sync,
+    {
+        let Some(root) = self.root else {
+            return;
+        };
+        let options = self.options;
+
+        let root = match options.read_entry(root, 0) {
+            Ok(root) => root,
+            Err(e) => {
+                visit(Err(e));
+                return;
+            }
+        };
+
+        let root_dev = root.metadata.dev();
+        let enter = options.should_enter(&root, root_dev);
+        if !visit(Ok(root.clone())) || !enter {
+            return;
+        }
+
+        // The directories left to walk (with their ancestors), and
+        // how many threads are still walking one, adding more.
+        let queue = Mutex::new((VecDeque::from([(root, Vec::new())]), 0));
+        let changed = Condvar::new();
+
+        let worker = || loop {
+            let mut state = queue.lock().unwrap();
+            let (dir, ids) = loop {
+                if let Some(job) = state.0.pop_front() {
+                    state.1 += 1;
+                    break job;
+                }
+
+                // Nothing left to walk and nobody can add anything.
+                if state.1 == 0 {
+                    return;
+                }
+
+                state = changed.wait(state).unwrap();
+            };
+            drop(state);
+
+            let (entries, ids) = open_dir(&dir, ids);
+            let mut subdirs = Vec::new();
+
+            match entries {
+                Ok(entries) => {
+                    for entry in entries {
+                        let entry = match entry {
+                            Ok(entry) => options.read_entry(entry.path(), dir.depth + 1),
+                            Err(error) => Err(WalkError {
+                                path: dir.path.clone(),
+                                error,
+                            }),
+                        };
+
+                        match entry {
+                            Ok(entry) if options.should_enter(&entry, root_dev) => {
+                                if visit(Ok(entry.clone())) {
+                                    subdirs.push((entry, ids.clone()));
+                                }
+                            }
+                            entry => {
+                                visit(entry);
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    visit(Err(e));
+                }
+            }
+
+            let mut state = queue.lock().unwrap();
+            state.0.extend(subdirs);
+            state.1 -= 1;
+            changed.notify_all();
+        };
+
+        std::thread::scope(|scope| {
+            for _ in 0..threads.max(1) {
+                scope.spawn(worker);
+            }
+        });
+    }
+}
+
+impl Iterator for Walk {
+    type Item = Result<DirEntry, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(dir) = self.pending.take() {
+                let ids = self
+                    .stack
+                    .last()
+                    .map(|frame| frame.ids.clone())
+                    .unwrap_or_default();
+
+                if self.options.breadth_first {
+                    self.queue.push_back((dir, ids));
+                } else if let Err(e) = self.enter_dir(dir, ids) {
+                    return Some(Err(e));
+                }
+            }
+
+            // Walking breadth-first, there is at most a directory on the
+            // stack, and the next one is taken when it's fully walked.
+            if self.stack.is_empty() {
+                if let Some((dir, ids)) = self.queue.pop_front() {
+                    if let Err(e) = self.enter_dir(dir, ids) {
+                        return Some(Err(e));
+                    }
+                }
+            }
+
+            let entry = if let Some(root) = self.root.take() {
+                let root = self.options.read_entry(root, 0);
+                if let Ok(root) = &root {
+                    self.root_dev = root.metadata.dev();
+                }
+
+                root
+            } else {
+                let frame = self.stack.last_mut()?;
+
+                match frame.entries.as_mut().and_then(|entries| entries.next()) {
+                    Some(Ok(entry)) => self.options.read_entry(entry.path(), frame.depth + 1),
+                    Some(Err(error)) => {
+                        frame.entries = None;
+
+                        Err(WalkError {
+                            path: frame.path.clone(),
+                            error,
+                        })
+                    }
+                    None => {
+                        // The directory was fully walked.
+                        let frame = self.stack.pop()?;
+                        match frame.dir {
+                            Some(dir) => return Some(Ok(dir)),
+                            None => continue,
+                        }
+                    }
+                }
+            };
+
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if self.options.should_enter(&entry, self.root_dev) {
+                if self.is_contents_first() {
+                    // The directory will be returned after its contents.
+                    self.pending = Some(entry);
+                    continue;
+                }
+
+                self.pending = Some(entry.clone());
+            }
+
+            return Some(Ok(entry));
+        }
+    }
+}
@@ -0,0 +1,425 @@
+//! The `wc` command, counting the lines, words, characters and bytes
+//! of files.
+
+use std::io::Read;
+
+use crate::utils::*;
+
+extern "C" {
+    fn iswprint(chr: libc::wchar_t) -> libc::c_int;
+    fn iswspace(chr: libc::wchar_t) -> libc::c_int;
+    fn wcwidth(chr: libc::wchar_t) -> libc::c_int;
+}
+
+/// The counts of a file, in the order they're printed.
+#[derive(Clone, Copy, Default)]
+struct Counts {
+    lines: u64,
+    words: u64,
+    chars: u64,
+    bytes: u64,
+    max_line: u64,
+}
+
+/// Which of the counts are printed.
+struct Selection {
+    lines: bool,
+    words: bool,
+    chars: bool,
+    bytes: bool,
+    max_line: bool,
+}
+
+impl Selection {
+    /// Get the selected counts, in the order they're printed.
+    fn values(&self, counts: &Counts) -> Vec<u64> {
+        [
+            (self.lines, counts.lines),
+            (self.words, counts.words),
+            (self.chars, counts.chars),
+            (self.bytes, counts.bytes),
+            (self.max_line, counts.max_line),
+        ]
+        .into_iter()
+        .filter_map(|(selected, value)| selected.then_some(value))
+        .collect()
+    }
+}
+
+/// Count the occurrences of `byte`. It's written so that
+/// the compiler can vectorize it.
+fn count_byte(data: &[u8], byte: u8) -> u64 {
+    let mut count = 0;
+
+    // The counts of a chunk fit in a byte, so many bytes are compared
+    // at once, and their counts only summed up after each chunk.
+    for chunk in data.chunks(255 * 64) {
+        let mut lanes = [0u8; 64];
+        let mut blocks = chunk.chunks_exact(64);
+
+        for block in &mut blocks {
+            let block: &[u8; 64] = block.try_into().unwrap();
+            for i in 0..64 {
+                lanes[i] += (block[i] == byte) as u8;
+            }
+        }
+
+        count += lanes.iter().map(|&lane| lane as u64).sum::<u64>();
+        count += blocks.remainder().iter().filter(|&&b| b == byte).count() as u64;
+    }
+
+    count
+}
+
+/// Counts the words, characters and line widths of a stream,
+/// decoding its characters as the locale says.
+struct Counter {
+    counts: Counts,
+    utf8: bool,
+    in_word: bool,
+    line_width: u64,
+    /// The code point decoded so far, and how many bytes it still needs.
+    partial: (u32, u32),
+}
+
+impl Counter {
+    fn new(utf8: bool) -> Self {
+        Counter {
+            counts: Counts::default(),
+            utf8,
+            in_word: false,
+            line_width: 0,
+            partial: (0, 0),
+        }
+    }
+
+    /// Count a character, and where it leaves the current word and line.
+    fn add_char(&mut self, chr: u32) {
+        self.counts.chars += 1;
+
+        let (space, printable, width) = match chr {
+            0x0a => {
+                self.counts.lines += 1;
+                self.counts.max_line = self.counts.max_line.max(self.line_width);
+                self.line_width = 0;
+                (true, false, 0)
+            }
+            0x09 => {
+                self.line_width = (self.line_width / 8 + 1) * 8;
+                (true, false, 0)
+            }
+            0x0d | 0x0c => {
+                self.line_width = 0;
+                (true, false, 0)
+            }
+            0x20 => (true, true, 1),
+            0x0b => (true, false, 0),
+            0x21..=0x7e => (false, true, 1),
+            _ if self.utf8 => {
+                // SAFETY: these functions only look at the locale settings.
+                let space = unsafe { iswspace(chr as libc::wchar_t) } != 0;
+                let printable = unsafe { iswprint(chr as libc::wchar_t) } != 0;
+                let width = unsafe { wcwidth(chr as libc::wchar_t) };
+                (space, printable, width.max(0) as u64)
+            }
+            _ => {
+                // SAFETY: `chr` is a byte, as required by these functions.
+                let space = unsafe { libc::isspace(chr as libc::c_int) } != 0;
+                let printable = unsafe { libc::isprint(chr as libc::c_int) } != 0;
+                (space, printable, printable as u64)
+            }
+        };
+
+        self.line_width += width;
+
+        // Like in GNU wc, a word needs a printable character, while the
+        // others neither start nor end a word.
+        if space {
+            self.in_word = false;
+        } else if printable && !self.in_word {
+            self.in_word = true;
+            self.counts.words += 1;
+        }
+    }
+
+    /// Account for bytes that aren't a valid character. Like the
+    /// non-printable characters, they aren't part of a word.
+    fn add_invalid(&mut self) {
+        self.partial = (0, 0);
+    }
+
+    fn add(&mut self, data: &[u8]) {
+        self.counts.bytes += data.len() as u64;
+
+        if !self.utf8 {
+            for &b in data {
+                self.add_char(b as u32);
+            }
+            return;
+        }
+
+        for &b in data {
+            let (code, needed) = self.partial;
+
+            if needed > 0 {
+                if b & 0xc0 == 0x80 {
+                    let code = code << 6 | (b & 0x3f) as u32;
+                    self.partial = (code, needed - 1);
+
+                    if needed == 1 {
+                        // Overlong encodings and surrogates are invalid.
+                        match char::from_u32(code) {
+                            Some(chr) if chr.len_utf8() == utf8_len(code) => {
+                                self.partial = (0, 0);
+                                self.add_char(code)
+                            }
+                            _ => self.add_invalid(),
+                        }
+                    }
+                    continue;
+                }
+
+                // The sequence was cut short, but this byte may start another.
+                self.add_invalid();
+            }
+
+            match b {
+                0x00..=0x7f => self.add_char(b as u32),
+                0xc2..=0xdf => self.partial = ((b & 0x1f) as u32, 1),
+                0xe0..=0xef => self.partial = ((b & 0x0f) as u32, 2),
+                0xf0..=0xf4 => self.partial = ((b & 0x07) as u32, 3),
+                _ => self.add_invalid(),
+            }
+        }
+    }
+
+    fn finish(mut self) -> Counts {
+        if self.partial.1 > 0 {
+            self.add_invalid();
+        }
+
+        self.counts.max_line = self.counts.max_line.max(self.line_width);
+        self.counts
+    }
+}
+
+/// The length of the shortest UTF-8 encoding of `code`.
+fn utf8_len(code: u32) -> usize {
+    match code {
+        0..=0x7f => 1,
+        0x80..=0x7ff => 2,
+        0x800..=0xffff => 3,
+        _ => 4,
+    }
+}
+
+/// Count everything that is selected in `input`, reading it in chunks.
+fn count(input: &mut dyn Read, selection: &Selection, utf8: bool) -> std::io::Result<Counts> {
+    let mut buffer = vec![0; 1 << 18];
+    let only_lines = !(selection.words || selection.chars || selection.max_line);
+
+    let mut counts = Counts::default();
+    let mut counter = Counter::new(utf8);
+
+    loop {
+        let len = match input.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        if only_lines {
+            counts.bytes += len as u64;
+            counts.lines += count_byte(&buffer[..len], b'\n');
+        } else {
+            counter.add(&buffer[..len]);
+        }
+    }
+
+    Ok(if only_lines { counts } else { counter.finish() })
+}
+
+/// Count a file (or the standard input, for `-`). The size of a regular
+/// file is taken from its metadata, if it's the only thing needed. Files
+/// reported as empty are read, since those of `/proc` have no size.
+fn count_file(path: &str, selection: &Selection, utf8: bool) -> std::io::Result<Counts> {
+    if path == "-" {
+        return count(&mut std::io::stdin().lock(), selection, utf8);
+    }
+
+    let mut file = std::fs::File::open(path)?;
+
+    let only_bytes = !(selection.lines || selection.words || selection.chars || selection.max_line);
+    if only_bytes {
+        let metadata = file.metadata()?;
+        if metadata.is_file() && metadata.len() > 0 {
+            return Ok(Counts {
+                bytes: metadata.len(),
+                ..Counts::default()
+            });
+        }
+    }
+
+    count(&mut file, selection, utf8)
+}
+
+/// Read the NUL separated file names given to `--files0-from`.
+fn read_file_list(path: &str) -> std::io::Result<Vec<String>> {
+    let mut list = Vec::new();
+    open_input(path)?.read_to_end(&mut list)?;
+
+    Ok(list
+        .split(|&b| b == b'\0')
+        .map(|name| String::from_utf8_lossy(name).into_owned())
+        .collect())
+}
+
+pub fn wc(args: &[String]) {
+    let Some((opts, files)) = parse_options(args, &["--files0-from"]) else {
+        eprintln!("Usage: wc [-clmwL] [--files0-from=F] [FILE]...");
+        std::process::exit(-140);
+    };
+
+    let mut selection = Selection {
+        lines: false,
+        words: false,
+        chars: false,
+        bytes: false,
+        max_line: false,
+    };
+    let mut files0_from = None;
+
+    for (opt, value) in opts {
+        match opt.as_str() {
+            "-l" | "--lines" => selection.lines = true,
+            "-w" | "--words" => selection.words = true,
+            "-m" | "--chars" => selection.chars = true,
+            "-c" | "--bytes" => selection.bytes = true,
+            "-L" | "--max-line-length" => selection.max_line = true,
+            "--files0-from" => files0_from = value,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-140);
+            }
+        }
+    }
+
+    if !(selection.lines
+        || selection.words
+        || selection.chars
+        || selection.bytes
+        || selection.max_line)
+    {
+        selection.lines = true;
+        selection.words = true;
+        selection.bytes = true;
+    }
+
+    let implicit_stdin = files.is_empty() && files0_from.is_none();
+    let files = match files0_from {
+        Some(_) if !files.is_empty() => {
+            eprintln!("wc: extra operand '{}'", files[0]);
+            eprintln!("file operands cannot be combined with --files0-from");
+            std::process::exit(-140);
+        }
+        Some(list) => match read_file_list(&list) {
+            Ok(mut names) => {
+                // The list ends with a NUL, which doesn't start another name.
+                if names.last().is_some_and(String::is_empty) {
+                    names.pop();
+                }
+                names
+            }
+            Err(e) => {
+                eprintln!(
+                    "wc: cannot open '{}' for reading: {}",
+                    list,
+                    error_reason(&e)
+                );
+                std::process::exit(-140);
+            }
+        },
+        None if files.is_empty() => vec![String::from("-")],
+        None => files.into_iter().cloned().collect(),
+    };
+
+    // Like GNU wc, the columns are as wide as the total size of the files,
+    // and wider when the size isn't known in advance.
+    let single_value = selection.values(&Counts::default()).len() == 1 && files.len() == 1;
+    let width = if single_value {
+        1
+    } else {
+        let mut unknown_size = false;
+        let total_size = files
+            .iter()
+            .map(|path| match std::fs::metadata(path) {
+                Ok(metadata) if path != "-" && metadata.is_file() => metadata.len(),
+                Err(_) if path != "-" => 0,
+                _ => {
+                    unknown_size = true;
+                    0
+                }
+            })
+            .sum::<u64>();
+
+        let width = total_size.to_string().len();
+        if unknown_size {
+            width.max(7)
+        } else {
+            width
+        }
+    };
+
+    let utf8 = locale_is_utf8();
+    let mut total = Counts::default();
+    let mut was_error = false;
+
+    let print = |counts: &Counts, name: &str| {
+        let values = selection
+            .values(counts)
+            .iter()
+            .map(|value| format!("{:>width$}", value, width = width))
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        // The standard input has no name, unless it's given as `-`.
+        if implicit_stdin {
+            println!("{}", values);
+        } else {
+            println!("{} {}", values, name);
+        }
+    };
+
+    for path in &files {
+        if path.is_empty() {
+            eprintln!("wc: invalid zero-length file name");
+            was_error = true;
+            continue;
+        }
+
+        match count_file(path, &selection, utf8) {
+            Ok(counts) => {
+                print(&counts, path);
+
+                total.lines += counts.lines;
+                total.words += counts.words;
+                total.chars += counts.chars;
+                total.bytes += counts.bytes;
+                total.max_line = total.max_line.max(counts.max_line);
+            }
+            Err(e) => {
+                eprintln!("wc: {}: {}", path, error_reason(&e));
+                was_error = true;
+            }
+        }
+    }
+
+    if files.len() > 1 {
+        print(&total, "total");
+    }
+
+    if was_error {
+        std::process::exit(-140);
+    }
+}
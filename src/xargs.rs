@@ -0,0 +1,442 @@
+//! The `xargs` command: build command lines from the standard input.
+
+use std::{
+    io::{BufRead, Write},
+    os::fd::AsRawFd,
+};
+
+use crate::utils::*;
+
+/// How the input is split into arguments.
+enum InputFormat {
+    /// Blank separated, with quotes and backslash escapes.
+    Blanks,
+    /// Separated by the given byte (`-0`, `-d`), taken literally.
+    Delimiter(u8),
+}
+
+/// The limits of a single command line.
+struct Limits {
+    max_args: usize,
+    max_lines: usize,
+    max_size: usize,
+}
+
+/// The settings of `xargs`, shared by all the command runs.
+struct Runner {
+    command: Vec<String>,
+    /// The string replaced by each input line in `command` (`-I`).
+    replace: Option<String>,
+    max_procs: usize,
+    verbose: bool,
+    interactive: bool,
+    /// The processes that are still running, and whether each one runs a
+    /// rustybox command in-process.
+    running: Vec<(libc::pid_t, bool)>,
+    /// The exit status of `xargs`, if any command failed.
+    status: i32,
+}
+
+/// Exit with an error message about the options.
+fn usage_error(message: &str) -> ! {
+    eprintln!("xargs: {}", message);
+    std::process::exit(-120);
+}
+
+/// Decode the escape sequences allowed by `-d` (`\n`, `\t`, `\0`, `\\`
+/// and octal `\NNN`) into a single byte.
+fn parse_delimiter(delimiter: &str) -> Option<u8> {
+    match delimiter.as_bytes() {
+        [byte] => Some(*byte),
+        [b'\\', b'n'] => Some(b'\n'),
+        [b'\\', b't'] => Some(b'\t'),
+        [b'\\', b'\\'] => Some(b'\\'),
+        [b'\\', digits @ ..] if digits.iter().all(u8::is_ascii_digit) => {
+            u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok()
+        }
+        _ => None,
+    }
+}
+
+/// Split a line into arguments, separated by blanks. Quotes group blanks
+/// into an argument, and a backslash escapes the following character.
+fn split_blanks(line: &str) -> Result<Vec<String>, char> {
+    let mut args = Vec::new();
+    let mut current = None::<String>;
+    let mut chars = line.chars();
+
+    while let Some(chr) = chars.next() {
+        match chr {
+            ' ' | '\t' | '\n' => {
+                if let Some(arg) = current.take() {
+                    args.push(arg);
+                }
+            }
+            '\'' | '"' => {
+                let arg = current.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some(c) if c == chr => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(chr),
+                    }
+                }
+            }
+            '\\' => {
+                let arg = current.get_or_insert_with(String::new);
+                if let Some(c) = chars.next() {
+                    arg.push(c);
+                }
+            }
+            _ => current.get_or_insert_with(String::new).push(chr),
+        }
+    }
+
+    args.extend(current);
+    Ok(args)
+}
+
+/// The maximum length of a command line, given by `ARG_MAX` without the
+/// environment and some headroom, but no more than 128 KiB, like GNU xargs.
+fn max_command_size() -> usize {
+    // SAFETY: `sysconf` has no preconditions.
+    let arg_max = unsafe { libc::sysconf(libc::_SC_ARG_MAX) };
+    let arg_max = if arg_max > 0 {
+        arg_max as usize
+    } else {
+        128 * 1024
+    };
+
+    let env_size = std::env::vars_os()
+        .map(|(key, value)| key.len() + value.len() + 2)
+        .sum::<usize>();
+
+    arg_max.saturating_sub(env_size + 2048).min(128 * 1024)
+}
+
+/// The length that `args` take on a command line.
+fn command_size(args: &[String]) -> usize {
+    args.iter().map(|arg| arg.len() + 1).sum()
+}
+
+impl Runner {
+    /// Run the command with `args` appended, without waiting for it,
+    /// unless too many processes are running.
+    fn run(&mut self, args: &[String]) {
+        let command = match &self.replace {
+            Some(replace) => self
+                .command
+                .iter()
+                .map(|arg| arg.replace(replace.as_str(), &args.join(" ")))
+                .collect::<Vec<_>>(),
+            None => self.command.iter().chain(args).cloned().collect(),
+        };
+
+        if self.verbose || self.interactive {
+            eprint!("{}", command.join(" "));
+
+            if self.interactive {
+                eprint!(" ?...");
+                if !confirm() {
+                    return;
+                }
+            } else {
+                eprintln!();
+            }
+        }
+
+        while self.max_procs != 0 && self.running.len() >= self.max_procs {
+            self.wait_one();
+        }
+
+        match spawn(&command) {
+            Ok(running) => self.running.push(running),
+            Err(e) => {
+                eprintln!("xargs: {}: {}", command[0], error_reason(&e));
+                let status = if e.kind() == std::io::ErrorKind::NotFound {
+                    127
+                } else {
+                    126
+                };
+
+                self.finish();
+                std::process::exit(status);
+            }
+        }
+    }
+
+    /// Wait for any of the running processes to end.
+    fn wait_one(&mut self) {
+        let mut status = 0;
+
+        // SAFETY: `status` is a valid pointer.
+        let pid = unsafe { libc::waitpid(-1, &mut status, 0) };
+        if pid < 0 {
+            self.running.clear();
+            return;
+        }
+
+        let Some(i) = self.running.iter().position(|&(running, _)| running == pid) else {
+            return;
+        };
+        let (_, in_process) = self.running.swap_remove(i);
+
+        if libc::WIFEXITED(status) {
+            match libc::WEXITSTATUS(status) {
+                // The commands run in-process exit with negative statuses on
+                // errors, which become 255: they can't ask xargs to abort.
+                255 if in_process => self.status = 123,
+                0 => {}
+                255 => {
+                    eprintln!(
+                        "xargs: {}: exited with status 255; aborting",
+                        self.command[0]
+                    );
+                    self.finish();
+                    std::process::exit(124);
+                }
+                _ => self.status = 123,
+            }
+        } else if libc::WIFSIGNALED(status) {
+            eprintln!(
+                "xargs: {}: terminated by signal {}",
+                self.command[0],
+                libc::WTERMSIG(status)
+            );
+            self.finish();
+            std::process::exit(125);
+        }
+    }
+
+    /// Wait for all the running processes.
+    fn finish(&mut self) {
+        while !self.running.is_empty() {
+            self.wait_one();
+        }
+    }
+}
+
+/// Ask the user (through the terminal) whether to run a command.
+fn confirm() -> bool {
+    let Ok(tty) = std::fs::File::open("/dev/tty") else {
+        eprintln!();
+        return false;
+    };
+
+    let mut answer = String::new();
+    let _ = std::io::BufReader::new(tty).read_line(&mut answer);
+
+    answer.starts_with(['y', 'Y'])
+}
+
+/// Start running `command`, with its input from `/dev/null`. If it's one of
+/// the rustybox commands, the process is just forked and the command runs
+/// without executing the binary again. Returns the id of the new process,
+/// and whether it runs a rustybox command.
+fn spawn(command: &[String]) -> std::io::Result<(libc::pid_t, bool)> {
+    let (program, args) = command.split_first().expect("the command isn't empty");
+
+    let Some(run) = crate::applet(program).filter(|_| !program.contains('/')) else {
+        let child = std::process::Command::new(program)
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .spawn()?;
+        return Ok((child.id() as libc::pid_t, false));
+    };
+
+    // Anything still buffered would be written by both processes.
+    let _ = std::io::stdout().flush();
+
+    // SAFETY: the process has a single thread, so the child can
+    // safely keep running Rust code.
+    match unsafe { libc::fork() } {
+        -1 => Err(std::io::Error::last_os_error()),
+        0 => {
+            // The input of xargs is not meant for the command.
+            if let Ok(null) = std::fs::File::open("/dev/null") {
+                // SAFETY: both file descriptors are valid.
+                unsafe { libc::dup2(null.as_raw_fd(), libc::STDIN_FILENO) };
+            }
+
+            run(args);
+            std::process::exit(0);
+        }
+        pid => Ok((pid, true)),
+    }
+}
+
+pub fn xargs(args: &[String]) {
+    const WITH_VALUE: [&str; 12] = [
+        "-d",
+        "--delimiter",
+        "-n",
+        "--max-args",
+        "-L",
+        "--max-lines",
+        "-s",
+        "--max-chars",
+        "-I",
+        "--replace",
+        "-P",
+        "--max-procs",
+    ];
+
+    let command_start = operands_start(args, &WITH_VALUE);
+    let Some((opts, _)) = parse_options(&args[..command_start], &WITH_VALUE) else {
+        usage_error("option requires an argument");
+    };
+
+    let mut format = InputFormat::Blanks;
+    let mut limits = Limits {
+        max_args: usize::MAX,
+        max_lines: usize::MAX,
+        max_size: max_command_size(),
+    };
+    let mut replace = None;
+    let mut max_procs = 1;
+    let mut no_run_if_empty = false;
+    let mut verbose = false;
+    let mut interactive = false;
+
+    let parse_count = |opt: &str, value: Option<String>| -> usize {
+        match value.as_deref().map(str::parse) {
+            Some(Ok(count)) => count,
+            _ => usage_error(&format!("invalid number for {} option", opt)),
+        }
+    };
+
+    for (opt, value) in opts {
+        match opt.as_str() {
+            "-0" | "--null" => format = InputFormat::Delimiter(b'\0'),
+            "-d" | "--delimiter" => match value.as_deref().and_then(parse_delimiter) {
+                Some(delimiter) => format = InputFormat::Delimiter(delimiter),
+                None => usage_error("invalid delimiter"),
+            },
+            "-n" | "--max-args" => limits.max_args = parse_count(&opt, value).max(1),
+            "-L" | "--max-lines" => limits.max_lines = parse_count(&opt, value).max(1),
+            "-s" | "--max-chars" => {
+                limits.max_size = parse_count(&opt, value).min(limits.max_size);
+            }
+            "-I" | "--replace" => {
+                replace = value;
+                limits.max_lines = 1;
+            }
+            "-P" | "--max-procs" => max_procs = parse_count(&opt, value),
+            "-r" | "--no-run-if-empty" => no_run_if_empty = true,
+            "-t" | "--verbose" => verbose = true,
+            "-p" | "--interactive" => interactive = true,
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-120);
+            }
+        }
+    }
+
+    let command = match &args[command_start..] {
+        [] => vec![String::from("echo")],
+        command => command.to_vec(),
+    };
+
+    let base_size = command_size(&command);
+    if base_size >= limits.max_size {
+        usage_error("argument list too long");
+    }
+
+    let mut runner = Runner {
+        command,
+        replace,
+        max_procs,
+        verbose,
+        interactive,
+        running: Vec::new(),
+        status: 0,
+    };
+
+    // The arguments (and input lines) of the next command.
+    let mut batch = Vec::new();
+    let mut lines = 0;
+    let mut ran = false;
+
+    let stdin = std::io::stdin();
+    let mut input = stdin.lock();
+    let mut record = Vec::new();
+
+    loop {
+        record.clear();
+        let delimiter = match format {
+            InputFormat::Blanks => b'\n',
+            InputFormat::Delimiter(delimiter) => delimiter,
+        };
+
+        match input.read_until(delimiter, &mut record) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("xargs: failed to read input: {}", error_reason(&e));
+                std::process::exit(-120);
+            }
+        }
+
+        if record.last() == Some(&delimiter) {
+            record.pop();
+        }
+        let record = String::from_utf8_lossy(&record);
+
+        let items = match (&format, &runner.replace) {
+            // With '-I', every line is a single argument.
+            (InputFormat::Blanks, Some(_)) => {
+                let line = record.trim_start();
+                if line.is_empty() {
+                    continue;
+                }
+
+                vec![line.to_owned()]
+            }
+            (InputFormat::Blanks, None) => match split_blanks(&record) {
+                Ok(items) if items.is_empty() => continue,
+                Ok(items) => items,
+                Err(quote) => {
+                    let kind = if quote == '"' { "double" } else { "single" };
+                    eprintln!("xargs: unmatched {} quote", kind);
+                    std::process::exit(-120);
+                }
+            },
+            (InputFormat::Delimiter(_), _) => vec![record.into_owned()],
+        };
+
+        for item in items {
+            let size = base_size + command_size(&batch) + item.len() + 1;
+
+            if !batch.is_empty() && (batch.len() >= limits.max_args || size > limits.max_size) {
+                runner.run(&batch);
+                ran = true;
+                batch.clear();
+                lines = 0;
+            }
+
+            batch.push(item);
+        }
+
+        lines += 1;
+        if lines >= limits.max_lines {
+            runner.run(&batch);
+            ran = true;
+            batch.clear();
+            lines = 0;
+        }
+    }
+
+    // Like GNU xargs, the command runs once even without any input, unless
+    // '-r' is given. Echoing nothing doesn't matter, so it's skipped too.
+    let default_command = command_start == args.len();
+    if !batch.is_empty() || !(ran || no_run_if_empty || default_command || runner.replace.is_some())
+    {
+        runner.run(&batch);
+    }
+
+    runner.finish();
+
+    if runner.status != 0 {
+        std::process::exit(runner.status);
+    }
+}
@@ -0,0 +1,274 @@
+//! The `xxd` command, dumping a file in hexadecimal, or converting such
+//! a dump back into binary with `-r`.
+
+use std::io::{BufRead, BufWriter, Read, Write};
+
+use crate::dump::*;
+use crate::utils::*;
+
+fn usage() -> ! {
+    eprintln!("Usage: xxd [-p | -i | -r [-p]] [-c COLS] [-g BYTES] [-s [-]SEEK] [-l LEN] [-u] [INFILE [OUTFILE]]");
+    std::process::exit(-222);
+}
+
+/// Exit with an error message.
+fn fail(message: std::fmt::Arguments) -> ! {
+    eprintln!("xxd: {}", message);
+    std::process::exit(-222);
+}
+
+/// What `xxd` writes.
+#[derive(PartialEq)]
+enum Mode {
+    /// Offsets, grouped hexadecimal bytes and text.
+    Dump,
+    /// Only the hexadecimal bytes (`-p`).
+    Plain,
+    /// A C array (`-i`).
+    Include,
+}
+
+/// Get the name of the C array of `-i` for `path`, where the characters
+/// which can't be in an identifier are replaced with underscores.
+fn array_name(path: &str) -> String {
+    let name: String = path
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("__{}", name)
+    } else {
+        name
+    }
+}
+
+/// Decode the hexadecimal digits in `text`, two per byte.
+fn decode_hex(text: &str, bytes: &mut Vec<u8>) {
+    let digits: Vec<u8> = text
+        .chars()
+        .filter_map(|c| c.to_digit(16))
+        .map(|digit| digit as u8)
+        .collect();
+
+    bytes.extend(digits.chunks_exact(2).map(|pair| pair[0] << 4 | pair[1]));
+}
+
+/// Convert a hex dump read from `input` back into binary. Plain dumps
+/// are only made of hexadecimal digits; the other ones are lines with an
+/// offset and the bytes, where the text column, after two spaces, is
+/// ignored. The bytes missing between the offsets are zeros.
+fn revert(input: &mut dyn BufRead, out: &mut impl Write, plain: bool) -> std::io::Result<()> {
+    let mut position = 0;
+    let mut bytes = Vec::new();
+
+    for line in input.lines() {
+        let line = line?;
+        bytes.clear();
+
+        if plain {
+            decode_hex(&line, &mut bytes);
+            out.write_all(&bytes)?;
+            continue;
+        }
+
+        let Some((offset, data)) = line.split_once(':') else {
+            continue;
+        };
+        let Ok(offset) = u64::from_str_radix(offset.trim(), 16) else {
+            continue;
+        };
+
+        let data = data.strip_prefix(' ').unwrap_or(data);
+        for group in data.split(' ') {
+            if group.is_empty() || !group.chars().all(|c| c.is_ascii_hexdigit()) {
+                break;
+            }
+            decode_hex(group, &mut bytes);
+        }
+
+        if offset > position {
+            std::io::copy(&mut std::io::repeat(0).take(offset - position), out)?;
+            position = offset;
+        }
+        out.write_all(&bytes)?;
+        position += bytes.len() as u64;
+    }
+
+    Ok(())
+}
+
+pub fn xxd(args: &[String]) {
+    // xxd has long options with a single dash.
+    let args: Vec<String> = args
+        .iter()
+        .map(|arg| {
+            match arg.as_str() {
+                "-ps" | "-postscript" | "-plain" => "-p",
+                "-include" => "-i",
+                "-revert" => "-r",
+                "-seek" => "-s",
+                "-len" => "-l",
+                "-cols" => "-c",
+                "-groupsize" => "-g",
+                arg => arg,
+            }
+            .to_string()
+        })
+        .collect();
+
+    let Some((opts, files)) = parse_options(&args, &["-s", "-l", "-c", "-g"]) else {
+        usage();
+    };
+
+    let mut mode = Mode::Dump;
+    let mut reverse = false;
+    let mut upper = false;
+    let mut cols = None;
+    let mut group = 2;
+    let mut seek = 0;
+    let mut from_end = false;
+    let mut limit = None;
+
+    for (opt, value) in opts {
+        let value = value.unwrap_or_default();
+        let number = |value: &str| {
+            parse_offset(value).unwrap_or_else(|| fail(format_args!("invalid number '{}'", value)))
+        };
+
+        match opt.as_str() {
+            "-p" => mode = Mode::Plain,
+            "-i" => mode = Mode::Include,
+            "-r" => reverse = true,
+            "-u" => upper = true,
+            "-c" => cols = Some(number(&value) as usize).filter(|&cols| cols > 0),
+            "-g" => group = number(&value) as usize,
+            "-l" => limit = Some(number(&value)),
+            "-s" => {
+                from_end = value.starts_with('-');
+                seek = number(value.trim_start_matches(['+', '-']));
+            }
+            _ => {
+                println!("Invalid command");
+                std::process::exit(-222);
+            }
+        }
+    }
+
+    let (input, output) = match files[..] {
+        [] => ("-", None),
+        [input] => (input.as_str(), None),
+        [input, output] => (input.as_str(), Some(output)),
+        _ => usage(),
+    };
+
+    let output: Box<dyn Write> = match output {
+        Some(path) => match std::fs::File::create(path) {
+            Ok(file) => Box::new(file),
+            Err(e) => fail(format_args!("{}: {}", path, error_reason(&e))),
+        },
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut out = BufWriter::new(output);
+
+    if reverse {
+        let result = open_input(input)
+            .and_then(|mut input| revert(&mut input, &mut out, mode == Mode::Plain))
+            .and_then(|_| out.flush());
+        if let Err(e) = result {
+            fail(format_args!("{}: {}", input, error_reason(&e)));
+        }
+        return;
+    }
+
+    // A negative seek is from the end of the file.
+    if from_end {
+        match std::fs::metadata(input) {
+            Ok(metadata) if input != "-" && metadata.is_file() => {
+                seek = metadata.len().saturating_sub(seek);
+            }
+            _ => fail(format_args!("{}: cannot seek from the end", input)),
+        }
+    }
+
+    let cols = cols.unwrap_or(match mode {
+        Mode::Dump => 16,
+        Mode::Plain => 30,
+        Mode::Include => 12,
+    });
+    let group = if group == 0 { cols } else { group };
+    let hex_width = cols * 2 + cols.div_ceil(group);
+
+    let mut reader = Reader::new("xxd", vec![input.to_string()], seek, limit);
+    let mut block = vec![0; cols];
+    let mut offset = seek;
+    let mut line = String::new();
+
+    let result = (|| -> std::io::Result<()> {
+        if mode == Mode::Include && input != "-" {
+            writeln!(out, "unsigned char {}[] = {{", array_name(input))?;
+        }
+
+        loop {
+            let len = reader.read(&mut block);
+            if len == 0 {
+                break;
+            }
+
+            let bytes = &block[..len];
+            line.clear();
+
+            match mode {
+                Mode::Dump => {
+                    line.push_str(&format!("{:08x}: ", offset));
+                    let start = line.len();
+                    for group in bytes.chunks(group) {
+                        write_hex(&mut line, group, upper);
+                        line.push(' ');
+                    }
+                    line.push_str(&" ".repeat(hex_width - (line.len() - start)));
+                    line.push(' ');
+                    line.extend(bytes.iter().map(|&byte| printable(byte)));
+                    writeln!(out, "{}", line)?;
+                }
+                Mode::Plain => {
+                    write_hex(&mut line, bytes, upper);
+                    writeln!(out, "{}", line)?;
+                }
+                Mode::Include => {
+                    if offset > seek {
+                        writeln!(out, ",")?;
+                    }
+                    for (i, &byte) in bytes.iter().enumerate() {
+                        line.push_str(if i == 0 { "  0x" } else { ", 0x" });
+                        write_hex(&mut line, &[byte], upper);
+                    }
+                    write!(out, "{}", line)?;
+                }
+            }
+
+            offset += len as u64;
+        }
+
+        if mode == Mode::Include {
+            if offset > seek {
+                writeln!(out)?;
+            }
+            if input != "-" {
+                let name = array_name(input);
+                writeln!(out, "}};")?;
+                writeln!(out, "unsigned int {}_len = {};", name, offset - seek)?;
+            }
+        }
+
+        out.flush()
+    })();
+
+    if let Err(e) = result {
+        fail(format_args!("{}", error_reason(&e)));
+    }
+
+    if reader.failed {
+        std::process::exit(-222);
+    }
+}